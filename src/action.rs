@@ -0,0 +1,249 @@
+//! Action module.
+//!
+//! Everything a hotkey, a tray menu entry or a second launch can ask the
+//! application to do. Actions are written as `name` or `name(argument)` in
+//! the config, e.g. `toggle`, `switch-profile(work)` or `snooze(15)`.
+
+use crate::config::{is_valid_profile, MonitorPolicy};
+use crate::indicator::Position;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Something the application can be asked to do.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Action {
+    /// Toggle indicator visibility (`toggle`).
+    Toggle,
+    /// Enable the indicators (`show`).
+    Show,
+    /// Disable the indicators (`hide`).
+    Hide,
+    /// Show the current layout now, even if indicators are disabled
+    /// (`show-now`).
+    Peek,
+    /// Show the current layout for as long as the hotkey is held
+    /// (`show-while-held`).
+    PeekWhileHeld,
+    /// End a `show-while-held` peek (`end-peek`); hotkey backends send it
+    /// when the held keys are released.
+    EndPeek,
+    /// Quit the application (`exit`).
+    Exit,
+    /// Re-read the config file (`reload-config`).
+    ReloadConfig,
+    /// Open the config file in the default editor (`open-config`).
+    OpenConfig,
+    /// Mute or unmute the layout change sound (`mute-sound`).
+    MuteSound,
+    /// Switch to the next profile in alphabetical order (`next-profile`).
+    NextProfile,
+    /// Switch to a profile (`switch-profile(NAME)`).
+    SwitchProfile(String),
+    /// Switch to the next keyboard layout (`cycle-layout`).
+    CycleLayout,
+    /// Switch to a keyboard layout by code (`set-layout(CODE)`).
+    SetLayout(String),
+    /// Disable the indicators for some minutes (`snooze(MINUTES)`).
+    Snooze(u32),
+    /// Show or hide the indicators at a position and save it to the config
+    /// (`toggle-position(top-left)`).
+    TogglePosition(Position),
+    /// Turn the layout change sound on or off and save it to the config
+    /// (`toggle-sound`).
+    ToggleSound,
+    /// Choose the monitors that show indicators and save it to the config
+    /// (`set-monitors(all)`, `set-monitors(primary)`).
+    SetMonitors(MonitorPolicy),
+    /// Set the indicator opacity in percent and save it to the config
+    /// (`set-opacity(PERCENT)`).
+    SetOpacity(u32),
+    /// Open the folder with the log file (`open-log-folder`).
+    OpenLogFolder,
+}
+
+/// Error returned for an unknown or malformed action.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseActionError(String);
+
+impl fmt::Display for ParseActionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid action: {}", self.0)
+    }
+}
+
+impl std::error::Error for ParseActionError {}
+
+impl FromStr for Action {
+    type Err = ParseActionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || ParseActionError(s.to_string());
+        let s = s.trim();
+        let (name, argument) = match s.split_once('(') {
+            Some((name, rest)) => {
+                let argument = rest.strip_suffix(')').ok_or_else(error)?.trim();
+                (name.trim(), Some(argument))
+            }
+            None => (s, None),
+        };
+
+        let action = match (name, argument) {
+            ("toggle", None) => Action::Toggle,
+            ("show", None) => Action::Show,
+            ("hide", None) => Action::Hide,
+            ("show-now", None) => Action::Peek,
+            ("show-while-held", None) => Action::PeekWhileHeld,
+            ("end-peek", None) => Action::EndPeek,
+            ("exit", None) => Action::Exit,
+            ("reload-config", None) => Action::ReloadConfig,
+            ("open-config", None) => Action::OpenConfig,
+            ("mute-sound", None) => Action::MuteSound,
+            ("next-profile", None) => Action::NextProfile,
+            ("switch-profile", Some(name)) if is_valid_profile(name) => {
+                Action::SwitchProfile(name.to_string())
+            }
+            ("cycle-layout", None) => Action::CycleLayout,
+            ("set-layout", Some(code)) if !code.is_empty() => Action::SetLayout(code.to_string()),
+            ("snooze", Some(minutes)) => match minutes.parse() {
+                Ok(minutes) if minutes > 0 => Action::Snooze(minutes),
+                _ => return Err(error()),
+            },
+            ("toggle-position", Some(name)) => {
+                Action::TogglePosition(Position::from_name(name).ok_or_else(error)?)
+            }
+            ("toggle-sound", None) => Action::ToggleSound,
+            ("set-monitors", Some(name)) => {
+                Action::SetMonitors(MonitorPolicy::from_name(name).ok_or_else(error)?)
+            }
+            ("set-opacity", Some(percent)) => match percent.parse() {
+                Ok(percent) if (1..=100).contains(&percent) => Action::SetOpacity(percent),
+                _ => return Err(error()),
+            },
+            ("open-log-folder", None) => Action::OpenLogFolder,
+            _ => return Err(error()),
+        };
+        Ok(action)
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Toggle => f.write_str("toggle"),
+            Action::Show => f.write_str("show"),
+            Action::Hide => f.write_str("hide"),
+            Action::Peek => f.write_str("show-now"),
+            Action::PeekWhileHeld => f.write_str("show-while-held"),
+            Action::EndPeek => f.write_str("end-peek"),
+            Action::Exit => f.write_str("exit"),
+            Action::ReloadConfig => f.write_str("reload-config"),
+            Action::OpenConfig => f.write_str("open-config"),
+            Action::MuteSound => f.write_str("mute-sound"),
+            Action::NextProfile => f.write_str("next-profile"),
+            Action::SwitchProfile(name) => write!(f, "switch-profile({})", name),
+            Action::CycleLayout => f.write_str("cycle-layout"),
+            Action::SetLayout(code) => write!(f, "set-layout({})", code),
+            Action::Snooze(minutes) => write!(f, "snooze({})", minutes),
+            Action::TogglePosition(position) => write!(f, "toggle-position({})", position.name()),
+            Action::ToggleSound => f.write_str("toggle-sound"),
+            Action::SetMonitors(policy) => write!(f, "set-monitors({})", policy.name()),
+            Action::SetOpacity(percent) => write!(f, "set-opacity({})", percent),
+            Action::OpenLogFolder => f.write_str("open-log-folder"),
+        }
+    }
+}
+
+impl TryFrom<String> for Action {
+    type Error = ParseActionError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Action> for String {
+    fn from(action: Action) -> Self {
+        action.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn actions_round_trip() {
+        let actions = [
+            Action::Toggle,
+            Action::Show,
+            Action::Hide,
+            Action::Peek,
+            Action::PeekWhileHeld,
+            Action::EndPeek,
+            Action::Exit,
+            Action::ReloadConfig,
+            Action::OpenConfig,
+            Action::MuteSound,
+            Action::NextProfile,
+            Action::SwitchProfile("work".to_string()),
+            Action::CycleLayout,
+            Action::SetLayout("ru".to_string()),
+            Action::Snooze(15),
+            Action::TogglePosition(Position::BottomRight),
+            Action::ToggleSound,
+            Action::SetMonitors(MonitorPolicy::Primary),
+            Action::SetOpacity(60),
+            Action::OpenLogFolder,
+        ];
+        for action in actions {
+            assert_eq!(action.to_string().parse(), Ok(action));
+        }
+    }
+
+    #[test]
+    fn arguments_are_trimmed() {
+        assert_eq!(" snooze( 5 ) ".parse(), Ok(Action::Snooze(5)));
+        assert_eq!(
+            "set-layout(de)".parse(),
+            Ok(Action::SetLayout("de".to_string()))
+        );
+    }
+
+    #[test]
+    fn bad_actions_are_rejected() {
+        for text in [
+            "",
+            "bogus",
+            "toggle(1)",
+            "snooze",
+            "snooze(0)",
+            "snooze(soon)",
+            "switch-profile(../etc)",
+            "switch-profile()",
+            "set-layout()",
+            "set-layout(ru",
+            "toggle-position(middle)",
+            "set-monitors(second)",
+            "set-opacity(0)",
+            "set-opacity(101)",
+        ] {
+            assert_eq!(
+                text.parse::<Action>(),
+                Err(ParseActionError(text.to_string())),
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn actions_are_strings_in_json() {
+        let json = serde_json::to_string(&Action::Snooze(10)).unwrap();
+        assert_eq!(json, "\"snooze(10)\"");
+        let action: Action = serde_json::from_str("\"next-profile\"").unwrap();
+        assert_eq!(action, Action::NextProfile);
+        assert!(serde_json::from_str::<Action>("\"nope\"").is_err());
+    }
+}
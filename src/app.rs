@@ -0,0 +1,1604 @@
+//! Application module.
+//!
+//! Platform-independent application flow: wires the platform backends to the
+//! indicator controller and runs the main loop.
+
+use crate::action::Action;
+use crate::cli;
+use crate::config::{AppConfig, ConfigManager, HotkeyConfig, MonitorPolicy};
+use crate::controller::{Clock, Command, IndicatorController, Input, SystemClock};
+use crate::hotkey::BindingStatus;
+use crate::indicator::get_enabled_positions;
+use crate::layout::{HookTimings, LayoutEvent, LayoutInfo, LayoutSwitch};
+use crate::logging;
+use crate::menu::{self, settings_menu, MenuEntry};
+use crate::platform::{Backends, InstanceLock, Overlay, PlatformEvent};
+use crate::scenario::TraceRecorder;
+use crate::sound::play_layout_sound;
+use crate::status::{Status, SwitchCounter, Visibility};
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+// Config check interval
+const CONFIG_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The running application.
+pub struct App<C: Clock + Clone = SystemClock> {
+    backends: Backends,
+    config_manager: ConfigManager,
+    config: AppConfig,
+    clock: C,
+    controller: IndicatorController<C>,
+    indicators: Vec<Box<dyn Overlay>>,
+    /// Channel for controller inputs (from hook, hotkey and tray to main thread)
+    input_tx: Sender<Input>,
+    input_rx: Receiver<Input>,
+    /// Actions requested by hotkeys and the tray menu
+    action_tx: Sender<Action>,
+    action_rx: Receiver<Action>,
+    /// Arguments forwarded by later launches
+    forward_tx: Sender<Vec<String>>,
+    forward_rx: Receiver<Vec<String>>,
+    /// Layout change sound muted at runtime (`mute-sound`)
+    sound_muted: bool,
+    /// Why the config file was replaced by the defaults on the last load
+    config_error: Option<String>,
+    /// Hotkeys that couldn't be registered
+    hotkey_conflicts: Vec<BindingStatus>,
+    /// Full name of the layout shown, if the layout source knows it
+    layout_description: Option<String>,
+    /// Profiles listed in the menu, read again when the config changes
+    profiles: Vec<String>,
+    /// Settings entries last shown in the tray menu
+    menu: Vec<MenuEntry>,
+    /// Status last shown in the tray tooltip
+    status: Option<Status>,
+    switches: SwitchCounter,
+    last_config_mtime: Option<SystemTime>,
+    last_config_check: Instant,
+    started_at: Instant,
+    recorder: Option<TraceRecorder>,
+}
+
+impl<C: Clock + Clone> App<C> {
+    /// Loads the configuration and creates the indicator windows.
+    pub fn new(backends: Backends, config_manager: ConfigManager, clock: C) -> Self {
+        let (config, config_error) = config_manager.load_with_error();
+        log::info!("Configuration loaded from {:?}", config_manager.path());
+
+        // Get initial layout BEFORE starting the layout source to prevent false trigger
+        let initial_layout = backends.layout.current_layout();
+        log::info!("Initial layout: {}", initial_layout.name);
+
+        let hide_delay = Duration::from_millis(config.hide_delay_ms as u64);
+        let controller = IndicatorController::new(clock.clone(), initial_layout, hide_delay);
+        let (input_tx, input_rx) = mpsc::channel();
+        let (action_tx, action_rx) = mpsc::channel();
+        let (forward_tx, forward_rx) = mpsc::channel();
+        let now = clock.now();
+
+        let mut app = Self {
+            last_config_mtime: config_manager.get_modified_time(),
+            last_config_check: now,
+            started_at: now,
+            backends,
+            config_manager,
+            config,
+            clock,
+            controller,
+            indicators: Vec::new(),
+            input_tx,
+            input_rx,
+            action_tx,
+            action_rx,
+            sound_muted: false,
+            config_error,
+            hotkey_conflicts: Vec::new(),
+            layout_description: None,
+            profiles: Vec::new(),
+            menu: Vec::new(),
+            status: None,
+            switches: SwitchCounter::default(),
+            forward_tx,
+            forward_rx,
+            recorder: None,
+        };
+
+        // Create indicator windows
+        app.create_indicators();
+        log::info!("Created {} indicator windows", app.indicators.len());
+
+        app
+    }
+
+    /// Starts tray, layout tracking and hotkeys, and shows the initial layout.
+    pub fn start(&mut self) {
+        let tray_tx = self.action_tx.clone();
+        self.backends.tray.set_callback(Arc::new(move |action| {
+            log::debug!("Tray: {}", action);
+            let _ = tray_tx.send(action);
+        }));
+
+        if let Err(e) = self.backends.tray.start() {
+            log::error!("Failed to start tray icon: {}", e);
+        }
+        self.profiles = self.config_manager.profiles();
+        self.update_menu();
+
+        // Layout source callback just sends to channel
+        let layout_tx = self.input_tx.clone();
+        let layout_callback = Arc::new(move |event: LayoutEvent| {
+            log::debug!(
+                "Layout callback: {} ({}ms after trigger)",
+                event.layout.name,
+                event.latency().as_millis()
+            );
+            if let Err(e) = layout_tx.send(Input::Layout(event.layout)) {
+                log::error!("Failed to send layout event: {}", e);
+            }
+        });
+
+        let initial_layout = self.controller.layout().clone();
+        self.backends.layout.start(
+            layout_callback,
+            &initial_layout,
+            HookTimings::from_config(&self.config),
+        );
+        log::info!("Layout tracking started");
+
+        self.start_hotkeys();
+        self.report_hotkey_conflicts();
+
+        let forward_tx = self.forward_tx.clone();
+        self.backends.instance.listen(Arc::new(move |args| {
+            let _ = forward_tx.send(args);
+        }));
+
+        // Show initial indicators
+        self.recorder = TraceRecorder::from_env(
+            &initial_layout,
+            Duration::from_millis(self.config.hide_delay_ms as u64),
+        );
+        let commands = self.controller.start();
+        if let Some(ref mut recorder) = self.recorder {
+            recorder.record_start(&commands);
+        }
+        self.execute(commands);
+
+        log::info!("LangTip running");
+    }
+
+    /// Runs one iteration of the main loop.
+    ///
+    /// `events` are the window system events pumped since the last step.
+    /// Returns false when the application should exit.
+    pub fn step(&mut self, events: &[PlatformEvent]) -> bool {
+        self.backends.tray.process_menu_events();
+
+        // Inputs from hook, hotkey and tray threads
+        let mut inputs: Vec<Input> = self.input_rx.try_iter().collect();
+
+        let actions: Vec<Action> = self.action_rx.try_iter().collect();
+        for action in actions {
+            inputs.extend(self.handle_action(action));
+        }
+
+        let forwarded: Vec<Vec<String>> = self.forward_rx.try_iter().collect();
+        for args in forwarded {
+            inputs.extend(self.handle_forwarded(&args));
+        }
+
+        for event in events {
+            match event {
+                PlatformEvent::Quit => return false,
+                PlatformEvent::DisplayChanged => {
+                    log::info!("Display configuration changed, recreating indicators...");
+                    inputs.push(Input::DisplayChanged {
+                        layout: self.backends.layout.current_layout(),
+                    });
+                }
+            }
+        }
+
+        if let Some(input) = self.check_config() {
+            inputs.push(input);
+        }
+
+        // Auto-hide and cooldown timers
+        inputs.push(Input::Tick);
+
+        for input in inputs {
+            let commands = self.controller.handle(input.clone());
+            let (executed, probes) = self.execute(commands);
+            if let Some(ref mut recorder) = self.recorder {
+                let at = self.clock.now().saturating_duration_since(self.started_at);
+                recorder.record(at, &input, &probes, &executed);
+            }
+            if executed.contains(&Command::Exit) {
+                return false;
+            }
+        }
+
+        // Update fade animations
+        for indicator in &self.indicators {
+            indicator.update_fade();
+        }
+
+        let status = self.status();
+        if !self.status.as_ref().is_some_and(|last| last.shows_same(&status)) {
+            self.backends.tray.set_status(&status);
+            self.status = Some(status);
+        }
+        true
+    }
+
+    /// Collects the status shown in the tray tooltip.
+    fn status(&self) -> Status {
+        let visibility = match self.controller.snooze_left() {
+            Some(left) => Visibility::Snoozed(left),
+            None if self.controller.is_visible() => Visibility::Visible,
+            None => Visibility::Hidden,
+        };
+        let mut warnings: Vec<String> = self.config_error.iter().cloned().collect();
+        warnings.extend(
+            self.hotkey_conflicts
+                .iter()
+                .map(|conflict| format!("Hotkey {}", conflict)),
+        );
+        Status {
+            layout: self
+                .layout_description
+                .clone()
+                .unwrap_or_else(|| self.controller.layout().name.clone()),
+            visibility,
+            profile: self.config_manager.profile(),
+            switches_today: self.switches.count(self.clock.today()),
+            warnings,
+        }
+    }
+
+    /// Runs the main loop until exit.
+    pub fn run(&mut self) {
+        let mut events = Vec::new();
+        while self.step(&events) {
+            events = self.backends.overlays.pump_events();
+        }
+    }
+
+    /// Stops all backends.
+    pub fn shutdown(&mut self) {
+        log::info!("LangTip shutting down...");
+
+        self.backends.layout.stop();
+        self.backends.hotkeys.stop();
+        self.backends.tray.stop();
+        self.indicators.clear();
+    }
+
+    /// Registers the configured hotkeys.
+    fn start_hotkeys(&mut self) {
+        let hotkey_tx = self.action_tx.clone();
+        self.backends.hotkeys.start(
+            &self.config.hotkeys,
+            Arc::new(move |action| {
+                log::debug!("Hotkey: {}", action);
+                let _ = hotkey_tx.send(action);
+            }),
+        );
+        log::info!("Hotkey manager started");
+    }
+
+    /// Applies a changed hotkey config: starts or stops the hotkeys when
+    /// they are switched on or off, re-registers the bindings otherwise.
+    fn reload_hotkeys(&mut self, previous: &HotkeyConfig) {
+        let hotkeys = &self.config.hotkeys;
+        if *hotkeys == *previous {
+            return;
+        }
+        match (previous.enabled, hotkeys.enabled) {
+            (true, false) => {
+                self.backends.hotkeys.stop();
+                log::info!("Hotkey manager stopped");
+            }
+            (false, true) => self.start_hotkeys(),
+            _ => {
+                self.backends.hotkeys.reload(hotkeys);
+                log::info!("Hotkeys reloaded");
+            }
+        }
+        self.report_hotkey_conflicts();
+    }
+
+    /// Lists the hotkeys that couldn't be registered in the tray menu and
+    /// tells the user about them once per new set of conflicts.
+    fn report_hotkey_conflicts(&mut self) {
+        let mut conflicts: Vec<BindingStatus> = self
+            .backends
+            .hotkeys
+            .status()
+            .into_iter()
+            .filter(|s| !s.is_registered())
+            .collect();
+        if self.config.hotkeys.enabled {
+            conflicts.extend(self.config.hotkeys.invalid_gestures());
+        }
+        self.backends.tray.set_hotkey_conflicts(&conflicts);
+        let changed = conflicts != self.hotkey_conflicts;
+        self.hotkey_conflicts = conflicts.clone();
+        if conflicts.is_empty() || !changed {
+            return;
+        }
+
+        let text = conflicts
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("\n");
+        log::warn!("Hotkeys not registered:\n{}", text);
+        self.backends
+            .tray
+            .notify("LangTip: hotkeys not registered", &text);
+    }
+
+    /// Checks for config file changes (hot reload).
+    fn check_config(&mut self) -> Option<Input> {
+        let now = self.clock.now();
+        if now.saturating_duration_since(self.last_config_check) < CONFIG_CHECK_INTERVAL {
+            return None;
+        }
+        self.last_config_check = now;
+
+        let current_mtime = self.config_manager.get_modified_time()?;
+        let config_changed = match self.last_config_mtime {
+            Some(last) => current_mtime != last,
+            None => true,
+        };
+        if !config_changed {
+            return None;
+        }
+
+        log::info!("Config file changed, reloading...");
+        Some(self.reload_config())
+    }
+
+    /// Re-reads the config file.
+    fn reload_config(&mut self) -> Input {
+        self.last_config_mtime = self.config_manager.get_modified_time();
+        let (config, config_error) = self.config_manager.load_with_error();
+        self.apply_config(config, config_error)
+    }
+
+    /// Changes a setting from the tray menu: saves the changed config and
+    /// applies it right away. A config file that failed to load is left
+    /// alone, so the user's settings in it aren't replaced by the defaults.
+    fn change_config(&mut self, change: impl FnOnce(&mut AppConfig)) -> Vec<Input> {
+        if let Some(error) = self.config_error.clone() {
+            log::warn!("Settings not saved, the config file is broken: {}", error);
+            self.backends.tray.notify(
+                "LangTip: settings not saved",
+                &format!("Fix the config file first.\n{}", error),
+            );
+            // The menu may show the click already
+            self.backends.tray.set_menu(&self.menu);
+            return Vec::new();
+        }
+
+        let mut config = self.config.clone();
+        change(&mut config);
+        if let Err(e) = self.config_manager.save(&config) {
+            log::warn!("Failed to save {:?}: {}", self.config_manager.path(), e);
+            self.backends
+                .tray
+                .notify("LangTip: settings not saved", &e.to_string());
+            self.backends.tray.set_menu(&self.menu);
+            return Vec::new();
+        }
+        // Our own write isn't a change to reload
+        self.last_config_mtime = self.config_manager.get_modified_time();
+        vec![self.apply_config(config, None)]
+    }
+
+    /// Switches to a new config: updates timings, hotkeys and the menu.
+    /// Returns the input that recreates the indicators.
+    fn apply_config(&mut self, config: AppConfig, config_error: Option<String>) -> Input {
+        self.config_error = config_error;
+        let previous = std::mem::replace(&mut self.config, config);
+        self.backends
+            .layout
+            .set_timings(HookTimings::from_config(&self.config));
+        self.reload_hotkeys(&previous.hotkeys);
+        self.profiles = self.config_manager.profiles();
+        self.update_menu();
+
+        Input::ConfigReloaded {
+            hide_delay: Duration::from_millis(self.config.hide_delay_ms as u64),
+            layout: self.backends.layout.current_layout(),
+        }
+    }
+
+    /// Acts on arguments forwarded by a later launch. A launch without
+    /// arguments brings the indicators back.
+    fn handle_forwarded(&mut self, args: &[String]) -> Vec<Input> {
+        log::info!("Arguments from another launch: {:?}", args);
+        let mut actions = cli::parse_args(args);
+        if args.is_empty() {
+            actions.push(Action::Show);
+        }
+
+        let mut inputs = Vec::new();
+        for action in actions {
+            inputs.extend(self.handle_action(action));
+        }
+        inputs
+    }
+
+    /// Carries out an action from a hotkey, the tray menu or another launch.
+    ///
+    /// Returns the controller inputs it results in.
+    fn handle_action(&mut self, action: Action) -> Vec<Input> {
+        log::info!("Action: {}", action);
+        match action {
+            Action::Toggle => vec![Input::Toggle],
+            Action::Show => vec![Input::SetVisible(true)],
+            Action::Hide => vec![Input::SetVisible(false)],
+            Action::Peek => vec![Input::Peek],
+            Action::PeekWhileHeld => vec![Input::PeekHeld(true)],
+            Action::EndPeek => vec![Input::PeekHeld(false)],
+            Action::Exit => vec![Input::Exit],
+            Action::Snooze(minutes) => {
+                vec![Input::Snooze(Duration::from_secs(u64::from(minutes) * 60))]
+            }
+            Action::ReloadConfig => vec![self.reload_config()],
+            Action::OpenConfig => {
+                if !self.backends.opener.open(self.config_manager.path()) {
+                    log::warn!("Failed to open {:?}", self.config_manager.path());
+                }
+                Vec::new()
+            }
+            Action::OpenLogFolder => {
+                match logging::log_dir() {
+                    Some(dir) => {
+                        let _ = std::fs::create_dir_all(&dir);
+                        if !self.backends.opener.open(&dir) {
+                            log::warn!("Failed to open {:?}", dir);
+                        }
+                    }
+                    None => log::warn!("No log folder on this system"),
+                }
+                Vec::new()
+            }
+            Action::TogglePosition(position) => self.change_config(|config| {
+                let enabled = config.positions.is_enabled(position);
+                config.positions.set(position, !enabled);
+            }),
+            Action::ToggleSound => {
+                self.change_config(|config| config.sound.enabled = !config.sound.enabled)
+            }
+            Action::SetMonitors(policy) => self.change_config(|config| config.monitors = policy),
+            Action::SetOpacity(percent) => self.change_config(|config| config.opacity = percent),
+            Action::MuteSound => {
+                self.sound_muted = !self.sound_muted;
+                log::info!(
+                    "Sound {}",
+                    if self.sound_muted { "muted" } else { "unmuted" }
+                );
+                Vec::new()
+            }
+            Action::NextProfile => {
+                let profiles = self.config_manager.profiles();
+                let current = self.config_manager.profile();
+                let next = profiles
+                    .iter()
+                    .position(|p| *p == current)
+                    .map_or(0, |i| (i + 1) % profiles.len());
+                match profiles.get(next) {
+                    Some(name) if *name != current => {
+                        let name = name.clone();
+                        vec![self.switch_profile(&name)]
+                    }
+                    _ => {
+                        log::info!("No other profile to switch to");
+                        Vec::new()
+                    }
+                }
+            }
+            Action::SwitchProfile(name) => vec![self.switch_profile(&name)],
+            Action::CycleLayout => {
+                self.backends.layout.switch_layout(&LayoutSwitch::Next);
+                Vec::new()
+            }
+            Action::SetLayout(code) => {
+                self.backends.layout.switch_layout(&LayoutSwitch::To(code));
+                Vec::new()
+            }
+        }
+    }
+
+    /// Switches to the config file of a profile and loads it.
+    fn switch_profile(&mut self, name: &str) -> Input {
+        self.config_manager = self.config_manager.for_profile(name);
+        log::info!(
+            "Switched to profile {} ({:?})",
+            name,
+            self.config_manager.path()
+        );
+        self.reload_config()
+    }
+
+    /// Shows the settings of the current config in the tray menu if they
+    /// changed. A layout switch only moves the check of the Layout submenu.
+    fn update_menu(&mut self) {
+        let layouts = self.backends.layout.installed_layouts();
+        let entries = settings_menu(
+            &self.config,
+            &self.config_manager.profile(),
+            &self.profiles,
+            &layouts,
+        );
+        if entries == self.menu {
+            return;
+        }
+        match menu::layout_switch(&self.menu, &entries) {
+            Some(code) => self.backends.tray.set_active_layout(code),
+            None => self.backends.tray.set_menu(&entries),
+        }
+        self.menu = entries;
+    }
+
+    /// Creates indicator windows based on config.
+    fn create_indicators(&mut self) {
+        // Destroy old windows before creating new ones
+        self.indicators.clear();
+
+        let mut monitors = self.backends.monitors.monitors();
+        if self.config.monitors == MonitorPolicy::Primary {
+            // Monitors are listed primary first
+            monitors.truncate(1);
+        }
+        let positions = get_enabled_positions(&self.config);
+
+        for monitor in &monitors {
+            for position in &positions {
+                if let Some(overlay) =
+                    self.backends
+                        .overlays
+                        .create_overlay(*position, &self.config, monitor)
+                {
+                    self.indicators.push(overlay);
+                }
+            }
+        }
+    }
+
+    /// Carries out controller commands in order.
+    ///
+    /// `RefreshLayout` reads the current layout and feeds it back to the
+    /// controller. Returns every command carried out, including the ones
+    /// produced by such follow-ups, and the layouts read along the way.
+    fn execute(&mut self, commands: Vec<Command>) -> (Vec<Command>, Vec<LayoutInfo>) {
+        let mut queue: VecDeque<Command> = commands.into();
+        let mut executed = Vec::new();
+        let mut probes = Vec::new();
+
+        while let Some(command) = queue.pop_front() {
+            match &command {
+                Command::RecreateIndicators => {
+                    self.create_indicators();
+                    log::info!("Recreated {} indicator windows", self.indicators.len());
+                }
+                Command::UpdateText(layout) => {
+                    for indicator in &self.indicators {
+                        indicator.update_text(&layout.name, layout.is_russian);
+                    }
+                    let color = self.config.colors.for_layout(layout.is_russian);
+                    self.backends.tray.set_layout(layout, color);
+                    self.layout_description = self.backends.layout.layout_description();
+                    self.update_menu();
+                }
+                Command::Show => {
+                    for indicator in &self.indicators {
+                        indicator.show();
+                    }
+                }
+                Command::Hide => {
+                    for indicator in &self.indicators {
+                        indicator.hide();
+                    }
+                }
+                Command::PlaySound(layout) => {
+                    // Played once per layout change
+                    self.switches.record(self.clock.today());
+                    if !self.sound_muted {
+                        play_layout_sound(
+                            self.backends.sound.as_ref(),
+                            layout.is_russian,
+                            &self.config.sound,
+                        )
+                    }
+                }
+                Command::RefreshLayout => {
+                    let layout = self.backends.layout.current_layout();
+                    probes.push(layout.clone());
+                    queue.extend(self.controller.handle(Input::Layout(layout)));
+                }
+                Command::Exit => {}
+            }
+            executed.push(command);
+        }
+
+        (executed, probes)
+    }
+}
+
+/// Runs the application until exit.
+///
+/// `args` are the command line arguments and `actions` what they ask for. If
+/// another instance holds `instance`, the arguments are forwarded to it
+/// instead and `backends` is never called, so a second launch doesn't
+/// connect to the window system. Otherwise the actions run once started;
+/// `--profile` is left out, the caller already picked the config file.
+pub fn run(
+    mut instance: Box<dyn InstanceLock>,
+    backends: impl FnOnce() -> Backends,
+    config_manager: ConfigManager,
+    args: &[String],
+    actions: Vec<Action>,
+) {
+    if !instance.acquire() {
+        if instance.forward(args) {
+            log::info!("Another instance is already running, arguments passed to it");
+        } else {
+            log::warn!("Another instance is already running");
+            instance.notify_already_running();
+        }
+        return;
+    }
+
+    let mut backends = backends();
+    backends.instance = instance;
+    let mut app = App::new(backends, config_manager, SystemClock);
+    app.start();
+    for action in actions {
+        if !matches!(action, Action::SwitchProfile(_)) {
+            let _ = app.action_tx.send(action);
+        }
+    }
+    app.run();
+    app.shutdown();
+    app.backends.instance.release();
+
+    log::info!("LangTip stopped");
+}
+
+/// Registers the configured hotkeys once and writes how each went to `out`
+/// (`langtip hotkeys --check`). Returns true if all of them work.
+pub fn check_hotkeys(
+    mut backends: Backends,
+    config_manager: &ConfigManager,
+    out: &mut dyn Write,
+) -> io::Result<bool> {
+    let config = config_manager.load();
+    if !config.hotkeys.enabled {
+        writeln!(
+            out,
+            "Hotkeys are disabled in {}",
+            config_manager.path().display()
+        )?;
+        return Ok(true);
+    }
+
+    let running = !backends.instance.acquire();
+    backends.hotkeys.start(&config.hotkeys, Arc::new(|_| {}));
+    let status = backends.hotkeys.status();
+    backends.hotkeys.stop();
+    if !running {
+        backends.instance.release();
+    }
+
+    for binding in &status {
+        writeln!(out, "{}", binding)?;
+    }
+    let registered = status.iter().filter(|s| s.is_registered()).count();
+    writeln!(out, "{} of {} hotkeys registered", registered, status.len())?;
+    if running && registered < status.len() {
+        writeln!(
+            out,
+            "LangTip is running, so its own hotkeys are reported as taken"
+        )?;
+    }
+    Ok(registered == status.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{GestureBinding, HotkeyBinding, KeyRemap};
+    use crate::controller::ManualClock;
+    use crate::indicator::Position;
+    use crate::menu::MenuEntry;
+    use crate::platform::headless::{HeadlessHandle, HeadlessPlatform, TrayClick};
+    use std::path::PathBuf;
+
+    /// Config file in a fresh temporary directory.
+    fn temp_config(name: &str, config: &AppConfig) -> ConfigManager {
+        let dir = std::env::temp_dir().join(format!("langtip-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let manager = ConfigManager::with_path(dir.join("config.json"));
+        manager.save(config).unwrap();
+        manager
+    }
+
+    /// Rewrites the config file with `write`, moving its modification time
+    /// a second on so the next check sees the change however coarse the
+    /// file system clock is.
+    fn rewrite_config(manager: &ConfigManager, write: impl FnOnce()) {
+        let before = manager.get_modified_time().unwrap();
+        write();
+        std::fs::File::options()
+            .write(true)
+            .open(manager.path())
+            .unwrap()
+            .set_modified(before + Duration::from_secs(1))
+            .unwrap();
+    }
+
+    fn remove_config(manager: &ConfigManager) {
+        if let Some(dir) = manager.path().parent().map(PathBuf::from) {
+            let _ = std::fs::remove_dir_all(dir);
+        }
+    }
+
+    fn started_app(
+        name: &str,
+        config: &AppConfig,
+    ) -> (App<ManualClock>, HeadlessHandle, ManualClock) {
+        let platform = HeadlessPlatform::new();
+        let handle = platform.handle();
+        let clock = ManualClock::new();
+        let mut app = App::new(
+            platform.backends(),
+            temp_config(name, config),
+            clock.clone(),
+        );
+        app.start();
+        (app, handle, clock)
+    }
+
+    /// Runs enough steps for fade animations to finish.
+    fn settle(app: &mut App<ManualClock>) {
+        for _ in 0..20 {
+            assert!(app.step(&[]));
+        }
+    }
+
+    fn texts(handle: &HeadlessHandle) -> Vec<String> {
+        handle.overlays().into_iter().map(|o| o.text).collect()
+    }
+
+    fn all_shown(handle: &HeadlessHandle) -> bool {
+        handle.overlays().iter().all(|o| o.alpha > 0)
+    }
+
+    fn all_hidden(handle: &HeadlessHandle) -> bool {
+        handle.overlays().iter().all(|o| o.alpha == 0)
+    }
+
+    #[test]
+    fn full_app_flow() {
+        let config = AppConfig::default();
+        let (mut app, handle, clock) = started_app("flow", &config);
+
+        assert!(handle.tray_started());
+        assert!(handle.layout_source_started());
+        assert_eq!(handle.timings(), Some(HookTimings::from_config(&config)));
+        assert!(handle.hotkey_config().is_some());
+
+        // Startup: one overlay per enabled position, showing the initial layout
+        settle(&mut app);
+        let positions: Vec<Position> = handle.overlays().iter().map(|o| o.position).collect();
+        assert_eq!(positions, get_enabled_positions(&config));
+        assert!(texts(&handle).iter().all(|t| t == "EN"));
+        assert!(all_shown(&handle));
+        assert_eq!(handle.tray_layout().as_deref(), Some("EN"));
+
+        // Layout switch updates text, beeps and shows
+        clock.set(Duration::from_millis(1000));
+        handle.switch_layout("RU");
+        settle(&mut app);
+        assert!(texts(&handle).iter().all(|t| t == "RU"));
+        assert!(handle.overlays().iter().all(|o| o.is_russian));
+        assert_eq!(handle.beeps(), vec![(600, 50)]);
+        assert_eq!(handle.tray_layout().as_deref(), Some("RU"));
+
+        // Auto-hide after hide_delay_ms
+        clock.set(Duration::from_millis(6000));
+        settle(&mut app);
+        assert!(all_hidden(&handle));
+
+        // Switch right after the hide: queued, then applied after the cooldown
+        clock.set(Duration::from_millis(6100));
+        handle.switch_layout("EN");
+        settle(&mut app);
+        assert!(all_hidden(&handle));
+        clock.set(Duration::from_millis(6600));
+        settle(&mut app);
+        assert!(texts(&handle).iter().all(|t| t == "EN"));
+        assert!(all_shown(&handle));
+
+        // Hotkey toggle hides, the tray entry brings them back and hides
+        // them again; its checkmark follows every source
+        assert!(handle.tray_indicators_enabled());
+        assert!(handle.press_toggle());
+        settle(&mut app);
+        assert!(all_hidden(&handle));
+        assert!(!handle.tray_indicators_enabled());
+        handle.click_tray(TrayClick::Indicators);
+        settle(&mut app);
+        assert!(all_shown(&handle));
+        assert!(handle.tray_indicators_enabled());
+        handle.click_tray(TrayClick::Indicators);
+        settle(&mut app);
+        assert!(all_hidden(&handle));
+        assert!(!handle.tray_indicators_enabled());
+
+        // About doesn't change anything
+        handle.click_tray(TrayClick::About);
+        settle(&mut app);
+        assert!(all_hidden(&handle));
+
+        // Tray autostart toggles the autostart backend
+        handle.click_tray(TrayClick::Autostart);
+        settle(&mut app);
+        assert!(handle.autostart_enabled());
+
+        // Exit from the tray
+        handle.click_tray(TrayClick::Exit);
+        assert!(!app.step(&[]));
+        app.shutdown();
+        assert!(!handle.tray_started());
+        assert!(!handle.layout_source_started());
+        assert!(handle.hotkey_config().is_none());
+        assert!(handle.overlays().is_empty());
+
+        remove_config(&app.config_manager);
+    }
+
+    #[test]
+    fn config_reload_and_display_change_recreate_indicators() {
+        let (mut app, handle, clock) = started_app("reload", &AppConfig::default());
+        settle(&mut app);
+        assert_eq!(handle.overlays().len(), 5);
+
+        // Disable two positions and change timings; picked up on the next check
+        let mut config = AppConfig::default();
+        config.positions.center = false;
+        config.positions.top_left = false;
+        config.debounce_ms = 40;
+        rewrite_config(&app.config_manager, || app.config_manager.save(&config).unwrap());
+
+        clock.set(CONFIG_CHECK_INTERVAL);
+        settle(&mut app);
+        assert_eq!(handle.overlays().len(), 3);
+        assert_eq!(
+            handle.timings().map(|t| t.debounce),
+            Some(Duration::from_millis(40))
+        );
+
+        // A second monitor appears
+        let mut second = handle.overlays()[0].monitor.clone();
+        second.x = 1920;
+        second.work_x = 1920;
+        second.is_primary = false;
+        let first = handle.overlays()[0].monitor.clone();
+        handle.change_display(vec![first, second]);
+        assert!(app.step(&[PlatformEvent::DisplayChanged]));
+        assert_eq!(handle.overlays().len(), 6);
+
+        // Window system quit ends the main loop
+        handle.quit();
+        app.run();
+        app.shutdown();
+        remove_config(&app.config_manager);
+    }
+
+    #[test]
+    fn exit_hotkey_stops_the_app() {
+        let (mut app, handle, _clock) = started_app("exit", &AppConfig::default());
+        assert!(app.step(&[]));
+        assert!(handle.press_exit());
+        assert!(!app.step(&[]));
+        app.shutdown();
+        assert!(!handle.press_exit());
+        remove_config(&app.config_manager);
+    }
+
+    #[test]
+    fn hotkey_bindings_dispatch_actions() {
+        let mut config = AppConfig::default();
+        config.hotkeys.bindings = [
+            ("ctrl+alt+p", "show-now"),
+            ("ctrl+alt+m", "mute-sound"),
+            ("ctrl+alt+s", "snooze(1)"),
+            ("ctrl+alt+k", "cycle-layout"),
+            ("ctrl+alt+e", "set-layout(us)"),
+            ("ctrl+alt+o", "open-config"),
+            ("ctrl+alt+n", "next-profile"),
+        ]
+        .into_iter()
+        .map(|(keys, action)| HotkeyBinding {
+            keys: keys.to_string(),
+            action: action.to_string(),
+        })
+        .collect();
+        let (mut app, handle, clock) = started_app("actions", &config);
+        settle(&mut app);
+
+        // Peek shows the layout even while disabled
+        assert!(handle.press_toggle());
+        settle(&mut app);
+        assert!(all_hidden(&handle));
+        assert!(handle.press("ctrl+alt+p"));
+        settle(&mut app);
+        assert!(all_shown(&handle));
+        assert!(handle.press("ctrl+alt+l"));
+        settle(&mut app);
+
+        // Layout switching goes through the layout source; muted switches are silent
+        clock.set(Duration::from_millis(1000));
+        assert!(handle.press("ctrl+alt+m"));
+        assert!(handle.press("ctrl+alt+k"));
+        settle(&mut app);
+        assert!(texts(&handle).iter().all(|t| t == "RU"));
+        assert!(handle.beeps().is_empty());
+        assert!(handle.press("ctrl+alt+m"));
+        assert!(handle.press("ctrl+alt+e"));
+        settle(&mut app);
+        assert!(texts(&handle).iter().all(|t| t == "EN"));
+        assert_eq!(handle.beeps(), vec![(800, 50)]);
+
+        // Snoozed switches stay hidden until the snooze runs out
+        assert!(handle.press("ctrl+alt+s"));
+        settle(&mut app);
+        assert!(all_hidden(&handle));
+        clock.set(Duration::from_millis(2000));
+        handle.switch_layout("RU");
+        settle(&mut app);
+        assert!(all_hidden(&handle));
+        clock.set(Duration::from_millis(62000));
+        settle(&mut app);
+        handle.switch_layout("EN");
+        settle(&mut app);
+        assert!(all_shown(&handle));
+
+        assert!(handle.press("ctrl+alt+o"));
+        settle(&mut app);
+        assert_eq!(handle.opened(), vec![app.config_manager.path().clone()]);
+
+        // Next profile cycles through the config files in alphabetical order
+        let mut work = config.clone();
+        work.positions.center = false;
+        app.config_manager.for_profile("work").save(&work).unwrap();
+        assert!(handle.press("ctrl+alt+n"));
+        settle(&mut app);
+        assert_eq!(app.config_manager.profile(), "work");
+        assert_eq!(handle.overlays().len(), 4);
+        assert!(handle.press("ctrl+alt+n"));
+        settle(&mut app);
+        assert_eq!(app.config_manager.profile(), "default");
+
+        // Unbound combinations do nothing
+        assert!(!handle.press("ctrl+alt+z"));
+        app.shutdown();
+        remove_config(&app.config_manager);
+    }
+
+    #[test]
+    fn held_hotkey_shows_until_released() {
+        let mut config = AppConfig::default();
+        config.hotkeys.bindings.push(HotkeyBinding {
+            keys: "ctrl+alt+h".to_string(),
+            action: "show-while-held".to_string(),
+        });
+        let (mut app, handle, clock) = started_app("held", &config);
+        settle(&mut app);
+        assert!(handle.press_toggle());
+        settle(&mut app);
+        assert!(all_hidden(&handle));
+
+        assert!(handle.press("ctrl+alt+h"));
+        settle(&mut app);
+        assert!(all_shown(&handle));
+
+        // Held well past the hide delay
+        clock.set(Duration::from_secs(60));
+        settle(&mut app);
+        assert!(all_shown(&handle));
+
+        assert!(handle.release("ctrl+alt+h"));
+        settle(&mut app);
+        assert!(all_hidden(&handle));
+
+        // Still disabled afterwards
+        clock.set(Duration::from_secs(61));
+        handle.switch_layout("RU");
+        settle(&mut app);
+        assert!(all_hidden(&handle));
+        assert!(!handle.release("ctrl+alt+l"));
+        app.shutdown();
+        remove_config(&app.config_manager);
+    }
+
+    #[test]
+    fn gestures_dispatch_actions() {
+        let mut config = AppConfig::default();
+        config.hotkeys.gestures = [
+            ("double-tap(lshift)", "cycle-layout"),
+            ("hold(ralt, 400)", "cycle-layout"),
+            ("release(rctrl)", "exit"),
+        ]
+        .into_iter()
+        .map(|(gesture, action)| GestureBinding {
+            gesture: gesture.to_string(),
+            action: action.to_string(),
+        })
+        .collect();
+        let (mut app, handle, _clock) = started_app("gestures", &config);
+        settle(&mut app);
+
+        let ms = Duration::from_millis;
+        handle.key_event("lshift", true, ms(0));
+        handle.key_event("lshift", false, ms(80));
+        handle.key_event("lshift", true, ms(200));
+        handle.key_event("lshift", false, ms(260));
+        settle(&mut app);
+        assert!(texts(&handle).iter().all(|t| t == "RU"));
+
+        // A hold fires while the key is still down
+        handle.key_event("ralt", true, ms(500));
+        handle.advance_gestures(ms(1000));
+        settle(&mut app);
+        assert!(texts(&handle).iter().all(|t| t == "EN"));
+        handle.key_event("ralt", false, ms(1100));
+
+        // Ctrl+C isn't a lone Right Ctrl
+        handle.key_event("rctrl", true, ms(2000));
+        handle.key_event("c", true, ms(2050));
+        handle.key_event("c", false, ms(2100));
+        handle.key_event("rctrl", false, ms(2150));
+        assert!(app.step(&[]));
+        handle.key_event("rctrl", true, ms(3000));
+        handle.key_event("rctrl", false, ms(3900));
+        assert!(!app.step(&[]));
+        app.shutdown();
+        remove_config(&app.config_manager);
+    }
+
+    #[test]
+    fn remapped_keys_switch_layouts() {
+        let mut config = AppConfig::default();
+        config.hotkeys.remap = [("capslock", "cycle-layout"), ("ralt", "set-layout(ru-RU)")]
+            .into_iter()
+            .map(|(key, action)| KeyRemap {
+                key: key.to_string(),
+                action: action.to_string(),
+            })
+            .collect();
+        let (mut app, handle, _clock) = started_app("remap", &config);
+        settle(&mut app);
+
+        // Caps Lock is swallowed and switches the layout
+        let ms = Duration::from_millis;
+        assert!(!handle.key_event("capslock", true, ms(0)));
+        assert!(!handle.key_event("capslock", false, ms(50)));
+        settle(&mut app);
+        assert!(texts(&handle).iter().all(|t| t == "RU"));
+
+        // Shift+CapsLock reaches applications and doesn't switch
+        assert!(handle.key_event("lshift", true, ms(1000)));
+        assert!(handle.key_event("capslock", true, ms(1050)));
+        assert!(handle.key_event("capslock", false, ms(1100)));
+        assert!(handle.key_event("lshift", false, ms(1150)));
+        assert!(!handle.key_event("capslock", true, ms(1200)));
+        assert!(!handle.key_event("capslock", false, ms(1250)));
+        settle(&mut app);
+        assert!(texts(&handle).iter().all(|t| t == "EN"));
+
+        // A lone Right Alt picks its layout, AltGr+key doesn't
+        assert!(handle.key_event("ralt", true, ms(2000)));
+        assert!(handle.key_event("e", true, ms(2050)));
+        assert!(handle.key_event("e", false, ms(2100)));
+        assert!(handle.key_event("ralt", false, ms(2150)));
+        settle(&mut app);
+        assert!(texts(&handle).iter().all(|t| t == "EN"));
+        assert!(handle.key_event("ralt", true, ms(3000)));
+        assert!(!handle.key_event("ralt", false, ms(3100)));
+        settle(&mut app);
+        assert!(texts(&handle).iter().all(|t| t == "RU"));
+        app.shutdown();
+        remove_config(&app.config_manager);
+    }
+
+    #[test]
+    fn hotkey_conflicts_are_reported() {
+        let platform = HeadlessPlatform::new();
+        let handle = platform.handle();
+        handle.occupy_hotkey("ctrl+alt+l");
+        handle.occupy_hotkey("ctrl+alt+shift+l");
+        let mut config = AppConfig::default();
+        config.hotkeys.bindings.push(HotkeyBinding {
+            keys: "ctrl+pgupp".to_string(),
+            action: "mute-sound".to_string(),
+        });
+        let manager = temp_config("conflicts", &config);
+        let mut app = App::new(platform.backends(), manager, ManualClock::new());
+        app.start();
+
+        let conflicts = vec![
+            "ctrl+alt+l (toggle): taken by another application, \
+             try ctrl+alt+win+l, ctrl+alt+shift+win+l"
+                .to_string(),
+            "ctrl+pgupp (mute-sound): unknown key \"pgupp\"".to_string(),
+        ];
+        assert_eq!(handle.tray_conflicts(), conflicts);
+        assert!(app.step(&[]));
+        let warnings: Vec<String> = conflicts.iter().map(|c| format!("Hotkey {}", c)).collect();
+        assert_eq!(handle.tray_status().unwrap().warnings, warnings);
+        assert_eq!(
+            handle.notifications(),
+            vec![(
+                "LangTip: hotkeys not registered".to_string(),
+                conflicts.join("\n")
+            )]
+        );
+
+        // A reload that leaves the same conflicts doesn't repeat the notice
+        config.hotkeys.bindings.push(HotkeyBinding {
+            keys: "ctrl+alt+m".to_string(),
+            action: "mute-sound".to_string(),
+        });
+        app.config_manager.save(&config).unwrap();
+        app.action_tx.send(Action::ReloadConfig).unwrap();
+        assert!(app.step(&[]));
+        assert!(handle.press("ctrl+alt+m"));
+        assert_eq!(handle.notifications().len(), 1);
+
+        // The taken combination doesn't fire, the free one does
+        assert!(!handle.press("ctrl+alt+l"));
+        assert!(handle.press_exit());
+        app.shutdown();
+        remove_config(&app.config_manager);
+    }
+
+    #[test]
+    fn typos_in_bindings_only_disable_their_entry() {
+        let platform = HeadlessPlatform::new();
+        let handle = platform.handle();
+        let manager = temp_config("binding-typos", &AppConfig::default());
+        std::fs::write(
+            manager.path(),
+            r#"{
+                "opacity": 40,
+                "hotkeys": {
+                    "bindings": [
+                        { "keys": "ctrl+alt+m", "action": "mute-sond" },
+                        { "keys": "ctrl+alt+p", "action": "show-now" }
+                    ],
+                    "gestures": [
+                        { "gesture": "wiggle(a)", "action": "show-now" },
+                        { "gesture": "double-tap(lshift)", "action": "cycle-layot" }
+                    ],
+                    "remap": [{ "key": "capslok", "action": "cycle-layout" }]
+                }
+            }"#,
+        )
+        .unwrap();
+        let mut app = App::new(platform.backends(), manager, ManualClock::new());
+        app.start();
+
+        // The rest of the config still applies
+        assert_eq!(app.config.opacity, 40);
+        assert!(app.config_error.is_none());
+        assert!(handle.press("ctrl+alt+p"));
+        assert!(!handle.press("ctrl+alt+m"));
+        assert_eq!(
+            handle.tray_conflicts(),
+            [
+                "ctrl+alt+m (mute-sond): invalid action: mute-sond",
+                "wiggle(a) (show-now): invalid gesture: wiggle(a)",
+                "double-tap(lshift) (cycle-layot): invalid action: cycle-layot",
+                "capslok (cycle-layout): unknown key \"capslok\"",
+            ]
+        );
+        app.shutdown();
+        remove_config(&app.config_manager);
+    }
+
+    #[test]
+    fn changed_hotkeys_are_registered_on_reload() {
+        let (mut app, handle, clock) = started_app("hotkey-reload", &AppConfig::default());
+        handle.occupy_hotkey("ctrl+alt+t");
+
+        // Toggle moves to a taken combination, exit to a free one
+        let mut config = AppConfig::default();
+        config.hotkeys.toggle = "ctrl+alt+t".to_string();
+        config.hotkeys.exit = "ctrl+alt+e".to_string();
+        rewrite_config(&app.config_manager, || app.config_manager.save(&config).unwrap());
+        clock.set(CONFIG_CHECK_INTERVAL);
+        assert!(app.step(&[]));
+
+        assert!(handle.press("ctrl+alt+l"));
+        assert!(!handle.press("ctrl+alt+t"));
+        assert!(!handle.press("ctrl+alt+q"));
+        let conflicts = handle.tray_conflicts();
+        assert_eq!(conflicts.len(), 1);
+        assert!(conflicts[0].starts_with("ctrl+alt+t (toggle): taken by another application"));
+        assert!(conflicts[0].ends_with("; kept ctrl+alt+l"));
+
+        // Disabling hotkeys unregisters all of them
+        config.hotkeys.enabled = false;
+        rewrite_config(&app.config_manager, || app.config_manager.save(&config).unwrap());
+        clock.set(CONFIG_CHECK_INTERVAL * 2);
+        assert!(app.step(&[]));
+        assert!(!handle.press("ctrl+alt+l"));
+        assert!(handle.tray_conflicts().is_empty());
+
+        config.hotkeys.enabled = true;
+        rewrite_config(&app.config_manager, || app.config_manager.save(&config).unwrap());
+        clock.set(CONFIG_CHECK_INTERVAL * 3);
+        assert!(app.step(&[]));
+        assert!(handle.press_exit());
+        app.shutdown();
+        remove_config(&app.config_manager);
+    }
+
+    #[test]
+    fn tray_tooltip_shows_live_status() {
+        let (mut app, handle, clock) = started_app("status", &AppConfig::default());
+        settle(&mut app);
+        let status = handle.tray_status().unwrap();
+        assert_eq!(status.layout, "EN");
+        assert_eq!(status.visibility, Visibility::Visible);
+        assert_eq!(status.profile, "default");
+        assert_eq!(status.switches_today, 0);
+        assert!(status.warnings.is_empty());
+
+        // Switches are counted until the day changes
+        clock.set(Duration::from_millis(1000));
+        handle.switch_layout("RU");
+        settle(&mut app);
+        handle.switch_layout("EN");
+        settle(&mut app);
+        let status = handle.tray_status().unwrap();
+        assert_eq!((status.layout.as_str(), status.switches_today), ("EN", 2));
+        clock.set(Duration::from_secs(86_400));
+        settle(&mut app);
+        assert_eq!(handle.tray_status().unwrap().switches_today, 0);
+
+        // Snoozing and hiding show in the tooltip, a snooze once a minute
+        app.action_tx.send(Action::Snooze(10)).unwrap();
+        settle(&mut app);
+        assert_eq!(
+            handle.tray_status().unwrap().visibility,
+            Visibility::Snoozed(Duration::from_secs(600))
+        );
+        let sets = handle.tray_status_sets();
+        clock.set(Duration::from_secs(86_400 + 30));
+        settle(&mut app);
+        assert_eq!(handle.tray_status_sets(), sets);
+        clock.set(Duration::from_secs(86_400 + 60));
+        settle(&mut app);
+        assert_eq!(handle.tray_status_sets(), sets + 1);
+        assert_eq!(
+            handle.tray_status().unwrap().visibility,
+            Visibility::Snoozed(Duration::from_secs(540))
+        );
+        app.action_tx.send(Action::Hide).unwrap();
+        settle(&mut app);
+        assert_eq!(handle.tray_status().unwrap().visibility, Visibility::Hidden);
+
+        // A broken config file is a warning until it is fixed
+        rewrite_config(&app.config_manager, || {
+            std::fs::write(app.config_manager.path(), "{ broken").unwrap()
+        });
+        clock.set(Duration::from_secs(86_460) + CONFIG_CHECK_INTERVAL);
+        settle(&mut app);
+        let warnings = handle.tray_status().unwrap().warnings;
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("Failed to parse config: "));
+        rewrite_config(&app.config_manager, || {
+            app.config_manager.save(&AppConfig::default()).unwrap()
+        });
+        clock.set(Duration::from_secs(86_460) + CONFIG_CHECK_INTERVAL * 2);
+        settle(&mut app);
+        assert!(handle.tray_status().unwrap().warnings.is_empty());
+
+        app.shutdown();
+        remove_config(&app.config_manager);
+    }
+
+    /// Returns the checked entries of a settings submenu.
+    fn checked(handle: &HeadlessHandle, submenu: &str) -> Vec<String> {
+        handle
+            .tray_menu()
+            .into_iter()
+            .find_map(|entry| match entry {
+                MenuEntry::Submenu { label, entries } if label == submenu => Some(entries),
+                _ => None,
+            })
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|entry| match entry {
+                MenuEntry::Item {
+                    label,
+                    toggle: Some(toggle),
+                    ..
+                } if toggle.is_checked() => Some(label),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn layout_switches_only_move_the_menu_check() {
+        let (mut app, handle, _clock) = started_app("menu-layout", &AppConfig::default());
+        settle(&mut app);
+        let sets = handle.tray_menu_sets();
+        assert_eq!(checked(&handle, "Layout"), ["EN"]);
+
+        handle.switch_layout("RU");
+        settle(&mut app);
+        assert_eq!(checked(&handle, "Layout"), ["RU"]);
+        assert_eq!(handle.tray_menu_sets(), sets);
+
+        // A settings change still sends the whole menu
+        assert!(handle.click_tray_menu(&["Sound"]));
+        settle(&mut app);
+        assert_eq!(handle.tray_menu_sets(), sets + 1);
+
+        app.shutdown();
+        remove_config(&app.config_manager);
+    }
+
+    #[test]
+    fn tray_settings_are_saved_and_applied() {
+        let (mut app, handle, clock) = started_app("settings", &AppConfig::default());
+        settle(&mut app);
+        assert_eq!(checked(&handle, "Positions").len(), 5);
+        assert_eq!(checked(&handle, "Opacity"), ["80%"]);
+        assert_eq!(checked(&handle, "Layout"), ["EN"]);
+
+        // A position turned off is saved and its indicator goes away
+        assert!(handle.click_tray_menu(&["Positions", "Center"]));
+        settle(&mut app);
+        assert_eq!(handle.overlays().len(), 4);
+        assert!(!app.config_manager.load().positions.center);
+        assert!(!checked(&handle, "Positions").contains(&"Center".to_string()));
+
+        // Only the primary of two monitors
+        let first = handle.overlays()[0].monitor.clone();
+        let mut second = first.clone();
+        second.x = 1920;
+        second.is_primary = false;
+        handle.change_display(vec![first, second]);
+        assert!(app.step(&[PlatformEvent::DisplayChanged]));
+        assert_eq!(handle.overlays().len(), 8);
+        assert!(handle.click_tray_menu(&["Monitors", "Primary monitor"]));
+        settle(&mut app);
+        assert_eq!(handle.overlays().len(), 4);
+        assert!(handle.overlays().iter().all(|o| o.monitor.is_primary));
+        assert_eq!(app.config_manager.load().monitors, MonitorPolicy::Primary);
+
+        assert!(handle.click_tray_menu(&["Opacity", "40%"]));
+        settle(&mut app);
+        assert_eq!(app.config_manager.load().opacity, 40);
+        assert_eq!(checked(&handle, "Opacity"), ["40%"]);
+        assert!(handle.overlays().iter().all(|o| o.alpha == 102));
+
+        // Sound off silences switches; picking a layout switches to it
+        assert!(handle.click_tray_menu(&["Sound"]));
+        assert!(handle.click_tray_menu(&["Layout", "RU"]));
+        clock.set(Duration::from_millis(1000));
+        settle(&mut app);
+        assert!(texts(&handle).iter().all(|t| t == "RU"));
+        assert!(handle.beeps().is_empty());
+        assert!(!app.config_manager.load().sound.enabled);
+        assert_eq!(checked(&handle, "Layout"), ["RU"]);
+
+        // Saved settings aren't reloaded as an outside change
+        clock.set(Duration::from_millis(1000) + CONFIG_CHECK_INTERVAL);
+        settle(&mut app);
+        assert_eq!(handle.overlays().len(), 4);
+
+        // Profiles with a config file are listed and can be picked
+        let mut work = AppConfig::default();
+        work.positions.top_left = false;
+        app.config_manager.for_profile("work").save(&work).unwrap();
+        assert!(handle.click_tray_menu(&["Reload config"]));
+        settle(&mut app);
+        assert_eq!(checked(&handle, "Profile"), ["default"]);
+        assert!(handle.click_tray_menu(&["Profile", "work"]));
+        settle(&mut app);
+        assert_eq!(app.config_manager.profile(), "work");
+        assert_eq!(checked(&handle, "Profile"), ["work"]);
+        assert_eq!(handle.overlays().len(), 8);
+
+        assert!(handle.click_tray_menu(&["Open config"]));
+        settle(&mut app);
+        assert_eq!(handle.opened(), vec![app.config_manager.path().clone()]);
+
+        // A broken file isn't overwritten by a menu change
+        std::fs::write(app.config_manager.path(), "{ broken").unwrap();
+        assert!(handle.click_tray_menu(&["Reload config"]));
+        assert!(handle.click_tray_menu(&["Sound"]));
+        settle(&mut app);
+        assert_eq!(
+            std::fs::read_to_string(app.config_manager.path()).unwrap(),
+            "{ broken"
+        );
+        assert_eq!(
+            handle.notifications().last().map(|(title, _)| title.as_str()),
+            Some("LangTip: settings not saved")
+        );
+        assert!(!handle.click_tray_menu(&["Positions"]));
+        assert!(!handle.click_tray_menu(&["Opacity", "55%"]));
+
+        app.shutdown();
+        remove_config(&app.config_manager);
+    }
+
+    #[test]
+    fn hotkey_check_prints_every_binding() {
+        let platform = HeadlessPlatform::new();
+        let handle = platform.handle();
+        let manager = temp_config("check", &AppConfig::default());
+
+        let mut out = Vec::new();
+        assert!(check_hotkeys(platform.backends(), &manager, &mut out).unwrap());
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "ctrl+alt+l (toggle): registered\n\
+             ctrl+alt+q (exit): registered\n\
+             2 of 2 hotkeys registered\n"
+        );
+        assert!(!handle.instance_locked());
+        assert!(handle.hotkey_config().is_none());
+
+        handle.occupy_hotkey("ctrl+alt+q");
+        let mut out = Vec::new();
+        assert!(!check_hotkeys(platform.backends(), &manager, &mut out).unwrap());
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("ctrl+alt+q (exit): taken by another application, try "));
+        assert!(out.ends_with("1 of 2 hotkeys registered\n"));
+        remove_config(&manager);
+    }
+
+    #[test]
+    fn second_instance_is_refused() {
+        let platform = HeadlessPlatform::new();
+        let handle = platform.handle();
+        let mut first = platform.backends();
+        assert!(first.instance.acquire());
+
+        // The first instance doesn't listen, so the second one can only
+        // complain, and does so without creating the backends
+        let config = temp_config("instance", &AppConfig::default());
+        let path = config.path().clone();
+        run(
+            platform.backends().instance,
+            || panic!("backends created for a second launch"),
+            config,
+            &["--show".to_string()],
+            vec![Action::Show],
+        );
+        assert_eq!(handle.already_running_notices(), 1);
+        assert!(handle.overlays().is_empty());
+
+        first.instance.release();
+        assert!(!handle.instance_locked());
+        remove_config(&ConfigManager::with_path(path));
+    }
+
+    #[test]
+    fn first_launch_runs_its_arguments() {
+        let platform = HeadlessPlatform::new();
+        let handle = platform.handle();
+        let config = temp_config("first-launch", &AppConfig::default());
+        let path = config.path().clone();
+        let first = {
+            let platform = platform.clone();
+            std::thread::spawn(move || {
+                let args = ["--hide".to_string()];
+                run(
+                    platform.backends().instance,
+                    || platform.backends(),
+                    config,
+                    &args,
+                    cli::parse_args(&args),
+                );
+            })
+        };
+
+        let started = Instant::now();
+        while handle.tray_status().is_none() {
+            assert!(started.elapsed() < Duration::from_secs(5), "never started");
+            std::thread::yield_now();
+        }
+        assert!(!handle.tray_indicators_enabled());
+        assert!(all_hidden(&handle));
+
+        assert!(handle.forward_args(&["--exit"]));
+        first.join().unwrap();
+        assert!(handle.overlays().is_empty());
+        assert!(!handle.instance_locked());
+        remove_config(&ConfigManager::with_path(path));
+    }
+
+    #[test]
+    fn second_launch_forwards_arguments() {
+        let platform = HeadlessPlatform::new();
+        let handle = platform.handle();
+        let clock = ManualClock::new();
+        let mut first = App::new(
+            platform.backends(),
+            temp_config("forward", &AppConfig::default()),
+            clock.clone(),
+        );
+        assert!(first.backends.instance.acquire());
+        first.start();
+        settle(&mut first);
+        assert!(all_shown(&handle));
+
+        // A second launch hands its arguments over instead of complaining
+        let config = temp_config("forward-second", &AppConfig::default());
+        let second_path = config.path().clone();
+        run(
+            platform.backends().instance,
+            || platform.backends(),
+            config,
+            &["--hide".to_string()],
+            vec![Action::Hide],
+        );
+        assert_eq!(handle.already_running_notices(), 0);
+        settle(&mut first);
+        assert!(all_hidden(&handle));
+        assert!(!handle.tray_indicators_enabled());
+
+        // A bare launch brings the indicators back
+        assert!(handle.forward_args(&[]));
+        settle(&mut first);
+        assert!(all_shown(&handle));
+        assert!(handle.tray_indicators_enabled());
+
+        // A profile switch loads the profile's config file
+        let mut work = AppConfig::default();
+        work.positions.center = false;
+        first
+            .config_manager
+            .for_profile("work")
+            .save(&work)
+            .unwrap();
+        assert!(handle.forward_args(&["--profile", "work"]));
+        settle(&mut first);
+        assert!(first.config_manager.path().ends_with("config.work.json"));
+        assert_eq!(handle.overlays().len(), 4);
+
+        // Reload re-reads the file right away, without waiting for the check
+        work.positions.top_left = false;
+        first.config_manager.save(&work).unwrap();
+        assert!(handle.forward_args(&["--reload"]));
+        settle(&mut first);
+        assert_eq!(handle.overlays().len(), 3);
+
+        assert!(handle.forward_args(&["--exit"]));
+        assert!(!first.step(&[]));
+        first.shutdown();
+        first.backends.instance.release();
+        assert!(!handle.forward_args(&["--show"]));
+        remove_config(&first.config_manager);
+        remove_config(&ConfigManager::with_path(second_path));
+    }
+}
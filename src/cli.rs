@@ -0,0 +1,177 @@
+//! Command line module.
+//!
+//! Parses the command line. A second launch doesn't start another instance:
+//! it forwards its arguments to the running one, which acts on them.
+//! Subcommands like `langtip hotkeys --check` run instead of the indicator.
+
+use crate::action::Action;
+use crate::config::is_valid_profile;
+use std::ffi::OsString;
+
+/// A command run instead of the indicator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Subcommand {
+    /// `hotkeys --check`: print the registration status of every hotkey.
+    CheckHotkeys,
+}
+
+/// Usage of the subcommands.
+pub const SUBCOMMAND_USAGE: &str = "Usage: langtip hotkeys --check";
+
+/// Converts the command line arguments (without the program name) to
+/// strings. Errors on the first one that isn't valid UTF-8, e.g. a profile
+/// name passed on from a non-UTF-8 locale.
+pub fn utf8_args(args: impl IntoIterator<Item = OsString>) -> Result<Vec<String>, String> {
+    args.into_iter()
+        .map(|arg| {
+            arg.into_string()
+                .map_err(|arg| format!("Argument {:?} is not valid UTF-8", arg))
+        })
+        .collect()
+}
+
+/// Returns the subcommand the arguments ask for, if any. Errors on a
+/// subcommand used with wrong arguments.
+pub fn subcommand(args: &[String]) -> Option<Result<Subcommand, String>> {
+    let (command, rest) = args.split_first()?;
+    match (command.as_str(), rest) {
+        ("hotkeys", [flag]) if flag == "--check" => Some(Ok(Subcommand::CheckHotkeys)),
+        ("hotkeys", _) => Some(Err(SUBCOMMAND_USAGE.to_string())),
+        _ => None,
+    }
+}
+
+/// Parses command line arguments (without the program name) into the
+/// actions they ask for.
+///
+/// `--show`, `--hide`, `--toggle`, `--reload`, `--exit` and
+/// `--profile NAME` are supported. Unknown arguments are logged and skipped.
+pub fn parse_args(args: &[String]) -> Vec<Action> {
+    let mut actions = Vec::new();
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) => (flag, Some(value.to_string())),
+            None => (arg.as_str(), None),
+        };
+        match flag {
+            "--show" => actions.push(Action::Show),
+            "--hide" => actions.push(Action::Hide),
+            "--toggle" => actions.push(Action::Toggle),
+            "--reload" => actions.push(Action::ReloadConfig),
+            "--exit" => actions.push(Action::Exit),
+            "--profile" => match inline_value.or_else(|| args.next().cloned()) {
+                Some(name) if is_valid_profile(&name) => actions.push(Action::SwitchProfile(name)),
+                Some(name) => log::warn!("Invalid profile name: {:?}", name),
+                None => log::warn!("--profile needs a name"),
+            },
+            _ => log::warn!("Unknown argument: {}", arg),
+        }
+    }
+
+    actions
+}
+
+/// Returns the last profile requested, if any.
+pub fn profile(actions: &[Action]) -> Option<&str> {
+    actions.iter().rev().find_map(|a| match a {
+        Action::SwitchProfile(name) => Some(name.as_str()),
+        _ => None,
+    })
+}
+
+/// Encodes arguments for forwarding to the running instance.
+pub fn encode_args(args: &[String]) -> Vec<u8> {
+    serde_json::to_vec(args).unwrap_or_default()
+}
+
+/// Decodes forwarded arguments.
+pub fn decode_args(bytes: &[u8]) -> Option<Vec<String>> {
+    serde_json::from_slice(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn arguments_are_parsed() {
+        assert_eq!(
+            parse_args(&args(&["--show", "--reload", "--profile", "work"])),
+            vec![
+                Action::Show,
+                Action::ReloadConfig,
+                Action::SwitchProfile("work".to_string())
+            ]
+        );
+        assert_eq!(
+            parse_args(&args(&["--profile=home", "--hide", "--toggle", "--exit"])),
+            vec![
+                Action::SwitchProfile("home".to_string()),
+                Action::Hide,
+                Action::Toggle,
+                Action::Exit
+            ]
+        );
+        assert!(parse_args(&[]).is_empty());
+    }
+
+    #[test]
+    fn bad_arguments_are_skipped() {
+        assert_eq!(
+            parse_args(&args(&["--bogus", "--profile", "../etc", "--show"])),
+            vec![Action::Show]
+        );
+        assert!(parse_args(&args(&["--profile"])).is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_arguments_are_an_error() {
+        use std::os::unix::ffi::OsStringExt;
+
+        let valid = vec![OsString::from("--profile"), OsString::from("работа")];
+        assert_eq!(utf8_args(valid), Ok(args(&["--profile", "работа"])));
+
+        let invalid = vec![
+            OsString::from("--profile"),
+            OsString::from_vec(b"r\xE0bota".to_vec()),
+        ];
+        let err = utf8_args(invalid).unwrap_err();
+        assert!(err.contains("not valid UTF-8"), "{}", err);
+    }
+
+    #[test]
+    fn subcommands_are_recognised() {
+        assert_eq!(
+            subcommand(&args(&["hotkeys", "--check"])),
+            Some(Ok(Subcommand::CheckHotkeys))
+        );
+        assert!(matches!(subcommand(&args(&["hotkeys"])), Some(Err(_))));
+        assert!(matches!(
+            subcommand(&args(&["hotkeys", "--bogus"])),
+            Some(Err(_))
+        ));
+        assert_eq!(subcommand(&args(&["--show"])), None);
+        assert_eq!(subcommand(&[]), None);
+    }
+
+    #[test]
+    fn last_profile_wins() {
+        let requests = parse_args(&args(&["--profile", "a", "--show", "--profile", "b"]));
+        assert_eq!(profile(&requests), Some("b"));
+        assert_eq!(profile(&[Action::Show]), None);
+    }
+
+    #[test]
+    fn forwarded_arguments_round_trip() {
+        let list = args(&["--profile", "work", "ünïcode arg"]);
+        assert_eq!(decode_args(&encode_args(&list)), Some(list));
+        assert_eq!(decode_args(b"not json"), None);
+    }
+}
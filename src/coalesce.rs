@@ -0,0 +1,180 @@
+//! Layout event coalescing module.
+//!
+//! Hiding the indicators can produce spurious layout events (focus moves while
+//! the windows fade out), so events received shortly after a hide are not
+//! trusted. Instead of dropping them, they are queued and resolved against the
+//! real current layout once the cooldown is over.
+
+use crate::layout::LayoutInfo;
+use std::time::{Duration, Instant};
+
+/// Coalescing policy for layout events around indicator hides.
+///
+/// - Outside the cooldown an event is applied as is.
+/// - During the cooldown events are queued; any number of them collapses
+///   into a single pending check.
+/// - After the cooldown the pending check is resolved by reading the
+///   current layout, never by replaying the queued (possibly stale) events.
+#[derive(Debug)]
+pub struct CooldownCoalescer {
+    cooldown: Duration,
+    cooldown_until: Option<Instant>,
+    /// Whether events were queued; their layouts aren't kept, the current
+    /// one is read instead.
+    pending: bool,
+}
+
+impl CooldownCoalescer {
+    /// Creates a coalescer with the given cooldown after each hide.
+    pub fn new(cooldown: Duration) -> Self {
+        Self {
+            cooldown,
+            cooldown_until: None,
+            pending: false,
+        }
+    }
+
+    /// Starts (or restarts) the cooldown. Call when indicators are hidden.
+    pub fn start_cooldown(&mut self, now: Instant) {
+        self.cooldown_until = Some(now + self.cooldown);
+    }
+
+    /// Returns true if `now` falls within the cooldown.
+    pub fn in_cooldown(&self, now: Instant) -> bool {
+        self.cooldown_until.is_some_and(|until| now < until)
+    }
+
+    /// Returns true if events are queued waiting for the cooldown to end.
+    #[cfg(test)]
+    pub fn has_pending(&self) -> bool {
+        self.pending
+    }
+
+    /// Handles a layout event.
+    ///
+    /// Returns the layout to apply now, or `None` if the event was queued.
+    pub fn on_event(&mut self, layout: LayoutInfo, now: Instant) -> Option<LayoutInfo> {
+        if self.in_cooldown(now) {
+            log::debug!("Queueing event during hide cooldown: {}", layout.name);
+            self.pending = true;
+            return None;
+        }
+
+        // A live event supersedes anything still queued
+        self.pending = false;
+        Some(layout)
+    }
+
+    /// Clears the queue once the cooldown has ended.
+    ///
+    /// Returns true if events were queued, i.e. the caller must read the
+    /// current layout and apply it.
+    pub fn take_pending(&mut self, now: Instant) -> bool {
+        if self.in_cooldown(now) {
+            return false;
+        }
+        std::mem::take(&mut self.pending)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COOLDOWN: Duration = Duration::from_millis(500);
+
+    fn layout(name: &str) -> LayoutInfo {
+        LayoutInfo {
+            name: name.to_string(),
+            is_russian: name == "RU",
+        }
+    }
+
+    fn ms(start: Instant, offset: u64) -> Instant {
+        start + Duration::from_millis(offset)
+    }
+
+    #[test]
+    fn event_outside_cooldown_is_applied() {
+        let t0 = Instant::now();
+        let mut c = CooldownCoalescer::new(COOLDOWN);
+
+        assert_eq!(c.on_event(layout("RU"), t0), Some(layout("RU")));
+        assert!(!c.has_pending());
+        assert!(!c.take_pending(t0));
+    }
+
+    #[test]
+    fn switch_right_after_hide_is_not_lost() {
+        let t0 = Instant::now();
+        let mut c = CooldownCoalescer::new(COOLDOWN);
+
+        c.start_cooldown(t0);
+        assert_eq!(c.on_event(layout("RU"), ms(t0, 100)), None);
+        assert!(c.has_pending());
+
+        // Still cooling down: nothing to check yet
+        assert!(!c.take_pending(ms(t0, 300)));
+        assert!(c.has_pending());
+
+        assert!(c.take_pending(ms(t0, 500)));
+        assert!(!c.has_pending());
+        assert!(!c.take_pending(ms(t0, 600)));
+    }
+
+    #[test]
+    fn several_events_in_cooldown_collapse_into_one_check() {
+        let t0 = Instant::now();
+        let mut c = CooldownCoalescer::new(COOLDOWN);
+
+        c.start_cooldown(t0);
+        assert_eq!(c.on_event(layout("RU"), ms(t0, 50)), None);
+        assert_eq!(c.on_event(layout("EN"), ms(t0, 150)), None);
+        assert_eq!(c.on_event(layout("RU"), ms(t0, 250)), None);
+
+        assert!(c.take_pending(ms(t0, 700)));
+        assert!(!c.take_pending(ms(t0, 710)));
+    }
+
+    #[test]
+    fn second_hide_extends_cooldown_and_keeps_queue() {
+        let t0 = Instant::now();
+        let mut c = CooldownCoalescer::new(COOLDOWN);
+
+        c.start_cooldown(t0);
+        assert_eq!(c.on_event(layout("RU"), ms(t0, 100)), None);
+        c.start_cooldown(ms(t0, 400));
+
+        assert!(!c.take_pending(ms(t0, 600)));
+        assert!(c.has_pending());
+        assert!(c.take_pending(ms(t0, 900)));
+    }
+
+    #[test]
+    fn live_event_after_cooldown_supersedes_queue() {
+        let t0 = Instant::now();
+        let mut c = CooldownCoalescer::new(COOLDOWN);
+
+        c.start_cooldown(t0);
+        assert_eq!(c.on_event(layout("RU"), ms(t0, 100)), None);
+        assert_eq!(c.on_event(layout("EN"), ms(t0, 600)), Some(layout("EN")));
+        assert!(!c.has_pending());
+        assert!(!c.take_pending(ms(t0, 700)));
+    }
+
+    #[test]
+    fn switch_hide_switch_back_interleaving() {
+        let t0 = Instant::now();
+        let mut c = CooldownCoalescer::new(COOLDOWN);
+
+        // EN -> RU before the hide is applied directly
+        assert_eq!(c.on_event(layout("RU"), t0), Some(layout("RU")));
+
+        // Auto-hide, then the user immediately switches back to EN
+        c.start_cooldown(ms(t0, 5000));
+        assert_eq!(c.on_event(layout("EN"), ms(t0, 5100)), None);
+
+        assert!(!c.take_pending(ms(t0, 5499)));
+        assert!(c.take_pending(ms(t0, 5500)));
+    }
+}
@@ -2,9 +2,13 @@
 //!
 //! Handles loading and saving application settings from JSON file.
 
+use crate::action::Action;
+use crate::gesture::{Gesture, GestureKey};
+use crate::hotkey::{self, BindingStatus, Hotkey, Registration};
+use crate::indicator::Position;
 use serde::{Deserialize, Serialize};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 
 /// Sound configuration for layout change notifications.
@@ -36,7 +40,7 @@ impl Default for SoundConfig {
 }
 
 /// Hotkey configuration.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct HotkeyConfig {
     /// Whether hotkeys are enabled.
     #[serde(default = "default_true")]
@@ -47,6 +51,15 @@ pub struct HotkeyConfig {
     /// Exit hotkey (e.g., "ctrl+alt+q").
     #[serde(default = "default_exit")]
     pub exit: String,
+    /// Further hotkeys mapped to actions.
+    #[serde(default)]
+    pub bindings: Vec<HotkeyBinding>,
+    /// Key gestures mapped to actions.
+    #[serde(default)]
+    pub gestures: Vec<GestureBinding>,
+    /// Single keys turned into layout switch keys.
+    #[serde(default)]
+    pub remap: Vec<KeyRemap>,
 }
 
 impl Default for HotkeyConfig {
@@ -55,10 +68,109 @@ impl Default for HotkeyConfig {
             enabled: true,
             toggle: "ctrl+alt+l".to_string(),
             exit: "ctrl+alt+q".to_string(),
+            bindings: Vec::new(),
+            gestures: Vec::new(),
+            remap: Vec::new(),
         }
     }
 }
 
+impl HotkeyConfig {
+    /// Returns all bindings: toggle and exit first (unless set to an empty
+    /// string), then the `bindings` list.
+    pub fn all_bindings(&self) -> Vec<HotkeyBinding> {
+        [(&self.toggle, Action::Toggle), (&self.exit, Action::Exit)]
+            .into_iter()
+            .filter(|(keys, _)| !keys.trim().is_empty())
+            .map(|(keys, action)| HotkeyBinding {
+                keys: keys.clone(),
+                action: action.to_string(),
+            })
+            .chain(self.bindings.iter().cloned())
+            .collect()
+    }
+
+    /// Returns the gestures and remaps that can't be parsed, as failed
+    /// bindings with the gesture or key in place of the hotkey.
+    pub fn invalid_gestures(&self) -> Vec<BindingStatus> {
+        let gestures = self
+            .gestures
+            .iter()
+            .map(|g| (&g.gesture, &g.action, g.parse().err()));
+        let remaps = self
+            .remap
+            .iter()
+            .map(|r| (&r.key, &r.action, r.parse().err()));
+        gestures
+            .chain(remaps)
+            .filter_map(|(keys, action, error)| {
+                Some(BindingStatus {
+                    keys: keys.clone(),
+                    action: action.clone(),
+                    registration: Registration::Invalid(error?),
+                })
+            })
+            .collect()
+    }
+}
+
+// Binding fields stay strings in the file, so a typo only disables its own
+// entry instead of failing the whole config.
+
+/// A key combination and the action it triggers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HotkeyBinding {
+    /// Key combination (e.g., "ctrl+alt+m").
+    pub keys: String,
+    /// Action (e.g., "mute-sound", "snooze(15)").
+    pub action: String,
+}
+
+impl HotkeyBinding {
+    /// Parses the key combination and the action.
+    pub fn parse(&self) -> Result<(Hotkey, Action), String> {
+        let hotkey = hotkey::parse_hotkey(&self.keys).map_err(|e| e.to_string())?;
+        let action = self.action.parse::<Action>().map_err(|e| e.to_string())?;
+        Ok((hotkey, action))
+    }
+}
+
+/// A key gesture and the action it triggers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GestureBinding {
+    /// Gesture (e.g., "double-tap(lshift)", "hold(rctrl, 500)").
+    pub gesture: String,
+    /// Action (e.g., "show-now").
+    pub action: String,
+}
+
+impl GestureBinding {
+    /// Parses the gesture and the action.
+    pub fn parse(&self) -> Result<(Gesture, Action), String> {
+        let gesture = self.gesture.parse::<Gesture>().map_err(|e| e.to_string())?;
+        let action = self.action.parse::<Action>().map_err(|e| e.to_string())?;
+        Ok((gesture, action))
+    }
+}
+
+/// A key and the action it runs instead of its own function.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyRemap {
+    /// Key (e.g., "capslock", "ralt", "rctrl").
+    pub key: String,
+    /// Action (e.g., "cycle-layout", "set-layout(ru)").
+    pub action: String,
+}
+
+impl KeyRemap {
+    /// Parses the key and the action.
+    pub fn parse(&self) -> Result<(GestureKey, Action), String> {
+        let key = self.key.parse::<GestureKey>().map_err(|e| e.to_string())?;
+        let action = self.action.parse::<Action>().map_err(|e| e.to_string())?;
+        Ok((key, action))
+    }
+}
+
 /// Colors configuration.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ColorsConfig {
@@ -79,6 +191,13 @@ impl Default for ColorsConfig {
     }
 }
 
+impl ColorsConfig {
+    /// Returns the RGB colour of a layout.
+    pub fn for_layout(&self, is_russian: bool) -> (u8, u8, u8) {
+        parse_hex_color(if is_russian { &self.ru } else { &self.en })
+    }
+}
+
 /// Position visibility configuration.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PositionsConfig {
@@ -106,6 +225,68 @@ impl Default for PositionsConfig {
     }
 }
 
+impl PositionsConfig {
+    /// Returns whether indicators are shown at `position`.
+    pub fn is_enabled(&self, position: Position) -> bool {
+        match position {
+            Position::TopLeft => self.top_left,
+            Position::TopRight => self.top_right,
+            Position::BottomLeft => self.bottom_left,
+            Position::BottomRight => self.bottom_right,
+            Position::Center => self.center,
+        }
+    }
+
+    /// Shows or hides the indicators at `position`.
+    pub fn set(&mut self, position: Position, enabled: bool) {
+        let flag = match position {
+            Position::TopLeft => &mut self.top_left,
+            Position::TopRight => &mut self.top_right,
+            Position::BottomLeft => &mut self.bottom_left,
+            Position::BottomRight => &mut self.bottom_right,
+            Position::Center => &mut self.center,
+        };
+        *flag = enabled;
+    }
+}
+
+/// Which monitors show indicators.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MonitorPolicy {
+    /// Every connected monitor.
+    #[default]
+    All,
+    /// The primary monitor only.
+    Primary,
+}
+
+impl MonitorPolicy {
+    /// Both policies, in menu order.
+    pub const ALL: [MonitorPolicy; 2] = [MonitorPolicy::All, MonitorPolicy::Primary];
+
+    /// Returns the name used in the config and in actions.
+    pub fn name(self) -> &'static str {
+        match self {
+            MonitorPolicy::All => "all",
+            MonitorPolicy::Primary => "primary",
+        }
+    }
+
+    /// Finds a policy by its `name`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|p| p.name() == name)
+    }
+
+    /// Returns the label shown in the tray menu.
+    pub fn label(self) -> &'static str {
+        match self {
+            MonitorPolicy::All => "All monitors",
+            MonitorPolicy::Primary => "Primary monitor",
+        }
+    }
+}
+
 /// Fade animation configuration.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FadeConfig {
@@ -126,6 +307,30 @@ impl Default for FadeConfig {
     }
 }
 
+/// How autostart is set up on Linux.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AutostartMethod {
+    /// XDG autostart entry (`~/.config/autostart/langtip.desktop`).
+    #[default]
+    Desktop,
+    /// systemd user unit, restarted on failure.
+    Systemd,
+}
+
+/// Who shares one running instance.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InstanceScope {
+    /// One instance on the machine.
+    Global,
+    /// One instance per login session (e.g. per Remote Desktop session).
+    #[default]
+    Session,
+    /// One instance per user, across the user's sessions.
+    User,
+}
+
 /// Main application configuration.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppConfig {
@@ -138,9 +343,16 @@ pub struct AppConfig {
     /// Font family.
     #[serde(default = "default_font_family")]
     pub font_family: String,
-    /// Update delay in milliseconds.
+    /// How long a pending layout check keeps re-reading the layout
+    /// after a trigger, for switches that are applied late (ms).
     #[serde(default = "default_update_delay")]
     pub update_delay_ms: u32,
+    /// Minimum interval between two layout checks (ms).
+    #[serde(default = "default_debounce")]
+    pub debounce_ms: u32,
+    /// Delay after a modifier key release before the layout is read (ms).
+    #[serde(default = "default_settle_delay")]
+    pub settle_delay_ms: u32,
     /// Hide delay in milliseconds.
     #[serde(default = "default_hide_delay")]
     pub hide_delay_ms: u32,
@@ -156,6 +368,9 @@ pub struct AppConfig {
     /// Positions configuration.
     #[serde(default)]
     pub positions: PositionsConfig,
+    /// Monitors that show indicators.
+    #[serde(default)]
+    pub monitors: MonitorPolicy,
     /// Fade animation configuration.
     #[serde(default)]
     pub fade: FadeConfig,
@@ -165,6 +380,12 @@ pub struct AppConfig {
     /// Hotkeys configuration.
     #[serde(default)]
     pub hotkeys: HotkeyConfig,
+    /// Autostart method on Linux (ignored on Windows).
+    #[serde(default)]
+    pub autostart_method: AutostartMethod,
+    /// Scope of the single instance lock; read at startup.
+    #[serde(default)]
+    pub instance_scope: InstanceScope,
 }
 
 impl Default for AppConfig {
@@ -174,14 +395,19 @@ impl Default for AppConfig {
             font_size_center: 64,
             font_family: "Arial".to_string(),
             update_delay_ms: 250,
+            debounce_ms: 100,
+            settle_delay_ms: 50,
             hide_delay_ms: 5000,
             margin: 20,
             opacity: 80,
             colors: ColorsConfig::default(),
             positions: PositionsConfig::default(),
+            monitors: MonitorPolicy::default(),
             fade: FadeConfig::default(),
             sound: SoundConfig::default(),
             hotkeys: HotkeyConfig::default(),
+            autostart_method: AutostartMethod::default(),
+            instance_scope: InstanceScope::default(),
         }
     }
 }
@@ -223,6 +449,12 @@ fn default_font_family() -> String {
 fn default_update_delay() -> u32 {
     250
 }
+fn default_debounce() -> u32 {
+    100
+}
+fn default_settle_delay() -> u32 {
+    50
+}
 fn default_hide_delay() -> u32 {
     5000
 }
@@ -251,6 +483,59 @@ impl ConfigManager {
         Self { config_path }
     }
 
+    /// Creates a configuration manager for a config file at `path`.
+    #[cfg(test)]
+    pub fn with_path(path: PathBuf) -> Self {
+        Self { config_path: path }
+    }
+
+    /// Returns the manager for the config file of a profile,
+    /// `config.NAME.json` next to the current one. The `default` profile is
+    /// `config.json`.
+    pub fn for_profile(&self, name: &str) -> Self {
+        let file = match name {
+            "default" => "config.json".to_string(),
+            name => format!("config.{}.json", name),
+        };
+        let dir = self
+            .config_path
+            .parent()
+            .map(PathBuf::from)
+            .unwrap_or_default();
+        Self {
+            config_path: dir.join(file),
+        }
+    }
+
+    /// Returns the name of the profile this manager's config file belongs to.
+    pub fn profile(&self) -> String {
+        profile_of(&self.config_path).unwrap_or_else(|| "default".to_string())
+    }
+
+    /// Lists the profiles with a config file next to the current one, in
+    /// alphabetical order, `default` first.
+    pub fn profiles(&self) -> Vec<String> {
+        let dir = self
+            .config_path
+            .parent()
+            .map(PathBuf::from)
+            .unwrap_or_default();
+        let dir = if dir.as_os_str().is_empty() {
+            PathBuf::from(".")
+        } else {
+            dir
+        };
+        let mut profiles: Vec<String> = fs::read_dir(dir)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| profile_of(&entry.ok()?.path()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        profiles.sort_by_key(|name| (name != "default", name.clone()));
+        profiles
+    }
+
     /// Gets the path to the configuration file.
     fn get_config_path() -> PathBuf {
         // Try to use the directory where the executable is located
@@ -267,22 +552,27 @@ impl ConfigManager {
     ///
     /// If the file doesn't exist, creates it with default values.
     pub fn load(&self) -> AppConfig {
+        self.load_with_error().0
+    }
+
+    /// Loads configuration like `load`, also returning why the file was
+    /// replaced by the defaults, if it was.
+    pub fn load_with_error(&self) -> (AppConfig, Option<String>) {
         if !self.config_path.exists() {
             let config = AppConfig::default();
             let _ = self.save(&config);
-            return config;
+            return (config, None);
         }
 
-        match fs::read_to_string(&self.config_path) {
-            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
-                log::warn!("Failed to parse config: {}, using defaults", e);
-                AppConfig::default()
-            }),
-            Err(e) => {
-                log::warn!("Failed to read config: {}, using defaults", e);
-                AppConfig::default()
-            }
-        }
+        let error = match fs::read_to_string(&self.config_path) {
+            Ok(content) => match serde_json::from_str(&content) {
+                Ok(config) => return (config, None),
+                Err(e) => format!("Failed to parse config: {}", e),
+            },
+            Err(e) => format!("Failed to read config: {}", e),
+        };
+        log::warn!("{}, using defaults", error);
+        (AppConfig::default(), Some(error))
     }
 
     /// Saves configuration to file.
@@ -311,6 +601,26 @@ impl Default for ConfigManager {
     }
 }
 
+/// Checks that a profile name is usable as part of a file name.
+pub fn is_valid_profile(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Returns the profile of a config file path (`config.json` is `default`,
+/// `config.NAME.json` is `NAME`).
+fn profile_of(path: &Path) -> Option<String> {
+    let file = path.file_name()?.to_str()?;
+    if file == "config.json" {
+        return Some("default".to_string());
+    }
+    let name = file.strip_prefix("config.")?.strip_suffix(".json")?;
+    // `config.default.json` is never read; `default` is `config.json`
+    (is_valid_profile(name) && name != "default").then(|| name.to_string())
+}
+
 /// Parses a hex color string to RGB values.
 pub fn parse_hex_color(hex: &str) -> (u8, u8, u8) {
     let hex = hex.trim_start_matches('#');
@@ -0,0 +1,534 @@
+//! Indicator controller module.
+//!
+//! Platform-independent state machine behind the main loop. It takes typed
+//! inputs (layout events, toggles, ticks, reloads) and returns the commands
+//! the platform layer has to carry out, so all show/hide/cooldown logic can
+//! be tested without windows or hooks.
+
+use crate::coalesce::CooldownCoalescer;
+use crate::layout::LayoutInfo;
+use std::time::{Duration, Instant};
+
+/// Layout events right after a hide are queued for this long (prevents false triggers).
+pub const HIDE_COOLDOWN: Duration = Duration::from_millis(500);
+
+/// Source of the current time for the controller.
+pub trait Clock {
+    /// Returns the current instant.
+    fn now(&self) -> Instant;
+    /// Returns the local calendar day, as days since 1970-01-01.
+    fn today(&self) -> i64;
+}
+
+/// Clock backed by `Instant::now()`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn today(&self) -> i64 {
+        crate::platform::local_day()
+    }
+}
+
+/// Manually advanced clock for tests and trace replay.
+#[cfg(test)]
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    start: Instant,
+    offset: std::rc::Rc<std::cell::Cell<Duration>>,
+}
+
+#[cfg(test)]
+impl ManualClock {
+    /// Creates a clock frozen at its start instant.
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            offset: Default::default(),
+        }
+    }
+
+    /// Moves the clock to `offset` after its start instant.
+    pub fn set(&self, offset: Duration) {
+        self.offset.set(offset);
+    }
+}
+
+#[cfg(test)]
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.start + self.offset.get()
+    }
+
+    /// Day 0 starts at the clock's start instant.
+    fn today(&self) -> i64 {
+        (self.offset.get().as_secs() / 86_400) as i64
+    }
+}
+
+/// Input to the controller.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Input {
+    /// Layout change reported by the hook.
+    Layout(LayoutInfo),
+    /// Toggle indicator visibility (hotkey).
+    Toggle,
+    /// Enable or disable indicators (tray menu).
+    SetVisible(bool),
+    /// Show the current layout now, even if indicators are disabled.
+    Peek,
+    /// Show the current layout while a key is held (true), even if
+    /// indicators are disabled, and hide it on release (false).
+    PeekHeld(bool),
+    /// Disable indicators for a while; they come back on their own.
+    Snooze(Duration),
+    /// Periodic tick from the main loop.
+    Tick,
+    /// Config file was reloaded.
+    ConfigReloaded {
+        /// New auto-hide delay.
+        hide_delay: Duration,
+        /// Layout active right after the reload.
+        layout: LayoutInfo,
+    },
+    /// Display configuration changed.
+    DisplayChanged {
+        /// Layout active when the change was handled.
+        layout: LayoutInfo,
+    },
+    /// Exit requested (hotkey or tray).
+    Exit,
+}
+
+/// Command for the platform layer.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// Destroy and recreate all indicator windows.
+    RecreateIndicators,
+    /// Set the text of all indicators.
+    UpdateText(LayoutInfo),
+    /// Show all indicators (fade in).
+    Show,
+    /// Hide all indicators (fade out).
+    Hide,
+    /// Play the layout change sound.
+    PlaySound(LayoutInfo),
+    /// Read the current layout and feed it back as `Input::Layout`.
+    RefreshLayout,
+    /// Shut the application down.
+    Exit,
+}
+
+/// Show/hide state machine for the indicators.
+pub struct IndicatorController<C: Clock = SystemClock> {
+    clock: C,
+    layout: LayoutInfo,
+    /// Indicators enabled by the user (hotkey/tray toggle).
+    visible: bool,
+    /// Indicators snoozed until this instant.
+    snoozed_until: Option<Instant>,
+    /// Indicators currently shown (until auto-hide).
+    shown: bool,
+    /// Indicators shown while a key is held; no auto-hide until released.
+    /// Independent of `visible`, so peeking never changes it.
+    peek_held: bool,
+    last_show: Instant,
+    hide_delay: Duration,
+    coalescer: CooldownCoalescer,
+}
+
+impl<C: Clock> IndicatorController<C> {
+    /// Creates a controller for the given initial layout.
+    pub fn new(clock: C, initial_layout: LayoutInfo, hide_delay: Duration) -> Self {
+        let now = clock.now();
+        Self {
+            clock,
+            layout: initial_layout,
+            visible: true,
+            snoozed_until: None,
+            shown: false,
+            peek_held: false,
+            last_show: now,
+            hide_delay,
+            coalescer: CooldownCoalescer::new(HIDE_COOLDOWN),
+        }
+    }
+
+    /// Returns the commands that show the initial layout on startup.
+    pub fn start(&mut self) -> Vec<Command> {
+        self.shown = true;
+        self.last_show = self.clock.now();
+        vec![Command::UpdateText(self.layout.clone()), Command::Show]
+    }
+
+    /// Handles an input and returns the commands to carry out, in order.
+    pub fn handle(&mut self, input: Input) -> Vec<Command> {
+        let now = self.clock.now();
+        match input {
+            Input::Layout(layout) => match self.coalescer.on_event(layout, now) {
+                Some(layout) => self.apply_layout(layout, now),
+                None => Vec::new(),
+            },
+            Input::Toggle => self.set_visible(!self.is_enabled(now), now),
+            Input::SetVisible(visible) => self.set_visible(visible, now),
+            Input::Peek => self.peek(now),
+            Input::PeekHeld(held) => self.peek_held(held, now),
+            Input::Snooze(duration) => self.snooze(duration, now),
+            Input::Tick => self.tick(now),
+            Input::ConfigReloaded { hide_delay, layout } => {
+                self.hide_delay = hide_delay;
+                self.recreate(layout, now)
+            }
+            Input::DisplayChanged { layout } => self.recreate(layout, now),
+            Input::Exit => vec![Command::Exit],
+        }
+    }
+
+    /// Returns the last applied layout.
+    pub fn layout(&self) -> &LayoutInfo {
+        &self.layout
+    }
+
+    /// Returns whether indicators are enabled.
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    /// Returns whether indicators are snoozed.
+    #[cfg(test)]
+    pub fn is_snoozed(&self) -> bool {
+        self.snooze_left().is_some()
+    }
+
+    /// Returns how long indicators stay snoozed, if they are.
+    pub fn snooze_left(&self) -> Option<Duration> {
+        self.snoozed_until
+            .map(|until| until.saturating_duration_since(self.clock.now()))
+            .filter(|left| !left.is_zero())
+    }
+
+    /// Returns whether indicators are shown while a key is held.
+    #[cfg(test)]
+    pub fn is_peek_held(&self) -> bool {
+        self.peek_held
+    }
+
+    /// Returns whether indicators are currently shown.
+    #[cfg(test)]
+    pub fn is_shown(&self) -> bool {
+        self.shown
+    }
+
+    fn apply_layout(&mut self, layout: LayoutInfo, now: Instant) -> Vec<Command> {
+        log::debug!(
+            "Received layout event: {}, current: {}",
+            layout.name,
+            self.layout.name
+        );
+        if layout.name == self.layout.name {
+            return Vec::new();
+        }
+
+        log::info!("Layout: {} -> {}", self.layout.name, layout.name);
+        self.layout = layout.clone();
+        self.last_show = now;
+
+        let mut commands = vec![
+            Command::UpdateText(layout.clone()),
+            Command::PlaySound(layout),
+        ];
+        if self.is_enabled(now) || self.peek_held {
+            commands.push(Command::Show);
+            self.shown = true;
+        }
+        commands
+    }
+
+    /// Whether layout changes show the indicators: enabled and not snoozed.
+    /// An expired snooze counts as over even before the tick clears it.
+    fn is_enabled(&self, now: Instant) -> bool {
+        self.visible && self.snoozed_until.is_none_or(|until| now >= until)
+    }
+
+    fn set_visible(&mut self, visible: bool, now: Instant) -> Vec<Command> {
+        // An explicit choice ends a snooze
+        let was_enabled = self.is_enabled(now);
+        self.snoozed_until = None;
+        self.visible = visible;
+        if visible == was_enabled {
+            return Vec::new();
+        }
+        log::debug!("Visibility -> {}", visible);
+
+        if visible {
+            self.last_show = now;
+            self.shown = true;
+            vec![Command::Show]
+        } else if self.peek_held {
+            // Stays up until the held key is released
+            Vec::new()
+        } else {
+            self.hide(now)
+        }
+    }
+
+    fn peek(&mut self, now: Instant) -> Vec<Command> {
+        self.last_show = now;
+        self.shown = true;
+        vec![Command::UpdateText(self.layout.clone()), Command::Show]
+    }
+
+    fn peek_held(&mut self, held: bool, now: Instant) -> Vec<Command> {
+        if held == self.peek_held {
+            // Auto-repeat, or a release without a press
+            return Vec::new();
+        }
+        self.peek_held = held;
+        if held {
+            self.peek(now)
+        } else if self.shown {
+            self.hide(now)
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn snooze(&mut self, duration: Duration, now: Instant) -> Vec<Command> {
+        log::info!("Indicators snoozed for {}s", duration.as_secs());
+        self.snoozed_until = Some(now + duration);
+        self.peek_held = false;
+        if self.shown {
+            self.hide(now)
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn tick(&mut self, now: Instant) -> Vec<Command> {
+        if self.snoozed_until.is_some_and(|until| now >= until) {
+            log::info!("Snooze is over");
+            self.snoozed_until = None;
+        }
+
+        // Auto-hide check - only hide once, never while a peek key is held
+        if self.shown && !self.peek_held && now.duration_since(self.last_show) >= self.hide_delay {
+            log::debug!(
+                "Auto-hide triggered after {}ms",
+                now.duration_since(self.last_show).as_millis()
+            );
+            return self.hide(now);
+        }
+
+        // Events queued during the cooldown are resolved against the real layout
+        if self.coalescer.take_pending(now) {
+            return vec![Command::RefreshLayout];
+        }
+
+        Vec::new()
+    }
+
+    fn hide(&mut self, now: Instant) -> Vec<Command> {
+        self.shown = false;
+        self.coalescer.start_cooldown(now);
+        vec![Command::Hide]
+    }
+
+    fn recreate(&mut self, layout: LayoutInfo, now: Instant) -> Vec<Command> {
+        self.layout = layout.clone();
+        self.shown = self.is_enabled(now) || self.peek_held;
+        self.last_show = now;
+
+        let mut commands = vec![Command::RecreateIndicators, Command::UpdateText(layout)];
+        if self.shown {
+            commands.push(Command::Show);
+        }
+        commands
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(name: &str) -> LayoutInfo {
+        LayoutInfo {
+            name: name.to_string(),
+            is_russian: name == "RU",
+        }
+    }
+
+    fn controller() -> (ManualClock, IndicatorController<ManualClock>) {
+        let clock = ManualClock::new();
+        let mut c = IndicatorController::new(clock.clone(), layout("EN"), Duration::from_secs(5));
+        c.start();
+        (clock, c)
+    }
+
+    #[test]
+    fn toggle_off_hides_and_blocks_show_on_switch() {
+        let (clock, mut c) = controller();
+
+        assert_eq!(c.handle(Input::Toggle), vec![Command::Hide]);
+        assert!(!c.is_visible());
+
+        clock.set(Duration::from_secs(1));
+        assert_eq!(
+            c.handle(Input::Layout(layout("RU"))),
+            vec![
+                Command::UpdateText(layout("RU")),
+                Command::PlaySound(layout("RU"))
+            ]
+        );
+        assert!(!c.is_shown());
+    }
+
+    #[test]
+    fn set_visible_is_idempotent() {
+        let (_clock, mut c) = controller();
+
+        assert!(c.handle(Input::SetVisible(true)).is_empty());
+        assert_eq!(c.handle(Input::SetVisible(false)), vec![Command::Hide]);
+        assert!(c.handle(Input::SetVisible(false)).is_empty());
+    }
+
+    #[test]
+    fn queued_event_asks_for_refresh_after_cooldown() {
+        let (clock, mut c) = controller();
+
+        clock.set(Duration::from_secs(5));
+        assert_eq!(c.handle(Input::Tick), vec![Command::Hide]);
+
+        clock.set(Duration::from_millis(5100));
+        assert!(c.handle(Input::Layout(layout("RU"))).is_empty());
+        assert!(c.handle(Input::Tick).is_empty());
+
+        clock.set(Duration::from_millis(5500));
+        assert_eq!(c.handle(Input::Tick), vec![Command::RefreshLayout]);
+        assert!(c.handle(Input::Tick).is_empty());
+    }
+
+    #[test]
+    fn queued_events_refresh_once_after_hide_cooldown() {
+        let (clock, mut c) = controller();
+        clock.set(Duration::from_secs(5));
+        assert_eq!(c.handle(Input::Tick), vec![Command::Hide]);
+
+        // Spurious events while the indicators fade out
+        for (ms, name) in [(5020, "DE"), (5100, "RU"), (5300, "EN")] {
+            clock.set(Duration::from_millis(ms));
+            assert!(c.handle(Input::Layout(layout(name))).is_empty());
+        }
+
+        let refreshes = |c: &mut IndicatorController<ManualClock>| {
+            c.handle(Input::Tick)
+                .into_iter()
+                .filter(|command| *command == Command::RefreshLayout)
+                .count()
+        };
+        clock.set(Duration::from_secs(5) + HIDE_COOLDOWN - Duration::from_millis(1));
+        assert_eq!(refreshes(&mut c), 0);
+        clock.set(Duration::from_secs(5) + HIDE_COOLDOWN);
+        assert_eq!(refreshes(&mut c), 1);
+        clock.set(Duration::from_secs(6));
+        assert_eq!(refreshes(&mut c), 0);
+    }
+
+    #[test]
+    fn peek_shows_while_disabled_and_auto_hides() {
+        let (clock, mut c) = controller();
+        c.handle(Input::SetVisible(false));
+
+        clock.set(Duration::from_secs(1));
+        assert_eq!(
+            c.handle(Input::Peek),
+            vec![Command::UpdateText(layout("EN")), Command::Show]
+        );
+        assert!(c.is_shown());
+        assert!(!c.is_visible());
+
+        clock.set(Duration::from_secs(6));
+        assert_eq!(c.handle(Input::Tick), vec![Command::Hide]);
+    }
+
+    #[test]
+    fn held_peek_hides_on_release_only() {
+        let (clock, mut c) = controller();
+        clock.set(Duration::from_secs(5));
+        c.handle(Input::Tick);
+
+        clock.set(Duration::from_secs(6));
+        assert_eq!(
+            c.handle(Input::PeekHeld(true)),
+            vec![Command::UpdateText(layout("EN")), Command::Show]
+        );
+        clock.set(Duration::from_secs(60));
+        assert!(c.handle(Input::Tick).is_empty());
+        assert!(c.is_peek_held());
+
+        assert_eq!(c.handle(Input::PeekHeld(false)), vec![Command::Hide]);
+        assert!(!c.is_shown());
+        assert!(c.is_visible());
+
+        // Snoozing ends the peek
+        c.handle(Input::PeekHeld(true));
+        c.handle(Input::Snooze(Duration::from_secs(60)));
+        assert!(!c.is_peek_held());
+        assert!(c.handle(Input::PeekHeld(false)).is_empty());
+    }
+
+    #[test]
+    fn snooze_blocks_show_until_it_expires() {
+        let (clock, mut c) = controller();
+
+        assert_eq!(
+            c.handle(Input::Snooze(Duration::from_secs(60))),
+            vec![Command::Hide]
+        );
+        assert!(c.is_snoozed());
+
+        clock.set(Duration::from_secs(10));
+        assert_eq!(
+            c.handle(Input::Layout(layout("RU"))),
+            vec![
+                Command::UpdateText(layout("RU")),
+                Command::PlaySound(layout("RU"))
+            ]
+        );
+
+        clock.set(Duration::from_secs(60));
+        assert!(c.handle(Input::Tick).is_empty());
+        assert!(!c.is_snoozed());
+        clock.set(Duration::from_secs(61));
+        assert!(c
+            .handle(Input::Layout(layout("EN")))
+            .contains(&Command::Show));
+    }
+
+    #[test]
+    fn expired_snooze_is_over_without_a_tick() {
+        let (clock, mut c) = controller();
+        c.handle(Input::Snooze(Duration::from_secs(60)));
+
+        // Recorded traces drop idle ticks, so the expiry must not need one
+        clock.set(Duration::from_secs(61));
+        assert!(!c.is_snoozed());
+        assert!(c
+            .handle(Input::Layout(layout("RU")))
+            .contains(&Command::Show));
+    }
+
+    #[test]
+    fn toggle_ends_snooze() {
+        let (clock, mut c) = controller();
+        c.handle(Input::Snooze(Duration::from_secs(60)));
+
+        clock.set(Duration::from_secs(1));
+        assert_eq!(c.handle(Input::Toggle), vec![Command::Show]);
+        assert!(!c.is_snoozed());
+        assert!(c.is_visible());
+    }
+}
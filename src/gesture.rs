@@ -0,0 +1,618 @@
+//! Gesture module.
+//!
+//! Key gestures that global hotkey APIs can't express: tapping a key alone,
+//! double-tapping it, holding it, or pressing and releasing a modifier with
+//! no other key in between. `GestureRecognizer` is a pure state machine over
+//! timestamped key presses and releases; a low-level keyboard hook feeds it
+//! and dispatches the actions it returns like any hotkey.
+
+#[cfg(any(windows, test))]
+use crate::action::Action;
+#[cfg(any(windows, test))]
+use crate::config::GestureBinding;
+use crate::hotkey::{self, HotkeyError, Key, Modifier, Side};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Longest press that still counts as a tap.
+#[cfg(any(windows, test))]
+pub const TAP_TIMEOUT: Duration = Duration::from_millis(250);
+
+/// Longest pause between the two taps of a double tap.
+#[cfg(any(windows, test))]
+pub const DOUBLE_TAP_GAP: Duration = Duration::from_millis(300);
+
+/// A key a gesture is made with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum GestureKey {
+    /// A modifier; `Side::Either` accepts both keys of the pair.
+    Modifier(Modifier, Side),
+    Key(Key),
+}
+
+impl GestureKey {
+    /// Returns the physical keys this key stands for: both keys of the pair
+    /// for a modifier with `Side::Either`.
+    pub fn physical(self) -> Vec<GestureKey> {
+        match self {
+            GestureKey::Modifier(modifier, Side::Either) => vec![
+                GestureKey::Modifier(modifier, Side::Left),
+                GestureKey::Modifier(modifier, Side::Right),
+            ],
+            key => vec![key],
+        }
+    }
+}
+
+impl FromStr for GestureKey {
+    type Err = HotkeyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim().to_lowercase();
+        if name.is_empty() {
+            return Err(HotkeyError::Empty);
+        }
+        if let Some((modifier, side)) = hotkey::parse_modifier(&name) {
+            return Ok(GestureKey::Modifier(modifier, side));
+        }
+        hotkey::parse_key(&name)
+            .map(GestureKey::Key)
+            .ok_or(HotkeyError::UnknownToken(name))
+    }
+}
+
+impl fmt::Display for GestureKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GestureKey::Modifier(modifier, side) => {
+                f.write_str(&hotkey::modifier_name(*modifier, *side))
+            }
+            GestureKey::Key(key) => write!(f, "{}", key),
+        }
+    }
+}
+
+impl TryFrom<String> for GestureKey {
+    type Error = HotkeyError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<GestureKey> for String {
+    fn from(key: GestureKey) -> Self {
+        key.to_string()
+    }
+}
+
+/// How the key of a gesture is pressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GestureKind {
+    /// Pressed and released quickly with no other key (`tap(KEY)`).
+    Tap,
+    /// Tapped twice in a row (`double-tap(KEY)`).
+    DoubleTap,
+    /// Held alone for a while (`hold(KEY, MS)`); fires while still held.
+    Hold(Duration),
+    /// Pressed and released with no other key in between, however long it
+    /// was held (`release(KEY)`).
+    Release,
+}
+
+/// A key gesture, written like `double-tap(lshift)` or `hold(rctrl, 500)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Gesture {
+    pub kind: GestureKind,
+    pub key: GestureKey,
+}
+
+/// Error returned for an unknown or malformed gesture.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseGestureError(String);
+
+impl fmt::Display for ParseGestureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid gesture: {}", self.0)
+    }
+}
+
+impl std::error::Error for ParseGestureError {}
+
+impl FromStr for Gesture {
+    type Err = ParseGestureError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || ParseGestureError(s.to_string());
+        let (name, rest) = s.trim().split_once('(').ok_or_else(error)?;
+        let arguments: Vec<&str> = rest
+            .strip_suffix(')')
+            .ok_or_else(error)?
+            .split(',')
+            .map(str::trim)
+            .collect();
+
+        let kind = match (name.trim().to_lowercase().as_str(), arguments.as_slice()) {
+            ("tap", [_]) => GestureKind::Tap,
+            ("double-tap", [_]) => GestureKind::DoubleTap,
+            ("release", [_]) => GestureKind::Release,
+            ("hold", [_, ms]) => match ms.parse() {
+                Ok(ms) if ms > 0 => GestureKind::Hold(Duration::from_millis(ms)),
+                _ => return Err(error()),
+            },
+            _ => return Err(error()),
+        };
+        let key = arguments[0].parse().map_err(|_| error())?;
+        Ok(Gesture { kind, key })
+    }
+}
+
+impl fmt::Display for Gesture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            GestureKind::Tap => write!(f, "tap({})", self.key),
+            GestureKind::DoubleTap => write!(f, "double-tap({})", self.key),
+            GestureKind::Hold(duration) => {
+                write!(f, "hold({}, {})", self.key, duration.as_millis())
+            }
+            GestureKind::Release => write!(f, "release({})", self.key),
+        }
+    }
+}
+
+impl TryFrom<String> for Gesture {
+    type Error = ParseGestureError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Gesture> for String {
+    fn from(gesture: Gesture) -> Self {
+        gesture.to_string()
+    }
+}
+
+/// A key press or release. `K` identifies a physical key on the platform.
+#[cfg(any(windows, test))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent<K> {
+    pub key: K,
+    pub down: bool,
+    /// Time since any fixed point, the same for all events.
+    pub at: Duration,
+}
+
+/// A gesture binding resolved to platform keys.
+#[cfg(any(windows, test))]
+struct Resolved<K> {
+    kind: GestureKind,
+    keys: Vec<K>,
+    action: Action,
+}
+
+/// Keys pressed from the moment one goes down with none held until all are
+/// released again.
+#[cfg(any(windows, test))]
+struct Stroke<K> {
+    /// The key that started the stroke.
+    key: K,
+    down_at: Duration,
+    /// No other key went down during the stroke.
+    alone: bool,
+    /// Hold gestures (indices) fired during the stroke.
+    fired: Vec<usize>,
+}
+
+/// Recognises gestures in a stream of key events.
+#[cfg(any(windows, test))]
+pub struct GestureRecognizer<K> {
+    gestures: Vec<Resolved<K>>,
+    /// Keys held down.
+    held: Vec<K>,
+    stroke: Option<Stroke<K>>,
+    /// Last tap a double tap may start with: key and release time.
+    last_tap: Option<(K, Duration)>,
+    /// Tap actions held back until a double tap can no longer follow:
+    /// deadline and actions.
+    pending_taps: Option<(Duration, Vec<Action>)>,
+}
+
+#[cfg(any(windows, test))]
+impl<K: Copy + PartialEq> GestureRecognizer<K> {
+    /// Creates a recogniser for the bindings. `key` maps a physical key to
+    /// the platform's key; gestures with unmapped keys never fire, invalid
+    /// bindings are skipped.
+    pub fn new(bindings: &[GestureBinding], key: impl Fn(GestureKey) -> Option<K>) -> Self {
+        let gestures = bindings
+            .iter()
+            .filter_map(|binding| binding.parse().ok())
+            .map(|(gesture, action)| Resolved {
+                kind: gesture.kind,
+                keys: gesture.key.physical().into_iter().filter_map(&key).collect(),
+                action,
+            })
+            .collect();
+        Self {
+            gestures,
+            held: Vec::new(),
+            stroke: None,
+            last_tap: None,
+            pending_taps: None,
+        }
+    }
+
+    /// Returns whether there are no gestures to recognise.
+    #[cfg(windows)]
+    pub fn is_empty(&self) -> bool {
+        self.gestures.is_empty()
+    }
+
+    /// Feeds a key event. Returns the actions of the gestures it completes,
+    /// including ones that came due before it.
+    pub fn handle(&mut self, event: KeyEvent<K>) -> Vec<Action> {
+        let mut actions = self.tick(event.at);
+        if event.down {
+            actions.extend(self.key_down(event.key, event.at));
+        } else {
+            actions.extend(self.key_up(event.key, event.at));
+        }
+        actions
+    }
+
+    /// Advances time to `at`. Returns the actions of held keys and held
+    /// back taps that came due.
+    pub fn tick(&mut self, at: Duration) -> Vec<Action> {
+        let mut actions = Vec::new();
+        if let Some((deadline, _)) = self.pending_taps {
+            if at >= deadline {
+                actions.extend(self.take_pending_taps());
+            }
+        }
+
+        if let Some(ref mut stroke) = self.stroke {
+            if stroke.alone && self.held.contains(&stroke.key) {
+                for (index, gesture) in self.gestures.iter().enumerate() {
+                    let GestureKind::Hold(duration) = gesture.kind else {
+                        continue;
+                    };
+                    if gesture.keys.contains(&stroke.key)
+                        && !stroke.fired.contains(&index)
+                        && at.saturating_sub(stroke.down_at) >= duration
+                    {
+                        stroke.fired.push(index);
+                        actions.push(gesture.action.clone());
+                    }
+                }
+            }
+        }
+        actions
+    }
+
+    /// Returns when `tick` has something to do next, if ever.
+    pub fn next_deadline(&self) -> Option<Duration> {
+        let pending = self.pending_taps.as_ref().map(|(deadline, _)| *deadline);
+        let hold = self.stroke.as_ref().filter(|s| s.alone).and_then(|stroke| {
+            self.gestures
+                .iter()
+                .enumerate()
+                .filter(|(index, g)| g.keys.contains(&stroke.key) && !stroke.fired.contains(index))
+                .filter_map(|(_, g)| match g.kind {
+                    GestureKind::Hold(duration) => Some(stroke.down_at + duration),
+                    _ => None,
+                })
+                .min()
+        });
+        pending.into_iter().chain(hold).min()
+    }
+
+    fn key_down(&mut self, key: K, at: Duration) -> Vec<Action> {
+        // Auto-repeat
+        if self.held.contains(&key) {
+            return Vec::new();
+        }
+
+        // A double tap can't follow any more; let held back taps through
+        let mut actions = Vec::new();
+        if self.last_tap.is_some_and(|(k, _)| k != key) {
+            self.last_tap = None;
+            actions.extend(self.take_pending_taps());
+        }
+
+        match self.stroke {
+            Some(ref mut stroke) => stroke.alone = false,
+            None => {
+                self.stroke = Some(Stroke {
+                    key,
+                    down_at: at,
+                    alone: true,
+                    fired: Vec::new(),
+                })
+            }
+        }
+        self.held.push(key);
+        actions
+    }
+
+    fn key_up(&mut self, key: K, at: Duration) -> Vec<Action> {
+        self.held.retain(|k| *k != key);
+        let stroke = match self.stroke.take() {
+            Some(stroke) if stroke.key == key => stroke,
+            stroke => {
+                // Keys of a chord; the stroke ends with the last one
+                if !self.held.is_empty() {
+                    self.stroke = stroke;
+                }
+                return Vec::new();
+            }
+        };
+        if !stroke.alone || !stroke.fired.is_empty() {
+            self.last_tap = None;
+            // A peek held by a hold gesture ends with the key
+            let held_peek = stroke
+                .fired
+                .iter()
+                .any(|&index| self.gestures[index].action == Action::PeekWhileHeld);
+            return if held_peek {
+                vec![Action::EndPeek]
+            } else {
+                Vec::new()
+            };
+        }
+
+        let mut actions = self.actions_for(key, |kind| kind == GestureKind::Release);
+        if at.saturating_sub(stroke.down_at) > TAP_TIMEOUT {
+            self.last_tap = None;
+            return actions;
+        }
+
+        let double = self.last_tap.is_some_and(|(k, up_at)| {
+            k == key && stroke.down_at.saturating_sub(up_at) <= DOUBLE_TAP_GAP
+        });
+        if double {
+            self.last_tap = None;
+            self.pending_taps = None;
+            actions.extend(self.actions_for(key, |kind| kind == GestureKind::DoubleTap));
+            return actions;
+        }
+
+        self.last_tap = Some((key, at));
+        let taps = self.actions_for(key, |kind| kind == GestureKind::Tap);
+        let double_bound = !self
+            .actions_for(key, |kind| kind == GestureKind::DoubleTap)
+            .is_empty();
+        if double_bound && !taps.is_empty() {
+            // Wait and see whether this is the first half of a double tap
+            self.pending_taps = Some((at + DOUBLE_TAP_GAP, taps));
+        } else {
+            actions.extend(taps);
+        }
+        actions
+    }
+
+    /// Returns the actions of the gestures of a kind made with `key`.
+    fn actions_for(&self, key: K, kind: impl Fn(GestureKind) -> bool) -> Vec<Action> {
+        self.gestures
+            .iter()
+            .filter(|g| kind(g.kind) && g.keys.contains(&key))
+            .map(|g| g.action.clone())
+            .collect()
+    }
+
+    fn take_pending_taps(&mut self) -> Vec<Action> {
+        self.pending_taps
+            .take()
+            .map(|(_, actions)| actions)
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recognizer(bindings: &[(&str, Action)]) -> GestureRecognizer<GestureKey> {
+        let bindings: Vec<GestureBinding> = bindings
+            .iter()
+            .map(|(gesture, action)| GestureBinding {
+                gesture: gesture.to_string(),
+                action: action.to_string(),
+            })
+            .collect();
+        GestureRecognizer::new(&bindings, Some)
+    }
+
+    fn key(name: &str) -> GestureKey {
+        let key: GestureKey = name.parse().unwrap();
+        key.physical()[0]
+    }
+
+    fn down(r: &mut GestureRecognizer<GestureKey>, name: &str, ms: u64) -> Vec<Action> {
+        r.handle(KeyEvent {
+            key: key(name),
+            down: true,
+            at: Duration::from_millis(ms),
+        })
+    }
+
+    fn up(r: &mut GestureRecognizer<GestureKey>, name: &str, ms: u64) -> Vec<Action> {
+        r.handle(KeyEvent {
+            key: key(name),
+            down: false,
+            at: Duration::from_millis(ms),
+        })
+    }
+
+    #[test]
+    fn gestures_round_trip() {
+        for text in [
+            "tap(rctrl)",
+            "double-tap(lshift)",
+            "hold(ralt, 500)",
+            "release(win)",
+            "tap(f13)",
+        ] {
+            let gesture: Gesture = text.parse().unwrap();
+            assert_eq!(gesture.to_string(), text);
+        }
+        assert_eq!(
+            " Hold( RCtrl ,300 ) "
+                .parse::<Gesture>()
+                .unwrap()
+                .to_string(),
+            "hold(rctrl, 300)"
+        );
+        for text in [
+            "",
+            "tap",
+            "tap()",
+            "tap(hyper)",
+            "hold(rctrl)",
+            "hold(rctrl, 0)",
+            "tap(a, b)",
+            "swipe(a)",
+        ] {
+            assert!(text.parse::<Gesture>().is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn taps_must_be_quick_and_alone() {
+        let mut r = recognizer(&[("tap(rctrl)", Action::Peek)]);
+        assert!(down(&mut r, "rctrl", 0).is_empty());
+        assert_eq!(up(&mut r, "rctrl", 100), vec![Action::Peek]);
+
+        // Too long
+        down(&mut r, "rctrl", 1000);
+        assert!(up(&mut r, "rctrl", 1400).is_empty());
+
+        // Part of a chord
+        down(&mut r, "rctrl", 2000);
+        down(&mut r, "c", 2050);
+        up(&mut r, "c", 2080);
+        assert!(up(&mut r, "rctrl", 2100).is_empty());
+
+        // The other Ctrl is a different key
+        down(&mut r, "lctrl", 3000);
+        assert!(up(&mut r, "lctrl", 3050).is_empty());
+    }
+
+    #[test]
+    fn double_taps_hold_back_single_taps() {
+        let mut r = recognizer(&[
+            ("tap(lshift)", Action::Peek),
+            ("double-tap(shift)", Action::CycleLayout),
+        ]);
+
+        // Two quick taps: only the double tap
+        down(&mut r, "lshift", 0);
+        assert!(up(&mut r, "lshift", 80).is_empty());
+        assert_eq!(r.next_deadline(), Some(Duration::from_millis(380)));
+        down(&mut r, "lshift", 200);
+        assert_eq!(up(&mut r, "lshift", 260), vec![Action::CycleLayout]);
+        assert_eq!(r.next_deadline(), None);
+        assert!(r.tick(Duration::from_millis(1000)).is_empty());
+
+        // One tap: fires once the double tap can't follow
+        down(&mut r, "lshift", 2000);
+        up(&mut r, "lshift", 2080);
+        assert!(r.tick(Duration::from_millis(2200)).is_empty());
+        assert_eq!(r.tick(Duration::from_millis(2380)), vec![Action::Peek]);
+
+        // ...or once another key is pressed
+        down(&mut r, "lshift", 3000);
+        up(&mut r, "lshift", 3080);
+        assert_eq!(down(&mut r, "a", 3100), vec![Action::Peek]);
+        up(&mut r, "a", 3150);
+
+        // Taps too far apart are two single taps
+        down(&mut r, "lshift", 4000);
+        up(&mut r, "lshift", 4050);
+        assert_eq!(down(&mut r, "lshift", 4500), vec![Action::Peek]);
+        assert!(up(&mut r, "lshift", 4550).is_empty());
+    }
+
+    #[test]
+    fn holds_fire_once_while_held() {
+        let mut r = recognizer(&[
+            ("hold(f13, 500)", Action::Peek),
+            ("release(f13)", Action::Toggle),
+        ]);
+        down(&mut r, "f13", 0);
+        assert_eq!(r.next_deadline(), Some(Duration::from_millis(500)));
+        assert!(r.tick(Duration::from_millis(499)).is_empty());
+        // Auto-repeat changes nothing
+        assert!(down(&mut r, "f13", 450).is_empty());
+        assert_eq!(r.tick(Duration::from_millis(500)), vec![Action::Peek]);
+        assert!(r.tick(Duration::from_millis(900)).is_empty());
+        assert_eq!(r.next_deadline(), None);
+        // A release after a hold isn't a lone release
+        assert!(up(&mut r, "f13", 1000).is_empty());
+
+        // Not with another key
+        down(&mut r, "f13", 2000);
+        down(&mut r, "a", 2100);
+        assert!(r.tick(Duration::from_millis(3000)).is_empty());
+        up(&mut r, "a", 3100);
+        up(&mut r, "f13", 3200);
+
+        // Released early: a lone release
+        down(&mut r, "f13", 4000);
+        assert_eq!(up(&mut r, "f13", 4300), vec![Action::Toggle]);
+    }
+
+    #[test]
+    fn held_peeks_end_with_the_key() {
+        let mut r = recognizer(&[("hold(ralt, 300)", Action::PeekWhileHeld)]);
+        down(&mut r, "ralt", 0);
+        assert_eq!(
+            r.tick(Duration::from_millis(300)),
+            vec![Action::PeekWhileHeld]
+        );
+        // Other keys while peeking don't end it
+        down(&mut r, "a", 400);
+        assert!(up(&mut r, "a", 450).is_empty());
+        assert_eq!(up(&mut r, "ralt", 2000), vec![Action::EndPeek]);
+
+        // Released before the hold fired: nothing to end
+        down(&mut r, "ralt", 3000);
+        assert!(up(&mut r, "ralt", 3100).is_empty());
+    }
+
+    #[test]
+    fn lone_modifier_release_ignores_duration() {
+        let mut r = recognizer(&[("release(ctrl)", Action::Show)]);
+        down(&mut r, "rctrl", 0);
+        assert_eq!(up(&mut r, "rctrl", 2000), vec![Action::Show]);
+        down(&mut r, "lctrl", 3000);
+        assert_eq!(up(&mut r, "lctrl", 3010), vec![Action::Show]);
+
+        // Ctrl+C isn't a lone Ctrl, whichever key comes up first
+        down(&mut r, "lctrl", 4000);
+        down(&mut r, "c", 4010);
+        assert!(up(&mut r, "lctrl", 4020).is_empty());
+        assert!(up(&mut r, "c", 4030).is_empty());
+
+        // Ctrl pressed while another key is held
+        down(&mut r, "a", 5000);
+        down(&mut r, "lctrl", 5010);
+        up(&mut r, "a", 5020);
+        assert!(up(&mut r, "lctrl", 5030).is_empty());
+    }
+
+    #[test]
+    fn gestures_are_strings_in_json() {
+        let binding: GestureBinding =
+            serde_json::from_str(r#"{"gesture": "double-tap(lshift)", "action": "show-now"}"#)
+                .unwrap();
+        let (gesture, action) = binding.parse().unwrap();
+        assert_eq!(gesture.kind, GestureKind::DoubleTap);
+        assert_eq!(action, Action::Peek);
+        assert!(serde_json::from_str::<Gesture>("\"wiggle(a)\"").is_err());
+    }
+}
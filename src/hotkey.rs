@@ -0,0 +1,938 @@
+//! Hotkey module.
+//!
+//! Platform-independent hotkey representation. Hotkey strings from the
+//! config (`ctrl+alt+l`) are parsed into modifiers and a neutral key, which
+//! each hotkey backend maps to its own key codes. A parsed hotkey displays
+//! as its canonical string, so `Ctrl + PgUp` comes back as `ctrl+pageup`.
+//! Backends report how registering each binding went as a `BindingStatus`.
+
+use crate::action::Action;
+use crate::config::HotkeyBinding;
+use std::fmt;
+use std::str::FromStr;
+
+/// Which key of a left/right modifier pair a hotkey needs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Either,
+    Left,
+    Right,
+}
+
+impl Side {
+    /// Checks whether the pressed keys of the pair satisfy this side.
+    pub fn accepts(self, left_down: bool, right_down: bool) -> bool {
+        match self {
+            Side::Either => left_down || right_down,
+            Side::Left => left_down,
+            Side::Right => right_down,
+        }
+    }
+}
+
+/// A modifier key pair.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Modifier {
+    Ctrl,
+    Alt,
+    Shift,
+    /// Windows key (Super on Linux).
+    Win,
+}
+
+/// Modifier keys of a hotkey; `None` if the modifier isn't part of it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Modifiers {
+    pub ctrl: Option<Side>,
+    pub alt: Option<Side>,
+    pub shift: Option<Side>,
+    pub win: Option<Side>,
+}
+
+impl Modifiers {
+    /// Returns the side required for a modifier.
+    pub fn get(&self, modifier: Modifier) -> Option<Side> {
+        match modifier {
+            Modifier::Ctrl => self.ctrl,
+            Modifier::Alt => self.alt,
+            Modifier::Shift => self.shift,
+            Modifier::Win => self.win,
+        }
+    }
+
+    fn set(&mut self, modifier: Modifier, side: Side) {
+        let slot = match modifier {
+            Modifier::Ctrl => &mut self.ctrl,
+            Modifier::Alt => &mut self.alt,
+            Modifier::Shift => &mut self.shift,
+            Modifier::Win => &mut self.win,
+        };
+        *slot = Some(side);
+    }
+
+    /// Returns the modifiers with either key of each pair accepted.
+    fn without_sides(&self) -> Modifiers {
+        let either = |side: Option<Side>| side.map(|_| Side::Either);
+        Modifiers {
+            ctrl: either(self.ctrl),
+            alt: either(self.alt),
+            shift: either(self.shift),
+            win: either(self.win),
+        }
+    }
+
+    /// Returns the modifiers that need a specific (left or right) key.
+    ///
+    /// Global hotkey APIs only know "some Ctrl is down"; backends check
+    /// these against the keyboard state when the hotkey fires.
+    pub fn sided(&self) -> Vec<(Modifier, Side)> {
+        MODIFIERS
+            .iter()
+            .filter_map(|&m| self.get(m).map(|side| (m, side)))
+            .filter(|&(_, side)| side != Side::Either)
+            .collect()
+    }
+}
+
+/// Modifiers in canonical order.
+const MODIFIERS: [Modifier; 4] = [
+    Modifier::Ctrl,
+    Modifier::Alt,
+    Modifier::Shift,
+    Modifier::Win,
+];
+
+/// A non-modifier key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    /// Latin letter (lower case), digit or punctuation of the main block
+    /// (`;=,-./\`[\]'`).
+    Char(char),
+    /// Function key F1-F24.
+    F(u8),
+    Space,
+    Enter,
+    Escape,
+    Tab,
+    Backspace,
+    Up,
+    Down,
+    Left,
+    Right,
+    Insert,
+    Delete,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    PrintScreen,
+    CapsLock,
+    ScrollLock,
+    Pause,
+    /// Numpad digit 0-9.
+    Numpad(u8),
+    NumpadAdd,
+    NumpadSubtract,
+    NumpadMultiply,
+    NumpadDivide,
+    NumpadDecimal,
+    VolumeUp,
+    VolumeDown,
+    VolumeMute,
+    MediaNext,
+    MediaPrev,
+    MediaPlayPause,
+    MediaStop,
+}
+
+/// Named keys; the first name of a key is its canonical one.
+const NAMED_KEYS: &[(&str, Key)] = &[
+    ("space", Key::Space),
+    ("enter", Key::Enter),
+    ("return", Key::Enter),
+    ("escape", Key::Escape),
+    ("esc", Key::Escape),
+    ("tab", Key::Tab),
+    ("backspace", Key::Backspace),
+    ("up", Key::Up),
+    ("down", Key::Down),
+    ("left", Key::Left),
+    ("right", Key::Right),
+    ("insert", Key::Insert),
+    ("ins", Key::Insert),
+    ("delete", Key::Delete),
+    ("del", Key::Delete),
+    ("home", Key::Home),
+    ("end", Key::End),
+    ("pageup", Key::PageUp),
+    ("pgup", Key::PageUp),
+    ("pagedown", Key::PageDown),
+    ("pgdn", Key::PageDown),
+    ("printscreen", Key::PrintScreen),
+    ("prtsc", Key::PrintScreen),
+    ("capslock", Key::CapsLock),
+    ("caps", Key::CapsLock),
+    ("scrolllock", Key::ScrollLock),
+    ("pause", Key::Pause),
+    ("numadd", Key::NumpadAdd),
+    ("numsubtract", Key::NumpadSubtract),
+    ("numsub", Key::NumpadSubtract),
+    ("nummultiply", Key::NumpadMultiply),
+    ("nummul", Key::NumpadMultiply),
+    ("numdivide", Key::NumpadDivide),
+    ("numdiv", Key::NumpadDivide),
+    ("numdecimal", Key::NumpadDecimal),
+    ("volumeup", Key::VolumeUp),
+    ("volumedown", Key::VolumeDown),
+    ("volumemute", Key::VolumeMute),
+    ("medianext", Key::MediaNext),
+    ("mediaprev", Key::MediaPrev),
+    ("mediaplaypause", Key::MediaPlayPause),
+    ("mediaplay", Key::MediaPlayPause),
+    ("mediastop", Key::MediaStop),
+];
+
+/// Punctuation keys by name. `+` separates tokens, so `=`/`+` is `equal`.
+const PUNCTUATION: &[(&str, char)] = &[
+    ("semicolon", ';'),
+    ("equal", '='),
+    ("comma", ','),
+    ("minus", '-'),
+    ("period", '.'),
+    ("slash", '/'),
+    ("grave", '`'),
+    ("leftbracket", '['),
+    ("backslash", '\\'),
+    ("rightbracket", ']'),
+    ("quote", '\''),
+];
+
+/// Modifier names; `l`/`r` prefixes pick a side.
+const MODIFIER_NAMES: &[(&str, Modifier)] = &[
+    ("ctrl", Modifier::Ctrl),
+    ("control", Modifier::Ctrl),
+    ("alt", Modifier::Alt),
+    ("shift", Modifier::Shift),
+    ("win", Modifier::Win),
+    ("super", Modifier::Win),
+];
+
+/// A key combination.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hotkey {
+    pub modifiers: Modifiers,
+    pub key: Key,
+}
+
+/// Error returned for a hotkey string that can't be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HotkeyError {
+    /// The string is empty.
+    Empty,
+    /// A token names neither a modifier nor a key.
+    UnknownToken(String),
+    /// The combination has modifiers only.
+    MissingKey,
+    /// A second non-modifier key; only one is allowed.
+    ExtraKey(String),
+}
+
+impl fmt::Display for HotkeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HotkeyError::Empty => write!(f, "empty hotkey"),
+            HotkeyError::UnknownToken(token) => write!(f, "unknown key {:?}", token),
+            HotkeyError::MissingKey => write!(f, "hotkey has modifiers only"),
+            HotkeyError::ExtraKey(token) => write!(f, "second key {:?} in hotkey", token),
+        }
+    }
+}
+
+impl std::error::Error for HotkeyError {}
+
+/// Parses a modifier name like `ctrl`, `lalt` or `rsuper`.
+pub(crate) fn parse_modifier(name: &str) -> Option<(Modifier, Side)> {
+    let lookup = |name: &str| {
+        MODIFIER_NAMES
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, m)| *m)
+    };
+    if let Some(modifier) = lookup(name) {
+        return Some((modifier, Side::Either));
+    }
+    let (side, rest) = match name.split_at_checked(1)? {
+        ("l", rest) => (Side::Left, rest),
+        ("r", rest) => (Side::Right, rest),
+        _ => return None,
+    };
+    lookup(rest).map(|modifier| (modifier, side))
+}
+
+/// Parses a key name like `l`, `f5`, `pgup`, `num7` or `comma`.
+pub(crate) fn parse_key(name: &str) -> Option<Key> {
+    if let Some((_, key)) = NAMED_KEYS.iter().find(|(n, _)| *n == name) {
+        return Some(*key);
+    }
+    if let Some((_, c)) = PUNCTUATION.iter().find(|(n, _)| *n == name) {
+        return Some(Key::Char(*c));
+    }
+
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        let is_punctuation = PUNCTUATION.iter().any(|(_, p)| *p == c);
+        return (c.is_ascii_lowercase() || c.is_ascii_digit() || is_punctuation)
+            .then_some(Key::Char(c));
+    }
+
+    if let Some(digit) = name.strip_prefix("num") {
+        let n: u8 = digit.parse().ok()?;
+        return (n <= 9 && digit.len() == 1).then_some(Key::Numpad(n));
+    }
+    let n: u8 = name.strip_prefix('f')?.parse().ok()?;
+    (1..=24).contains(&n).then_some(Key::F(n))
+}
+
+/// Parses a hotkey string like "ctrl+alt+l" into modifiers and a key.
+///
+/// Names are case-insensitive and may be surrounded by spaces.
+pub fn parse_hotkey(hotkey: &str) -> Result<Hotkey, HotkeyError> {
+    let lower = hotkey.trim().to_lowercase();
+    if lower.is_empty() {
+        return Err(HotkeyError::Empty);
+    }
+
+    let mut modifiers = Modifiers::default();
+    let mut key = None;
+    for part in lower.split('+') {
+        let token = part.trim();
+        if let Some((modifier, side)) = parse_modifier(token) {
+            modifiers.set(modifier, side);
+        } else if let Some(k) = parse_key(token) {
+            if key.is_some() {
+                return Err(HotkeyError::ExtraKey(token.to_string()));
+            }
+            key = Some(k);
+        } else {
+            return Err(HotkeyError::UnknownToken(token.to_string()));
+        }
+    }
+
+    key.map(|key| Hotkey { modifiers, key })
+        .ok_or(HotkeyError::MissingKey)
+}
+
+impl FromStr for Hotkey {
+    type Err = HotkeyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_hotkey(s)
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Key::Char(c) => match PUNCTUATION.iter().find(|(_, p)| p == c) {
+                Some((name, _)) => f.write_str(name),
+                None => write!(f, "{}", c),
+            },
+            Key::F(n) => write!(f, "f{}", n),
+            Key::Numpad(n) => write!(f, "num{}", n),
+            key => {
+                let (name, _) = NAMED_KEYS
+                    .iter()
+                    .find(|(_, k)| k == key)
+                    .expect("every named key is in the table");
+                f.write_str(name)
+            }
+        }
+    }
+}
+
+/// Returns the canonical name of a modifier, like `ctrl` or `ralt`.
+pub(crate) fn modifier_name(modifier: Modifier, side: Side) -> String {
+    let prefix = match side {
+        Side::Either => "",
+        Side::Left => "l",
+        Side::Right => "r",
+    };
+    let (name, _) = MODIFIER_NAMES
+        .iter()
+        .find(|(_, m)| *m == modifier)
+        .expect("every modifier has a name");
+    format!("{}{}", prefix, name)
+}
+
+impl fmt::Display for Hotkey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for modifier in MODIFIERS {
+            if let Some(side) = self.modifiers.get(modifier) {
+                write!(f, "{}+", modifier_name(modifier, side))?;
+            }
+        }
+        write!(f, "{}", self.key)
+    }
+}
+
+/// How many free alternatives are suggested for a taken hotkey.
+const ALTERNATIVES: usize = 3;
+
+/// Suggests combinations to use instead of a taken hotkey: the same key with
+/// one or two more modifiers.
+///
+/// `configured` are the combinations the config already binds, with any
+/// modifier sides; `is_free` asks the system whether a combination can be
+/// registered.
+pub fn suggest_alternatives(
+    hotkey: &Hotkey,
+    configured: &[Hotkey],
+    mut is_free: impl FnMut(&Hotkey) -> bool,
+) -> Vec<Hotkey> {
+    let missing: Vec<Modifier> = MODIFIERS
+        .into_iter()
+        .filter(|&m| hotkey.modifiers.get(m).is_none())
+        .collect();
+    let singles = missing.iter().map(|&m| vec![m]);
+    let pairs = missing
+        .iter()
+        .enumerate()
+        .flat_map(|(i, &a)| missing[i + 1..].iter().map(move |&b| vec![a, b]));
+
+    singles
+        .chain(pairs)
+        .map(|added| {
+            let mut candidate = *hotkey;
+            for modifier in added {
+                candidate.modifiers.set(modifier, Side::Either);
+            }
+            candidate
+        })
+        .filter(|candidate| {
+            !configured.iter().any(|c| {
+                c.key == candidate.key
+                    && c.modifiers.without_sides() == candidate.modifiers.without_sides()
+            })
+        })
+        .filter(|candidate| is_free(candidate))
+        .take(ALTERNATIVES)
+        .collect()
+}
+
+/// Outcome of registering a configured hotkey with the system.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Registration {
+    Registered,
+    /// Another application holds the combination. Holds free combinations
+    /// to use instead.
+    Taken(Vec<Hotkey>),
+    /// The keyboard has no such key (X11).
+    #[cfg_attr(windows, allow(dead_code))]
+    NoKey,
+    /// The hotkey or the action can't be parsed; holds the parse error.
+    Invalid(String),
+    /// A reload changed the hotkey to one that failed; the `previous`
+    /// hotkey of the binding stays registered instead.
+    Kept {
+        failure: Box<Registration>,
+        previous: String,
+    },
+}
+
+impl fmt::Display for Registration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Registration::Registered => f.write_str("registered"),
+            Registration::Taken(alternatives) if alternatives.is_empty() => {
+                f.write_str("taken by another application")
+            }
+            Registration::Taken(alternatives) => {
+                let alternatives: Vec<String> =
+                    alternatives.iter().map(ToString::to_string).collect();
+                write!(
+                    f,
+                    "taken by another application, try {}",
+                    alternatives.join(", ")
+                )
+            }
+            Registration::NoKey => f.write_str("no such key on this keyboard"),
+            Registration::Invalid(e) => write!(f, "{}", e),
+            Registration::Kept { failure, previous } => {
+                write!(f, "{}; kept {}", failure, previous.trim())
+            }
+        }
+    }
+}
+
+/// Registration result of one configured hotkey binding.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BindingStatus {
+    /// The hotkey as written in the config.
+    pub keys: String,
+    /// The action as written in the config.
+    pub action: String,
+    pub registration: Registration,
+}
+
+impl BindingStatus {
+    /// Returns whether the hotkey works.
+    pub fn is_registered(&self) -> bool {
+        self.registration == Registration::Registered
+    }
+
+    /// Returns the hotkey that works for this binding: its own, or the one
+    /// kept after a failed reload.
+    pub fn active_keys(&self) -> Option<&str> {
+        match self.registration {
+            Registration::Registered => Some(&self.keys),
+            Registration::Kept { ref previous, .. } => Some(previous),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for BindingStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}): {}",
+            self.keys.trim(),
+            self.action,
+            self.registration
+        )
+    }
+}
+
+/// Parses the hotkeys of the bindings, skipping invalid ones.
+pub fn configured_hotkeys(bindings: &[HotkeyBinding]) -> Vec<Hotkey> {
+    bindings
+        .iter()
+        .filter_map(|b| parse_hotkey(&b.keys).ok())
+        .collect()
+}
+
+/// Returns whether two hotkey strings mean the same combination.
+fn same_keys(a: &str, b: &str) -> bool {
+    match (parse_hotkey(a), parse_hotkey(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a.trim().eq_ignore_ascii_case(b.trim()),
+    }
+}
+
+/// Returns whether two action strings mean the same action.
+fn same_action(a: &str, b: &str) -> bool {
+    match (a.parse::<Action>(), b.parse::<Action>()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a.trim().eq_ignore_ascii_case(b.trim()),
+    }
+}
+
+/// Registers and unregisters single bindings with the system, for
+/// `reload_bindings`.
+pub trait HotkeyRegistry {
+    fn register(&mut self, binding: &HotkeyBinding) -> Registration;
+    fn unregister(&mut self, binding: &HotkeyBinding);
+}
+
+/// Applies changed bindings on top of the registered ones (`current`, as
+/// returned by the backend). Unchanged bindings stay registered; the other
+/// old ones are unregistered, then the new ones are registered. A new
+/// binding that fails gets the old hotkey of its action back, unless a new
+/// binding took it. Returns the status of each new binding.
+pub fn reload_bindings(
+    current: &[BindingStatus],
+    bindings: &[HotkeyBinding],
+    registry: &mut impl HotkeyRegistry,
+) -> Vec<BindingStatus> {
+    let mut old: Vec<Option<HotkeyBinding>> = current
+        .iter()
+        .map(|status| {
+            status.active_keys().map(|keys| HotkeyBinding {
+                keys: keys.to_string(),
+                action: status.action.clone(),
+            })
+        })
+        .collect();
+
+    // Unchanged bindings keep their registration
+    let unchanged: Vec<bool> = bindings
+        .iter()
+        .map(|binding| {
+            let found = old.iter().position(|o| {
+                o.as_ref().is_some_and(|o| {
+                    same_action(&o.action, &binding.action) && same_keys(&o.keys, &binding.keys)
+                })
+            });
+            found.map(|i| old[i] = None).is_some()
+        })
+        .collect();
+
+    let removed: Vec<HotkeyBinding> = old.into_iter().flatten().collect();
+    for binding in &removed {
+        registry.unregister(binding);
+    }
+
+    let mut status: Vec<BindingStatus> = bindings
+        .iter()
+        .zip(&unchanged)
+        .map(|(binding, &unchanged)| BindingStatus {
+            keys: binding.keys.clone(),
+            action: binding.action.clone(),
+            registration: if unchanged {
+                Registration::Registered
+            } else {
+                registry.register(binding)
+            },
+        })
+        .collect();
+
+    // Roll failed ones back once every new binding had its chance
+    let mut restored = vec![false; removed.len()];
+    for i in 0..status.len() {
+        if status[i].is_registered() {
+            continue;
+        }
+        let taken = |keys: &str| {
+            status
+                .iter()
+                .any(|s| s.is_registered() && same_keys(&s.keys, keys))
+        };
+        let previous = (0..removed.len()).find(|&j| {
+            !restored[j]
+                && same_action(&removed[j].action, &status[i].action)
+                && !taken(&removed[j].keys)
+        });
+        let Some(j) = previous else {
+            continue;
+        };
+        if registry.register(&removed[j]) == Registration::Registered {
+            restored[j] = true;
+            log::warn!(
+                "Kept {} hotkey {} instead of {}",
+                removed[j].action,
+                removed[j].keys.trim(),
+                status[i].keys.trim()
+            );
+            let failure = status[i].registration.clone();
+            status[i].registration = Registration::Kept {
+                failure: Box::new(failure),
+                previous: removed[j].keys.clone(),
+            };
+        }
+    }
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hotkeys_are_parsed() {
+        assert_eq!(
+            parse_hotkey("Ctrl+Alt+L"),
+            Ok(Hotkey {
+                modifiers: Modifiers {
+                    ctrl: Some(Side::Either),
+                    alt: Some(Side::Either),
+                    ..Default::default()
+                },
+                key: Key::Char('l'),
+            })
+        );
+        let hotkey = parse_hotkey("control + shift + f12").unwrap();
+        assert!(hotkey.modifiers.ctrl.is_some() && hotkey.modifiers.shift.is_some());
+        assert_eq!(hotkey.key, Key::F(12));
+        assert_eq!(parse_hotkey("alt+space").unwrap().key, Key::Space);
+        assert_eq!(parse_hotkey("7").unwrap().key, Key::Char('7'));
+    }
+
+    #[test]
+    fn extended_keys_are_parsed() {
+        let key = |s: &str| parse_hotkey(s).map(|h| h.key);
+        assert_eq!(key("ctrl+alt+pgup"), Ok(Key::PageUp));
+        assert_eq!(key("win+left"), Ok(Key::Left));
+        assert_eq!(key("f24"), Ok(Key::F(24)));
+        assert_eq!(key("ctrl+num7"), Ok(Key::Numpad(7)));
+        assert_eq!(key("numadd"), Ok(Key::NumpadAdd));
+        assert_eq!(key("ctrl+;"), Ok(Key::Char(';')));
+        assert_eq!(key("ctrl+comma"), Ok(Key::Char(',')));
+        assert_eq!(key("alt+-"), Ok(Key::Char('-')));
+        assert_eq!(key("volumemute"), Ok(Key::VolumeMute));
+        assert_eq!(key("shift+Del"), Ok(Key::Delete));
+        assert_eq!(key("shift+capslock"), Ok(Key::CapsLock));
+    }
+
+    #[test]
+    fn sided_modifiers_are_parsed() {
+        let hotkey = parse_hotkey("rctrl+lalt+super+k").unwrap();
+        assert_eq!(hotkey.modifiers.ctrl, Some(Side::Right));
+        assert_eq!(hotkey.modifiers.alt, Some(Side::Left));
+        assert_eq!(hotkey.modifiers.win, Some(Side::Either));
+        assert_eq!(hotkey.modifiers.shift, None);
+        assert_eq!(
+            hotkey.modifiers.sided(),
+            vec![(Modifier::Ctrl, Side::Right), (Modifier::Alt, Side::Left)]
+        );
+
+        assert!(Side::Either.accepts(false, true));
+        assert!(!Side::Left.accepts(false, true));
+        assert!(Side::Right.accepts(false, true));
+    }
+
+    #[test]
+    fn errors_name_the_bad_token() {
+        assert_eq!(parse_hotkey(""), Err(HotkeyError::Empty));
+        assert_eq!(parse_hotkey("ctrl+alt"), Err(HotkeyError::MissingKey));
+        assert_eq!(
+            parse_hotkey("ctrl+f25"),
+            Err(HotkeyError::UnknownToken("f25".to_string()))
+        );
+        assert_eq!(
+            parse_hotkey("ctrl+hyper+l"),
+            Err(HotkeyError::UnknownToken("hyper".to_string()))
+        );
+        assert_eq!(
+            parse_hotkey("ctrl++"),
+            Err(HotkeyError::UnknownToken(String::new()))
+        );
+        assert_eq!(
+            parse_hotkey("ctrl+a+b"),
+            Err(HotkeyError::ExtraKey("b".to_string()))
+        );
+        assert_eq!(
+            HotkeyError::UnknownToken("pgupp".to_string()).to_string(),
+            "unknown key \"pgupp\""
+        );
+    }
+
+    #[test]
+    fn display_gives_the_canonical_string() {
+        for (input, canonical) in [
+            ("Ctrl + Alt + L", "ctrl+alt+l"),
+            ("shift+control+PgUp", "ctrl+shift+pageup"),
+            ("super+rctrl+return", "rctrl+win+enter"),
+            ("lshift+ralt+;", "ralt+lshift+semicolon"),
+            ("num0", "num0"),
+            ("ctrl+=", "ctrl+equal"),
+            ("f13", "f13"),
+            ("mediaplay", "mediaplaypause"),
+        ] {
+            let hotkey = parse_hotkey(input).unwrap();
+            assert_eq!(hotkey.to_string(), canonical);
+            assert_eq!(canonical.parse(), Ok(hotkey));
+        }
+    }
+
+    fn hotkeys(list: &[&str]) -> Vec<Hotkey> {
+        list.iter().map(|h| parse_hotkey(h).unwrap()).collect()
+    }
+
+    #[test]
+    fn alternatives_add_modifiers() {
+        let taken = parse_hotkey("ctrl+alt+l").unwrap();
+        assert_eq!(
+            suggest_alternatives(&taken, &[], |_| true),
+            hotkeys(&["ctrl+alt+shift+l", "ctrl+alt+win+l", "ctrl+alt+shift+win+l"])
+        );
+
+        // Configured and occupied combinations are skipped
+        let configured = hotkeys(&["ctrl+alt+rshift+l"]);
+        let occupied = hotkeys(&["ctrl+alt+win+l"]);
+        assert_eq!(
+            suggest_alternatives(&taken, &configured, |h| !occupied.contains(h)),
+            hotkeys(&["ctrl+alt+shift+win+l"])
+        );
+
+        let f1 = parse_hotkey("f1").unwrap();
+        assert_eq!(
+            suggest_alternatives(&f1, &[], |_| true),
+            hotkeys(&["ctrl+f1", "alt+f1", "shift+f1"])
+        );
+        assert!(suggest_alternatives(&f1, &[], |_| false).is_empty());
+    }
+
+    #[test]
+    fn binding_status_is_described() {
+        let status = |keys: &str, registration| BindingStatus {
+            keys: keys.to_string(),
+            action: "toggle".to_string(),
+            registration,
+        };
+        assert_eq!(
+            status("ctrl+alt+l", Registration::Registered).to_string(),
+            "ctrl+alt+l (toggle): registered"
+        );
+        assert_eq!(
+            status(
+                "ctrl+alt+l",
+                Registration::Taken(hotkeys(&["ctrl+alt+shift+l", "ctrl+alt+win+l"]))
+            )
+            .to_string(),
+            "ctrl+alt+l (toggle): taken by another application, try ctrl+alt+shift+l, ctrl+alt+win+l"
+        );
+        assert_eq!(
+            status(
+                "ctrl+pgupp",
+                Registration::Invalid(HotkeyError::UnknownToken("pgupp".to_string()).to_string())
+            )
+            .to_string(),
+            "ctrl+pgupp (toggle): unknown key \"pgupp\""
+        );
+        assert!(!status("f13", Registration::NoKey).is_registered());
+    }
+
+    /// Registry with combinations held by other applications, recording
+    /// every call.
+    #[derive(Default)]
+    struct FakeRegistry {
+        occupied: Vec<&'static str>,
+        calls: Vec<String>,
+    }
+
+    impl HotkeyRegistry for FakeRegistry {
+        fn register(&mut self, binding: &HotkeyBinding) -> Registration {
+            self.calls.push(format!("+{}", binding.keys));
+            if self
+                .occupied
+                .iter()
+                .any(|&keys| same_keys(keys, &binding.keys))
+            {
+                Registration::Taken(Vec::new())
+            } else {
+                Registration::Registered
+            }
+        }
+
+        fn unregister(&mut self, binding: &HotkeyBinding) {
+            self.calls.push(format!("-{}", binding.keys));
+        }
+    }
+
+    fn binding(keys: &str, action: Action) -> HotkeyBinding {
+        HotkeyBinding {
+            keys: keys.to_string(),
+            action: action.to_string(),
+        }
+    }
+
+    fn registered(bindings: &[HotkeyBinding]) -> Vec<BindingStatus> {
+        bindings
+            .iter()
+            .map(|b| BindingStatus {
+                keys: b.keys.clone(),
+                action: b.action.clone(),
+                registration: Registration::Registered,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reload_only_touches_changed_bindings() {
+        let current = registered(&[
+            binding("ctrl+alt+l", Action::Toggle),
+            binding("ctrl+alt+q", Action::Exit),
+            binding("ctrl+alt+m", Action::MuteSound),
+        ]);
+        let mut registry = FakeRegistry::default();
+        let status = reload_bindings(
+            &current,
+            &[
+                binding("Ctrl+Alt+L", Action::Toggle),
+                binding("ctrl+alt+e", Action::Exit),
+                binding("ctrl+alt+s", Action::Peek),
+            ],
+            &mut registry,
+        );
+        assert!(status.iter().all(BindingStatus::is_registered));
+        assert_eq!(
+            registry.calls,
+            ["-ctrl+alt+q", "-ctrl+alt+m", "+ctrl+alt+e", "+ctrl+alt+s"]
+        );
+
+        // Swapping two hotkeys frees both before registering either
+        let current = registered(&[
+            binding("ctrl+alt+l", Action::Toggle),
+            binding("ctrl+alt+q", Action::Exit),
+        ]);
+        let mut registry = FakeRegistry::default();
+        let status = reload_bindings(
+            &current,
+            &[
+                binding("ctrl+alt+q", Action::Toggle),
+                binding("ctrl+alt+l", Action::Exit),
+            ],
+            &mut registry,
+        );
+        assert!(status.iter().all(BindingStatus::is_registered));
+        assert_eq!(
+            registry.calls,
+            ["-ctrl+alt+l", "-ctrl+alt+q", "+ctrl+alt+q", "+ctrl+alt+l"]
+        );
+    }
+
+    #[test]
+    fn failed_reload_keeps_the_previous_hotkey() {
+        let current = registered(&[
+            binding("ctrl+alt+l", Action::Toggle),
+            binding("ctrl+alt+q", Action::Exit),
+        ]);
+        let mut registry = FakeRegistry {
+            occupied: vec!["ctrl+alt+t"],
+            ..Default::default()
+        };
+        let status = reload_bindings(
+            &current,
+            &[
+                binding("ctrl+alt+t", Action::Toggle),
+                binding("ctrl+alt+q", Action::Exit),
+            ],
+            &mut registry,
+        );
+        assert_eq!(
+            status[0].registration,
+            Registration::Kept {
+                failure: Box::new(Registration::Taken(Vec::new())),
+                previous: "ctrl+alt+l".to_string(),
+            }
+        );
+        assert_eq!(status[0].active_keys(), Some("ctrl+alt+l"));
+        assert_eq!(
+            status[0].to_string(),
+            "ctrl+alt+t (toggle): taken by another application; kept ctrl+alt+l"
+        );
+        assert!(status[1].is_registered());
+        assert_eq!(
+            registry.calls,
+            ["-ctrl+alt+l", "+ctrl+alt+t", "+ctrl+alt+l"]
+        );
+
+        // A kept hotkey stays active through the next reload
+        let mut registry = FakeRegistry::default();
+        let status = reload_bindings(
+            &status,
+            &[binding("ctrl+alt+l", Action::Toggle)],
+            &mut registry,
+        );
+        assert!(status[0].is_registered());
+        assert_eq!(registry.calls, ["-ctrl+alt+q"]);
+
+        // No rollback to a hotkey another binding took over
+        let current = registered(&[binding("ctrl+alt+l", Action::Toggle)]);
+        let mut registry = FakeRegistry {
+            occupied: vec!["ctrl+alt+t"],
+            ..Default::default()
+        };
+        let status = reload_bindings(
+            &current,
+            &[
+                binding("ctrl+alt+t", Action::Toggle),
+                binding("ctrl+alt+l", Action::Exit),
+            ],
+            &mut registry,
+        );
+        assert_eq!(status[0].registration, Registration::Taken(Vec::new()));
+        assert_eq!(status[0].active_keys(), None);
+        assert!(status[1].is_registered());
+    }
+}
@@ -0,0 +1,375 @@
+//! Keyboard layout module.
+//!
+//! Platform-independent layout types shared by the layout sources and the
+//! indicator controller.
+
+use crate::config::AppConfig;
+use parking_lot::Mutex;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Layout information.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayoutInfo {
+    /// Layout name (EN, RU, or hex code).
+    pub name: String,
+    /// Whether this is Russian layout.
+    pub is_russian: bool,
+}
+
+/// A keyboard layout installed on the system.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstalledLayout {
+    /// Code `set-layout` switches to exactly this layout by (e.g. `F0020409`,
+    /// `ru(phonetic)`).
+    pub code: String,
+    /// Full name shown in the tray menu.
+    pub label: String,
+    /// Whether it is the active layout.
+    pub active: bool,
+}
+
+/// Layout a layout source is asked to switch to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LayoutSwitch {
+    /// The next layout in the system's list.
+    Next,
+    /// A layout by code (e.g. `ru`, `en-US` or `00000409`).
+    To(String),
+}
+
+/// Language part of a layout code: `en` of `en-US`, `ru` of `ru(phonetic)`.
+fn code_language(code: &str) -> String {
+    code.split(['-', '_', '('])
+        .next()
+        .unwrap_or(code)
+        .trim()
+        .to_lowercase()
+}
+
+/// Finds the installed layout a `LayoutSwitch::To` code names. Each layout
+/// is given by the codes it answers to, such as its locale name and KLID on
+/// Windows (`ru-RU`, `00000419`) or its XKB code (`ru(phonetic)`).
+///
+/// An exact code wins; otherwise the first layout of the same language is
+/// taken, so `en` finds `en-US` and `ru-RU` finds `ru(phonetic)`.
+pub fn find_layout<L, S>(code: &str, layouts: &[L]) -> Option<usize>
+where
+    L: AsRef<[S]>,
+    S: AsRef<str>,
+{
+    let code = code.trim();
+    let exact = layouts.iter().position(|codes| {
+        codes
+            .as_ref()
+            .iter()
+            .any(|c| c.as_ref().eq_ignore_ascii_case(code))
+    });
+    let language = code_language(code);
+    exact.or_else(|| {
+        layouts.iter().position(|codes| {
+            codes
+                .as_ref()
+                .iter()
+                .any(|c| code_language(c.as_ref()) == language)
+        })
+    })
+}
+
+impl LayoutInfo {
+    /// Builds layout information from an XKB layout code such as `us`, `ru`
+    /// or `de(nodeadkeys)`.
+    ///
+    /// English layouts are shown as `EN` like on Windows; other layouts use
+    /// the upper-cased code without the variant.
+    #[cfg_attr(not(any(target_os = "linux", test)), allow(dead_code))]
+    pub fn from_xkb(code: &str) -> Self {
+        let base = code.split('(').next().unwrap_or(code).trim();
+        match base {
+            "us" | "gb" | "au" => Self {
+                name: "EN".to_string(),
+                is_russian: false,
+            },
+            "ru" => Self {
+                name: "RU".to_string(),
+                is_russian: true,
+            },
+            _ => Self {
+                name: base.to_uppercase(),
+                is_russian: false,
+            },
+        }
+    }
+}
+
+/// Languages of XKB layout descriptions mapped to layout codes.
+#[cfg_attr(not(any(target_os = "linux", test)), allow(dead_code))]
+const XKB_LANGUAGES: &[(&str, &str)] = &[
+    ("English", "us"),
+    ("Russian", "ru"),
+    ("Ukrainian", "ua"),
+    ("Belarusian", "by"),
+    ("Kazakh", "kz"),
+    ("German", "de"),
+    ("French", "fr"),
+    ("Spanish", "es"),
+    ("Italian", "it"),
+    ("Portuguese", "pt"),
+    ("Polish", "pl"),
+    ("Czech", "cz"),
+    ("Hungarian", "hu"),
+    ("Dutch", "nl"),
+    ("Swedish", "se"),
+    ("Norwegian", "no"),
+    ("Danish", "dk"),
+    ("Finnish", "fi"),
+    ("Turkish", "tr"),
+    ("Greek", "gr"),
+    ("Hebrew", "il"),
+    ("Arabic", "ara"),
+    ("Georgian", "ge"),
+    ("Armenian", "am"),
+    ("Japanese", "jp"),
+    ("Korean", "kr"),
+    ("Chinese", "cn"),
+];
+
+impl LayoutInfo {
+    /// Builds layout information from an XKB layout description such as
+    /// `English (US)` or `Russian (phonetic)`, as Wayland compositors report
+    /// layouts.
+    ///
+    /// Unknown languages use the first two letters of the description.
+    #[cfg_attr(not(any(target_os = "linux", test)), allow(dead_code))]
+    pub fn from_xkb_description(description: &str) -> Self {
+        let language = description
+            .split(['(', ','])
+            .next()
+            .unwrap_or(description)
+            .trim();
+        match XKB_LANGUAGES.iter().find(|(name, _)| *name == language) {
+            Some((_, code)) => Self::from_xkb(code),
+            None => Self {
+                name: language.chars().take(2).collect::<String>().to_uppercase(),
+                is_russian: false,
+            },
+        }
+    }
+}
+
+/// Describes an XKB layout code for people: `ru(phonetic)` is
+/// `Russian (phonetic)`. Codes of unknown languages are kept as they are.
+#[cfg_attr(not(any(target_os = "linux", test)), allow(dead_code))]
+pub fn describe_xkb(code: &str) -> String {
+    let (base, variant) = match code.split_once('(') {
+        Some((base, variant)) => (base.trim(), Some(variant.trim_end_matches(')'))),
+        None => (code.trim(), None),
+    };
+    match (XKB_LANGUAGES.iter().find(|(_, c)| *c == base), variant) {
+        (Some((language, _)), Some(variant)) => format!("{} ({})", language, variant),
+        (Some((language, _)), None) => language.to_string(),
+        (None, _) => code.to_string(),
+    }
+}
+
+/// A detected layout change.
+#[derive(Debug, Clone)]
+pub struct LayoutEvent {
+    /// The new layout.
+    pub layout: LayoutInfo,
+    /// When the trigger (modifier key release or focus change) happened.
+    pub triggered_at: Instant,
+    /// When the change was detected, right before the callback is invoked.
+    pub detected_at: Instant,
+}
+
+impl LayoutEvent {
+    /// Time from the trigger to the detection of the change.
+    pub fn latency(&self) -> Duration {
+        self.detected_at
+            .saturating_duration_since(self.triggered_at)
+    }
+}
+
+/// Callback type for layout changes.
+pub type LayoutCallback = Arc<dyn Fn(LayoutEvent) + Send + Sync>;
+
+/// State of a started reporter.
+struct ReporterState {
+    callback: LayoutCallback,
+    last_layout: String,
+}
+
+/// Delivers layout changes from a layout source's event thread to the
+/// callback, dropping repeats of the last reported layout.
+///
+/// Clones share the same state, so the source keeps one copy for `start` and
+/// `stop` and hands another to its thread.
+#[cfg_attr(not(any(target_os = "linux", test)), allow(dead_code))]
+#[derive(Clone, Default)]
+pub struct LayoutReporter {
+    state: Arc<Mutex<Option<ReporterState>>>,
+}
+
+#[cfg_attr(not(any(target_os = "linux", test)), allow(dead_code))]
+impl LayoutReporter {
+    /// Starts reporting to `callback`; `initial` is the layout already shown.
+    pub fn start(&self, callback: LayoutCallback, initial: &LayoutInfo) {
+        *self.state.lock() = Some(ReporterState {
+            callback,
+            last_layout: initial.name.clone(),
+        });
+    }
+
+    /// Stops reporting; later changes are ignored.
+    pub fn stop(&self) {
+        *self.state.lock() = None;
+    }
+
+    /// Name of the last reported layout, if started.
+    pub fn last_layout(&self) -> Option<String> {
+        self.state.lock().as_ref().map(|s| s.last_layout.clone())
+    }
+
+    /// Calls the callback if the layout differs from the last reported one.
+    pub fn report(&self, layout: LayoutInfo, triggered_at: Instant) {
+        // Get callback outside of lock to avoid holding lock during callback
+        let callback = {
+            let mut state = self.state.lock();
+            match *state {
+                Some(ref mut s) if s.last_layout != layout.name => {
+                    log::debug!("Layout: {} -> {}", s.last_layout, layout.name);
+                    s.last_layout = layout.name.clone();
+                    Some(s.callback.clone())
+                }
+                _ => None,
+            }
+        };
+
+        if let Some(cb) = callback {
+            cb(LayoutEvent {
+                layout,
+                triggered_at,
+                detected_at: Instant::now(),
+            });
+        }
+    }
+}
+
+/// Timing parameters for layout change detection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HookTimings {
+    /// Minimum interval between two debounced layout checks.
+    pub debounce: Duration,
+    /// Delay after a modifier key release before the layout is read.
+    pub settle_delay: Duration,
+    /// How long a pending check keeps re-reading an unchanged layout.
+    pub update_delay: Duration,
+}
+
+impl HookTimings {
+    /// Builds the timings from the application config.
+    pub fn from_config(config: &AppConfig) -> Self {
+        Self {
+            debounce: Duration::from_millis(config.debounce_ms as u64),
+            settle_delay: Duration::from_millis(config.settle_delay_ms as u64),
+            update_delay: Duration::from_millis(config.update_delay_ms as u64),
+        }
+    }
+}
+
+impl Default for HookTimings {
+    fn default() -> Self {
+        Self::from_config(&AppConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn xkb_codes_map_to_short_names() {
+        assert_eq!(LayoutInfo::from_xkb("us").name, "EN");
+        assert_eq!(LayoutInfo::from_xkb("gb(extd)").name, "EN");
+        let ru = LayoutInfo::from_xkb("ru(phonetic)");
+        assert_eq!(ru.name, "RU");
+        assert!(ru.is_russian);
+        assert_eq!(LayoutInfo::from_xkb("de").name, "DE");
+        assert!(!LayoutInfo::from_xkb("ua").is_russian);
+    }
+
+    #[test]
+    fn xkb_descriptions_map_to_short_names() {
+        assert_eq!(LayoutInfo::from_xkb_description("English (US)").name, "EN");
+        assert_eq!(
+            LayoutInfo::from_xkb_description("English (US, intl., with dead keys)").name,
+            "EN"
+        );
+        let ru = LayoutInfo::from_xkb_description("Russian (phonetic)");
+        assert_eq!(ru.name, "RU");
+        assert!(ru.is_russian);
+        assert_eq!(LayoutInfo::from_xkb_description("German").name, "DE");
+        assert_eq!(LayoutInfo::from_xkb_description("Mongolian").name, "MO");
+    }
+
+    #[test]
+    fn xkb_codes_are_described() {
+        assert_eq!(describe_xkb("us"), "English");
+        assert_eq!(describe_xkb("ru(phonetic)"), "Russian (phonetic)");
+        assert_eq!(describe_xkb("mn"), "mn");
+    }
+
+    #[test]
+    fn layouts_are_found_by_code() {
+        let windows = [
+            ["en-GB", "00000809"],
+            ["en-US", "00000409"],
+            ["ru-RU", "00000419"],
+        ];
+        assert_eq!(find_layout("en-US", &windows), Some(1));
+        assert_eq!(find_layout(" RU-ru ", &windows), Some(2));
+        assert_eq!(find_layout("00000409", &windows), Some(1));
+        assert_eq!(find_layout("en", &windows), Some(0));
+        assert_eq!(find_layout("de-DE", &windows), None);
+        assert_eq!(find_layout("00000407", &windows), None);
+
+        // Variants of one locale are told apart by KLID or HKL
+        let variants = [
+            ["en-US", "00000409", "04090409"],
+            ["en-US", "00010409", "F0020409"],
+        ];
+        assert_eq!(find_layout("F0020409", &variants), Some(1));
+        assert_eq!(find_layout("00010409", &variants), Some(1));
+        assert_eq!(find_layout("en-US", &variants), Some(0));
+
+        let xkb = [vec!["us", "en"], vec!["ru(phonetic)", "ru"]];
+        assert_eq!(find_layout("ru", &xkb), Some(1));
+        assert_eq!(find_layout("ru-RU", &xkb), Some(1));
+        assert_eq!(find_layout("en-US", &xkb), Some(0));
+        assert_eq!(find_layout("ru(phonetic)", &xkb), Some(1));
+        assert_eq!(find_layout("", &xkb), None);
+    }
+
+    #[test]
+    fn reporter_drops_repeats_and_stops() {
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let reporter = LayoutReporter::default();
+        assert_eq!(reporter.last_layout(), None);
+
+        let sink = reported.clone();
+        reporter.start(
+            Arc::new(move |event: LayoutEvent| sink.lock().push(event.layout.name)),
+            &LayoutInfo::from_xkb("us"),
+        );
+        let now = Instant::now();
+        reporter.report(LayoutInfo::from_xkb("us"), now);
+        reporter.report(LayoutInfo::from_xkb("ru"), now);
+        reporter.clone().report(LayoutInfo::from_xkb("ru"), now);
+        assert_eq!(reporter.last_layout().as_deref(), Some("RU"));
+
+        reporter.stop();
+        reporter.report(LayoutInfo::from_xkb("us"), now);
+        assert_eq!(*reported.lock(), vec!["RU".to_string()]);
+    }
+}
@@ -0,0 +1,76 @@
+//! Logging module.
+//!
+//! Writes the log to stderr and to `langtip.log` in the log folder, so
+//! release builds without a console leave one behind too.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+/// Log file name.
+const LOG_FILE: &str = "langtip.log";
+
+/// A log file larger than this is moved aside at startup (bytes).
+const MAX_LOG_SIZE: u64 = 1024 * 1024;
+
+/// Returns the folder with the log file: `%LOCALAPPDATA%\LangTip` on Windows,
+/// `$XDG_STATE_HOME/langtip` (`~/.local/state/langtip`) on Linux.
+pub fn log_dir() -> Option<PathBuf> {
+    if cfg!(windows) {
+        dirs::data_local_dir().map(|dir| dir.join("LangTip"))
+    } else {
+        dirs::state_dir()
+            .or_else(dirs::data_local_dir)
+            .map(|dir| dir.join("langtip"))
+    }
+}
+
+/// Opens the log file for appending. A file over `MAX_LOG_SIZE` becomes
+/// `langtip.log.old`, replacing the previous one.
+fn open_log_file() -> io::Result<File> {
+    let dir = log_dir().ok_or_else(|| io::Error::other("no log folder"))?;
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(LOG_FILE);
+    if fs::metadata(&path).is_ok_and(|m| m.len() > MAX_LOG_SIZE) {
+        // Fails while another instance has it open on Windows; keep appending
+        let _ = fs::rename(&path, dir.join(format!("{}.old", LOG_FILE)));
+    }
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+/// Copies log output to stderr and the log file.
+struct Tee {
+    file: File,
+}
+
+impl Write for Tee {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // A missing console must not lose the file copy
+        let _ = io::stderr().write_all(buf);
+        self.file.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let _ = io::stderr().flush();
+        self.file.flush()
+    }
+}
+
+/// Sets up logging at `info` level unless `RUST_LOG` says otherwise.
+pub fn init() {
+    let mut builder =
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"));
+    let file_error = match open_log_file() {
+        Ok(file) => {
+            builder.target(env_logger::Target::Pipe(Box::new(Tee { file })));
+            None
+        }
+        Err(e) => Some(e),
+    };
+    builder.init();
+
+    if let Some(e) = file_error {
+        log::warn!("Logging to stderr only, no log file: {}", e);
+    }
+}
@@ -0,0 +1,329 @@
+//! Tray menu module.
+//!
+//! The settings part of the tray menu, built from the config: checkable
+//! submenus for positions, monitors, opacity, profile and layout, and the
+//! config file entries. Tray backends render the entries and dispatch the
+//! action of the clicked one; the application saves the change to the config
+//! file and applies it.
+
+use crate::action::Action;
+use crate::config::{AppConfig, MonitorPolicy};
+use crate::indicator::Position;
+use crate::layout::InstalledLayout;
+
+/// Opacity presets offered in the menu (%).
+pub const OPACITY_PRESETS: [u32; 5] = [100, 80, 60, 40, 20];
+
+/// Check state of a menu entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Toggle {
+    /// A checkbox of its own.
+    Check(bool),
+    /// One of a group of choices, checked if it is the chosen one.
+    Radio(bool),
+}
+
+impl Toggle {
+    /// Returns whether the entry is checked.
+    pub fn is_checked(self) -> bool {
+        match self {
+            Toggle::Check(checked) | Toggle::Radio(checked) => checked,
+        }
+    }
+}
+
+/// An entry of the tray menu.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MenuEntry {
+    /// Runs `action` when clicked.
+    Item {
+        label: String,
+        action: Action,
+        toggle: Option<Toggle>,
+    },
+    /// Opens the nested `entries`.
+    Submenu {
+        label: String,
+        entries: Vec<MenuEntry>,
+    },
+    Separator,
+}
+
+impl MenuEntry {
+    fn item(label: impl Into<String>, action: Action, toggle: Option<Toggle>) -> Self {
+        MenuEntry::Item {
+            label: label.into(),
+            action,
+            toggle,
+        }
+    }
+
+    fn submenu(label: &str, entries: Vec<MenuEntry>) -> Self {
+        MenuEntry::Submenu {
+            label: label.to_string(),
+            entries,
+        }
+    }
+}
+
+/// Builds the settings entries for `config`.
+///
+/// `profile` is the active profile and `profiles` the ones to choose from;
+/// `layouts` are the installed keyboard layouts, left out if empty.
+pub fn settings_menu(
+    config: &AppConfig,
+    profile: &str,
+    profiles: &[String],
+    layouts: &[InstalledLayout],
+) -> Vec<MenuEntry> {
+    let positions = Position::ALL
+        .into_iter()
+        .map(|position| {
+            MenuEntry::item(
+                position.label(),
+                Action::TogglePosition(position),
+                Some(Toggle::Check(config.positions.is_enabled(position))),
+            )
+        })
+        .collect();
+    let monitors = MonitorPolicy::ALL
+        .into_iter()
+        .map(|policy| {
+            MenuEntry::item(
+                policy.label(),
+                Action::SetMonitors(policy),
+                Some(Toggle::Radio(config.monitors == policy)),
+            )
+        })
+        .collect();
+    let opacity = OPACITY_PRESETS
+        .into_iter()
+        .map(|percent| {
+            MenuEntry::item(
+                format!("{}%", percent),
+                Action::SetOpacity(percent),
+                Some(Toggle::Radio(config.opacity == percent)),
+            )
+        })
+        .collect();
+
+    // The active profile's file may be missing until it is saved
+    let mut profiles = profiles.to_vec();
+    if !profiles.iter().any(|p| p == profile) {
+        profiles.insert(0, profile.to_string());
+    }
+    let profiles = profiles
+        .iter()
+        .map(|name| {
+            MenuEntry::item(
+                name.as_str(),
+                Action::SwitchProfile(name.clone()),
+                Some(Toggle::Radio(name == profile)),
+            )
+        })
+        .collect();
+
+    let mut entries = vec![
+        MenuEntry::submenu("Positions", positions),
+        MenuEntry::submenu("Monitors", monitors),
+        MenuEntry::submenu("Opacity", opacity),
+        MenuEntry::item(
+            "Sound",
+            Action::ToggleSound,
+            Some(Toggle::Check(config.sound.enabled)),
+        ),
+        MenuEntry::submenu("Profile", profiles),
+    ];
+    if !layouts.is_empty() {
+        let layouts = layouts
+            .iter()
+            .map(|layout| {
+                MenuEntry::item(
+                    layout.label.as_str(),
+                    Action::SetLayout(layout.code.clone()),
+                    Some(Toggle::Radio(layout.active)),
+                )
+            })
+            .collect();
+        entries.push(MenuEntry::submenu("Layout", layouts));
+    }
+    entries.extend([
+        MenuEntry::Separator,
+        MenuEntry::item("Open config", Action::OpenConfig, None),
+        MenuEntry::item("Reload config", Action::ReloadConfig, None),
+        MenuEntry::item("Open log folder", Action::OpenLogFolder, None),
+    ]);
+    entries
+}
+
+/// Checks the layout `code` in the Layout submenu and unchecks the others.
+/// Returns false if that is what the entries showed already.
+pub fn check_layout(entries: &mut [MenuEntry], code: &str) -> bool {
+    let mut changed = false;
+    for entry in entries {
+        match entry {
+            MenuEntry::Item {
+                action: Action::SetLayout(layout),
+                toggle: Some(Toggle::Radio(checked)),
+                ..
+            } => {
+                let active = layout == code;
+                changed |= *checked != active;
+                *checked = active;
+            }
+            MenuEntry::Submenu { entries, .. } => changed |= check_layout(entries, code),
+            _ => {}
+        }
+    }
+    changed
+}
+
+/// Returns the layout checked in `entries`, if any.
+fn active_layout(entries: &[MenuEntry]) -> Option<&str> {
+    entries.iter().find_map(|entry| match entry {
+        MenuEntry::Item {
+            action: Action::SetLayout(code),
+            toggle: Some(Toggle::Radio(true)),
+            ..
+        } => Some(code.as_str()),
+        MenuEntry::Submenu { entries, .. } => active_layout(entries),
+        _ => None,
+    })
+}
+
+/// Returns the layout checked in `new` if the entries differ from `old` only
+/// in which layout is checked, so the tray can move the check instead of
+/// rebuilding the menu.
+pub fn layout_switch<'a>(old: &[MenuEntry], new: &'a [MenuEntry]) -> Option<&'a str> {
+    let code = active_layout(new)?;
+    let mut old = old.to_vec();
+    check_layout(&mut old, code);
+    (old == new).then_some(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the labels and check states of a submenu.
+    fn submenu(entries: &[MenuEntry], label: &str) -> Vec<(String, bool)> {
+        entries
+            .iter()
+            .find_map(|entry| match entry {
+                MenuEntry::Submenu { label: l, entries } if l == label => Some(entries),
+                _ => None,
+            })
+            .unwrap()
+            .iter()
+            .map(|entry| match entry {
+                MenuEntry::Item { label, toggle, .. } => {
+                    (label.clone(), toggle.is_some_and(Toggle::is_checked))
+                }
+                _ => panic!("unexpected entry {:?}", entry),
+            })
+            .collect()
+    }
+
+    fn checked(entries: &[MenuEntry], label: &str) -> Vec<String> {
+        submenu(entries, label)
+            .into_iter()
+            .filter(|(_, checked)| *checked)
+            .map(|(label, _)| label)
+            .collect()
+    }
+
+    #[test]
+    fn entries_reflect_the_config() {
+        let mut config = AppConfig::default();
+        config.positions.center = false;
+        config.monitors = MonitorPolicy::Primary;
+        config.opacity = 60;
+        config.sound.enabled = false;
+        let layouts = [
+            InstalledLayout {
+                code: "us".to_string(),
+                label: "English (US)".to_string(),
+                active: false,
+            },
+            InstalledLayout {
+                code: "ru".to_string(),
+                label: "Russian".to_string(),
+                active: true,
+            },
+        ];
+        let profiles = ["default".to_string(), "work".to_string()];
+        let entries = settings_menu(&config, "work", &profiles, &layouts);
+
+        assert_eq!(
+            checked(&entries, "Positions"),
+            ["Top left", "Top right", "Bottom left", "Bottom right"]
+        );
+        assert_eq!(checked(&entries, "Monitors"), ["Primary monitor"]);
+        assert_eq!(checked(&entries, "Opacity"), ["60%"]);
+        assert_eq!(checked(&entries, "Profile"), ["work"]);
+        assert_eq!(checked(&entries, "Layout"), ["Russian"]);
+        assert!(entries.contains(&MenuEntry::item(
+            "Sound",
+            Action::ToggleSound,
+            Some(Toggle::Check(false))
+        )));
+        assert_eq!(
+            entries.last(),
+            Some(&MenuEntry::item(
+                "Open log folder",
+                Action::OpenLogFolder,
+                None
+            ))
+        );
+    }
+
+    #[test]
+    fn layout_switches_only_move_the_check() {
+        let config = AppConfig::default();
+        let layouts = |active: usize, count: usize| -> Vec<InstalledLayout> {
+            ["us", "ru", "de"][..count]
+                .iter()
+                .enumerate()
+                .map(|(i, code)| InstalledLayout {
+                    code: code.to_string(),
+                    label: code.to_uppercase(),
+                    active: i == active,
+                })
+                .collect()
+        };
+        let menu = |active, count| settings_menu(&config, "default", &[], &layouts(active, count));
+
+        let mut entries = menu(0, 2);
+        assert_eq!(layout_switch(&entries, &menu(1, 2)), Some("ru"));
+        assert!(check_layout(&mut entries, "ru"));
+        assert_eq!(entries, menu(1, 2));
+        assert!(!check_layout(&mut entries, "ru"));
+
+        // A new layout or any other change needs the whole menu
+        assert_eq!(layout_switch(&entries, &menu(0, 3)), None);
+        let other = AppConfig {
+            opacity: 60,
+            ..AppConfig::default()
+        };
+        let changed = settings_menu(&other, "default", &[], &layouts(0, 2));
+        assert_eq!(layout_switch(&entries, &changed), None);
+    }
+
+    #[test]
+    fn unknown_values_check_nothing() {
+        let config = AppConfig {
+            opacity: 55,
+            ..AppConfig::default()
+        };
+        let entries = settings_menu(&config, "default", &[], &[]);
+        assert!(checked(&entries, "Opacity").is_empty());
+        // The active profile is listed even without its file
+        assert_eq!(
+            submenu(&entries, "Profile"),
+            [("default".to_string(), true)]
+        );
+        assert!(!entries
+            .iter()
+            .any(|e| matches!(e, MenuEntry::Submenu { label, .. } if label == "Layout")));
+    }
+}
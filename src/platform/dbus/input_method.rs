@@ -0,0 +1,524 @@
+//! Input method layout source module.
+//!
+//! Follows the active engine of IBus (`GlobalEngineChanged` signal) or the
+//! current input method of Fcitx5 (`org.fcitx.Fcitx.Controller1`) and maps
+//! engine names such as `xkb:ru::rus` or `keyboard-de-nodeadkeys` to layouts.
+
+use crate::layout::{HookTimings, LayoutCallback, LayoutInfo, LayoutReporter};
+use crate::platform::LayoutSource;
+use parking_lot::Mutex;
+use std::sync::{mpsc, Arc};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use zbus::blocking::{fdo::DBusProxy, Connection, MessageIterator};
+use zbus::message::Type as MessageType;
+use zbus::zvariant::{OwnedValue, Value};
+use zbus::MatchRule;
+
+const IBUS_SERVICE: &str = "org.freedesktop.IBus";
+const IBUS_PATH: &str = "/org/freedesktop/IBus";
+const IBUS_INTERFACE: &str = "org.freedesktop.IBus";
+
+const FCITX_SERVICE: &str = "org.fcitx.Fcitx5";
+const FCITX_PATH: &str = "/controller";
+const FCITX_INTERFACE: &str = "org.fcitx.Fcitx.Controller1";
+
+/// Interface of the signal the source sends to itself to stop the event thread.
+const STOP_INTERFACE: &str = "org.langtip.LayoutSource";
+const STOP_MEMBER: &str = "Stop";
+
+/// Input method framework that owns the active layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Framework {
+    /// IBus, reached on its own bus (see `ibus address`).
+    IBus,
+    /// Fcitx5, reached on the session bus.
+    Fcitx5,
+}
+
+/// Short names for common non-keyboard engines.
+const ENGINE_NAMES: &[(&str, &str)] = &[
+    ("pinyin", "ZH"),
+    ("libpinyin", "ZH"),
+    ("shuangpin", "ZH"),
+    ("rime", "ZH"),
+    ("chewing", "ZH"),
+    ("wubi", "ZH"),
+    ("anthy", "JA"),
+    ("mozc", "JA"),
+    ("mozc-jp", "JA"),
+    ("kkc", "JA"),
+    ("skk", "JA"),
+    ("hangul", "KO"),
+    ("unikey", "VI"),
+    ("bamboo", "VI"),
+];
+
+/// Maps an IBus engine or Fcitx5 input method name to layout information.
+///
+/// Keyboard engines (`xkb:us::eng`, `keyboard-ru`) use the XKB layout code,
+/// m17n engines (`m17n:hi:inscript`) their language; other engines use a
+/// short language name if known, or the upper-cased engine name.
+fn layout_for_engine(engine: &str) -> LayoutInfo {
+    if let Some(rest) = engine.strip_prefix("xkb:") {
+        return LayoutInfo::from_xkb(rest.split(':').next().unwrap_or(rest));
+    }
+    if let Some(rest) = engine.strip_prefix("keyboard-") {
+        return LayoutInfo::from_xkb(rest.split('-').next().unwrap_or(rest));
+    }
+    if let Some(rest) = engine.strip_prefix("m17n:") {
+        return LayoutInfo::from_xkb(rest.split(':').next().unwrap_or(rest));
+    }
+
+    let name = ENGINE_NAMES
+        .iter()
+        .find(|(e, _)| *e == engine)
+        .map(|(_, name)| name.to_string())
+        .unwrap_or_else(|| {
+            engine
+                .split([':', '-'])
+                .next()
+                .unwrap_or(engine)
+                .chars()
+                .take(3)
+                .collect::<String>()
+                .to_uppercase()
+        });
+    LayoutInfo {
+        name,
+        is_russian: false,
+    }
+}
+
+/// Extracts the engine name from a serialized `IBusEngineDesc`
+/// (`(sa{sv}ss...)`: type name, attachments, engine name, ...), possibly
+/// wrapped in variants.
+fn engine_name_from_desc(value: &Value) -> Option<String> {
+    match value {
+        Value::Value(inner) => engine_name_from_desc(inner),
+        Value::Structure(desc) => match desc.fields().get(2)? {
+            Value::Str(name) => Some(name.to_string()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Connects to the bus of `framework`, or to `address` if given.
+fn connect(framework: Framework, address: Option<&str>) -> zbus::Result<Connection> {
+    match (address, framework) {
+        (Some(address), _) => zbus::blocking::connection::Builder::address(address)?.build(),
+        (None, Framework::IBus) => zbus::blocking::connection::Builder::ibus()?.build(),
+        (None, Framework::Fcitx5) => Connection::session(),
+    }
+}
+
+/// Checks whether the framework's service is running on the bus.
+fn has_service(conn: &Connection, framework: Framework) -> bool {
+    let name = match framework {
+        Framework::IBus => IBUS_SERVICE,
+        Framework::Fcitx5 => FCITX_SERVICE,
+    };
+    DBusProxy::new(conn)
+        .and_then(|proxy| Ok(proxy.name_has_owner(name.try_into()?)?))
+        .unwrap_or(false)
+}
+
+/// Reads the name of the active engine.
+fn read_engine(conn: &Connection, framework: Framework) -> zbus::Result<String> {
+    match framework {
+        Framework::IBus => {
+            let reply = conn.call_method(
+                Some(IBUS_SERVICE),
+                IBUS_PATH,
+                Some("org.freedesktop.DBus.Properties"),
+                "Get",
+                &(IBUS_INTERFACE, "GlobalEngine"),
+            )?;
+            let desc: OwnedValue = reply.body().deserialize()?;
+            engine_name_from_desc(&desc)
+                .ok_or_else(|| zbus::Error::Failure("Malformed IBus engine description".into()))
+        }
+        Framework::Fcitx5 => conn
+            .call_method(
+                Some(FCITX_SERVICE),
+                FCITX_PATH,
+                Some(FCITX_INTERFACE),
+                "CurrentInputMethod",
+                &(),
+            )?
+            .body()
+            .deserialize(),
+    }
+}
+
+/// Layout source following an input method framework.
+pub struct InputMethodLayoutSource {
+    framework: Framework,
+    conn: Connection,
+    reporter: LayoutReporter,
+    /// Fcitx5 has no change signal, so its input method is polled at the
+    /// debounce interval.
+    poll_interval: Arc<Mutex<Duration>>,
+    poll_stop: Option<mpsc::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl InputMethodLayoutSource {
+    /// Connects to the running input method framework, preferring Fcitx5.
+    /// Returns `None` if neither is running.
+    pub fn detect() -> Option<Self> {
+        [Framework::Fcitx5, Framework::IBus]
+            .into_iter()
+            .find_map(|framework| Self::connect(framework, None))
+    }
+
+    /// Connects to `framework` on its default bus or on `address`.
+    /// Returns `None` if the framework's service isn't running there.
+    pub fn connect(framework: Framework, address: Option<&str>) -> Option<Self> {
+        let conn = match connect(framework, address) {
+            Ok(conn) => conn,
+            Err(e) => {
+                log::debug!("No bus for {:?}: {}", framework, e);
+                return None;
+            }
+        };
+        if !has_service(&conn, framework) {
+            log::debug!("{:?} is not running", framework);
+            return None;
+        }
+        log::info!("Using {:?} as the layout source", framework);
+        Some(Self {
+            framework,
+            conn,
+            reporter: LayoutReporter::default(),
+            poll_interval: Arc::new(Mutex::new(HookTimings::default().debounce)),
+            poll_stop: None,
+            thread: None,
+        })
+    }
+}
+
+/// IBus event thread: reports `GlobalEngineChanged` until the stop signal.
+fn ibus_event_loop(conn: Connection, reporter: LayoutReporter, ready: mpsc::Sender<()>) {
+    // Subscribe before adding the match rule so no signal is missed
+    let messages = MessageIterator::from(&conn);
+    let subscribed = MatchRule::builder()
+        .msg_type(MessageType::Signal)
+        .path(IBUS_PATH)
+        .and_then(|b| b.interface(IBUS_INTERFACE))
+        .and_then(|b| b.member("GlobalEngineChanged"))
+        .map(|b| b.build())
+        .and_then(|rule| {
+            DBusProxy::new(&conn)?
+                .add_match_rule(rule)
+                .map_err(Into::into)
+        });
+    let _ = ready.send(());
+    if let Err(e) = subscribed {
+        log::error!("Failed to subscribe to IBus engine changes: {}", e);
+        return;
+    }
+
+    let own_name = conn.unique_name().map(|n| n.to_string());
+    for message in messages {
+        let Ok(message) = message else {
+            continue;
+        };
+        let header = message.header();
+        if header.message_type() != MessageType::Signal {
+            continue;
+        }
+        let interface = header.interface().map(|i| i.as_str());
+        let member = header.member().map(|m| m.as_str());
+        match (interface, member) {
+            (Some(IBUS_INTERFACE), Some("GlobalEngineChanged")) => {
+                match message.body().deserialize::<String>() {
+                    Ok(engine) => reporter.report(layout_for_engine(&engine), Instant::now()),
+                    Err(e) => log::warn!("Malformed GlobalEngineChanged signal: {}", e),
+                }
+            }
+            (Some(STOP_INTERFACE), Some(STOP_MEMBER))
+                if header.sender().map(|s| s.to_string()) == own_name =>
+            {
+                break
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Fcitx5 poll thread: reads the current input method until stopped.
+fn fcitx_poll_loop(
+    conn: Connection,
+    reporter: LayoutReporter,
+    poll_interval: Arc<Mutex<Duration>>,
+    stop: mpsc::Receiver<()>,
+) {
+    loop {
+        let interval = *poll_interval.lock();
+        match stop.recv_timeout(interval) {
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            _ => break,
+        }
+        match read_engine(&conn, Framework::Fcitx5) {
+            Ok(engine) => reporter.report(layout_for_engine(&engine), Instant::now()),
+            Err(e) => log::debug!("Failed to read the Fcitx5 input method: {}", e),
+        }
+    }
+}
+
+impl LayoutSource for InputMethodLayoutSource {
+    fn current_layout(&self) -> LayoutInfo {
+        match read_engine(&self.conn, self.framework) {
+            Ok(engine) => layout_for_engine(&engine),
+            Err(e) => {
+                log::warn!("Failed to read the {:?} engine: {}", self.framework, e);
+                let name = self
+                    .reporter
+                    .last_layout()
+                    .unwrap_or_else(|| "EN".to_string());
+                LayoutInfo {
+                    is_russian: name == "RU",
+                    name,
+                }
+            }
+        }
+    }
+
+    fn start(&mut self, callback: LayoutCallback, initial: &LayoutInfo, timings: HookTimings) {
+        self.reporter.start(callback, initial);
+        self.set_timings(timings);
+
+        let conn = self.conn.clone();
+        let reporter = self.reporter.clone();
+        match self.framework {
+            Framework::IBus => {
+                let (ready_tx, ready_rx) = mpsc::channel();
+                self.thread = Some(std::thread::spawn(move || {
+                    ibus_event_loop(conn, reporter, ready_tx);
+                }));
+                // Wait until the match rule is added so no switch is missed
+                let _ = ready_rx.recv();
+            }
+            Framework::Fcitx5 => {
+                let (stop_tx, stop_rx) = mpsc::channel();
+                let poll_interval = self.poll_interval.clone();
+                self.poll_stop = Some(stop_tx);
+                self.thread = Some(std::thread::spawn(move || {
+                    fcitx_poll_loop(conn, reporter, poll_interval, stop_rx);
+                }));
+            }
+        }
+    }
+
+    fn set_timings(&mut self, timings: HookTimings) {
+        *self.poll_interval.lock() = timings.debounce.max(Duration::from_millis(20));
+    }
+
+    fn stop(&mut self) {
+        self.reporter.stop();
+
+        let Some(thread) = self.thread.take() else {
+            return;
+        };
+        match self.poll_stop.take() {
+            Some(stop) => {
+                let _ = stop.send(());
+            }
+            None => {
+                // Wake the IBus thread with a signal addressed to ourselves
+                let _ = self.conn.emit_signal(
+                    self.conn.unique_name(),
+                    "/",
+                    STOP_INTERFACE,
+                    STOP_MEMBER,
+                    &(),
+                );
+            }
+        }
+        let _ = thread.join();
+    }
+}
+
+impl Drop for InputMethodLayoutSource {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::LayoutEvent;
+    use crate::platform::dbus::test_bus::TestBus;
+    use std::collections::HashMap;
+
+    #[test]
+    fn engine_names_map_to_layouts() {
+        assert_eq!(layout_for_engine("xkb:us::eng").name, "EN");
+        let ru = layout_for_engine("xkb:ru::rus");
+        assert_eq!(ru.name, "RU");
+        assert!(ru.is_russian);
+        assert_eq!(layout_for_engine("xkb:de:nodeadkeys:ger").name, "DE");
+        assert!(layout_for_engine("keyboard-ru").is_russian);
+        assert_eq!(layout_for_engine("keyboard-de-nodeadkeys").name, "DE");
+        assert_eq!(layout_for_engine("m17n:hi:inscript").name, "HI");
+        assert_eq!(layout_for_engine("libpinyin").name, "ZH");
+        assert_eq!(layout_for_engine("mozc-jp").name, "JA");
+        assert_eq!(layout_for_engine("table:cangjie5").name, "TAB");
+    }
+
+    /// `IBusEngineDesc` as IBus serializes it: wrapped in a variant.
+    fn engine_desc(name: &str) -> OwnedValue {
+        let desc = Value::from((
+            "IBusEngineDesc",
+            HashMap::<&str, Value>::new(),
+            name,
+            "Long name",
+        ));
+        Value::Value(Box::new(desc)).try_into().unwrap()
+    }
+
+    #[test]
+    fn engine_name_is_read_from_description() {
+        assert_eq!(
+            engine_name_from_desc(&engine_desc("xkb:ru::rus")).as_deref(),
+            Some("xkb:ru::rus")
+        );
+        assert_eq!(engine_name_from_desc(&Value::from("xkb:us::eng")), None);
+    }
+
+    struct MockIBus {
+        engine: Arc<Mutex<String>>,
+    }
+
+    #[zbus::interface(name = "org.freedesktop.IBus")]
+    impl MockIBus {
+        #[zbus(property)]
+        fn global_engine(&self) -> OwnedValue {
+            engine_desc(&self.engine.lock())
+        }
+    }
+
+    struct MockFcitx {
+        current: Arc<Mutex<String>>,
+    }
+
+    #[zbus::interface(name = "org.fcitx.Fcitx.Controller1")]
+    impl MockFcitx {
+        fn current_input_method(&self) -> String {
+            self.current.lock().clone()
+        }
+    }
+
+    /// Starts `source` and returns the receiver of reported layouts.
+    fn start_source(source: &mut InputMethodLayoutSource) -> mpsc::Receiver<LayoutInfo> {
+        let (tx, rx) = mpsc::channel();
+        let tx = Mutex::new(tx);
+        let initial = source.current_layout();
+        let timings = HookTimings {
+            debounce: Duration::from_millis(20),
+            ..Default::default()
+        };
+        source.start(
+            Arc::new(move |event: LayoutEvent| {
+                let _ = tx.lock().send(event.layout);
+            }),
+            &initial,
+            timings,
+        );
+        rx
+    }
+
+    #[test]
+    fn ibus_engine_changes_are_reported() {
+        let Some(bus) = TestBus::start() else {
+            return;
+        };
+        assert!(InputMethodLayoutSource::connect(Framework::IBus, Some(&bus.address)).is_none());
+
+        let engine = Arc::new(Mutex::new("xkb:us::eng".to_string()));
+        let service = zbus::blocking::connection::Builder::address(bus.address.as_str())
+            .unwrap()
+            .name(IBUS_SERVICE)
+            .unwrap()
+            .serve_at(
+                IBUS_PATH,
+                MockIBus {
+                    engine: engine.clone(),
+                },
+            )
+            .unwrap()
+            .build()
+            .unwrap();
+        let emit = |name: &str| {
+            *engine.lock() = name.to_string();
+            service
+                .emit_signal(
+                    None::<&str>,
+                    IBUS_PATH,
+                    IBUS_INTERFACE,
+                    "GlobalEngineChanged",
+                    &(name,),
+                )
+                .unwrap();
+        };
+
+        let mut source =
+            InputMethodLayoutSource::connect(Framework::IBus, Some(&bus.address)).unwrap();
+        assert_eq!(source.current_layout().name, "EN");
+        let rx = start_source(&mut source);
+
+        emit("xkb:ru::rus");
+        let layout = rx.recv_timeout(Duration::from_secs(2)).unwrap();
+        assert_eq!(layout.name, "RU");
+        assert!(layout.is_russian);
+        assert_eq!(source.current_layout().name, "RU");
+
+        emit("xkb:us::eng");
+        assert_eq!(rx.recv_timeout(Duration::from_secs(2)).unwrap().name, "EN");
+
+        source.stop();
+        emit("xkb:ru::rus");
+        assert!(rx.recv_timeout(Duration::from_millis(200)).is_err());
+    }
+
+    #[test]
+    fn fcitx_input_method_changes_are_reported() {
+        let Some(bus) = TestBus::start() else {
+            return;
+        };
+        let current = Arc::new(Mutex::new("keyboard-us".to_string()));
+        let _service = zbus::blocking::connection::Builder::address(bus.address.as_str())
+            .unwrap()
+            .name(FCITX_SERVICE)
+            .unwrap()
+            .serve_at(
+                FCITX_PATH,
+                MockFcitx {
+                    current: current.clone(),
+                },
+            )
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let mut source =
+            InputMethodLayoutSource::connect(Framework::Fcitx5, Some(&bus.address)).unwrap();
+        assert_eq!(source.current_layout().name, "EN");
+        let rx = start_source(&mut source);
+
+        *current.lock() = "keyboard-ru".to_string();
+        let layout = rx.recv_timeout(Duration::from_secs(2)).unwrap();
+        assert_eq!(layout.name, "RU");
+        assert!(layout.is_russian);
+
+        *current.lock() = "pinyin".to_string();
+        assert_eq!(rx.recv_timeout(Duration::from_secs(2)).unwrap().name, "ZH");
+
+        source.stop();
+        *current.lock() = "keyboard-us".to_string();
+        assert!(rx.recv_timeout(Duration::from_millis(200)).is_err());
+    }
+}
@@ -0,0 +1,97 @@
+//! D-Bus platform module.
+//!
+//! Linux desktop services reached over D-Bus. The IBus and Fcitx5 input
+//! method frameworks own the active layout on many desktops and are used as
+//! layout sources instead of XKB when they are running; the tray icon is a
+//! StatusNotifierItem.
+
+mod input_method;
+mod tray;
+
+pub use input_method::InputMethodLayoutSource;
+pub use tray::SniTray;
+
+#[cfg(test)]
+pub(crate) mod test_bus {
+    //! Private `dbus-daemon` for tests.
+
+    use std::io::{BufRead, BufReader};
+    use std::path::PathBuf;
+    use std::process::{Child, Command, Stdio};
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    static BUS_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+    /// A message bus running in its own `dbus-daemon` process.
+    pub struct TestBus {
+        child: Child,
+        dir: PathBuf,
+        pub address: String,
+    }
+
+    impl TestBus {
+        /// Starts a bus, or returns `None` if `dbus-daemon` is not installed.
+        pub fn start() -> Option<Self> {
+            let dir = std::env::temp_dir().join(format!(
+                "langtip-dbus-{}-{}",
+                std::process::id(),
+                BUS_COUNTER.fetch_add(1, Ordering::SeqCst)
+            ));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).ok()?;
+            let config = dir.join("bus.conf");
+            std::fs::write(
+                &config,
+                format!(
+                    r#"<!DOCTYPE busconfig PUBLIC "-//freedesktop//DTD D-Bus Bus Configuration 1.0//EN"
+ "http://www.freedesktop.org/standards/dbus/1.0/busconfig.dtd">
+<busconfig>
+  <type>session</type>
+  <listen>unix:dir={}</listen>
+  <auth>EXTERNAL</auth>
+  <policy context="default">
+    <allow send_destination="*" eavesdrop="true"/>
+    <allow eavesdrop="true"/>
+    <allow own="*"/>
+  </policy>
+</busconfig>
+"#,
+                    dir.display()
+                ),
+            )
+            .ok()?;
+
+            let mut child = match Command::new("dbus-daemon")
+                .arg(format!("--config-file={}", config.display()))
+                .args(["--print-address", "--nofork"])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+            {
+                Ok(child) => child,
+                Err(_) => {
+                    eprintln!("dbus-daemon not available, skipping");
+                    let _ = std::fs::remove_dir_all(&dir);
+                    return None;
+                }
+            };
+
+            let mut address = String::new();
+            let stdout = child.stdout.take()?;
+            BufReader::new(stdout).read_line(&mut address).ok()?;
+            Some(Self {
+                child,
+                dir,
+                address: address.trim().to_string(),
+            })
+        }
+    }
+
+    impl Drop for TestBus {
+        fn drop(&mut self) {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+}
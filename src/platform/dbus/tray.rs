@@ -0,0 +1,1344 @@
+//! StatusNotifierItem tray module.
+//!
+//! Tray icon for Linux desktops: a `org.kde.StatusNotifierItem` object that
+//! shows the current layout as its icon and title, registered with the
+//! `org.kde.StatusNotifierWatcher`, and a `com.canonical.dbusmenu` context
+//! menu with the same entries as the Windows tray. The icon follows the
+//! desktop colour scheme the Settings portal reports.
+
+use crate::action::Action;
+use crate::config::ColorsConfig;
+use crate::hotkey::BindingStatus;
+use crate::layout::LayoutInfo;
+use crate::menu::{self, MenuEntry, Toggle};
+use crate::platform::{ActionCallback, Autostart, TrayBackend};
+use crate::render::{layout_icon, IconTheme};
+use crate::status::Status;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread::JoinHandle;
+use zbus::blocking::{fdo::DBusProxy, Connection, MessageIterator};
+use zbus::message::Type as MessageType;
+use zbus::zvariant::{OwnedObjectPath, OwnedValue, Type, Value};
+use zbus::MatchRule;
+
+const ITEM_PATH: &str = "/StatusNotifierItem";
+const ITEM_INTERFACE: &str = "org.kde.StatusNotifierItem";
+const MENU_PATH: &str = "/MenuBar";
+const MENU_INTERFACE: &str = "com.canonical.dbusmenu";
+
+const WATCHER_SERVICE: &str = "org.kde.StatusNotifierWatcher";
+const WATCHER_PATH: &str = "/StatusNotifierWatcher";
+const WATCHER_INTERFACE: &str = "org.kde.StatusNotifierWatcher";
+
+const PORTAL_SERVICE: &str = "org.freedesktop.portal.Desktop";
+const PORTAL_PATH: &str = "/org/freedesktop/portal/desktop";
+const PORTAL_INTERFACE: &str = "org.freedesktop.portal.Settings";
+const APPEARANCE_NAMESPACE: &str = "org.freedesktop.appearance";
+const COLOR_SCHEME_KEY: &str = "color-scheme";
+
+/// Interface of the signal the tray sends to itself to stop the theme thread.
+const STOP_INTERFACE: &str = "org.langtip.Tray";
+const STOP_MEMBER: &str = "Stop";
+
+/// Counter for unique item bus names within the process.
+static ITEM_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+/// Icon sizes offered to the tray host.
+const ICON_SIZES: [u32; 3] = [16, 22, 32];
+
+/// Menu entry IDs (0 is the root).
+const MENU_INDICATORS: i32 = 1;
+const MENU_AUTOSTART: i32 = 4;
+const MENU_ABOUT: i32 = 6;
+const MENU_EXIT: i32 = 7;
+
+/// Context menu entries: ID, label (`None` for separators).
+const MENU_ITEMS: &[(i32, Option<&str>)] = &[
+    (MENU_INDICATORS, Some("Indicators enabled")),
+    (MENU_VISIBILITY_SEPARATOR, None),
+    (MENU_AUTOSTART, Some("Autostart")),
+    (5, None),
+    (MENU_ABOUT, Some("About")),
+    (MENU_EXIT, Some("Exit")),
+];
+
+/// Separator below the visibility entries, followed by the settings.
+const MENU_VISIBILITY_SEPARATOR: i32 = 3;
+/// Separator below the settings.
+const MENU_SETTINGS_SEPARATOR: i32 = 98;
+/// Separator below the hotkey conflicts.
+const MENU_CONFLICTS_SEPARATOR: i32 = 99;
+/// ID of the first hotkey conflict entry.
+const MENU_CONFLICTS: i32 = 100;
+/// ID of the first settings entry; nested entries are numbered in order.
+const MENU_SETTINGS: i32 = 1000;
+
+/// `(iiay)`: width, height and ARGB32 pixels in network byte order.
+type IconPixmap = (i32, i32, Vec<u8>);
+
+/// Renders the layout name over its colour as tray icons of all sizes.
+fn layout_icons(layout: &LayoutInfo, color: (u8, u8, u8), theme: IconTheme) -> Vec<IconPixmap> {
+    ICON_SIZES
+        .iter()
+        .map(|&size| {
+            let canvas = layout_icon(&layout.name, color, size, theme);
+            (size as i32, size as i32, canvas.to_argb_be_bytes())
+        })
+        .collect()
+}
+
+/// Maps the portal's `color-scheme` (0 no preference, 1 dark, 2 light) to
+/// the icon theme. Panels are mostly dark without a preference.
+fn icon_theme(color_scheme: u32) -> IconTheme {
+    if color_scheme == 2 {
+        IconTheme::Light
+    } else {
+        IconTheme::Dark
+    }
+}
+
+/// Unwraps the `color-scheme` value; `Read` of older portals nests it in
+/// one more variant than `ReadOne`.
+fn color_scheme_value(value: &Value) -> Option<u32> {
+    match value {
+        Value::U32(scheme) => Some(*scheme),
+        Value::Value(inner) => color_scheme_value(inner),
+        _ => None,
+    }
+}
+
+/// Reads the desktop colour scheme from the Settings portal. `None` if
+/// there is no portal.
+fn read_color_scheme(conn: &Connection) -> Option<u32> {
+    let read = |method: &str| {
+        conn.call_method(
+            Some(PORTAL_SERVICE),
+            PORTAL_PATH,
+            Some(PORTAL_INTERFACE),
+            method,
+            &(APPEARANCE_NAMESPACE, COLOR_SCHEME_KEY),
+        )
+    };
+    let reply = read("ReadOne").or_else(|_| read("Read"));
+    match reply {
+        Ok(reply) => match reply.body().deserialize::<OwnedValue>() {
+            Ok(value) => color_scheme_value(&value),
+            Err(e) => {
+                log::warn!("Malformed color-scheme setting: {}", e);
+                None
+            }
+        },
+        Err(e) => {
+            log::debug!("Failed to read the desktop color scheme: {}", e);
+            None
+        }
+    }
+}
+
+/// Theme thread: follows `SettingChanged` of the colour scheme and tells
+/// the host to fetch the redrawn icon, until the stop signal.
+fn theme_event_loop(conn: Connection, theme: Arc<Mutex<IconTheme>>, ready: mpsc::Sender<()>) {
+    // Subscribe before adding the match rule so no signal is missed
+    let messages = MessageIterator::from(&conn);
+    let subscribed = MatchRule::builder()
+        .msg_type(MessageType::Signal)
+        .path(PORTAL_PATH)
+        .and_then(|b| b.interface(PORTAL_INTERFACE))
+        .and_then(|b| b.member("SettingChanged"))
+        .map(|b| b.build())
+        .and_then(|rule| {
+            DBusProxy::new(&conn)?
+                .add_match_rule(rule)
+                .map_err(Into::into)
+        });
+    let _ = ready.send(());
+    if let Err(e) = subscribed {
+        log::warn!("Failed to subscribe to color scheme changes: {}", e);
+        return;
+    }
+
+    let own_name = conn.unique_name().map(|n| n.to_string());
+    for message in messages {
+        let Ok(message) = message else {
+            continue;
+        };
+        let header = message.header();
+        if header.message_type() != MessageType::Signal {
+            continue;
+        }
+        let interface = header.interface().map(|i| i.as_str());
+        let member = header.member().map(|m| m.as_str());
+        match (interface, member) {
+            (Some(PORTAL_INTERFACE), Some("SettingChanged")) => {
+                let Ok((namespace, key, value)) =
+                    message.body().deserialize::<(String, String, OwnedValue)>()
+                else {
+                    log::warn!("Malformed SettingChanged signal");
+                    continue;
+                };
+                if namespace != APPEARANCE_NAMESPACE || key != COLOR_SCHEME_KEY {
+                    continue;
+                }
+                let Some(scheme) = color_scheme_value(&value) else {
+                    continue;
+                };
+                let new_theme = icon_theme(scheme);
+                if std::mem::replace(&mut *theme.lock(), new_theme) != new_theme {
+                    log::info!("Desktop color scheme changed, icon theme {:?}", new_theme);
+                    let _ = conn.emit_signal(None::<&str>, ITEM_PATH, ITEM_INTERFACE, "NewIcon", &());
+                }
+            }
+            (Some(STOP_INTERFACE), Some(STOP_MEMBER))
+                if header.sender().map(|s| s.to_string()) == own_name =>
+            {
+                break
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Title shown for the layout.
+fn layout_title(layout: &LayoutInfo) -> String {
+    format!("LangTip: {}", layout.name)
+}
+
+/// Action chosen in the context menu.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum MenuAction {
+    /// The "Indicators enabled" checkmark.
+    Indicators,
+    Autostart,
+    About,
+    Exit,
+    /// A settings entry.
+    Setting(Action),
+}
+
+impl MenuAction {
+    fn from_id(id: i32) -> Option<Self> {
+        match id {
+            MENU_INDICATORS => Some(Self::Indicators),
+            MENU_AUTOSTART => Some(Self::Autostart),
+            MENU_ABOUT => Some(Self::About),
+            MENU_EXIT => Some(Self::Exit),
+            _ => None,
+        }
+    }
+}
+
+/// `org.kde.StatusNotifierItem` object.
+struct StatusNotifierItem {
+    layout: Arc<Mutex<LayoutInfo>>,
+    color: Arc<Mutex<(u8, u8, u8)>>,
+    theme: Arc<Mutex<IconTheme>>,
+    status: Arc<Mutex<Option<Status>>>,
+}
+
+#[zbus::interface(name = "org.kde.StatusNotifierItem")]
+impl StatusNotifierItem {
+    #[zbus(property)]
+    fn category(&self) -> String {
+        "ApplicationStatus".to_string()
+    }
+
+    #[zbus(property)]
+    fn id(&self) -> String {
+        "langtip".to_string()
+    }
+
+    #[zbus(property)]
+    fn title(&self) -> String {
+        layout_title(&self.layout.lock())
+    }
+
+    #[zbus(property)]
+    fn status(&self) -> String {
+        "Active".to_string()
+    }
+
+    #[zbus(property)]
+    fn window_id(&self) -> i32 {
+        0
+    }
+
+    #[zbus(property)]
+    fn icon_name(&self) -> String {
+        String::new()
+    }
+
+    #[zbus(property)]
+    fn icon_pixmap(&self) -> Vec<IconPixmap> {
+        layout_icons(&self.layout.lock(), *self.color.lock(), *self.theme.lock())
+    }
+
+    #[zbus(property)]
+    fn tool_tip(&self) -> (String, Vec<IconPixmap>, String, String) {
+        let (title, details) = match *self.status.lock() {
+            Some(ref status) => (status.title(), status.details()),
+            None => (layout_title(&self.layout.lock()), String::new()),
+        };
+        (String::new(), Vec::new(), title, details)
+    }
+
+    /// The menu opens on any click, `Activate` is never called.
+    #[zbus(property)]
+    fn item_is_menu(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn menu(&self) -> OwnedObjectPath {
+        OwnedObjectPath::try_from(MENU_PATH).unwrap()
+    }
+
+    fn activate(&self, _x: i32, _y: i32) {}
+
+    fn secondary_activate(&self, _x: i32, _y: i32) {}
+
+    fn context_menu(&self, _x: i32, _y: i32) {}
+
+    fn scroll(&self, _delta: i32, _orientation: &str) {}
+}
+
+/// A menu node as sent in `GetLayout`: `(ia{sv}av)`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Type, Value, OwnedValue)]
+struct MenuNode {
+    id: i32,
+    properties: HashMap<String, OwnedValue>,
+    children: Vec<OwnedValue>,
+}
+
+/// A menu entry as served over D-Bus.
+#[derive(Debug, Clone, PartialEq)]
+struct Entry {
+    id: i32,
+    /// `None` for separators.
+    label: Option<String>,
+    toggle: Option<Toggle>,
+    enabled: bool,
+    action: Option<MenuAction>,
+    /// Entries of a submenu.
+    children: Vec<Entry>,
+}
+
+impl Entry {
+    fn new(id: i32, label: Option<&str>) -> Self {
+        Self {
+            id,
+            label: label.map(String::from),
+            toggle: None,
+            enabled: true,
+            action: MenuAction::from_id(id),
+            children: Vec::new(),
+        }
+    }
+
+    /// Finds the entry `id` among this entry and its descendants.
+    fn find(&self, id: i32) -> Option<&Entry> {
+        if self.id == id {
+            return Some(self);
+        }
+        self.children.iter().find_map(|child| child.find(id))
+    }
+
+    /// Returns this entry and its descendants, depth first.
+    fn flatten(&self) -> Vec<&Entry> {
+        let mut entries = vec![self];
+        entries.extend(self.children.iter().flat_map(Entry::flatten));
+        entries
+    }
+}
+
+/// Converts settings entries, numbering them from `next_id` on.
+fn settings_entries(entries: &[MenuEntry], next_id: &mut i32) -> Vec<Entry> {
+    entries
+        .iter()
+        .map(|entry| {
+            let id = *next_id;
+            *next_id += 1;
+            match entry {
+                MenuEntry::Item {
+                    label,
+                    action,
+                    toggle,
+                } => Entry {
+                    toggle: *toggle,
+                    action: Some(MenuAction::Setting(action.clone())),
+                    ..Entry::new(id, Some(label))
+                },
+                MenuEntry::Submenu { label, entries } => Entry {
+                    children: settings_entries(entries, next_id),
+                    ..Entry::new(id, Some(label))
+                },
+                MenuEntry::Separator => Entry::new(id, None),
+            }
+        })
+        .collect()
+}
+
+/// Menu state shared with the D-Bus object.
+struct MenuState {
+    /// Whether indicators are enabled, as last reported by the application.
+    indicators: bool,
+    autostart: bool,
+    /// Hotkeys that couldn't be registered, listed above the entries.
+    conflicts: Vec<String>,
+    /// Settings entries, listed below the visibility entries.
+    settings: Vec<MenuEntry>,
+    revision: u32,
+}
+
+impl MenuState {
+    /// Returns the root of the menu: hotkey conflicts, then `MENU_ITEMS`
+    /// with the settings below the visibility entries.
+    fn root(&self) -> Entry {
+        let mut children: Vec<Entry> = self
+            .conflicts
+            .iter()
+            .zip(MENU_CONFLICTS..)
+            .map(|(label, id)| Entry {
+                enabled: false,
+                ..Entry::new(id, Some(label))
+            })
+            .collect();
+        if !children.is_empty() {
+            children.push(Entry::new(MENU_CONFLICTS_SEPARATOR, None));
+        }
+        let mut next_id = MENU_SETTINGS;
+        for &(id, label) in MENU_ITEMS {
+            let mut entry = Entry::new(id, label);
+            match id {
+                MENU_INDICATORS => entry.toggle = Some(Toggle::Check(self.indicators)),
+                MENU_AUTOSTART => entry.toggle = Some(Toggle::Check(self.autostart)),
+                _ => {}
+            }
+            children.push(entry);
+            if id == MENU_VISIBILITY_SEPARATOR && !self.settings.is_empty() {
+                children.extend(settings_entries(&self.settings, &mut next_id));
+                children.push(Entry::new(MENU_SETTINGS_SEPARATOR, None));
+            }
+        }
+        Entry {
+            children,
+            ..Entry::new(0, None)
+        }
+    }
+}
+
+/// Returns the properties of a menu entry.
+fn item_properties(entry: &Entry) -> HashMap<String, OwnedValue> {
+    let mut properties = HashMap::new();
+    let mut set = |name: &str, value: Value| {
+        properties.insert(name.to_string(), value.try_into().unwrap());
+    };
+    match (entry.id, &entry.label) {
+        (0, _) => {}
+        (_, Some(label)) => set("label", label.as_str().into()),
+        (_, None) => set("type", "separator".into()),
+    }
+    if entry.id == 0 || !entry.children.is_empty() {
+        set("children-display", "submenu".into());
+    }
+    match entry.toggle {
+        Some(Toggle::Check(checked)) => {
+            set("toggle-type", "checkmark".into());
+            set("toggle-state", (checked as i32).into());
+        }
+        Some(Toggle::Radio(checked)) => {
+            set("toggle-type", "radio".into());
+            set("toggle-state", (checked as i32).into());
+        }
+        None => {}
+    }
+    if !entry.enabled {
+        set("enabled", false.into());
+    }
+    properties
+}
+
+/// Converts an entry and its descendants to a `GetLayout` node.
+fn menu_node(entry: &Entry) -> MenuNode {
+    MenuNode {
+        id: entry.id,
+        properties: item_properties(entry),
+        children: entry
+            .children
+            .iter()
+            .map(|child| menu_node(child).try_into().unwrap())
+            .collect(),
+    }
+}
+
+/// `com.canonical.dbusmenu` object.
+struct DBusMenu {
+    state: Arc<Mutex<MenuState>>,
+    actions: mpsc::Sender<MenuAction>,
+}
+
+impl DBusMenu {
+    fn node(&self, id: i32) -> Option<MenuNode> {
+        self.state.lock().root().find(id).map(menu_node)
+    }
+
+    /// Returns the action of the entry `id`, or `None` if there is no such
+    /// entry. Entries without an action (submenus, separators) give
+    /// `Some(None)`.
+    fn action(&self, id: i32) -> Option<Option<MenuAction>> {
+        self.state
+            .lock()
+            .root()
+            .find(id)
+            .map(|entry| entry.action.clone())
+    }
+}
+
+#[zbus::interface(name = "com.canonical.dbusmenu")]
+impl DBusMenu {
+    #[zbus(out_args("revision", "layout"))]
+    fn get_layout(
+        &self,
+        parent_id: i32,
+        _recursion_depth: i32,
+        _property_names: Vec<String>,
+    ) -> zbus::fdo::Result<(u32, MenuNode)> {
+        let node = self
+            .node(parent_id)
+            .ok_or_else(|| zbus::fdo::Error::InvalidArgs(format!("No menu item {}", parent_id)))?;
+        Ok((self.state.lock().revision, node))
+    }
+
+    fn get_group_properties(
+        &self,
+        ids: Vec<i32>,
+        _property_names: Vec<String>,
+    ) -> Vec<(i32, HashMap<String, OwnedValue>)> {
+        let root = self.state.lock().root();
+        root.flatten()
+            .into_iter()
+            .filter(|entry| ids.is_empty() || ids.contains(&entry.id))
+            .map(|entry| (entry.id, item_properties(entry)))
+            .collect()
+    }
+
+    fn get_property(&self, id: i32, name: &str) -> zbus::fdo::Result<OwnedValue> {
+        let node = self
+            .node(id)
+            .ok_or_else(|| zbus::fdo::Error::InvalidArgs(format!("No menu item {}", id)))?;
+        node.properties
+            .get(name)
+            .map(|v| v.try_clone().unwrap())
+            .ok_or_else(|| zbus::fdo::Error::InvalidArgs(format!("No property {}", name)))
+    }
+
+    fn event(&self, id: i32, event_id: &str, _data: Value<'_>, _timestamp: u32) {
+        if event_id != "clicked" {
+            return;
+        }
+        if let Some(Some(action)) = self.action(id) {
+            let _ = self.actions.send(action);
+        }
+    }
+
+    fn event_group(&self, events: Vec<(i32, String, OwnedValue, u32)>) -> Vec<i32> {
+        let mut errors = Vec::new();
+        for (id, event_id, _, _) in events {
+            match self.action(id) {
+                Some(Some(action)) if event_id == "clicked" => {
+                    let _ = self.actions.send(action);
+                }
+                Some(_) => {}
+                None => errors.push(id),
+            }
+        }
+        errors
+    }
+
+    fn about_to_show(&self, _id: i32) -> bool {
+        false
+    }
+
+    #[zbus(out_args("updates_needed", "id_errors"))]
+    fn about_to_show_group(&self, _ids: Vec<i32>) -> (Vec<i32>, Vec<i32>) {
+        (Vec::new(), Vec::new())
+    }
+
+    #[zbus(property)]
+    fn version(&self) -> u32 {
+        3
+    }
+
+    #[zbus(property)]
+    fn text_direction(&self) -> String {
+        "ltr".to_string()
+    }
+
+    #[zbus(property)]
+    fn status(&self) -> String {
+        "normal".to_string()
+    }
+
+    #[zbus(property)]
+    fn icon_theme_path(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// StatusNotifierItem tray icon.
+pub struct SniTray {
+    /// Bus address; the session bus if `None`.
+    address: Option<String>,
+    autostart: Arc<dyn Autostart>,
+    conn: Option<Connection>,
+    layout: Arc<Mutex<LayoutInfo>>,
+    color: Arc<Mutex<(u8, u8, u8)>>,
+    theme: Arc<Mutex<IconTheme>>,
+    status: Arc<Mutex<Option<Status>>>,
+    menu: Arc<Mutex<MenuState>>,
+    actions: Option<mpsc::Receiver<MenuAction>>,
+    on_action: Option<ActionCallback>,
+    /// Thread following the desktop colour scheme.
+    theme_thread: Option<JoinHandle<()>>,
+}
+
+impl SniTray {
+    /// Creates a tray icon on the session bus, or on the bus at `address`.
+    pub fn new(autostart: Arc<dyn Autostart>, address: Option<String>) -> Self {
+        Self {
+            address,
+            autostart,
+            conn: None,
+            layout: Arc::new(Mutex::new(LayoutInfo::from_xkb("us"))),
+            color: Arc::new(Mutex::new(ColorsConfig::default().for_layout(false))),
+            theme: Arc::new(Mutex::new(IconTheme::Dark)),
+            status: Arc::new(Mutex::new(None)),
+            menu: Arc::new(Mutex::new(MenuState {
+                indicators: true,
+                autostart: false,
+                conflicts: Vec::new(),
+                settings: Vec::new(),
+                revision: 1,
+            })),
+            actions: None,
+            on_action: None,
+            theme_thread: None,
+        }
+    }
+
+    /// Emits a signal of the item or the menu; ignored before `start`.
+    fn emit<B>(&self, path: &str, interface: &str, signal: &str, body: &B)
+    where
+        B: Serialize + zbus::zvariant::DynamicType,
+    {
+        if let Some(ref conn) = self.conn {
+            if let Err(e) = conn.emit_signal(None::<&str>, path, interface, signal, body) {
+                log::warn!("Failed to emit {}: {}", signal, e);
+            }
+        }
+    }
+
+    /// Changes the menu with `change` and, if it returns true, tells the
+    /// host to fetch the new layout.
+    fn update_menu(&self, change: impl FnOnce(&mut MenuState) -> bool) {
+        let revision = {
+            let mut menu = self.menu.lock();
+            if !change(&mut menu) {
+                return;
+            }
+            menu.revision += 1;
+            menu.revision
+        };
+        self.emit(
+            MENU_PATH,
+            MENU_INTERFACE,
+            "LayoutUpdated",
+            &(revision, 0i32),
+        );
+    }
+
+    /// Sets the checkmark of the entry `id` to the state `check` stores in
+    /// the menu, and tells the host if it changed.
+    fn set_checked(&self, id: i32, checked: bool, check: fn(&mut MenuState) -> &mut bool) {
+        {
+            let mut menu = self.menu.lock();
+            if *check(&mut menu) == checked {
+                return;
+            }
+            *check(&mut menu) = checked;
+            menu.revision += 1;
+        }
+        self.emit_toggle_states(vec![(id, checked)]);
+    }
+
+    /// Tells the host the new toggle states of the entries.
+    fn emit_toggle_states(&self, states: Vec<(i32, bool)>) {
+        let updates: Vec<(i32, HashMap<&str, Value>)> = states
+            .into_iter()
+            .map(|(id, checked)| {
+                let mut properties: HashMap<&str, Value> = HashMap::new();
+                properties.insert("toggle-state", (checked as i32).into());
+                (id, properties)
+            })
+            .collect();
+        self.emit(
+            MENU_PATH,
+            MENU_INTERFACE,
+            "ItemsPropertiesUpdated",
+            &(updates, Vec::<(i32, Vec<String>)>::new()),
+        );
+    }
+
+    /// Toggles autostart and updates the menu checkmark.
+    fn toggle_autostart(&self) {
+        let enabled = if self.autostart.is_enabled() {
+            self.autostart.disable();
+            false
+        } else {
+            self.autostart.enable();
+            true
+        };
+        self.set_checked(MENU_AUTOSTART, enabled, |menu| &mut menu.autostart);
+    }
+
+    /// Shows the About notification.
+    fn show_about(&self) {
+        let version = env!("CARGO_PKG_VERSION");
+        let body = format!(
+            "LangTip v{version}\n\nKeyboard layout indicator\n\nhttps://github.com/alexhmt/LangTip"
+        );
+        if !self.send_notification("About LangTip", &body) {
+            log::info!("LangTip v{}", version);
+        }
+    }
+
+    /// Sends a notification through `org.freedesktop.Notifications`.
+    /// Returns false if it couldn't be shown.
+    fn send_notification(&self, title: &str, text: &str) -> bool {
+        let Some(ref conn) = self.conn else {
+            return false;
+        };
+        let result = conn.call_method(
+            Some("org.freedesktop.Notifications"),
+            "/org/freedesktop/Notifications",
+            Some("org.freedesktop.Notifications"),
+            "Notify",
+            &(
+                "LangTip",
+                0u32,
+                "",
+                title,
+                text,
+                Vec::<&str>::new(),
+                HashMap::<&str, Value>::new(),
+                -1i32,
+            ),
+        );
+        if let Err(e) = &result {
+            log::warn!("Failed to show notification {:?}: {}", title, e);
+        }
+        result.is_ok()
+    }
+}
+
+impl TrayBackend for SniTray {
+    fn set_callback(&mut self, on_action: ActionCallback) {
+        self.on_action = Some(on_action);
+    }
+
+    fn start(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let (tx, rx) = mpsc::channel();
+        self.menu.lock().autostart = self.autostart.is_enabled();
+
+        let name = format!(
+            "org.kde.StatusNotifierItem-{}-{}",
+            std::process::id(),
+            ITEM_COUNTER.fetch_add(1, Ordering::SeqCst) + 1
+        );
+        let builder = match self.address {
+            Some(ref address) => zbus::blocking::connection::Builder::address(address.as_str())?,
+            None => zbus::blocking::connection::Builder::session()?,
+        };
+        let conn = builder
+            .name(name.as_str())?
+            .serve_at(
+                ITEM_PATH,
+                StatusNotifierItem {
+                    layout: self.layout.clone(),
+                    color: self.color.clone(),
+                    theme: self.theme.clone(),
+                    status: self.status.clone(),
+                },
+            )?
+            .serve_at(
+                MENU_PATH,
+                DBusMenu {
+                    state: self.menu.clone(),
+                    actions: tx,
+                },
+            )?
+            .build()?;
+
+        // Draw the first icon for the desktop theme before the host asks
+        if let Some(scheme) = read_color_scheme(&conn) {
+            *self.theme.lock() = icon_theme(scheme);
+        }
+        let (ready_tx, ready_rx) = mpsc::channel();
+        let (theme_conn, theme) = (conn.clone(), self.theme.clone());
+        self.theme_thread = Some(std::thread::spawn(move || {
+            theme_event_loop(theme_conn, theme, ready_tx);
+        }));
+        let _ = ready_rx.recv();
+
+        conn.call_method(
+            Some(WATCHER_SERVICE),
+            WATCHER_PATH,
+            Some(WATCHER_INTERFACE),
+            "RegisterStatusNotifierItem",
+            &(name.as_str(),),
+        )?;
+        log::info!("Tray icon registered as {}", name);
+
+        self.conn = Some(conn);
+        self.actions = Some(rx);
+        Ok(())
+    }
+
+    fn set_layout(&self, layout: &LayoutInfo, color: (u8, u8, u8)) {
+        {
+            let mut current = self.layout.lock();
+            let mut current_color = self.color.lock();
+            if *current == *layout && *current_color == color {
+                return;
+            }
+            *current = layout.clone();
+            *current_color = color;
+        }
+        self.emit(ITEM_PATH, ITEM_INTERFACE, "NewIcon", &());
+        self.emit(ITEM_PATH, ITEM_INTERFACE, "NewTitle", &());
+        self.emit(ITEM_PATH, ITEM_INTERFACE, "NewToolTip", &());
+    }
+
+    fn set_status(&self, status: &Status) {
+        {
+            let mut current = self.status.lock();
+            if current.as_ref() == Some(status) {
+                return;
+            }
+            *current = Some(status.clone());
+        }
+        self.emit(ITEM_PATH, ITEM_INTERFACE, "NewToolTip", &());
+        self.set_checked(MENU_INDICATORS, status.indicators_enabled(), |menu| {
+            &mut menu.indicators
+        });
+    }
+
+    fn set_hotkey_conflicts(&self, conflicts: &[BindingStatus]) {
+        let conflicts: Vec<String> = conflicts.iter().map(ToString::to_string).collect();
+        self.update_menu(|menu| {
+            if menu.conflicts == conflicts {
+                return false;
+            }
+            menu.conflicts = conflicts;
+            true
+        });
+    }
+
+    fn set_menu(&self, entries: &[MenuEntry]) {
+        self.update_menu(|menu| {
+            if menu.settings == entries {
+                return false;
+            }
+            menu.settings = entries.to_vec();
+            true
+        });
+    }
+
+    /// Moves the Layout check with a property update instead of a new
+    /// layout; the entry IDs stay the same.
+    fn set_active_layout(&self, code: &str) {
+        let states: Vec<(i32, bool)> = {
+            let mut menu = self.menu.lock();
+            let before = menu.root();
+            if !menu::check_layout(&mut menu.settings, code) {
+                return;
+            }
+            menu.revision += 1;
+            let after = menu.root();
+            after
+                .flatten()
+                .into_iter()
+                .zip(before.flatten())
+                .filter(|(new, old)| new.toggle != old.toggle)
+                .filter_map(|(new, _)| Some((new.id, new.toggle?.is_checked())))
+                .collect()
+        };
+        self.emit_toggle_states(states);
+    }
+
+    fn notify(&self, title: &str, text: &str) {
+        self.send_notification(title, text);
+    }
+
+    fn process_menu_events(&self) {
+        let Some(ref actions) = self.actions else {
+            return;
+        };
+        while let Ok(action) = actions.try_recv() {
+            let action = match action {
+                MenuAction::Indicators => Some(Action::Toggle),
+                MenuAction::Exit => Some(Action::Exit),
+                MenuAction::Autostart => {
+                    self.toggle_autostart();
+                    None
+                }
+                MenuAction::About => {
+                    self.show_about();
+                    None
+                }
+                MenuAction::Setting(action) => Some(action),
+            };
+            if let (Some(action), Some(cb)) = (action, self.on_action.as_ref()) {
+                cb(action);
+            }
+        }
+    }
+
+    fn stop(&mut self) {
+        if let (Some(thread), Some(ref conn)) = (self.theme_thread.take(), &self.conn) {
+            // Wake the theme thread with a signal addressed to ourselves
+            let _ = conn.emit_signal(conn.unique_name(), "/", STOP_INTERFACE, STOP_MEMBER, &());
+            let _ = thread.join();
+        }
+        // Dropping the connection releases the name; the watcher drops the item
+        self.conn = None;
+        self.actions = None;
+    }
+}
+
+impl Drop for SniTray {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hotkey::Registration;
+    use crate::platform::dbus::test_bus::TestBus;
+    use crate::status::Visibility;
+    use std::sync::atomic::AtomicBool;
+
+    #[derive(Default)]
+    struct TestAutostart {
+        enabled: AtomicBool,
+    }
+
+    impl Autostart for TestAutostart {
+        fn is_enabled(&self) -> bool {
+            self.enabled.load(Ordering::SeqCst)
+        }
+
+        fn enable(&self) -> bool {
+            self.enabled.store(true, Ordering::SeqCst);
+            true
+        }
+
+        fn disable(&self) -> bool {
+            self.enabled.store(false, Ordering::SeqCst);
+            true
+        }
+    }
+
+    /// StatusNotifierWatcher stand-in that records registered items.
+    struct TestWatcher {
+        items: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[zbus::interface(name = "org.kde.StatusNotifierWatcher")]
+    impl TestWatcher {
+        fn register_status_notifier_item(&self, service: &str) {
+            self.items.lock().push(service.to_string());
+        }
+    }
+
+    #[test]
+    fn icons_are_rendered_for_all_sizes() {
+        let red = (0xFF, 0x55, 0x55);
+        let ru = LayoutInfo::from_xkb("ru");
+        let icons = layout_icons(&ru, red, IconTheme::Dark);
+        assert_eq!(icons.len(), ICON_SIZES.len());
+        for (width, height, pixels) in &icons {
+            assert_eq!(width, height);
+            assert_eq!(pixels.len(), (width * height * 4) as usize);
+        }
+        assert_ne!(
+            layout_icons(&LayoutInfo::from_xkb("us"), red, IconTheme::Dark),
+            icons
+        );
+        assert_ne!(
+            layout_icons(&ru, (0x55, 0xFF, 0x55), IconTheme::Dark),
+            icons
+        );
+        assert_ne!(layout_icons(&ru, red, IconTheme::Light), icons);
+    }
+
+    #[test]
+    fn color_scheme_picks_the_icon_theme() {
+        assert_eq!(icon_theme(0), IconTheme::Dark);
+        assert_eq!(icon_theme(1), IconTheme::Dark);
+        assert_eq!(icon_theme(2), IconTheme::Light);
+        assert_eq!(color_scheme_value(&Value::U32(2)), Some(2));
+        // `Read` wraps the value once more
+        let nested = Value::Value(Box::new(Value::U32(1)));
+        assert_eq!(color_scheme_value(&nested), Some(1));
+        assert_eq!(color_scheme_value(&Value::from("dark")), None);
+    }
+
+    /// Settings portal stand-in that reports a fixed colour scheme.
+    struct TestPortal {
+        scheme: u32,
+    }
+
+    #[zbus::interface(name = "org.freedesktop.portal.Settings")]
+    impl TestPortal {
+        fn read_one(&self, namespace: &str, key: &str) -> zbus::fdo::Result<OwnedValue> {
+            if (namespace, key) != (APPEARANCE_NAMESPACE, COLOR_SCHEME_KEY) {
+                return Err(zbus::fdo::Error::Failed("Unknown setting".to_string()));
+            }
+            Ok(OwnedValue::from(self.scheme))
+        }
+    }
+
+    #[test]
+    fn icon_follows_the_portal_color_scheme() {
+        let Some(bus) = TestBus::start() else {
+            return;
+        };
+        let connect = || {
+            zbus::blocking::connection::Builder::address(bus.address.as_str()).unwrap()
+        };
+        let items = Arc::new(Mutex::new(Vec::new()));
+        let _watcher = connect()
+            .name(WATCHER_SERVICE)
+            .unwrap()
+            .serve_at(WATCHER_PATH, TestWatcher { items: items.clone() })
+            .unwrap()
+            .build()
+            .unwrap();
+        let portal = connect()
+            .name(PORTAL_SERVICE)
+            .unwrap()
+            .serve_at(PORTAL_PATH, TestPortal { scheme: 2 })
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let mut tray = SniTray::new(Arc::new(TestAutostart::default()), Some(bus.address.clone()));
+        tray.start().unwrap();
+        let service = items.lock().first().cloned().unwrap();
+        let client = connect().build().unwrap();
+        let icon = || {
+            let pixmaps = get_property(&client, &service, ITEM_PATH, "IconPixmap");
+            Vec::<IconPixmap>::try_from(pixmaps).unwrap()
+        };
+        let layout = LayoutInfo::from_xkb("us");
+        let color = ColorsConfig::default().for_layout(false);
+
+        // Light desktop at start
+        assert_eq!(icon(), layout_icons(&layout, color, IconTheme::Light));
+
+        // Switching to dark redraws the icon
+        portal
+            .emit_signal(
+                None::<&str>,
+                PORTAL_PATH,
+                PORTAL_INTERFACE,
+                "SettingChanged",
+                &(APPEARANCE_NAMESPACE, COLOR_SCHEME_KEY, Value::from(1u32)),
+            )
+            .unwrap();
+        let dark = layout_icons(&layout, color, IconTheme::Dark);
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        while icon() != dark && std::time::Instant::now() < deadline {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert_eq!(icon(), dark);
+
+        tray.stop();
+    }
+
+    fn get_property(client: &Connection, service: &str, path: &str, name: &str) -> OwnedValue {
+        let interface = if path == ITEM_PATH {
+            ITEM_INTERFACE
+        } else {
+            MENU_INTERFACE
+        };
+        client
+            .call_method(
+                Some(service),
+                path,
+                Some("org.freedesktop.DBus.Properties"),
+                "Get",
+                &(interface, name),
+            )
+            .unwrap()
+            .body()
+            .deserialize()
+            .unwrap()
+    }
+
+    fn click(client: &Connection, service: &str, id: i32) {
+        client
+            .call_method(
+                Some(service),
+                MENU_PATH,
+                Some(MENU_INTERFACE),
+                "Event",
+                &(id, "clicked", Value::from(0i32), 0u32),
+            )
+            .unwrap();
+    }
+
+    fn toggle_state(client: &Connection, service: &str, id: i32) -> i32 {
+        let value: OwnedValue = client
+            .call_method(
+                Some(service),
+                MENU_PATH,
+                Some(MENU_INTERFACE),
+                "GetProperty",
+                &(id, "toggle-state"),
+            )
+            .unwrap()
+            .body()
+            .deserialize()
+            .unwrap();
+        i32::try_from(value).unwrap()
+    }
+
+    #[test]
+    fn tray_registers_and_handles_menu_on_private_bus() {
+        let Some(bus) = TestBus::start() else {
+            return;
+        };
+        let items = Arc::new(Mutex::new(Vec::new()));
+        let _watcher = zbus::blocking::connection::Builder::address(bus.address.as_str())
+            .unwrap()
+            .name(WATCHER_SERVICE)
+            .unwrap()
+            .serve_at(
+                WATCHER_PATH,
+                TestWatcher {
+                    items: items.clone(),
+                },
+            )
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let autostart = Arc::new(TestAutostart::default());
+        let mut tray = SniTray::new(autostart.clone(), Some(bus.address.clone()));
+        let clicks = Arc::new(Mutex::new(Vec::new()));
+        let tray_clicks = clicks.clone();
+        tray.set_callback(Arc::new(move |action| tray_clicks.lock().push(action)));
+        tray.start().unwrap();
+
+        // Registered with the watcher under its own bus name
+        let service = items.lock().first().cloned().unwrap();
+        assert!(service.starts_with("org.kde.StatusNotifierItem-"));
+
+        let client = zbus::blocking::connection::Builder::address(bus.address.as_str())
+            .unwrap()
+            .build()
+            .unwrap();
+        let title = |client: &Connection| {
+            String::try_from(get_property(client, &service, ITEM_PATH, "Title")).unwrap()
+        };
+        assert_eq!(title(&client), "LangTip: EN");
+        let menu = get_property(&client, &service, ITEM_PATH, "Menu");
+        assert_eq!(OwnedObjectPath::try_from(menu).unwrap().as_str(), MENU_PATH);
+
+        // Layout changes update the title and icon
+        let icon_before = get_property(&client, &service, ITEM_PATH, "IconPixmap");
+        tray.set_layout(&LayoutInfo::from_xkb("ru"), (0xFF, 0x55, 0x55));
+        assert_eq!(title(&client), "LangTip: RU");
+        assert_ne!(
+            get_property(&client, &service, ITEM_PATH, "IconPixmap"),
+            icon_before
+        );
+
+        // Menu layout mirrors the Windows tray
+        let (revision, root): (u32, MenuNode) = client
+            .call_method(
+                Some(service.as_str()),
+                MENU_PATH,
+                Some(MENU_INTERFACE),
+                "GetLayout",
+                &(0i32, -1i32, Vec::<String>::new()),
+            )
+            .unwrap()
+            .body()
+            .deserialize()
+            .unwrap();
+        assert_eq!(revision, 1);
+        let labels: Vec<Option<String>> = root
+            .children
+            .into_iter()
+            .map(|child| {
+                let node = MenuNode::try_from(child).unwrap();
+                node.properties
+                    .get("label")
+                    .map(|l| String::try_from(l.try_clone().unwrap()).unwrap())
+            })
+            .collect();
+        assert_eq!(
+            labels,
+            MENU_ITEMS
+                .iter()
+                .map(|(_, l)| l.map(String::from))
+                .collect::<Vec<_>>()
+        );
+
+        // Hotkey conflicts are listed as disabled entries above the others
+        tray.set_hotkey_conflicts(&[BindingStatus {
+            keys: "ctrl+alt+l".to_string(),
+            action: "toggle".to_string(),
+            registration: Registration::Taken(Vec::new()),
+        }]);
+        let (revision, root): (u32, MenuNode) = client
+            .call_method(
+                Some(service.as_str()),
+                MENU_PATH,
+                Some(MENU_INTERFACE),
+                "GetLayout",
+                &(0i32, -1i32, Vec::<String>::new()),
+            )
+            .unwrap()
+            .body()
+            .deserialize()
+            .unwrap();
+        assert_eq!(revision, 2);
+        assert_eq!(root.children.len(), MENU_ITEMS.len() + 2);
+        let enabled: OwnedValue = client
+            .call_method(
+                Some(service.as_str()),
+                MENU_PATH,
+                Some(MENU_INTERFACE),
+                "GetProperty",
+                &(MENU_CONFLICTS, "enabled"),
+            )
+            .unwrap()
+            .body()
+            .deserialize()
+            .unwrap();
+        assert!(!bool::try_from(enabled).unwrap());
+        tray.set_hotkey_conflicts(&[]);
+
+        // Settings go below the visibility entries, submenus nested
+        tray.set_menu(&[
+            MenuEntry::Submenu {
+                label: "Opacity".to_string(),
+                entries: vec![MenuEntry::Item {
+                    label: "60%".to_string(),
+                    action: Action::SetOpacity(60),
+                    toggle: Some(Toggle::Radio(true)),
+                }],
+            },
+            MenuEntry::Separator,
+        ]);
+        let (_, root): (u32, MenuNode) = client
+            .call_method(
+                Some(service.as_str()),
+                MENU_PATH,
+                Some(MENU_INTERFACE),
+                "GetLayout",
+                &(0i32, -1i32, Vec::<String>::new()),
+            )
+            .unwrap()
+            .body()
+            .deserialize()
+            .unwrap();
+        assert_eq!(root.children.len(), MENU_ITEMS.len() + 3);
+        let opacity = MenuNode::try_from(root.children[2].try_clone().unwrap()).unwrap();
+        assert_eq!(opacity.id, MENU_SETTINGS);
+        assert_eq!(opacity.children.len(), 1);
+        let toggle = |name: &str| {
+            let value: OwnedValue = client
+                .call_method(
+                    Some(service.as_str()),
+                    MENU_PATH,
+                    Some(MENU_INTERFACE),
+                    "GetProperty",
+                    &(MENU_SETTINGS + 1, name),
+                )
+                .unwrap()
+                .body()
+                .deserialize()
+                .unwrap();
+            value
+        };
+        assert_eq!(String::try_from(toggle("toggle-type")).unwrap(), "radio");
+        assert_eq!(i32::try_from(toggle("toggle-state")).unwrap(), 1);
+
+        // Clicks are dispatched from the main loop
+        click(&client, &service, MENU_INDICATORS);
+        click(&client, &service, MENU_SETTINGS);
+        click(&client, &service, MENU_SETTINGS + 1);
+        click(&client, &service, MENU_AUTOSTART);
+        click(&client, &service, MENU_ABOUT);
+        click(&client, &service, MENU_EXIT);
+        assert!(clicks.lock().is_empty());
+        tray.process_menu_events();
+        assert_eq!(
+            *clicks.lock(),
+            vec![Action::Toggle, Action::SetOpacity(60), Action::Exit]
+        );
+        assert!(autostart.is_enabled());
+
+        assert_eq!(toggle_state(&client, &service, MENU_AUTOSTART), 1);
+
+        // The indicators checkmark follows the application status
+        assert_eq!(toggle_state(&client, &service, MENU_INDICATORS), 1);
+        let mut status = Status {
+            layout: "Russian".to_string(),
+            visibility: Visibility::Hidden,
+            profile: "default".to_string(),
+            switches_today: 0,
+            warnings: Vec::new(),
+        };
+        tray.set_status(&status);
+        assert_eq!(toggle_state(&client, &service, MENU_INDICATORS), 0);
+        status.visibility = Visibility::Visible;
+        tray.set_status(&status);
+        assert_eq!(toggle_state(&client, &service, MENU_INDICATORS), 1);
+
+        // A layout switch only moves the check of the Layout submenu
+        let layout = |code: &str, active| MenuEntry::Item {
+            label: code.to_uppercase(),
+            action: Action::SetLayout(code.to_string()),
+            toggle: Some(Toggle::Radio(active)),
+        };
+        tray.set_menu(&[MenuEntry::Submenu {
+            label: "Layout".to_string(),
+            entries: vec![layout("us", true), layout("ru", false)],
+        }]);
+        tray.set_active_layout("ru");
+        assert_eq!(toggle_state(&client, &service, MENU_SETTINGS + 1), 0);
+        assert_eq!(toggle_state(&client, &service, MENU_SETTINGS + 2), 1);
+
+        tray.stop();
+        assert!(client
+            .call_method(
+                Some(service.as_str()),
+                ITEM_PATH,
+                Some("org.freedesktop.DBus.Peer"),
+                "Ping",
+                &(),
+            )
+            .is_err());
+    }
+}
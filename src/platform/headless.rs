@@ -0,0 +1,985 @@
+//! Headless platform module.
+//!
+//! In-memory implementation of every platform trait. Nothing is drawn or
+//! registered with the OS; all state lives in a shared `HeadlessState` that a
+//! `HeadlessHandle` can inspect and drive (layout switches, hotkey presses,
+//! key gestures, tray clicks and settings, display changes).
+
+use super::{
+    ActionCallback, Autostart, Backends, ForwardCallback, HotkeyBackend, InstanceLock,
+    LayoutSource, MonitorProvider, Opener, Overlay, OverlayBackend, PlatformEvent, SoundOutput,
+    TrayBackend,
+};
+use crate::action::Action;
+use crate::config::{AppConfig, HotkeyBinding, HotkeyConfig};
+#[cfg(test)]
+use crate::gesture::{GestureKey, GestureRecognizer, KeyEvent};
+use crate::hotkey::{self, BindingStatus, Hotkey, HotkeyRegistry, Registration};
+use crate::indicator::{Fade, Position};
+use crate::layout::{
+    self, HookTimings, InstalledLayout, LayoutCallback, LayoutEvent, LayoutInfo, LayoutSwitch,
+};
+use crate::menu::{self, MenuEntry};
+use crate::monitors::MonitorInfo;
+#[cfg(test)]
+use crate::remap::KeyRemapper;
+use crate::status::Status;
+use parking_lot::Mutex;
+use std::collections::{BTreeMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Snapshot of a headless overlay.
+#[derive(Debug, Clone)]
+pub struct OverlayState {
+    #[cfg(test)]
+    pub position: Position,
+    #[cfg(test)]
+    pub monitor: MonitorInfo,
+    pub text: String,
+    pub is_russian: bool,
+    pub alpha: u8,
+    pub target_alpha: u8,
+}
+
+/// Tray menu entries that can be clicked.
+#[cfg(test)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrayClick {
+    /// The "Indicators enabled" checkmark.
+    Indicators,
+    Autostart,
+    About,
+    Exit,
+}
+
+/// Layouts installed on the headless platform, in switching order, with the
+/// codes `set-layout` finds them by.
+const LAYOUTS: [(&str, [&str; 3]); 2] = [
+    ("EN", ["us", "en-US", "00000409"]),
+    ("RU", ["ru", "ru-RU", "00000419"]),
+];
+
+/// Registered hotkeys and their callback.
+struct HotkeyRegistration {
+    config: HotkeyConfig,
+    status: Vec<BindingStatus>,
+    #[cfg(test)]
+    gestures: GestureRecognizer<GestureKey>,
+    #[cfg(test)]
+    remap: KeyRemapper<GestureKey>,
+    #[cfg(test)]
+    on_action: ActionCallback,
+}
+
+/// Shared state of the headless platform.
+struct HeadlessState {
+    overlays: BTreeMap<u64, OverlayState>,
+    next_overlay_id: u64,
+    monitors: Vec<MonitorInfo>,
+    events: VecDeque<PlatformEvent>,
+    layout: LayoutInfo,
+    layout_callback: Option<LayoutCallback>,
+    reported_layout: Option<String>,
+    timings: Option<HookTimings>,
+    hotkeys: Option<HotkeyRegistration>,
+    /// Combinations held by "other applications".
+    occupied_hotkeys: Vec<Hotkey>,
+    tray_started: bool,
+    #[cfg(test)]
+    tray_clicks: VecDeque<TrayClick>,
+    /// Actions of clicked settings entries.
+    tray_menu_clicks: VecDeque<Action>,
+    tray_menu: Vec<MenuEntry>,
+    /// How many times the whole menu was set.
+    tray_menu_sets: usize,
+    tray_layout: Option<String>,
+    tray_conflicts: Vec<String>,
+    tray_status: Option<Status>,
+    /// How many times the status was set.
+    tray_status_sets: usize,
+    notifications: Vec<(String, String)>,
+    autostart: bool,
+    instance_locked: bool,
+    forward_callback: Option<ForwardCallback>,
+    already_running_notices: usize,
+    beeps: Vec<(u32, u32)>,
+    opened: Vec<PathBuf>,
+}
+
+impl Default for HeadlessState {
+    fn default() -> Self {
+        Self {
+            overlays: BTreeMap::new(),
+            next_overlay_id: 0,
+            monitors: vec![default_monitor()],
+            events: VecDeque::new(),
+            layout: LayoutInfo {
+                name: "EN".to_string(),
+                is_russian: false,
+            },
+            layout_callback: None,
+            reported_layout: None,
+            timings: None,
+            hotkeys: None,
+            occupied_hotkeys: Vec::new(),
+            tray_started: false,
+            #[cfg(test)]
+            tray_clicks: VecDeque::new(),
+            tray_menu_clicks: VecDeque::new(),
+            tray_menu: Vec::new(),
+            tray_menu_sets: 0,
+            tray_layout: None,
+            tray_conflicts: Vec::new(),
+            tray_status: None,
+            tray_status_sets: 0,
+            notifications: Vec::new(),
+            autostart: false,
+            instance_locked: false,
+            forward_callback: None,
+            already_running_notices: 0,
+            beeps: Vec::new(),
+            opened: Vec::new(),
+        }
+    }
+}
+
+/// A single 1920x1080 monitor with a 40 px taskbar at the bottom.
+fn default_monitor() -> MonitorInfo {
+    MonitorInfo {
+        x: 0,
+        y: 0,
+        width: 1920,
+        height: 1080,
+        work_x: 0,
+        work_y: 0,
+        work_width: 1920,
+        work_height: 1040,
+        is_primary: true,
+    }
+}
+
+type SharedState = Arc<Mutex<HeadlessState>>;
+
+/// Headless platform: creates backends and handles sharing one state.
+#[derive(Clone, Default)]
+pub struct HeadlessPlatform {
+    state: SharedState,
+}
+
+impl HeadlessPlatform {
+    /// Creates a headless platform with one monitor and the EN layout.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a handle to inspect and drive the platform.
+    #[cfg(test)]
+    pub fn handle(&self) -> HeadlessHandle {
+        HeadlessHandle {
+            state: self.state.clone(),
+        }
+    }
+
+    /// Creates a set of backends on this platform.
+    ///
+    /// Backends created from the same platform share state, including the
+    /// instance lock.
+    pub fn backends(&self) -> Backends {
+        let autostart = Arc::new(HeadlessAutostart {
+            state: self.state.clone(),
+        });
+        Backends {
+            overlays: Box::new(HeadlessOverlays {
+                state: self.state.clone(),
+            }),
+            monitors: Box::new(HeadlessMonitors {
+                state: self.state.clone(),
+            }),
+            layout: Box::new(HeadlessLayoutSource {
+                state: self.state.clone(),
+            }),
+            hotkeys: Box::new(HeadlessHotkeys {
+                state: self.state.clone(),
+            }),
+            tray: Box::new(HeadlessTray {
+                state: self.state.clone(),
+                #[cfg(test)]
+                autostart: autostart.clone(),
+                on_action: None,
+            }),
+            autostart,
+            instance: Box::new(HeadlessInstanceLock {
+                state: self.state.clone(),
+                held: false,
+            }),
+            sound: Box::new(HeadlessSound {
+                state: self.state.clone(),
+            }),
+            opener: Box::new(HeadlessOpener {
+                state: self.state.clone(),
+            }),
+        }
+    }
+}
+
+/// Inspects and drives a headless platform.
+#[cfg(test)]
+#[derive(Clone)]
+pub struct HeadlessHandle {
+    state: SharedState,
+}
+
+#[cfg(test)]
+impl HeadlessHandle {
+    /// Returns the live overlays in creation order.
+    pub fn overlays(&self) -> Vec<OverlayState> {
+        self.state.lock().overlays.values().cloned().collect()
+    }
+
+    /// Switches the current layout, reporting it if the source is started.
+    pub fn switch_layout(&self, name: &str) {
+        set_layout(&self.state, name);
+    }
+
+    /// Presses a key combination. Returns false if no registered binding
+    /// matches it.
+    pub fn press(&self, keys: &str) -> bool {
+        let Ok(pressed) = hotkey::parse_hotkey(keys) else {
+            return false;
+        };
+        let found = {
+            let state = self.state.lock();
+            state.hotkeys.as_ref().and_then(|h| {
+                h.status
+                    .iter()
+                    .find(|s| {
+                        s.active_keys()
+                            .is_some_and(|keys| hotkey::parse_hotkey(keys) == Ok(pressed))
+                    })
+                    .and_then(|s| Some((s.action.parse().ok()?, h.on_action.clone())))
+            })
+        };
+        found.map(|(action, cb)| cb(action)).is_some()
+    }
+
+    /// Releases a combination pressed with `press`. Ends the peek of a
+    /// `show-while-held` binding; returns false for any other combination.
+    pub fn release(&self, keys: &str) -> bool {
+        let Ok(released) = hotkey::parse_hotkey(keys) else {
+            return false;
+        };
+        let found = {
+            let state = self.state.lock();
+            state.hotkeys.as_ref().and_then(|h| {
+                h.status
+                    .iter()
+                    .filter(|s| s.action.parse() == Ok(Action::PeekWhileHeld))
+                    .find(|s| {
+                        s.active_keys()
+                            .is_some_and(|keys| hotkey::parse_hotkey(keys) == Ok(released))
+                    })
+                    .map(|_| h.on_action.clone())
+            })
+        };
+        found.map(|cb| cb(Action::EndPeek)).is_some()
+    }
+
+    /// Presses the toggle hotkey. Returns false if hotkeys aren't registered.
+    pub fn press_toggle(&self) -> bool {
+        let keys = self.hotkey_config().map(|c| c.toggle);
+        keys.is_some_and(|keys| self.press(&keys))
+    }
+
+    /// Presses the exit hotkey. Returns false if hotkeys aren't registered.
+    pub fn press_exit(&self) -> bool {
+        let keys = self.hotkey_config().map(|c| c.exit);
+        keys.is_some_and(|keys| self.press(&keys))
+    }
+
+    /// Presses (`down`) or releases a key at `at`. Runs the action of a
+    /// remapped key, then dispatches the actions of the gestures the event
+    /// completes. A modifier without a side stands for its left key.
+    /// Returns false if a remap keeps the event from applications.
+    pub fn key_event(&self, key: &str, down: bool, at: Duration) -> bool {
+        let Ok(key) = key.parse::<GestureKey>() else {
+            return true;
+        };
+        let key = key.physical()[0];
+        let remapped = {
+            let mut state = self.state.lock();
+            state
+                .hotkeys
+                .as_mut()
+                .map(|h| (h.remap.handle(key, down), h.on_action.clone()))
+        };
+        let swallow = match remapped {
+            Some((remapped, cb)) => {
+                if let Some(action) = remapped.action {
+                    cb(action);
+                }
+                remapped.swallow
+            }
+            None => false,
+        };
+        self.dispatch_gestures(|gestures| gestures.handle(KeyEvent { key, down, at }));
+        !swallow
+    }
+
+    /// Advances the gesture recogniser to `at`, dispatching held keys and
+    /// held back taps that came due.
+    pub fn advance_gestures(&self, at: Duration) {
+        self.dispatch_gestures(|gestures| gestures.tick(at));
+    }
+
+    fn dispatch_gestures(&self, f: impl FnOnce(&mut GestureRecognizer<GestureKey>) -> Vec<Action>) {
+        let dispatch = {
+            let mut state = self.state.lock();
+            state
+                .hotkeys
+                .as_mut()
+                .map(|h| (f(&mut h.gestures), h.on_action.clone()))
+        };
+        if let Some((actions, cb)) = dispatch {
+            actions.into_iter().for_each(|action| cb(action));
+        }
+    }
+
+    /// Returns the registered hotkey config, if hotkeys are started.
+    pub fn hotkey_config(&self) -> Option<HotkeyConfig> {
+        self.state.lock().hotkeys.as_ref().map(|h| h.config.clone())
+    }
+
+    /// Marks a combination as held by another application, so registering
+    /// it fails from the next `start` on.
+    pub fn occupy_hotkey(&self, keys: &str) {
+        if let Ok(hotkey) = hotkey::parse_hotkey(keys) {
+            self.state.lock().occupied_hotkeys.push(hotkey);
+        }
+    }
+
+    /// Queues a tray menu click, handled by the next `process_menu_events`.
+    pub fn click_tray(&self, click: TrayClick) {
+        self.state.lock().tray_clicks.push_back(click);
+    }
+
+    /// Clicks a settings entry of the tray menu, given by the labels of its
+    /// submenus and its own (e.g. `["Positions", "Center"]`). Returns false
+    /// if there is no such entry.
+    pub fn click_tray_menu(&self, path: &[&str]) -> bool {
+        let mut state = self.state.lock();
+        let mut entries = state.tray_menu.as_slice();
+        let mut action = None;
+        for (i, label) in path.iter().enumerate() {
+            let last = i + 1 == path.len();
+            let found = entries.iter().find_map(|entry| match entry {
+                MenuEntry::Submenu { label: l, entries } if l == label && !last => {
+                    Some((Some(entries.as_slice()), None))
+                }
+                MenuEntry::Item { label: l, action, .. } if l == label && last => {
+                    Some((None, Some(action.clone())))
+                }
+                _ => None,
+            });
+            match found {
+                Some((Some(submenu), _)) => entries = submenu,
+                Some((None, found)) => action = found,
+                None => return false,
+            }
+        }
+        match action {
+            Some(action) => {
+                state.tray_menu_clicks.push_back(action);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the settings entries shown in the tray menu.
+    pub fn tray_menu(&self) -> Vec<MenuEntry> {
+        self.state.lock().tray_menu.clone()
+    }
+
+    /// Returns how many times the whole settings menu was set.
+    pub fn tray_menu_sets(&self) -> usize {
+        self.state.lock().tray_menu_sets
+    }
+
+    /// Returns whether the tray icon is shown.
+    pub fn tray_started(&self) -> bool {
+        self.state.lock().tray_started
+    }
+
+    /// Returns the layout shown on the tray icon.
+    pub fn tray_layout(&self) -> Option<String> {
+        self.state.lock().tray_layout.clone()
+    }
+
+    /// Returns the hotkey conflicts listed in the tray menu.
+    pub fn tray_conflicts(&self) -> Vec<String> {
+        self.state.lock().tray_conflicts.clone()
+    }
+
+    /// Returns the status shown in the tray tooltip.
+    pub fn tray_status(&self) -> Option<Status> {
+        self.state.lock().tray_status.clone()
+    }
+
+    /// Returns how many times the status was set.
+    pub fn tray_status_sets(&self) -> usize {
+        self.state.lock().tray_status_sets
+    }
+
+    /// Returns whether the tray's "Indicators enabled" entry is checked.
+    pub fn tray_indicators_enabled(&self) -> bool {
+        self.state
+            .lock()
+            .tray_status
+            .as_ref()
+            .is_some_and(Status::indicators_enabled)
+    }
+
+    /// Returns the notifications shown as (title, text) pairs.
+    pub fn notifications(&self) -> Vec<(String, String)> {
+        self.state.lock().notifications.clone()
+    }
+
+    /// Returns whether the layout source is started.
+    pub fn layout_source_started(&self) -> bool {
+        self.state.lock().layout_callback.is_some()
+    }
+
+    /// Returns the timings the layout source was configured with.
+    pub fn timings(&self) -> Option<HookTimings> {
+        self.state.lock().timings
+    }
+
+    /// Returns whether autostart is enabled.
+    pub fn autostart_enabled(&self) -> bool {
+        self.state.lock().autostart
+    }
+
+    /// Returns whether the instance lock is held.
+    pub fn instance_locked(&self) -> bool {
+        self.state.lock().instance_locked
+    }
+
+    /// Forwards arguments to the instance holding the lock, like a second
+    /// launch does. Returns false if it isn't listening.
+    pub fn forward_args(&self, args: &[&str]) -> bool {
+        let callback = self.state.lock().forward_callback.clone();
+        match callback {
+            Some(callback) => {
+                callback(args.iter().map(|s| s.to_string()).collect());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns how many times "already running" was shown.
+    pub fn already_running_notices(&self) -> usize {
+        self.state.lock().already_running_notices
+    }
+
+    /// Returns the files opened so far.
+    pub fn opened(&self) -> Vec<PathBuf> {
+        self.state.lock().opened.clone()
+    }
+
+    /// Returns the played beeps as (frequency, duration) pairs.
+    pub fn beeps(&self) -> Vec<(u32, u32)> {
+        self.state.lock().beeps.clone()
+    }
+
+    /// Replaces the monitors and reports a display change.
+    pub fn change_display(&self, monitors: Vec<MonitorInfo>) {
+        let mut state = self.state.lock();
+        state.monitors = monitors;
+        state.events.push_back(PlatformEvent::DisplayChanged);
+    }
+
+    /// Asks the application to quit through the window system.
+    pub fn quit(&self) {
+        self.state.lock().events.push_back(PlatformEvent::Quit);
+    }
+}
+
+/// Headless overlay backend.
+struct HeadlessOverlays {
+    state: SharedState,
+}
+
+impl OverlayBackend for HeadlessOverlays {
+    #[cfg_attr(not(test), allow(unused_variables))]
+    fn create_overlay(
+        &mut self,
+        position: Position,
+        config: &AppConfig,
+        monitor: &MonitorInfo,
+    ) -> Option<Box<dyn Overlay>> {
+        let mut state = self.state.lock();
+        let id = state.next_overlay_id;
+        state.next_overlay_id += 1;
+        state.overlays.insert(
+            id,
+            OverlayState {
+                #[cfg(test)]
+                position,
+                #[cfg(test)]
+                monitor: monitor.clone(),
+                text: "EN".to_string(),
+                is_russian: false,
+                alpha: 0,
+                target_alpha: 0,
+            },
+        );
+
+        Some(Box::new(HeadlessOverlay {
+            state: self.state.clone(),
+            id,
+            fade: Fade::new(config.opacity),
+        }))
+    }
+
+    fn pump_events(&mut self) -> Vec<PlatformEvent> {
+        let events: Vec<PlatformEvent> = self.state.lock().events.drain(..).collect();
+        if events.is_empty() {
+            std::thread::sleep(Duration::from_millis(16));
+        }
+        events
+    }
+}
+
+/// Headless overlay, mirrored into the shared state.
+struct HeadlessOverlay {
+    state: SharedState,
+    id: u64,
+    fade: Fade,
+}
+
+impl HeadlessOverlay {
+    fn with_state(&self, f: impl FnOnce(&mut OverlayState)) {
+        if let Some(overlay) = self.state.lock().overlays.get_mut(&self.id) {
+            f(overlay);
+        }
+    }
+}
+
+impl Overlay for HeadlessOverlay {
+    fn update_text(&self, text: &str, is_russian: bool) {
+        self.with_state(|o| {
+            o.text = text.to_string();
+            o.is_russian = is_russian;
+        });
+    }
+
+    fn show(&self) {
+        self.fade.fade_in();
+        let target = self.fade.target();
+        self.with_state(|o| o.target_alpha = target);
+    }
+
+    fn hide(&self) {
+        self.fade.fade_out();
+        self.with_state(|o| o.target_alpha = 0);
+    }
+
+    fn update_fade(&self) -> bool {
+        match self.fade.step() {
+            Some(alpha) => {
+                self.with_state(|o| o.alpha = alpha);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl Drop for HeadlessOverlay {
+    fn drop(&mut self) {
+        self.state.lock().overlays.remove(&self.id);
+    }
+}
+
+/// Headless monitor provider.
+struct HeadlessMonitors {
+    state: SharedState,
+}
+
+impl MonitorProvider for HeadlessMonitors {
+    fn monitors(&self) -> Vec<MonitorInfo> {
+        self.state.lock().monitors.clone()
+    }
+}
+
+/// Headless layout source, driven by `HeadlessHandle::switch_layout`.
+struct HeadlessLayoutSource {
+    state: SharedState,
+}
+
+impl LayoutSource for HeadlessLayoutSource {
+    fn current_layout(&self) -> LayoutInfo {
+        self.state.lock().layout.clone()
+    }
+
+    fn start(&mut self, callback: LayoutCallback, initial: &LayoutInfo, timings: HookTimings) {
+        let mut state = self.state.lock();
+        state.layout_callback = Some(callback);
+        state.reported_layout = Some(initial.name.clone());
+        state.timings = Some(timings);
+    }
+
+    fn installed_layouts(&self) -> Vec<InstalledLayout> {
+        let current = self.state.lock().layout.name.clone();
+        LAYOUTS
+            .iter()
+            .map(|(name, codes)| InstalledLayout {
+                code: codes[0].to_string(),
+                label: name.to_string(),
+                active: *name == current,
+            })
+            .collect()
+    }
+
+    fn set_timings(&mut self, timings: HookTimings) {
+        self.state.lock().timings = Some(timings);
+    }
+
+    fn stop(&mut self) {
+        let mut state = self.state.lock();
+        state.layout_callback = None;
+        state.reported_layout = None;
+    }
+
+    fn switch_layout(&mut self, target: &LayoutSwitch) -> bool {
+        let index = match target {
+            LayoutSwitch::Next => {
+                let current = self.state.lock().layout.name.clone();
+                let index = LAYOUTS.iter().position(|(name, _)| *name == current);
+                (index.unwrap_or(0) + 1) % LAYOUTS.len()
+            }
+            LayoutSwitch::To(code) => {
+                let codes: Vec<[&str; 3]> = LAYOUTS.iter().map(|(_, codes)| *codes).collect();
+                match layout::find_layout(code, &codes) {
+                    Some(index) => index,
+                    None => return false,
+                }
+            }
+        };
+        set_layout(&self.state, LAYOUTS[index].0);
+        true
+    }
+}
+
+/// Sets the current layout, reporting it if the source is started.
+fn set_layout(state: &SharedState, name: &str) {
+    let layout = LayoutInfo {
+        name: name.to_string(),
+        is_russian: name == "RU",
+    };
+
+    let callback = {
+        let mut state = state.lock();
+        state.layout = layout.clone();
+        match state.reported_layout {
+            Some(ref reported) if *reported != layout.name => {
+                state.reported_layout = Some(layout.name.clone());
+                state.layout_callback.clone()
+            }
+            _ => None,
+        }
+    };
+
+    if let Some(cb) = callback {
+        let now = Instant::now();
+        cb(LayoutEvent {
+            layout,
+            triggered_at: now,
+            detected_at: now,
+        });
+    }
+}
+
+/// Headless hotkeys, driven by `HeadlessHandle::press_*`.
+struct HeadlessHotkeys {
+    state: SharedState,
+}
+
+/// Headless "registration": fails for combinations occupied by other
+/// applications.
+struct Registry<'a> {
+    occupied: &'a [Hotkey],
+    /// Every combination in the config, kept out of the suggestions.
+    configured: Vec<Hotkey>,
+}
+
+impl<'a> Registry<'a> {
+    fn new(occupied: &'a [Hotkey], bindings: &[HotkeyBinding]) -> Self {
+        Self {
+            occupied,
+            configured: hotkey::configured_hotkeys(bindings),
+        }
+    }
+}
+
+impl HotkeyRegistry for Registry<'_> {
+    fn register(&mut self, binding: &HotkeyBinding) -> Registration {
+        match binding.parse() {
+            Err(e) => Registration::Invalid(e),
+            Ok((hotkey, _)) if self.occupied.contains(&hotkey) => Registration::Taken(
+                hotkey::suggest_alternatives(&hotkey, &self.configured, |h| {
+                    !self.occupied.contains(h)
+                }),
+            ),
+            Ok(_) => Registration::Registered,
+        }
+    }
+
+    fn unregister(&mut self, _binding: &HotkeyBinding) {}
+}
+
+impl HotkeyBackend for HeadlessHotkeys {
+    #[cfg_attr(not(test), allow(unused_variables))]
+    fn start(&mut self, config: &HotkeyConfig, on_action: ActionCallback) {
+        if !config.enabled {
+            return;
+        }
+        let mut state = self.state.lock();
+        let bindings = config.all_bindings();
+        let mut registry = Registry::new(&state.occupied_hotkeys, &bindings);
+        let status = bindings
+            .iter()
+            .map(|binding| BindingStatus {
+                keys: binding.keys.clone(),
+                action: binding.action.clone(),
+                registration: registry.register(binding),
+            })
+            .collect();
+        state.hotkeys = Some(HotkeyRegistration {
+            config: config.clone(),
+            status,
+            #[cfg(test)]
+            gestures: GestureRecognizer::new(&config.gestures, Some),
+            #[cfg(test)]
+            remap: KeyRemapper::new(&config.remap, Some),
+            #[cfg(test)]
+            on_action,
+        });
+    }
+
+    fn status(&self) -> Vec<BindingStatus> {
+        let state = self.state.lock();
+        state
+            .hotkeys
+            .as_ref()
+            .map(|h| h.status.clone())
+            .unwrap_or_default()
+    }
+
+    fn reload(&mut self, config: &HotkeyConfig) {
+        let mut state = self.state.lock();
+        let state = &mut *state;
+        let Some(ref mut hotkeys) = state.hotkeys else {
+            return;
+        };
+        let bindings = config.all_bindings();
+        let mut registry = Registry::new(&state.occupied_hotkeys, &bindings);
+        hotkeys.status = hotkey::reload_bindings(&hotkeys.status, &bindings, &mut registry);
+        #[cfg(test)]
+        {
+            hotkeys.gestures = GestureRecognizer::new(&config.gestures, Some);
+            hotkeys.remap = KeyRemapper::new(&config.remap, Some);
+        }
+        hotkeys.config = config.clone();
+    }
+
+    fn stop(&mut self) {
+        self.state.lock().hotkeys = None;
+    }
+}
+
+/// Headless tray, driven by `HeadlessHandle::click_tray`.
+struct HeadlessTray {
+    state: SharedState,
+    #[cfg(test)]
+    autostart: Arc<dyn Autostart>,
+    on_action: Option<ActionCallback>,
+}
+
+impl TrayBackend for HeadlessTray {
+    fn set_callback(&mut self, on_action: ActionCallback) {
+        self.on_action = Some(on_action);
+    }
+
+    fn start(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.state.lock().tray_started = true;
+        Ok(())
+    }
+
+    fn set_layout(&self, layout: &LayoutInfo, _color: (u8, u8, u8)) {
+        self.state.lock().tray_layout = Some(layout.name.clone());
+    }
+
+    fn set_hotkey_conflicts(&self, conflicts: &[BindingStatus]) {
+        self.state.lock().tray_conflicts = conflicts.iter().map(ToString::to_string).collect();
+    }
+
+    fn set_menu(&self, entries: &[MenuEntry]) {
+        let mut state = self.state.lock();
+        state.tray_menu = entries.to_vec();
+        state.tray_menu_sets += 1;
+    }
+
+    fn set_active_layout(&self, code: &str) {
+        menu::check_layout(&mut self.state.lock().tray_menu, code);
+    }
+
+    fn set_status(&self, status: &Status) {
+        let mut state = self.state.lock();
+        state.tray_status = Some(status.clone());
+        state.tray_status_sets += 1;
+    }
+
+    fn notify(&self, title: &str, text: &str) {
+        self.state
+            .lock()
+            .notifications
+            .push((title.to_string(), text.to_string()));
+    }
+
+    fn process_menu_events(&self) {
+        let menu_click = self.state.lock().tray_menu_clicks.pop_front();
+        if let (Some(action), Some(cb)) = (menu_click, self.on_action.as_ref()) {
+            cb(action);
+        }
+        #[cfg(test)]
+        self.process_click();
+    }
+
+    fn stop(&mut self) {
+        self.state.lock().tray_started = false;
+    }
+}
+
+#[cfg(test)]
+impl HeadlessTray {
+    /// Handles a click queued with `HeadlessHandle::click_tray`.
+    fn process_click(&self) {
+        let click = self.state.lock().tray_clicks.pop_front();
+        let action = match click {
+            Some(TrayClick::Indicators) => Some(Action::Toggle),
+            Some(TrayClick::Exit) => Some(Action::Exit),
+            Some(TrayClick::Autostart) => {
+                if self.autostart.is_enabled() {
+                    self.autostart.disable();
+                } else {
+                    self.autostart.enable();
+                }
+                None
+            }
+            Some(TrayClick::About) => {
+                log::info!("LangTip v{}", env!("CARGO_PKG_VERSION"));
+                None
+            }
+            None => None,
+        };
+        if let (Some(action), Some(cb)) = (action, self.on_action.as_ref()) {
+            cb(action);
+        }
+    }
+}
+
+/// Headless autostart flag.
+struct HeadlessAutostart {
+    state: SharedState,
+}
+
+impl Autostart for HeadlessAutostart {
+    fn is_enabled(&self) -> bool {
+        self.state.lock().autostart
+    }
+
+    fn enable(&self) -> bool {
+        self.state.lock().autostart = true;
+        true
+    }
+
+    fn disable(&self) -> bool {
+        self.state.lock().autostart = false;
+        true
+    }
+}
+
+/// Headless instance lock, shared by all backends of one platform.
+struct HeadlessInstanceLock {
+    state: SharedState,
+    held: bool,
+}
+
+impl InstanceLock for HeadlessInstanceLock {
+    fn acquire(&mut self) -> bool {
+        let mut state = self.state.lock();
+        if state.instance_locked {
+            return false;
+        }
+        state.instance_locked = true;
+        self.held = true;
+        true
+    }
+
+    fn listen(&mut self, on_forward: ForwardCallback) {
+        if self.held {
+            self.state.lock().forward_callback = Some(on_forward);
+        }
+    }
+
+    fn forward(&self, args: &[String]) -> bool {
+        let callback = self.state.lock().forward_callback.clone();
+        match callback {
+            Some(callback) => {
+                callback(args.to_vec());
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn release(&mut self) {
+        if std::mem::take(&mut self.held) {
+            let mut state = self.state.lock();
+            state.instance_locked = false;
+            state.forward_callback = None;
+        }
+    }
+
+    fn notify_already_running(&self) {
+        log::warn!("LangTip is already running");
+        self.state.lock().already_running_notices += 1;
+    }
+}
+
+/// Headless sound output recording the beeps.
+struct HeadlessSound {
+    state: SharedState,
+}
+
+impl SoundOutput for HeadlessSound {
+    fn beep(&self, frequency: u32, duration_ms: u32) {
+        self.state.lock().beeps.push((frequency, duration_ms));
+    }
+}
+
+/// Headless opener recording the opened files.
+struct HeadlessOpener {
+    state: SharedState,
+}
+
+impl Opener for HeadlessOpener {
+    fn open(&self, path: &Path) -> bool {
+        self.state.lock().opened.push(path.to_path_buf());
+        true
+    }
+}
@@ -0,0 +1,310 @@
+//! Platform abstraction module.
+//!
+//! Traits for everything the application needs from the operating system.
+//! The Win32 implementation lives in `win32` (`cfg(windows)`), the Linux ones
+//! in `x11`, `wayland`, `dbus`, `xdg` and `unix_instance`; `headless` is an
+//! in-memory implementation used where no native backend exists and by the
+//! app flow tests.
+
+#[cfg(any(not(windows), test))]
+pub mod headless;
+#[cfg(windows)]
+pub mod win32;
+#[cfg(target_os = "linux")]
+pub mod dbus;
+#[cfg(target_os = "linux")]
+pub mod unix_instance;
+#[cfg(target_os = "linux")]
+pub mod wayland;
+#[cfg(target_os = "linux")]
+pub mod x11;
+#[cfg(target_os = "linux")]
+pub mod xdg;
+
+use crate::action::Action;
+use crate::config::{AppConfig, HotkeyConfig};
+use crate::hotkey::BindingStatus;
+use crate::indicator::Position;
+use crate::layout::{HookTimings, InstalledLayout, LayoutCallback, LayoutInfo, LayoutSwitch};
+use crate::menu::MenuEntry;
+use crate::monitors::MonitorInfo;
+use crate::status::Status;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Callback type for actions requested by hotkeys and the tray menu.
+pub type ActionCallback = Arc<dyn Fn(Action) + Send + Sync>;
+
+/// Callback type for arguments forwarded by a second launch.
+pub type ForwardCallback = Arc<dyn Fn(Vec<String>) + Send + Sync>;
+
+/// A single indicator window.
+pub trait Overlay {
+    /// Updates the indicator text.
+    fn update_text(&self, text: &str, is_russian: bool);
+    /// Shows the indicator with fade-in animation.
+    fn show(&self);
+    /// Hides the indicator with fade-out animation.
+    fn hide(&self);
+    /// Advances the fade animation. Returns true while it is in progress.
+    fn update_fade(&self) -> bool;
+}
+
+/// Window system event reported by the overlay backend.
+#[cfg_attr(not(any(windows, test)), allow(dead_code))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlatformEvent {
+    /// Monitor configuration changed; indicators must be recreated.
+    DisplayChanged,
+    /// The window system asked the application to quit.
+    Quit,
+}
+
+/// Window system backend that creates overlays and pumps their events.
+pub trait OverlayBackend {
+    /// Creates an indicator overlay at `position` on `monitor`.
+    fn create_overlay(
+        &mut self,
+        position: Position,
+        config: &AppConfig,
+        monitor: &MonitorInfo,
+    ) -> Option<Box<dyn Overlay>>;
+
+    /// Processes pending window system events, waiting about one frame if
+    /// there are none. Called once per main loop iteration.
+    fn pump_events(&mut self) -> Vec<PlatformEvent>;
+}
+
+/// Enumerates connected monitors.
+pub trait MonitorProvider {
+    /// Gets a list of all connected monitors, primary first.
+    fn monitors(&self) -> Vec<MonitorInfo>;
+}
+
+/// Reports the active keyboard layout and its changes.
+pub trait LayoutSource {
+    /// Gets the current keyboard layout.
+    fn current_layout(&self) -> LayoutInfo;
+    /// Gets the full name of the current layout with its variant (e.g.
+    /// `Russian (Russia) - Russian - Mnemonic`), if the system names it.
+    fn layout_description(&self) -> Option<String> {
+        None
+    }
+    /// Lists the installed layouts in switching order. Empty if the system
+    /// doesn't tell.
+    fn installed_layouts(&self) -> Vec<InstalledLayout> {
+        Vec::new()
+    }
+    /// Starts reporting layout changes to `callback`.
+    ///
+    /// `initial` is the layout already shown, so it isn't reported again.
+    fn start(&mut self, callback: LayoutCallback, initial: &LayoutInfo, timings: HookTimings);
+    /// Updates the detection timings (e.g. after a config reload).
+    fn set_timings(&mut self, timings: HookTimings);
+    /// Stops reporting layout changes.
+    fn stop(&mut self);
+    /// Switches the active keyboard layout. The change is reported like any
+    /// other. Returns false if the layout can't be switched.
+    fn switch_layout(&mut self, target: &LayoutSwitch) -> bool {
+        log::warn!("Switching layouts ({:?}) is not supported here", target);
+        false
+    }
+}
+
+/// Global hotkeys.
+pub trait HotkeyBackend {
+    /// Registers the configured hotkey bindings and dispatches their actions
+    /// to `on_action`.
+    fn start(&mut self, config: &HotkeyConfig, on_action: ActionCallback);
+    /// Returns how registering each binding went, in config order. Empty
+    /// when hotkeys aren't started.
+    fn status(&self) -> Vec<BindingStatus>;
+    /// Applies the bindings and gestures of a reloaded config to started
+    /// hotkeys: unchanged bindings stay registered, removed ones are
+    /// unregistered and new ones registered (see `hotkey::reload_bindings`).
+    fn reload(&mut self, config: &HotkeyConfig);
+    /// Unregisters all hotkeys.
+    fn stop(&mut self);
+}
+
+/// System tray icon with context menu.
+pub trait TrayBackend {
+    /// Sets the callback for actions picked from the menu.
+    fn set_callback(&mut self, on_action: ActionCallback);
+    /// Starts the tray icon.
+    fn start(&mut self) -> Result<(), Box<dyn std::error::Error>>;
+    /// Shows the current layout on the icon, in the layout's `color`, and in
+    /// its title.
+    fn set_layout(&self, layout: &LayoutInfo, color: (u8, u8, u8));
+    /// Lists the hotkeys that couldn't be registered at the top of the menu.
+    fn set_hotkey_conflicts(&self, conflicts: &[BindingStatus]);
+    /// Shows the settings entries (see `menu::settings_menu`) below the
+    /// visibility entries; clicking one dispatches its action. Called when
+    /// the config, the profiles or the installed layouts change.
+    fn set_menu(&self, entries: &[MenuEntry]);
+    /// Moves the check in the Layout submenu to the layout `code` (see
+    /// `menu::check_layout`). Called on layout switches instead of
+    /// `set_menu`.
+    fn set_active_layout(&self, code: &str);
+    /// Shows the application status in the tooltip. Called when what it
+    /// shows changes (see `Status::shows_same`).
+    fn set_status(&self, status: &Status);
+    /// Shows a desktop notification.
+    fn notify(&self, title: &str, text: &str);
+    /// Processes menu events. Called from the main loop.
+    fn process_menu_events(&self);
+    /// Stops the tray icon.
+    fn stop(&mut self);
+}
+
+/// Start with the user session.
+pub trait Autostart: Send + Sync {
+    /// Checks if autostart is enabled.
+    fn is_enabled(&self) -> bool;
+    /// Enables autostart. Returns true on success.
+    fn enable(&self) -> bool;
+    /// Disables autostart. Returns true on success.
+    fn disable(&self) -> bool;
+}
+
+/// Ensures only one instance of the application is running.
+pub trait InstanceLock {
+    /// Takes the lock. Returns false if another instance holds it.
+    fn acquire(&mut self) -> bool;
+    /// Starts accepting arguments forwarded by later launches.
+    fn listen(&mut self, on_forward: ForwardCallback);
+    /// Sends this launch's arguments to the instance holding the lock.
+    /// Returns false if it couldn't be reached.
+    fn forward(&self, args: &[String]) -> bool;
+    /// Releases the lock when the application exits.
+    fn release(&mut self);
+    /// Tells the user that another instance is already running and couldn't
+    /// be reached.
+    fn notify_already_running(&self);
+}
+
+/// Opens files in the application the desktop associates with them.
+pub trait Opener {
+    /// Opens `path`. Returns false if no application could be started.
+    fn open(&self, path: &Path) -> bool;
+}
+
+/// Sound output for layout change notifications.
+pub trait SoundOutput {
+    /// Plays a tone of the given frequency (Hz) and duration.
+    fn beep(&self, frequency: u32, duration_ms: u32);
+}
+
+/// The set of backends the application runs on.
+pub struct Backends {
+    pub overlays: Box<dyn OverlayBackend>,
+    pub monitors: Box<dyn MonitorProvider>,
+    pub layout: Box<dyn LayoutSource>,
+    pub hotkeys: Box<dyn HotkeyBackend>,
+    pub tray: Box<dyn TrayBackend>,
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    pub autostart: Arc<dyn Autostart>,
+    pub instance: Box<dyn InstanceLock>,
+    pub sound: Box<dyn SoundOutput>,
+    pub opener: Box<dyn Opener>,
+}
+
+/// Returns the backends for the platform the application was built for.
+#[cfg_attr(not(any(windows, target_os = "linux")), allow(unused_variables))]
+pub fn native_backends(config: &AppConfig) -> Backends {
+    #[cfg(windows)]
+    {
+        win32::backends(config)
+    }
+    #[cfg(target_os = "linux")]
+    {
+        let mut backends = x11::backends();
+        // Wayland session: layer-shell overlays, the rest from X11 (XWayland)
+        if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+            if let Some((overlays, monitors)) = wayland::backends() {
+                let backends = backends
+                    .get_or_insert_with(|| headless::HeadlessPlatform::new().backends());
+                backends.overlays = Box::new(overlays);
+                backends.monitors = Box::new(monitors);
+            }
+        }
+        if let Some(mut backends) = backends {
+            // A running input method framework owns the layout; under Wayland
+            // the compositor knows it, XKB of XWayland doesn't
+            if let Some(layout) = dbus::InputMethodLayoutSource::detect() {
+                backends.layout = Box::new(layout);
+            } else if let Some(layout) = wayland::layout_source() {
+                backends.layout = layout;
+            }
+            backends.autostart = Arc::new(xdg::XdgAutostart::new(config.autostart_method));
+            backends.instance = instance_lock(config);
+            backends.tray = Box::new(dbus::SniTray::new(backends.autostart.clone(), None));
+            backends.opener = Box::new(xdg::XdgOpener);
+            return backends;
+        }
+    }
+    #[cfg(not(windows))]
+    {
+        log::warn!("No native backend for this platform, running headless");
+        headless::HeadlessPlatform::new().backends()
+    }
+}
+
+/// Returns the instance lock for the platform the application was built
+/// for. Cheap to create: nothing is opened until `acquire`.
+#[cfg_attr(not(any(windows, target_os = "linux")), allow(unused_variables))]
+pub fn instance_lock(config: &AppConfig) -> Box<dyn InstanceLock> {
+    #[cfg(windows)]
+    {
+        win32::instance_lock(config)
+    }
+    #[cfg(target_os = "linux")]
+    {
+        Box::new(unix_instance::UnixInstanceLock::new(config.instance_scope))
+    }
+    #[cfg(not(any(windows, target_os = "linux")))]
+    {
+        headless::HeadlessPlatform::new().backends().instance
+    }
+}
+
+/// Returns the local calendar day, as days since 1970-01-01.
+pub fn local_day() -> i64 {
+    #[cfg(windows)]
+    {
+        win32::local_day()
+    }
+    #[cfg(target_os = "linux")]
+    {
+        let now = unsafe { libc::time(std::ptr::null_mut()) };
+        let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+        if !unsafe { libc::localtime_r(&now, &mut tm) }.is_null() {
+            return crate::status::days_from_civil(
+                i64::from(tm.tm_year) + 1900,
+                (tm.tm_mon + 1) as u32,
+                tm.tm_mday as u32,
+            );
+        }
+        utc_day()
+    }
+    #[cfg(not(any(windows, target_os = "linux")))]
+    {
+        // No time zone without a native backend
+        utc_day()
+    }
+}
+
+/// Returns the calendar day in UTC, as days since 1970-01-01.
+#[cfg(not(windows))]
+fn utc_day() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| (d.as_secs() / 86_400) as i64)
+}
+
+/// Attaches console output to the terminal the application was started
+/// from. Release builds on Windows don't get a console of their own.
+pub fn attach_console() {
+    #[cfg(windows)]
+    win32::attach_console();
+}
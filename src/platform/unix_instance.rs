@@ -0,0 +1,489 @@
+//! Unix instance lock module.
+//!
+//! Ensures only one instance runs per session, user or machine. The lock is
+//! an `flock` on a file in `$XDG_RUNTIME_DIR` (or a private directory in
+//! `/tmp`), which the kernel drops even if the process dies. The holder
+//! listens on an abstract Unix socket where later launches send their
+//! arguments, and replies whether it took them.
+
+use crate::cli;
+use crate::config::InstanceScope;
+use crate::platform::{ForwardCallback, InstanceLock};
+use std::fs::{self, DirBuilder, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::net::Shutdown;
+use std::os::fd::AsRawFd;
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt};
+use std::os::unix::net::{SocketAddr, UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// Largest forwarded message accepted.
+const MAX_MESSAGE: u64 = 64 * 1024;
+
+/// Replies of the holder to forwarded arguments.
+const REPLY_ACCEPTED: u8 = 0;
+const REPLY_OTHER_USER: u8 = 1;
+const REPLY_MALFORMED: u8 = 2;
+
+fn uid() -> u32 {
+    unsafe { libc::getuid() }
+}
+
+/// Replaces characters that don't belong in a file or socket name.
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Returns the lock file and abstract socket name for a lock scope.
+///
+/// Per-user files live in `runtime_dir` (`$XDG_RUNTIME_DIR`) or, without
+/// one, in the private directory `fallback_dir`. Without a session id the
+/// session scope falls back to the user scope.
+fn lock_names(
+    scope: InstanceScope,
+    uid: u32,
+    session: Option<&str>,
+    runtime_dir: Option<&Path>,
+) -> (PathBuf, String) {
+    let user_file = |name: &str| {
+        runtime_dir
+            .map_or_else(|| fallback_dir(uid), Path::to_path_buf)
+            .join(format!("langtip{}.lock", name))
+    };
+    match (scope, session) {
+        (InstanceScope::Global, _) => (PathBuf::from("/tmp/langtip.lock"), "langtip".to_string()),
+        (InstanceScope::Session, Some(session)) => {
+            let suffix = format!("-session-{}", sanitize(session));
+            (user_file(&suffix), format!("langtip-{}{}", uid, suffix))
+        }
+        (InstanceScope::User, _) | (InstanceScope::Session, None) => {
+            (user_file(""), format!("langtip-{}", uid))
+        }
+    }
+}
+
+/// Directory for per-user lock files without `$XDG_RUNTIME_DIR`.
+fn fallback_dir(uid: u32) -> PathBuf {
+    PathBuf::from(format!("/tmp/langtip-{}", uid))
+}
+
+/// Creates `dir` accessible only to `uid`, or checks that the existing one
+/// is. Anyone can create a name in `/tmp` first, so a directory (or symlink)
+/// planted by another user is refused.
+fn ensure_private_dir(dir: &Path, uid: u32) -> io::Result<()> {
+    match DirBuilder::new().mode(0o700).create(dir) {
+        Err(e) if e.kind() != io::ErrorKind::AlreadyExists => return Err(e),
+        _ => {}
+    }
+    let metadata = fs::symlink_metadata(dir)?;
+    if !metadata.is_dir() || metadata.uid() != uid || metadata.mode() & 0o077 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} is not a private directory of user {}", dir.display(), uid),
+        ));
+    }
+    Ok(())
+}
+
+/// Opens (creating if needed) the lock file. The machine-wide file is
+/// readable by everyone so other users can lock it too.
+fn open_lock_file(path: &Path, shared: bool) -> io::Result<File> {
+    let created = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .mode(if shared { 0o644 } else { 0o600 })
+        .open(path);
+    match created {
+        // Another user's file in sticky /tmp: `flock` works on a read-only
+        // descriptor as well
+        Err(e) if shared && e.kind() == io::ErrorKind::PermissionDenied => File::open(path),
+        result => result,
+    }
+}
+
+/// Returns the user id of the process at the other end of a connection.
+fn peer_uid(stream: &UnixStream) -> Option<u32> {
+    let mut cred: libc::ucred = unsafe { std::mem::zeroed() };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut cred as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    (result == 0).then_some(cred.uid)
+}
+
+/// Fails unless the process at the other end of `stream` runs as `uid`.
+/// Anyone can bind an abstract name, so both ends check each other.
+fn check_peer(stream: &UnixStream, uid: u32) -> io::Result<()> {
+    match peer_uid(stream) {
+        Some(peer) if peer == uid => Ok(()),
+        Some(peer) => Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("the socket belongs to user {}", peer),
+        )),
+        None => Err(io::Error::last_os_error()),
+    }
+}
+
+/// Thread accepting forwarded arguments.
+struct Listener {
+    stopping: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+/// Single instance lock backed by an `flock`ed file.
+pub struct UnixInstanceLock {
+    scope: InstanceScope,
+    lock_path: PathBuf,
+    /// Name of the abstract socket (without the leading NUL).
+    socket_name: String,
+    /// Private directory to create for the lock file (None if the file
+    /// lives in `$XDG_RUNTIME_DIR` or is machine-wide).
+    private_dir: Option<PathBuf>,
+    /// Locked file (None if not held).
+    file: Option<File>,
+    listener: Option<Listener>,
+}
+
+impl UnixInstanceLock {
+    /// Creates an unlocked instance lock for `scope`.
+    pub fn new(scope: InstanceScope) -> Self {
+        let session = std::env::var("XDG_SESSION_ID").ok();
+        let runtime_dir = std::env::var_os("XDG_RUNTIME_DIR")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute());
+        let (lock_path, socket_name) =
+            lock_names(scope, uid(), session.as_deref(), runtime_dir.as_deref());
+        log::debug!("Instance lock {} ({:?})", lock_path.display(), scope);
+        let mut lock = Self::with_names(scope, lock_path, socket_name);
+        if runtime_dir.is_none() && scope != InstanceScope::Global {
+            lock.private_dir = Some(fallback_dir(uid()));
+        }
+        lock
+    }
+
+    fn with_names(scope: InstanceScope, lock_path: PathBuf, socket_name: String) -> Self {
+        Self {
+            scope,
+            lock_path,
+            socket_name,
+            private_dir: None,
+            file: None,
+            listener: None,
+        }
+    }
+
+    fn socket_addr(&self) -> io::Result<SocketAddr> {
+        SocketAddr::from_abstract_name(self.socket_name.as_bytes())
+    }
+}
+
+/// Accepts connections until stopped, passing forwarded arguments on and
+/// replying whether they were taken.
+fn accept_loop(listener: UnixListener, stopping: Arc<AtomicBool>, on_forward: ForwardCallback) {
+    for stream in listener.incoming() {
+        if stopping.load(Ordering::SeqCst) {
+            break;
+        }
+        let Ok(mut stream) = stream else {
+            continue;
+        };
+
+        let _ = stream.set_read_timeout(Some(Duration::from_secs(1)));
+        let mut bytes = Vec::new();
+        if let Err(e) = (&stream).take(MAX_MESSAGE).read_to_end(&mut bytes) {
+            log::warn!("Failed to read forwarded arguments: {}", e);
+            continue;
+        }
+        let reply = if check_peer(&stream, uid()).is_err() {
+            log::warn!("Refusing arguments forwarded by another user");
+            REPLY_OTHER_USER
+        } else if let Some(args) = cli::decode_args(&bytes) {
+            on_forward(args);
+            REPLY_ACCEPTED
+        } else {
+            log::warn!("Malformed forwarded arguments");
+            REPLY_MALFORMED
+        };
+        let _ = stream.write_all(&[reply]);
+    }
+}
+
+impl InstanceLock for UnixInstanceLock {
+    /// Locks the file. Returns false if another instance holds the lock.
+    fn acquire(&mut self) -> bool {
+        if let Some(ref dir) = self.private_dir {
+            if let Err(e) = ensure_private_dir(dir, uid()) {
+                log::warn!("Cannot use lock directory: {}, running unlocked", e);
+                return true;
+            }
+        }
+        let file = match open_lock_file(&self.lock_path, self.scope == InstanceScope::Global) {
+            Ok(file) => file,
+            Err(e) => {
+                // Better a second instance than none at all
+                log::warn!(
+                    "Cannot open lock file {}: {}, running unlocked",
+                    self.lock_path.display(),
+                    e
+                );
+                return true;
+            }
+        };
+
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+            let error = io::Error::last_os_error();
+            if error.raw_os_error() == Some(libc::EWOULDBLOCK) {
+                return false;
+            }
+            log::warn!("Cannot lock {}: {}", self.lock_path.display(), error);
+            return true;
+        }
+
+        self.file = Some(file);
+        true
+    }
+
+    /// Binds the abstract socket and accepts forwarded arguments.
+    fn listen(&mut self, on_forward: ForwardCallback) {
+        if self.file.is_none() || self.listener.is_some() {
+            return;
+        }
+        let listener = match self
+            .socket_addr()
+            .and_then(|addr| UnixListener::bind_addr(&addr))
+        {
+            Ok(listener) => listener,
+            Err(e) => {
+                log::warn!("Cannot listen for other launches: {}", e);
+                return;
+            }
+        };
+
+        let stopping = Arc::new(AtomicBool::new(false));
+        let thread_stopping = stopping.clone();
+        let thread = std::thread::spawn(move || accept_loop(listener, thread_stopping, on_forward));
+        self.listener = Some(Listener { stopping, thread });
+    }
+
+    /// Sends the arguments over the abstract socket and waits for the
+    /// holder to take them. Nothing is sent to a socket of another user.
+    fn forward(&self, args: &[String]) -> bool {
+        let result = (|| -> io::Result<u8> {
+            let mut stream = UnixStream::connect_addr(&self.socket_addr()?)?;
+            check_peer(&stream, uid())?;
+            stream.write_all(&cli::encode_args(args))?;
+            stream.shutdown(Shutdown::Write)?;
+            stream.set_read_timeout(Some(Duration::from_secs(2)))?;
+            let mut reply = [0u8];
+            stream.read_exact(&mut reply)?;
+            Ok(reply[0])
+        })();
+        match result {
+            Ok(REPLY_ACCEPTED) => true,
+            Ok(REPLY_OTHER_USER) => {
+                log::error!(
+                    "The running instance belongs to another user and refused the arguments"
+                );
+                false
+            }
+            Ok(reply) => {
+                log::error!("The running instance refused the arguments ({})", reply);
+                false
+            }
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+                log::error!("Not forwarding arguments to the running instance: {}", e);
+                false
+            }
+            Err(e) => {
+                log::warn!("Cannot reach the running instance: {}", e);
+                false
+            }
+        }
+    }
+
+    /// Stops listening and unlocks the file.
+    fn release(&mut self) {
+        if let Some(listener) = self.listener.take() {
+            listener.stopping.store(true, Ordering::SeqCst);
+            // Wake the accept loop so it sees the flag
+            let _ = self
+                .socket_addr()
+                .and_then(|addr| UnixStream::connect_addr(&addr));
+            let _ = listener.thread.join();
+        }
+        // Closing the file drops the lock; the file itself stays so that a
+        // concurrent launch never locks a file that is being removed
+        self.file = None;
+    }
+
+    /// There is no dialog on Linux; the log has to do.
+    fn notify_already_running(&self) {
+        log::warn!("LangTip is already running, look for the icon in the tray");
+    }
+}
+
+impl Drop for UnixInstanceLock {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+    use std::sync::mpsc;
+
+    fn test_lock(name: &str) -> UnixInstanceLock {
+        let unique = format!("langtip-test-{}-{}", name, std::process::id());
+        UnixInstanceLock::with_names(
+            InstanceScope::User,
+            std::env::temp_dir().join(format!("{}.lock", unique)),
+            unique,
+        )
+    }
+
+    #[test]
+    fn scopes_give_lock_names() {
+        let runtime = Path::new("/run/user/1000");
+        assert_eq!(
+            lock_names(InstanceScope::Global, 1000, Some("3"), Some(runtime)),
+            (PathBuf::from("/tmp/langtip.lock"), "langtip".to_string())
+        );
+        assert_eq!(
+            lock_names(InstanceScope::User, 1000, Some("3"), Some(runtime)),
+            (
+                PathBuf::from("/run/user/1000/langtip.lock"),
+                "langtip-1000".to_string()
+            )
+        );
+        assert_eq!(
+            lock_names(InstanceScope::Session, 1000, Some("3"), Some(runtime)),
+            (
+                PathBuf::from("/run/user/1000/langtip-session-3.lock"),
+                "langtip-1000-session-3".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn scopes_without_runtime_dir_or_session() {
+        assert_eq!(
+            lock_names(InstanceScope::User, 1000, None, None),
+            (
+                PathBuf::from("/tmp/langtip-1000/langtip.lock"),
+                "langtip-1000".to_string()
+            )
+        );
+        assert_eq!(
+            lock_names(InstanceScope::Session, 1000, Some("c2/x"), None),
+            (
+                PathBuf::from("/tmp/langtip-1000/langtip-session-c2_x.lock"),
+                "langtip-1000-session-c2_x".to_string()
+            )
+        );
+        // No session id: one instance per user
+        assert_eq!(
+            lock_names(InstanceScope::Session, 1000, None, None),
+            lock_names(InstanceScope::User, 1000, None, None)
+        );
+    }
+
+    #[test]
+    fn fallback_directory_must_be_private() {
+        let dir = std::env::temp_dir().join(format!("langtip-test-dir-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        assert!(ensure_private_dir(&dir, uid()).is_ok());
+        assert_eq!(fs::metadata(&dir).unwrap().mode() & 0o777, 0o700);
+        // Existing and still private
+        assert!(ensure_private_dir(&dir, uid()).is_ok());
+        // Owned by someone else
+        assert!(ensure_private_dir(&dir, uid() + 1).is_err());
+
+        // Open to others
+        fs::set_permissions(&dir, std::os::unix::fs::PermissionsExt::from_mode(0o755)).unwrap();
+        assert!(ensure_private_dir(&dir, uid()).is_err());
+        fs::remove_dir(&dir).unwrap();
+
+        // A symlink planted in its place
+        std::os::unix::fs::symlink(std::env::temp_dir(), &dir).unwrap();
+        assert!(ensure_private_dir(&dir, uid()).is_err());
+        fs::remove_file(&dir).unwrap();
+    }
+
+    #[test]
+    fn peers_of_another_user_are_refused() {
+        let (ours, _theirs) = UnixStream::pair().unwrap();
+        assert!(check_peer(&ours, uid()).is_ok());
+        let err = check_peer(&ours, uid() + 1).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn second_lock_is_refused_until_release() {
+        let mut first = test_lock("refuse");
+        let mut second = test_lock("refuse");
+        assert!(first.acquire());
+        assert!(!second.acquire());
+
+        first.release();
+        assert!(second.acquire());
+        second.release();
+        let _ = std::fs::remove_file(&first.lock_path);
+    }
+
+    #[test]
+    fn arguments_are_forwarded_to_the_holder() {
+        let mut first = test_lock("forward");
+        let mut second = test_lock("forward");
+        assert!(first.acquire());
+        // Not listening yet
+        assert!(!second.forward(&["--show".to_string()]));
+
+        let (tx, rx) = mpsc::channel();
+        let tx = Mutex::new(tx);
+        first.listen(Arc::new(move |args| {
+            let _ = tx.lock().send(args);
+        }));
+
+        assert!(!second.acquire());
+        let args = vec!["--profile".to_string(), "work".to_string()];
+        assert!(second.forward(&args));
+        assert_eq!(rx.recv_timeout(Duration::from_secs(2)), Ok(args));
+        assert!(second.forward(&[]));
+        assert_eq!(rx.recv_timeout(Duration::from_secs(2)), Ok(Vec::new()));
+
+        // Refused arguments get a reply saying why
+        let mut raw = UnixStream::connect_addr(&second.socket_addr().unwrap()).unwrap();
+        raw.write_all(b"not json").unwrap();
+        raw.shutdown(Shutdown::Write).unwrap();
+        let mut reply = Vec::new();
+        raw.read_to_end(&mut reply).unwrap();
+        assert_eq!(reply, [REPLY_MALFORMED]);
+
+        first.release();
+        assert!(first.listener.is_none());
+        assert!(!second.forward(&["--show".to_string()]));
+        let _ = std::fs::remove_file(&first.lock_path);
+    }
+}
@@ -0,0 +1,282 @@
+//! Hyprland IPC layout source module.
+//!
+//! Follows `activelayout` events on the Hyprland event socket
+//! (`.socket2.sock`, one `EVENT>>DATA` line per event) and reads the current
+//! layout with the `j/devices` request on the command socket (`.socket.sock`).
+
+use crate::layout::{HookTimings, LayoutCallback, LayoutInfo, LayoutReporter};
+use crate::platform::LayoutSource;
+use serde::Deserialize;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::Shutdown;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::thread::JoinHandle;
+use std::time::Instant;
+
+/// Finds the socket directory of the running Hyprland instance.
+///
+/// Hyprland 0.40+ keeps sockets in `$XDG_RUNTIME_DIR/hypr`, older versions in
+/// `/tmp/hypr`.
+fn socket_dir() -> Option<PathBuf> {
+    let signature = std::env::var_os("HYPRLAND_INSTANCE_SIGNATURE")?;
+    let runtime = std::env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from);
+    runtime
+        .into_iter()
+        .map(|dir| dir.join("hypr"))
+        .chain(std::iter::once(PathBuf::from("/tmp/hypr")))
+        .map(|dir| dir.join(&signature))
+        .find(|dir| dir.join(".socket2.sock").exists())
+}
+
+/// Extracts the new layout from an event line
+/// (`activelayout>>KEYBOARD,LAYOUT`).
+///
+/// Layout descriptions may contain commas, keyboard names don't.
+fn layout_from_event(line: &str) -> Option<LayoutInfo> {
+    let (event, data) = line.split_once(">>")?;
+    if event != "activelayout" {
+        return None;
+    }
+    let (_keyboard, layout) = data.split_once(',')?;
+    Some(LayoutInfo::from_xkb_description(layout))
+}
+
+/// A keyboard from the `j/devices` reply.
+#[derive(Debug, Deserialize)]
+struct Keyboard {
+    active_keymap: String,
+    #[serde(default)]
+    main: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct Devices {
+    keyboards: Vec<Keyboard>,
+}
+
+/// Extracts the layout of the main keyboard (or the first one) from a
+/// `j/devices` reply.
+fn layout_from_devices(reply: &[u8]) -> Option<LayoutInfo> {
+    let devices: Devices = serde_json::from_slice(reply).ok()?;
+    let keyboard = devices
+        .keyboards
+        .iter()
+        .find(|k| k.main)
+        .or(devices.keyboards.first())?;
+    Some(LayoutInfo::from_xkb_description(&keyboard.active_keymap))
+}
+
+/// Layout source following Hyprland `activelayout` events.
+pub struct HyprlandLayoutSource {
+    dir: PathBuf,
+    reporter: LayoutReporter,
+    /// Event connection, shut down to stop the event thread.
+    events: Option<UnixStream>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl HyprlandLayoutSource {
+    /// Creates a source for the sockets in `dir`.
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            reporter: LayoutReporter::default(),
+            events: None,
+            thread: None,
+        }
+    }
+
+    /// Creates a source for the running Hyprland instance
+    /// (`$HYPRLAND_INSTANCE_SIGNATURE`).
+    pub fn from_env() -> Option<Self> {
+        let dir = socket_dir()?;
+        log::info!(
+            "Using Hyprland IPC at {} as the layout source",
+            dir.display()
+        );
+        Some(Self::new(dir))
+    }
+
+    /// Sends a command to the command socket and returns the reply.
+    fn request(&self, command: &str) -> io::Result<Vec<u8>> {
+        let mut stream = UnixStream::connect(self.dir.join(".socket.sock"))?;
+        stream.write_all(command.as_bytes())?;
+        let mut reply = Vec::new();
+        stream.read_to_end(&mut reply)?;
+        Ok(reply)
+    }
+}
+
+/// Event thread: reports layout changes until the connection is shut down.
+fn event_loop(stream: UnixStream, reporter: LayoutReporter) {
+    for line in BufReader::new(stream).lines() {
+        match line {
+            Ok(line) => {
+                if let Some(layout) = layout_from_event(&line) {
+                    reporter.report(layout, Instant::now());
+                }
+            }
+            Err(e) => {
+                log::debug!("Hyprland event connection closed: {}", e);
+                break;
+            }
+        }
+    }
+}
+
+/// Connects to the event socket in `dir`.
+fn connect_events(dir: &Path) -> io::Result<(UnixStream, UnixStream)> {
+    let stream = UnixStream::connect(dir.join(".socket2.sock"))?;
+    Ok((stream.try_clone()?, stream))
+}
+
+impl LayoutSource for HyprlandLayoutSource {
+    fn current_layout(&self) -> LayoutInfo {
+        match self.request("j/devices") {
+            Ok(reply) => layout_from_devices(&reply).unwrap_or_else(|| LayoutInfo::from_xkb("us")),
+            Err(e) => {
+                log::warn!("Failed to query Hyprland devices: {}", e);
+                LayoutInfo::from_xkb("us")
+            }
+        }
+    }
+
+    fn start(&mut self, callback: LayoutCallback, initial: &LayoutInfo, _timings: HookTimings) {
+        self.reporter.start(callback, initial);
+
+        let stream = match connect_events(&self.dir) {
+            Ok((events, stream)) => {
+                self.events = Some(events);
+                stream
+            }
+            Err(e) => {
+                log::error!("Failed to connect to Hyprland events: {}", e);
+                return;
+            }
+        };
+        let reporter = self.reporter.clone();
+        self.thread = Some(std::thread::spawn(move || event_loop(stream, reporter)));
+    }
+
+    /// Hyprland reports layout changes as they happen, so there is nothing to debounce.
+    fn set_timings(&mut self, _timings: HookTimings) {}
+
+    fn stop(&mut self) {
+        self.reporter.stop();
+        if let Some(events) = self.events.take() {
+            let _ = events.shutdown(Shutdown::Both);
+        }
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for HyprlandLayoutSource {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::LayoutEvent;
+    use parking_lot::Mutex;
+    use std::os::unix::net::UnixListener;
+    use std::sync::{mpsc, Arc};
+    use std::time::Duration;
+
+    fn recording(name: &str) -> String {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/ipc")
+            .join(name);
+        std::fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn activelayout_events_are_parsed() {
+        let layout =
+            layout_from_event("activelayout>>at-translated-set-2-keyboard,Russian").unwrap();
+        assert_eq!(layout.name, "RU");
+        assert_eq!(
+            layout_from_event("activelayout>>kbd,English (US, intl., with dead keys)")
+                .unwrap()
+                .name,
+            "EN"
+        );
+        assert!(layout_from_event("workspace>>2").is_none());
+        assert!(layout_from_event("activelayout>>broken").is_none());
+    }
+
+    #[test]
+    fn devices_reply_gives_main_keyboard_layout() {
+        let layout = layout_from_devices(recording("hyprland-devices.json").as_bytes()).unwrap();
+        assert_eq!(layout.name, "DE");
+        assert!(layout_from_devices(br#"{"keyboards": []}"#).is_none());
+    }
+
+    /// Fake Hyprland: answers `j/devices` and replays the recorded events to
+    /// every event socket client.
+    fn fake_hyprland(dir: &Path) {
+        let commands = UnixListener::bind(dir.join(".socket.sock")).unwrap();
+        let events = UnixListener::bind(dir.join(".socket2.sock")).unwrap();
+        let devices = recording("hyprland-devices.json");
+        let recorded = recording("hyprland-socket2.txt");
+        std::thread::spawn(move || {
+            for stream in commands.incoming() {
+                let Ok(mut stream) = stream else {
+                    break;
+                };
+                let mut command = [0u8; 64];
+                let n = stream.read(&mut command).unwrap_or(0);
+                if &command[..n] == b"j/devices" {
+                    let _ = stream.write_all(devices.as_bytes());
+                }
+            }
+        });
+        std::thread::spawn(move || {
+            for stream in events.incoming() {
+                let Ok(mut stream) = stream else {
+                    break;
+                };
+                let _ = stream.write_all(recorded.as_bytes());
+                // Keep the connection open like Hyprland does
+                std::mem::forget(stream);
+            }
+        });
+    }
+
+    #[test]
+    fn recorded_events_are_reported() {
+        let dir = std::env::temp_dir().join(format!("langtip-hypr-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        fake_hyprland(&dir);
+
+        let mut source = HyprlandLayoutSource::new(dir.clone());
+        assert_eq!(source.current_layout().name, "DE");
+
+        let (tx, rx) = mpsc::channel();
+        let tx = Mutex::new(tx);
+        source.start(
+            Arc::new(move |event: LayoutEvent| {
+                let _ = tx.lock().send(event.layout.name);
+            }),
+            &LayoutInfo::from_xkb("us"),
+            HookTimings::default(),
+        );
+
+        let reported: Vec<String> = (0..3)
+            .map(|_| rx.recv_timeout(Duration::from_secs(2)).unwrap())
+            .collect();
+        // Other events and repeats from the second keyboard are dropped
+        assert_eq!(reported, vec!["RU", "EN", "DE"]);
+        assert!(rx.recv_timeout(Duration::from_millis(200)).is_err());
+
+        source.stop();
+        assert!(source.thread.is_none());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,423 @@
+//! Indicator surface module.
+//!
+//! Indicators as `zwlr_layer_shell_v1` overlay-layer surfaces anchored to the
+//! matching screen edge or corner. The surface has an empty input region, so
+//! clicks pass through; content is rendered with `crate::render` into
+//! `wl_shm` buffers.
+
+use super::{SurfaceState, Wayland};
+use crate::config::{parse_hex_color, AppConfig};
+use crate::indicator::{Fade, Position};
+use crate::monitors::MonitorInfo;
+use crate::platform::Overlay;
+use crate::render::{text_width, Canvas};
+use parking_lot::Mutex;
+use std::fs::File;
+use std::os::fd::AsFd;
+use std::os::unix::fs::FileExt;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use wayland_client::protocol::{
+    wl_buffer::WlBuffer, wl_shm, wl_shm_pool::WlShmPool, wl_surface::WlSurface,
+};
+use wayland_client::Proxy;
+use wayland_protocols_wlr::layer_shell::v1::client::{
+    zwlr_layer_shell_v1::Layer,
+    zwlr_layer_surface_v1::{Anchor, KeyboardInteractivity, ZwlrLayerSurfaceV1},
+};
+
+/// Counter for unique shared memory file names.
+static SHM_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+/// Maps an indicator position to layer surface anchors.
+///
+/// The center indicator has no anchor, which centers it on the output.
+fn anchor_for(position: Position) -> Anchor {
+    match position {
+        Position::TopLeft => Anchor::Top | Anchor::Left,
+        Position::TopRight => Anchor::Top | Anchor::Right,
+        Position::BottomLeft => Anchor::Bottom | Anchor::Left,
+        Position::BottomRight => Anchor::Bottom | Anchor::Right,
+        Position::Center => Anchor::empty(),
+    }
+}
+
+/// Creates an unlinked file for a shared memory pool of `size` bytes.
+fn create_shm_file(size: usize) -> std::io::Result<File> {
+    let dir = std::env::var_os("XDG_RUNTIME_DIR")
+        .map(std::path::PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir);
+    let path = dir.join(format!(
+        "langtip-shm-{}-{}",
+        std::process::id(),
+        SHM_COUNTER.fetch_add(1, Ordering::SeqCst)
+    ));
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    std::fs::remove_file(&path)?;
+    file.set_len(size as u64)?;
+    Ok(file)
+}
+
+/// A `wl_shm` buffer together with its backing file.
+struct ShmBuffer {
+    file: File,
+    pool: WlShmPool,
+    buffer: WlBuffer,
+    /// Set while the compositor may read the buffer.
+    busy: Arc<AtomicBool>,
+}
+
+impl Drop for ShmBuffer {
+    fn drop(&mut self) {
+        self.buffer.destroy();
+        self.pool.destroy();
+    }
+}
+
+/// Text and colour state of an indicator.
+struct SurfaceContent {
+    text: String,
+    is_russian: bool,
+}
+
+/// A single indicator surface.
+pub struct IndicatorSurface {
+    wayland: Rc<Wayland>,
+    surface: WlSurface,
+    layer_surface: ZwlrLayerSurfaceV1,
+    surface_state: Arc<SurfaceState>,
+    /// Surface size in logical pixels.
+    width: u32,
+    height: u32,
+    /// Buffer scale of the output.
+    scale: u32,
+    font_size: u32,
+    color_en: (u8, u8, u8),
+    color_ru: (u8, u8, u8),
+    content: Mutex<SurfaceContent>,
+    buffers: Mutex<Vec<ShmBuffer>>,
+    /// Whether a buffer is attached (the surface is mapped).
+    mapped: AtomicBool,
+    fade: Fade,
+}
+
+impl IndicatorSurface {
+    /// Creates a new indicator surface on the output showing `monitor`.
+    pub fn new(
+        wayland: Rc<Wayland>,
+        position: Position,
+        config: &AppConfig,
+        monitor: &MonitorInfo,
+    ) -> Option<Self> {
+        let is_center = position == Position::Center;
+        let font_size = if is_center {
+            config.font_size_center
+        } else {
+            config.font_size_corner
+        };
+
+        // Calculate surface size based on font size
+        let width = font_size * 3;
+        let height = (font_size as f32 * 1.5) as u32;
+
+        let (surface, layer_surface, surface_state, scale) = {
+            let state = wayland.state.borrow();
+            let Some(output) = state.output_for(monitor) else {
+                log::error!("No Wayland output at ({}, {})", monitor.x, monitor.y);
+                return None;
+            };
+            let scale = state
+                .outputs
+                .iter()
+                .find(|o| &o.output == output)
+                .map(|o| o.info.scale.max(1) as u32)
+                .unwrap_or(1);
+
+            let qh = &wayland.qh;
+            let surface = state.compositor.create_surface(qh, ());
+
+            // Empty input region makes the surface click-through
+            let region = state.compositor.create_region(qh, ());
+            surface.set_input_region(Some(&region));
+            region.destroy();
+
+            let surface_state = Arc::new(SurfaceState::default());
+            let layer_surface = state.layer_shell.get_layer_surface(
+                &surface,
+                Some(output),
+                Layer::Overlay,
+                "langtip".to_string(),
+                qh,
+                surface_state.clone(),
+            );
+            layer_surface.set_size(width, height);
+            layer_surface.set_anchor(anchor_for(position));
+            let margin = config.margin;
+            layer_surface.set_margin(margin, margin, margin, margin);
+            layer_surface.set_exclusive_zone(0);
+            layer_surface.set_keyboard_interactivity(KeyboardInteractivity::None);
+            (surface, layer_surface, surface_state, scale)
+        };
+
+        let indicator = Self {
+            wayland,
+            surface,
+            layer_surface,
+            surface_state,
+            width,
+            height,
+            scale,
+            font_size,
+            color_en: parse_hex_color(&config.colors.en),
+            color_ru: parse_hex_color(&config.colors.ru),
+            content: Mutex::new(SurfaceContent {
+                text: "EN".to_string(),
+                is_russian: false,
+            }),
+            buffers: Mutex::new(Vec::new()),
+            mapped: AtomicBool::new(false),
+            fade: Fade::new(config.opacity),
+        };
+        indicator.configure();
+
+        log::debug!(
+            "Created layer surface {:?} for {:?} size {}x{} scale {}",
+            indicator.surface.id(),
+            position,
+            width,
+            height,
+            scale
+        );
+
+        Some(indicator)
+    }
+
+    /// Commits the surface without a buffer and waits for the compositor to
+    /// configure it. Required before the first buffer and after unmapping.
+    fn configure(&self) {
+        self.surface_state.configured.store(false, Ordering::SeqCst);
+        self.surface.commit();
+        for _ in 0..3 {
+            if self.surface_state.configured.load(Ordering::SeqCst) {
+                return;
+            }
+            self.wayland.roundtrip();
+        }
+        log::warn!("Layer surface was not configured by the compositor");
+    }
+
+    /// Renders the indicator at the current alpha.
+    fn render(&self) -> Canvas {
+        let scale = self.scale as f32;
+        let content = self.content.lock();
+        let color = if content.is_russian {
+            self.color_ru
+        } else {
+            self.color_en
+        };
+
+        let mut canvas = Canvas::new(self.width * self.scale, self.height * self.scale);
+        let text_height = self.font_size as f32 * 0.7 * scale;
+        let x = (canvas.width() as f32 - text_width(&content.text, text_height)) / 2.0;
+        let y = (canvas.height() as f32 - text_height) / 2.0;
+        canvas.draw_text(
+            &content.text,
+            x.max(0.0),
+            y,
+            text_height,
+            color,
+            self.fade.alpha(),
+        );
+        canvas
+    }
+
+    /// Returns a buffer the compositor doesn't use, creating one if needed.
+    fn free_buffer<'a>(&self, buffers: &'a mut Vec<ShmBuffer>) -> Option<&'a ShmBuffer> {
+        if let Some(index) = buffers.iter().position(|b| !b.busy.load(Ordering::SeqCst)) {
+            return Some(&buffers[index]);
+        }
+
+        let width = (self.width * self.scale) as i32;
+        let height = (self.height * self.scale) as i32;
+        let stride = width * 4;
+        let size = (stride * height) as usize;
+        let file = match create_shm_file(size) {
+            Ok(file) => file,
+            Err(e) => {
+                log::error!("Failed to create shared memory buffer: {}", e);
+                return None;
+            }
+        };
+
+        let state = self.wayland.state.borrow();
+        let qh = &self.wayland.qh;
+        let pool = state.shm.create_pool(file.as_fd(), size as i32, qh, ());
+        let busy = Arc::new(AtomicBool::new(false));
+        let buffer = pool.create_buffer(
+            0,
+            width,
+            height,
+            stride,
+            wl_shm::Format::Argb8888,
+            qh,
+            busy.clone(),
+        );
+        buffers.push(ShmBuffer {
+            file,
+            pool,
+            buffer,
+            busy,
+        });
+        buffers.last()
+    }
+
+    /// Draws the current content into a free buffer and commits it.
+    fn redraw(&self) {
+        if self.surface_state.closed.load(Ordering::SeqCst) {
+            return;
+        }
+        if !self.mapped.load(Ordering::SeqCst)
+            && !self.surface_state.configured.load(Ordering::SeqCst)
+        {
+            self.configure();
+        }
+
+        let canvas = self.render();
+        let mut buffers = self.buffers.lock();
+        let Some(buffer) = self.free_buffer(&mut buffers) else {
+            return;
+        };
+        if let Err(e) = buffer.file.write_all_at(&canvas.to_le_bytes(), 0) {
+            log::error!("Failed to write shared memory buffer: {}", e);
+            return;
+        }
+
+        buffer.busy.store(true, Ordering::SeqCst);
+        self.surface.set_buffer_scale(self.scale as i32);
+        self.surface.attach(Some(&buffer.buffer), 0, 0);
+        self.surface
+            .damage_buffer(0, 0, canvas.width() as i32, canvas.height() as i32);
+        self.surface.commit();
+        self.mapped.store(true, Ordering::SeqCst);
+        let _ = self.wayland.conn.flush();
+    }
+
+    /// Detaches the buffer, which unmaps the surface.
+    fn unmap(&self) {
+        self.surface.attach(None, 0, 0);
+        self.surface.commit();
+        self.mapped.store(false, Ordering::SeqCst);
+        self.surface_state.configured.store(false, Ordering::SeqCst);
+        let _ = self.wayland.conn.flush();
+    }
+
+    /// Returns whether a buffer is attached.
+    #[cfg(test)]
+    pub fn is_mapped(&self) -> bool {
+        self.mapped.load(Ordering::SeqCst)
+    }
+}
+
+impl Overlay for IndicatorSurface {
+    /// Updates the indicator text.
+    fn update_text(&self, text: &str, is_russian: bool) {
+        {
+            let mut content = self.content.lock();
+            content.text = text.to_string();
+            content.is_russian = is_russian;
+        }
+        if self.mapped.load(Ordering::SeqCst) {
+            self.redraw();
+        }
+    }
+
+    /// Starts the fade-in animation.
+    fn show(&self) {
+        self.fade.fade_in();
+    }
+
+    /// Starts the fade-out animation. The surface is unmapped when alpha reaches 0.
+    fn hide(&self) {
+        self.fade.fade_out();
+    }
+
+    /// Updates the fade animation. Returns true if animation is still in progress.
+    fn update_fade(&self) -> bool {
+        let Some(new_alpha) = self.fade.step() else {
+            return false; // Animation complete
+        };
+
+        if new_alpha == 0 {
+            self.unmap();
+        } else {
+            self.redraw();
+        }
+
+        true // Animation in progress
+    }
+}
+
+impl Drop for IndicatorSurface {
+    fn drop(&mut self) {
+        self.layer_surface.destroy();
+        self.surface.destroy();
+        self.buffers.lock().clear();
+        let _ = self.wayland.conn.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::wayland::tests::test_connection;
+    use crate::platform::wayland::WaylandMonitors;
+    use crate::platform::MonitorProvider;
+
+    #[test]
+    fn positions_map_to_edge_anchors() {
+        assert_eq!(anchor_for(Position::TopLeft), Anchor::Top | Anchor::Left);
+        assert_eq!(
+            anchor_for(Position::BottomRight),
+            Anchor::Bottom | Anchor::Right
+        );
+        assert!(anchor_for(Position::Center).is_empty());
+    }
+
+    #[test]
+    fn shm_file_has_requested_size_and_no_name() {
+        let file = create_shm_file(4096).unwrap();
+        assert_eq!(file.metadata().unwrap().len(), 4096);
+    }
+
+    #[test]
+    #[ignore = "needs a headless sway (WLR_BACKENDS=headless), run with --ignored"]
+    fn surface_maps_on_fade_in_and_unmaps_on_fade_out() {
+        let wayland = test_connection();
+        let monitors = WaylandMonitors {
+            wayland: wayland.clone(),
+        }
+        .monitors();
+        let config = AppConfig::default();
+        let surface =
+            IndicatorSurface::new(wayland.clone(), Position::TopRight, &config, &monitors[0])
+                .unwrap();
+        assert!(surface.surface_state.configured.load(Ordering::SeqCst));
+        assert!(!surface.is_mapped());
+
+        surface.update_text("RU", true);
+        surface.show();
+        while surface.update_fade() {}
+        assert!(surface.is_mapped());
+        wayland.roundtrip();
+        assert!(!surface.surface_state.closed.load(Ordering::SeqCst));
+
+        surface.hide();
+        while surface.update_fade() {}
+        assert!(!surface.is_mapped());
+    }
+}
@@ -0,0 +1,611 @@
+//! Wayland platform module.
+//!
+//! Overlay and monitor backends for wlroots-based compositors (Sway,
+//! Hyprland, ...) using `zwlr_layer_shell_v1` overlay-layer surfaces.
+//! Outputs are described through `wl_output` and, when available,
+//! `zxdg_output_v1` logical geometry. Keyboard layouts come from the Sway and
+//! Hyprland IPC sockets, as Wayland has no global layout API.
+
+mod hyprland;
+mod indicator;
+mod sway;
+
+use super::{LayoutSource, MonitorProvider, Overlay, OverlayBackend, PlatformEvent};
+use crate::config::AppConfig;
+use crate::indicator::Position;
+use crate::monitors::MonitorInfo;
+pub use hyprland::HyprlandLayoutSource;
+use indicator::IndicatorSurface;
+pub use sway::SwayLayoutSource;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use wayland_client::globals::{registry_queue_init, GlobalListContents};
+use wayland_client::protocol::{
+    wl_buffer::{self, WlBuffer},
+    wl_compositor::WlCompositor,
+    wl_output::{self, WlOutput},
+    wl_region::WlRegion,
+    wl_registry::{self, WlRegistry},
+    wl_shm::WlShm,
+    wl_shm_pool::WlShmPool,
+    wl_surface::WlSurface,
+};
+use wayland_client::{delegate_noop, Connection, Dispatch, EventQueue, Proxy, QueueHandle};
+use wayland_protocols::xdg::xdg_output::zv1::client::{
+    zxdg_output_manager_v1::ZxdgOutputManagerV1,
+    zxdg_output_v1::{self, ZxdgOutputV1},
+};
+use wayland_protocols_wlr::layer_shell::v1::client::{
+    zwlr_layer_shell_v1::ZwlrLayerShellV1,
+    zwlr_layer_surface_v1::{self, ZwlrLayerSurfaceV1},
+};
+
+/// Geometry of an output as reported by the compositor.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct OutputInfo {
+    /// Position from `wl_output.geometry`.
+    x: i32,
+    y: i32,
+    /// Current mode size in physical pixels.
+    mode_width: i32,
+    mode_height: i32,
+    scale: i32,
+    /// Logical geometry from `zxdg_output_v1`, if the compositor supports it.
+    logical: Option<(i32, i32, i32, i32)>,
+    /// Whether the first `done` event has been received.
+    done: bool,
+    /// Whether the output was plugged in after connecting.
+    added_later: bool,
+}
+
+impl OutputInfo {
+    /// Handles `wl_output.done`. Returns true if the monitor configuration
+    /// changed: the output was plugged in after connecting, or its mode,
+    /// scale or position changed.
+    fn finish_update(&mut self) -> bool {
+        let changed = self.done || self.added_later;
+        self.done = true;
+        changed
+    }
+
+    /// Logical geometry of the output: xdg-output values, or the mode size
+    /// divided by the output scale.
+    fn logical_geometry(&self) -> (i32, i32, i32, i32) {
+        self.logical.unwrap_or_else(|| {
+            let scale = self.scale.max(1);
+            (
+                self.x,
+                self.y,
+                self.mode_width / scale,
+                self.mode_height / scale,
+            )
+        })
+    }
+
+    /// Converts the output into monitor information.
+    ///
+    /// Layer surfaces with a zero exclusive zone are kept clear of panels by
+    /// the compositor, so the work area is the whole output.
+    fn to_monitor_info(&self, is_primary: bool) -> MonitorInfo {
+        let (x, y, width, height) = self.logical_geometry();
+        MonitorInfo {
+            x,
+            y,
+            width,
+            height,
+            work_x: x,
+            work_y: y,
+            work_width: width,
+            work_height: height,
+            is_primary,
+        }
+    }
+}
+
+/// A bound `wl_output`.
+struct Output {
+    /// Registry name of the global.
+    name: u32,
+    output: WlOutput,
+    xdg_output: Option<ZxdgOutputV1>,
+    info: OutputInfo,
+}
+
+/// Configure state of a layer surface, shared with its indicator.
+#[derive(Default)]
+struct SurfaceState {
+    configured: AtomicBool,
+    closed: AtomicBool,
+}
+
+/// Dispatch state of the Wayland event queue.
+pub struct WaylandState {
+    compositor: WlCompositor,
+    shm: WlShm,
+    layer_shell: ZwlrLayerShellV1,
+    xdg_output_manager: Option<ZxdgOutputManagerV1>,
+    outputs: Vec<Output>,
+    display_changed: bool,
+}
+
+impl WaylandState {
+    /// Binds a newly announced `wl_output`. `added_later` is set for
+    /// outputs announced after connecting.
+    fn add_output(
+        &mut self,
+        registry: &WlRegistry,
+        name: u32,
+        version: u32,
+        added_later: bool,
+        qh: &QueueHandle<Self>,
+    ) {
+        let output: WlOutput = registry.bind(name, version.min(4), qh, name);
+        let xdg_output = self
+            .xdg_output_manager
+            .as_ref()
+            .map(|manager| manager.get_xdg_output(&output, qh, name));
+        self.outputs.push(Output {
+            name,
+            output,
+            xdg_output,
+            info: OutputInfo {
+                scale: 1,
+                added_later,
+                ..Default::default()
+            },
+        });
+    }
+
+    fn output_mut(&mut self, name: u32) -> Option<&mut Output> {
+        self.outputs.iter_mut().find(|o| o.name == name)
+    }
+
+    /// Finds the output a monitor was created from.
+    fn output_for(&self, monitor: &MonitorInfo) -> Option<&WlOutput> {
+        self.outputs
+            .iter()
+            .find(|o| {
+                let (x, y, _, _) = o.info.logical_geometry();
+                x == monitor.x && y == monitor.y
+            })
+            .map(|o| &o.output)
+    }
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for WaylandState {
+    fn event(
+        state: &mut Self,
+        registry: &WlRegistry,
+        event: wl_registry::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            wl_registry::Event::Global {
+                name,
+                interface,
+                version,
+            } if interface == WlOutput::interface().name => {
+                log::debug!("Output {} added", name);
+                state.add_output(registry, name, version, true, qh);
+            }
+            wl_registry::Event::GlobalRemove { name } => {
+                if let Some(index) = state.outputs.iter().position(|o| o.name == name) {
+                    log::debug!("Output {} removed", name);
+                    let output = state.outputs.remove(index);
+                    if let Some(xdg_output) = output.xdg_output {
+                        xdg_output.destroy();
+                    }
+                    if output.output.version() >= 3 {
+                        output.output.release();
+                    }
+                    state.display_changed = true;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<WlOutput, u32> for WaylandState {
+    fn event(
+        state: &mut Self,
+        _: &WlOutput,
+        event: wl_output::Event,
+        name: &u32,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let Some(output) = state.output_mut(*name) else {
+            return;
+        };
+        match event {
+            wl_output::Event::Geometry { x, y, .. } => {
+                output.info.x = x;
+                output.info.y = y;
+            }
+            wl_output::Event::Mode {
+                flags,
+                width,
+                height,
+                ..
+            } => {
+                let current = flags
+                    .into_result()
+                    .map(|f| f.contains(wl_output::Mode::Current))
+                    .unwrap_or(false);
+                if current {
+                    output.info.mode_width = width;
+                    output.info.mode_height = height;
+                }
+            }
+            wl_output::Event::Scale { factor } => output.info.scale = factor,
+            wl_output::Event::Done => {
+                state.display_changed |= output.info.finish_update();
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ZxdgOutputV1, u32> for WaylandState {
+    fn event(
+        state: &mut Self,
+        _: &ZxdgOutputV1,
+        event: zxdg_output_v1::Event,
+        name: &u32,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let Some(output) = state.output_mut(*name) else {
+            return;
+        };
+        let (mut x, mut y, mut width, mut height) = output.info.logical.unwrap_or_default();
+        match event {
+            zxdg_output_v1::Event::LogicalPosition { x: lx, y: ly } => {
+                x = lx;
+                y = ly;
+            }
+            zxdg_output_v1::Event::LogicalSize {
+                width: lw,
+                height: lh,
+            } => {
+                width = lw;
+                height = lh;
+            }
+            _ => return,
+        }
+        output.info.logical = Some((x, y, width, height));
+    }
+}
+
+impl Dispatch<ZwlrLayerSurfaceV1, Arc<SurfaceState>> for WaylandState {
+    fn event(
+        _: &mut Self,
+        surface: &ZwlrLayerSurfaceV1,
+        event: zwlr_layer_surface_v1::Event,
+        state: &Arc<SurfaceState>,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            zwlr_layer_surface_v1::Event::Configure { serial, .. } => {
+                surface.ack_configure(serial);
+                state.configured.store(true, Ordering::SeqCst);
+            }
+            zwlr_layer_surface_v1::Event::Closed => {
+                state.closed.store(true, Ordering::SeqCst);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<WlBuffer, Arc<AtomicBool>> for WaylandState {
+    fn event(
+        _: &mut Self,
+        _: &WlBuffer,
+        event: wl_buffer::Event,
+        busy: &Arc<AtomicBool>,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let wl_buffer::Event::Release = event {
+            busy.store(false, Ordering::SeqCst);
+        }
+    }
+}
+
+delegate_noop!(WaylandState: ignore WlCompositor);
+delegate_noop!(WaylandState: ignore WlSurface);
+delegate_noop!(WaylandState: ignore WlShm);
+delegate_noop!(WaylandState: ignore WlShmPool);
+delegate_noop!(WaylandState: ignore WlRegion);
+delegate_noop!(WaylandState: ignore ZwlrLayerShellV1);
+delegate_noop!(WaylandState: ignore ZxdgOutputManagerV1);
+
+/// Wayland connection and event queue shared by the backends.
+pub struct Wayland {
+    conn: Connection,
+    qh: QueueHandle<WaylandState>,
+    queue: RefCell<EventQueue<WaylandState>>,
+    state: RefCell<WaylandState>,
+}
+
+impl Wayland {
+    /// Connects to `$WAYLAND_DISPLAY` and binds the globals the backends
+    /// need. Fails if the compositor has no layer-shell support.
+    pub fn connect() -> Result<Rc<Self>, Box<dyn std::error::Error>> {
+        let conn = Connection::connect_to_env()?;
+        let (globals, queue) = registry_queue_init::<WaylandState>(&conn)?;
+        let qh = queue.handle();
+
+        let compositor: WlCompositor = globals.bind(&qh, 4..=6, ())?;
+        let shm: WlShm = globals.bind(&qh, 1..=1, ())?;
+        let layer_shell: ZwlrLayerShellV1 = globals
+            .bind(&qh, 1..=4, ())
+            .map_err(|_| "compositor doesn't support zwlr_layer_shell_v1")?;
+        let xdg_output_manager: Option<ZxdgOutputManagerV1> = globals.bind(&qh, 1..=3, ()).ok();
+
+        let mut state = WaylandState {
+            compositor,
+            shm,
+            layer_shell,
+            xdg_output_manager,
+            outputs: Vec::new(),
+            display_changed: false,
+        };
+        let outputs: Vec<(u32, u32)> = globals.contents().with_list(|list| {
+            list.iter()
+                .filter(|g| g.interface == WlOutput::interface().name)
+                .map(|g| (g.name, g.version))
+                .collect()
+        });
+        for (name, version) in outputs {
+            state.add_output(globals.registry(), name, version, false, &qh);
+        }
+
+        let wayland = Rc::new(Self {
+            conn,
+            qh,
+            queue: RefCell::new(queue),
+            state: RefCell::new(state),
+        });
+
+        // Receive output geometry
+        wayland.roundtrip();
+        wayland.roundtrip();
+        Ok(wayland)
+    }
+
+    /// Sends pending requests and waits until the compositor processed them.
+    fn roundtrip(&self) {
+        let mut queue = self.queue.borrow_mut();
+        let mut state = self.state.borrow_mut();
+        if let Err(e) = queue.roundtrip(&mut state) {
+            log::error!("Wayland roundtrip failed: {}", e);
+        }
+    }
+
+    /// Reads and dispatches events without blocking.
+    ///
+    /// Returns false if the connection is broken.
+    fn dispatch(&self) -> bool {
+        let mut queue = self.queue.borrow_mut();
+        let mut state = self.state.borrow_mut();
+        if queue.flush().is_err() {
+            return false;
+        }
+        if let Some(guard) = queue.prepare_read() {
+            match guard.read() {
+                Ok(_) => {}
+                Err(wayland_client::backend::WaylandError::Io(e))
+                    if e.kind() == std::io::ErrorKind::WouldBlock => {}
+                Err(e) => {
+                    log::error!("Wayland connection lost: {}", e);
+                    return false;
+                }
+            }
+        }
+        queue.dispatch_pending(&mut state).is_ok()
+    }
+}
+
+/// Overlay backend creating layer-shell surfaces.
+pub struct WaylandOverlays {
+    wayland: Rc<Wayland>,
+}
+
+impl OverlayBackend for WaylandOverlays {
+    fn create_overlay(
+        &mut self,
+        position: Position,
+        config: &AppConfig,
+        monitor: &MonitorInfo,
+    ) -> Option<Box<dyn Overlay>> {
+        IndicatorSurface::new(self.wayland.clone(), position, config, monitor)
+            .map(|surface| Box::new(surface) as Box<dyn Overlay>)
+    }
+
+    fn pump_events(&mut self) -> Vec<PlatformEvent> {
+        if !self.wayland.dispatch() {
+            return vec![PlatformEvent::Quit];
+        }
+
+        let display_changed = std::mem::take(&mut self.wayland.state.borrow_mut().display_changed);
+        if display_changed {
+            log::info!("Output configuration changed");
+            vec![PlatformEvent::DisplayChanged]
+        } else {
+            std::thread::sleep(Duration::from_millis(16));
+            Vec::new()
+        }
+    }
+}
+
+/// Monitor provider listing the Wayland outputs.
+pub struct WaylandMonitors {
+    wayland: Rc<Wayland>,
+}
+
+impl MonitorProvider for WaylandMonitors {
+    /// Gets the outputs in announcement order; Wayland has no primary
+    /// output, so the first one is treated as primary.
+    fn monitors(&self) -> Vec<MonitorInfo> {
+        let state = self.wayland.state.borrow();
+        state
+            .outputs
+            .iter()
+            .filter(|o| o.info.done)
+            .enumerate()
+            .map(|(i, o)| o.info.to_monitor_info(i == 0))
+            .collect()
+    }
+}
+
+/// Connects to the compositor and returns the overlay and monitor backends,
+/// or `None` if it isn't a layer-shell capable Wayland compositor.
+pub fn backends() -> Option<(WaylandOverlays, WaylandMonitors)> {
+    match Wayland::connect() {
+        Ok(wayland) => Some((
+            WaylandOverlays {
+                wayland: wayland.clone(),
+            },
+            WaylandMonitors { wayland },
+        )),
+        Err(e) => {
+            log::warn!("Cannot use Wayland overlays: {}", e);
+            None
+        }
+    }
+}
+
+/// Returns the layout source of the running compositor (Sway or Hyprland),
+/// if it has one.
+pub fn layout_source() -> Option<Box<dyn LayoutSource>> {
+    if let Some(source) = SwayLayoutSource::from_env() {
+        return Some(Box::new(source));
+    }
+    HyprlandLayoutSource::from_env().map(|source| Box::new(source) as Box<dyn LayoutSource>)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn logical_geometry_prefers_xdg_output() {
+        let mut info = OutputInfo {
+            x: 0,
+            y: 0,
+            mode_width: 3840,
+            mode_height: 2160,
+            scale: 2,
+            logical: None,
+            done: true,
+            added_later: false,
+        };
+        assert_eq!(info.logical_geometry(), (0, 0, 1920, 1080));
+
+        // Fractional scale 1.5 is only visible through xdg-output
+        info.logical = Some((1920, 0, 2560, 1440));
+        let monitor = info.to_monitor_info(false);
+        assert_eq!(
+            (monitor.x, monitor.width, monitor.height),
+            (1920, 2560, 1440)
+        );
+        assert_eq!(
+            (
+                monitor.work_x,
+                monitor.work_y,
+                monitor.work_width,
+                monitor.work_height
+            ),
+            (1920, 0, 2560, 1440)
+        );
+    }
+
+    #[test]
+    fn outputs_plugged_in_later_change_the_display() {
+        // Outputs present at connect are part of the initial configuration
+        let mut info = OutputInfo::default();
+        assert!(!info.finish_update());
+        assert!(info.finish_update());
+
+        let mut info = OutputInfo {
+            added_later: true,
+            ..Default::default()
+        };
+        assert!(info.finish_update());
+    }
+
+    /// Connects to the test compositor at `$WAYLAND_DISPLAY`. The tests using
+    /// it are `#[ignore]`d, so running them without one fails instead of
+    /// passing unchecked.
+    pub(super) fn test_connection() -> Rc<Wayland> {
+        assert!(
+            std::env::var_os("WAYLAND_DISPLAY").is_some(),
+            "$WAYLAND_DISPLAY isn't set; run inside a headless sway"
+        );
+        Wayland::connect().expect("failed to connect to $WAYLAND_DISPLAY")
+    }
+
+    #[test]
+    #[ignore = "needs a headless sway (WLR_BACKENDS=headless), run with --ignored"]
+    fn outputs_are_listed_with_first_as_primary() {
+        let wayland = test_connection();
+        let monitors = WaylandMonitors { wayland }.monitors();
+        assert!(!monitors.is_empty());
+        assert!(monitors[0].is_primary);
+        assert!(monitors.iter().skip(1).all(|m| !m.is_primary));
+        assert!(monitors.iter().all(|m| m.width > 0 && m.height > 0));
+    }
+
+    /// Pumps events until the backend reports a display change.
+    fn wait_for_display_change(overlays: &mut WaylandOverlays) -> bool {
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while std::time::Instant::now() < deadline {
+            if overlays.pump_events().contains(&PlatformEvent::DisplayChanged) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Runs `swaymsg` with `args` and returns its output.
+    fn swaymsg(args: &[&str]) -> String {
+        let output = std::process::Command::new("swaymsg")
+            .args(args)
+            .output()
+            .expect("adding outputs needs sway (swaymsg)");
+        assert!(output.status.success(), "swaymsg {:?} failed", args);
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    }
+
+    #[test]
+    #[ignore = "needs a headless sway (WLR_BACKENDS=headless), run with --ignored"]
+    fn output_added_after_connect_changes_the_display() {
+        let wayland = test_connection();
+        let mut overlays = WaylandOverlays {
+            wayland: wayland.clone(),
+        };
+        let monitors = WaylandMonitors { wayland };
+        let before = monitors.monitors().len();
+
+        swaymsg(&["create_output"]);
+        assert!(wait_for_display_change(&mut overlays));
+        assert_eq!(monitors.monitors().len(), before + 1);
+
+        // Unplug it again; the last output is the one just created
+        let outputs: Vec<serde_json::Value> =
+            serde_json::from_str(&swaymsg(&["-t", "get_outputs", "-r"])).unwrap();
+        let name = outputs.last().and_then(|o| o["name"].as_str()).unwrap();
+        swaymsg(&["output", name, "unplug"]);
+        assert!(wait_for_display_change(&mut overlays));
+        assert_eq!(monitors.monitors().len(), before);
+    }
+}
@@ -0,0 +1,305 @@
+//! Sway IPC layout source module.
+//!
+//! Follows keyboard layout changes through `input` events on the Sway IPC
+//! socket (`$SWAYSOCK`). Messages are framed as `i3-ipc`, a 32-bit payload
+//! length and a 32-bit type in native byte order, followed by JSON.
+
+use crate::layout::{HookTimings, LayoutCallback, LayoutInfo, LayoutReporter};
+use crate::platform::LayoutSource;
+use serde::Deserialize;
+use std::io::{self, Read, Write};
+use std::net::Shutdown;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::thread::JoinHandle;
+use std::time::Instant;
+
+const MAGIC: &[u8; 6] = b"i3-ipc";
+const SUBSCRIBE: u32 = 2;
+const GET_INPUTS: u32 = 100;
+/// Event type of `input` events (high bit marks events).
+const INPUT_EVENT: u32 = 0x8000_0015;
+
+/// Writes a message to the IPC socket.
+fn write_message(stream: &mut impl Write, kind: u32, payload: &[u8]) -> io::Result<()> {
+    let mut message = Vec::with_capacity(14 + payload.len());
+    message.extend_from_slice(MAGIC);
+    message.extend_from_slice(&(payload.len() as u32).to_ne_bytes());
+    message.extend_from_slice(&kind.to_ne_bytes());
+    message.extend_from_slice(payload);
+    stream.write_all(&message)
+}
+
+/// Reads a message from the IPC socket, returning its type and payload.
+fn read_message(stream: &mut impl Read) -> io::Result<(u32, Vec<u8>)> {
+    let mut header = [0u8; 14];
+    stream.read_exact(&mut header)?;
+    if &header[..6] != MAGIC {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Bad IPC magic"));
+    }
+    let length = u32::from_ne_bytes(header[6..10].try_into().unwrap());
+    let kind = u32::from_ne_bytes(header[10..14].try_into().unwrap());
+    let mut payload = vec![0u8; length as usize];
+    stream.read_exact(&mut payload)?;
+    Ok((kind, payload))
+}
+
+/// An input device as reported by Sway.
+#[derive(Debug, Deserialize)]
+struct Input {
+    #[serde(rename = "type")]
+    kind: String,
+    xkb_active_layout_name: Option<String>,
+}
+
+impl Input {
+    /// Active layout of a keyboard; `None` for other devices.
+    fn layout(&self) -> Option<LayoutInfo> {
+        if self.kind != "keyboard" {
+            return None;
+        }
+        self.xkb_active_layout_name
+            .as_deref()
+            .map(LayoutInfo::from_xkb_description)
+    }
+}
+
+/// Payload of an `input` event.
+#[derive(Debug, Deserialize)]
+struct InputEvent {
+    change: String,
+    input: Input,
+}
+
+/// Extracts the new layout from an `input` event payload.
+fn layout_from_event(payload: &[u8]) -> Option<LayoutInfo> {
+    let event: InputEvent = serde_json::from_slice(payload).ok()?;
+    match event.change.as_str() {
+        "xkb_layout" | "xkb_keymap" => event.input.layout(),
+        _ => None,
+    }
+}
+
+/// Extracts the layout of the first keyboard from a `GET_INPUTS` reply.
+fn layout_from_inputs(payload: &[u8]) -> Option<LayoutInfo> {
+    let inputs: Vec<Input> = serde_json::from_slice(payload).ok()?;
+    inputs.iter().find_map(Input::layout)
+}
+
+/// Layout source following Sway `input` events.
+pub struct SwayLayoutSource {
+    socket: PathBuf,
+    reporter: LayoutReporter,
+    /// Event connection, shut down to stop the event thread.
+    events: Option<UnixStream>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl SwayLayoutSource {
+    pub fn new(socket: PathBuf) -> Self {
+        Self {
+            socket,
+            reporter: LayoutReporter::default(),
+            events: None,
+            thread: None,
+        }
+    }
+
+    /// Creates a source for the running Sway instance (`$SWAYSOCK`).
+    pub fn from_env() -> Option<Self> {
+        let socket = PathBuf::from(std::env::var_os("SWAYSOCK")?);
+        log::info!(
+            "Using Sway IPC at {} as the layout source",
+            socket.display()
+        );
+        Some(Self::new(socket))
+    }
+
+    /// Sends a request on a new connection and returns the reply payload.
+    fn request(&self, kind: u32, payload: &[u8]) -> io::Result<Vec<u8>> {
+        let mut stream = UnixStream::connect(&self.socket)?;
+        write_message(&mut stream, kind, payload)?;
+        read_message(&mut stream).map(|(_, reply)| reply)
+    }
+
+    /// Opens the event connection and subscribes to `input` events.
+    fn subscribe(&self) -> io::Result<UnixStream> {
+        let mut stream = UnixStream::connect(&self.socket)?;
+        write_message(&mut stream, SUBSCRIBE, br#"["input"]"#)?;
+        let (_, reply) = read_message(&mut stream)?;
+        if !String::from_utf8_lossy(&reply).contains("true") {
+            return Err(io::Error::other("Sway refused the subscription"));
+        }
+        Ok(stream)
+    }
+}
+
+/// Event thread: reports layout changes until the connection is shut down.
+fn event_loop(mut stream: UnixStream, reporter: LayoutReporter) {
+    loop {
+        match read_message(&mut stream) {
+            Ok((INPUT_EVENT, payload)) => {
+                if let Some(layout) = layout_from_event(&payload) {
+                    reporter.report(layout, Instant::now());
+                }
+            }
+            Ok(_) => {}
+            Err(e) => {
+                log::debug!("Sway event connection closed: {}", e);
+                break;
+            }
+        }
+    }
+}
+
+impl LayoutSource for SwayLayoutSource {
+    fn current_layout(&self) -> LayoutInfo {
+        match self.request(GET_INPUTS, b"") {
+            Ok(reply) => layout_from_inputs(&reply).unwrap_or_else(|| LayoutInfo::from_xkb("us")),
+            Err(e) => {
+                log::warn!("Failed to query Sway inputs: {}", e);
+                LayoutInfo::from_xkb("us")
+            }
+        }
+    }
+
+    fn start(&mut self, callback: LayoutCallback, initial: &LayoutInfo, _timings: HookTimings) {
+        self.reporter.start(callback, initial);
+
+        let stream = match self.subscribe().and_then(|s| Ok((s.try_clone()?, s))) {
+            Ok((events, stream)) => {
+                self.events = Some(events);
+                stream
+            }
+            Err(e) => {
+                log::error!("Failed to subscribe to Sway input events: {}", e);
+                return;
+            }
+        };
+        let reporter = self.reporter.clone();
+        self.thread = Some(std::thread::spawn(move || event_loop(stream, reporter)));
+    }
+
+    /// Sway reports layout changes as they happen, so there is nothing to debounce.
+    fn set_timings(&mut self, _timings: HookTimings) {}
+
+    fn stop(&mut self) {
+        self.reporter.stop();
+        if let Some(events) = self.events.take() {
+            let _ = events.shutdown(Shutdown::Both);
+        }
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for SwayLayoutSource {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::LayoutEvent;
+    use parking_lot::Mutex;
+    use std::os::unix::net::UnixListener;
+    use std::path::Path;
+    use std::sync::{mpsc, Arc};
+    use std::time::Duration;
+
+    fn recording(name: &str) -> String {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/ipc")
+            .join(name);
+        std::fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        let mut buffer = Vec::new();
+        write_message(&mut buffer, SUBSCRIBE, br#"["input"]"#).unwrap();
+        assert_eq!(&buffer[..6], MAGIC);
+        let (kind, payload) = read_message(&mut buffer.as_slice()).unwrap();
+        assert_eq!(kind, SUBSCRIBE);
+        assert_eq!(payload, br#"["input"]"#);
+
+        buffer[0] = b'x';
+        assert!(read_message(&mut buffer.as_slice()).is_err());
+    }
+
+    #[test]
+    fn inputs_reply_gives_first_keyboard_layout() {
+        let layout = layout_from_inputs(recording("sway-get-inputs.json").as_bytes()).unwrap();
+        assert_eq!(layout.name, "RU");
+        assert!(layout_from_inputs(b"[]").is_none());
+    }
+
+    /// Fake Sway: answers `GET_INPUTS` and replays the recorded events to
+    /// every subscriber.
+    fn fake_sway(socket: &Path) {
+        let listener = UnixListener::bind(socket).unwrap();
+        let inputs = recording("sway-get-inputs.json");
+        let events = recording("sway-input-events.jsonl");
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else {
+                    break;
+                };
+                let Ok((kind, _)) = read_message(&mut stream) else {
+                    continue;
+                };
+                match kind {
+                    GET_INPUTS => {
+                        let _ = write_message(&mut stream, GET_INPUTS, inputs.as_bytes());
+                    }
+                    SUBSCRIBE => {
+                        let _ = write_message(&mut stream, SUBSCRIBE, br#"{"success": true}"#);
+                        for event in events.lines().filter(|l| !l.is_empty()) {
+                            let _ = write_message(&mut stream, INPUT_EVENT, event.as_bytes());
+                        }
+                        // Keep the connection open like Sway does
+                        std::mem::forget(stream);
+                    }
+                    _ => {}
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn recorded_input_events_are_reported() {
+        let dir = std::env::temp_dir().join(format!("langtip-sway-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let socket = dir.join("sway-ipc.sock");
+        fake_sway(&socket);
+
+        let mut source = SwayLayoutSource::new(socket);
+        let initial = LayoutInfo::from_xkb("us");
+        assert_eq!(source.current_layout().name, "RU");
+
+        let (tx, rx) = mpsc::channel();
+        let tx = Mutex::new(tx);
+        source.start(
+            Arc::new(move |event: LayoutEvent| {
+                let _ = tx.lock().send(event.layout.name);
+            }),
+            &initial,
+            HookTimings::default(),
+        );
+
+        let reported: Vec<String> = (0..3)
+            .map(|_| rx.recv_timeout(Duration::from_secs(2)).unwrap())
+            .collect();
+        // Repeats from the second keyboard and non-layout changes are dropped
+        assert_eq!(reported, vec!["RU", "EN", "DE"]);
+        assert!(rx.recv_timeout(Duration::from_millis(200)).is_err());
+
+        source.stop();
+        assert!(source.thread.is_none());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,171 @@
+//! Balloon notification module.
+//!
+//! Shows notifications as notification-area balloons (toasts on Windows 10
+//! and later). The tray icon library can't show balloons on its own icon, so
+//! a warning icon is added next to it for as long as the balloon is up.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use windows::{
+    core::{w, PCWSTR},
+    Win32::{
+        Foundation::{HWND, LPARAM, LRESULT, WPARAM},
+        System::LibraryLoader::GetModuleHandleW,
+        UI::{
+            Shell::{
+                Shell_NotifyIconW, NIF_ICON, NIF_INFO, NIF_MESSAGE, NIF_TIP, NIIF_WARNING,
+                NIM_ADD, NIM_DELETE, NIN_BALLOONHIDE, NIN_BALLOONTIMEOUT, NIN_BALLOONUSERCLICK,
+                NOTIFYICONDATAW,
+            },
+            WindowsAndMessaging::{
+                CreateWindowExW, DefWindowProcW, DestroyWindow, LoadIconW, RegisterClassW,
+                HWND_MESSAGE, IDI_WARNING, WINDOW_EX_STYLE, WINDOW_STYLE, WM_APP, WNDCLASSW,
+            },
+        },
+    },
+};
+
+/// Window class of the balloon owner window.
+const CLASS_NAME: PCWSTR = w!("LangTipBalloon");
+
+/// ID of the balloon icon among the owner window's icons.
+const ICON_ID: u32 = 1;
+
+/// Message the notification area sends balloon events with.
+const WM_BALLOON: u32 = WM_APP + 1;
+
+static CLASS_REGISTERED: AtomicBool = AtomicBool::new(false);
+
+/// Registers the window class of the owner window.
+fn register_class() -> bool {
+    if CLASS_REGISTERED.load(Ordering::SeqCst) {
+        return true;
+    }
+
+    unsafe {
+        let hinstance = GetModuleHandleW(None).unwrap_or_default();
+        let wc = WNDCLASSW {
+            lpfnWndProc: Some(window_proc),
+            hInstance: hinstance.into(),
+            lpszClassName: CLASS_NAME,
+            ..Default::default()
+        };
+        if RegisterClassW(&wc) != 0 {
+            CLASS_REGISTERED.store(true, Ordering::SeqCst);
+            true
+        } else {
+            log::error!("RegisterClassW failed for the balloon window");
+            false
+        }
+    }
+}
+
+/// Removes the icon once its balloon is gone.
+unsafe extern "system" fn window_proc(
+    hwnd: HWND,
+    msg: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    if msg == WM_BALLOON {
+        let event = lparam.0 as u32;
+        if matches!(
+            event,
+            NIN_BALLOONTIMEOUT | NIN_BALLOONUSERCLICK | NIN_BALLOONHIDE
+        ) {
+            remove_icon(hwnd);
+        }
+        return LRESULT(0);
+    }
+    DefWindowProcW(hwnd, msg, wparam, lparam)
+}
+
+/// Removes the balloon icon of `hwnd`, if it is there.
+fn remove_icon(hwnd: HWND) {
+    let data = NOTIFYICONDATAW {
+        cbSize: std::mem::size_of::<NOTIFYICONDATAW>() as u32,
+        hWnd: hwnd,
+        uID: ICON_ID,
+        ..Default::default()
+    };
+    unsafe {
+        let _ = Shell_NotifyIconW(NIM_DELETE, &data);
+    }
+}
+
+/// Copies `text` into a fixed UTF-16 buffer, cut to leave the terminator.
+fn copy_wide(buffer: &mut [u16], text: &str) {
+    let max = buffer.len() - 1;
+    for (slot, unit) in buffer.iter_mut().zip(text.encode_utf16().take(max)) {
+        *slot = unit;
+    }
+}
+
+/// Owner of the balloon icon: a message-only window, so its events arrive
+/// through the main loop's message pump.
+pub struct Balloon {
+    hwnd: HWND,
+}
+
+impl Balloon {
+    /// Creates the owner window on the calling thread.
+    pub fn new() -> Option<Self> {
+        if !register_class() {
+            return None;
+        }
+        let hwnd = unsafe {
+            let hinstance = GetModuleHandleW(None).unwrap_or_default();
+            CreateWindowExW(
+                WINDOW_EX_STYLE::default(),
+                CLASS_NAME,
+                PCWSTR::null(),
+                WINDOW_STYLE::default(),
+                0,
+                0,
+                0,
+                0,
+                HWND_MESSAGE,
+                None,
+                hinstance,
+                None,
+            )
+        };
+        match hwnd {
+            Ok(hwnd) => Some(Self { hwnd }),
+            Err(e) => {
+                log::error!("Failed to create the balloon window: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Shows a warning balloon, replacing the one still up.
+    pub fn show(&self, title: &str, text: &str) {
+        remove_icon(self.hwnd);
+
+        let mut data = NOTIFYICONDATAW {
+            cbSize: std::mem::size_of::<NOTIFYICONDATAW>() as u32,
+            hWnd: self.hwnd,
+            uID: ICON_ID,
+            uFlags: NIF_ICON | NIF_TIP | NIF_INFO | NIF_MESSAGE,
+            uCallbackMessage: WM_BALLOON,
+            hIcon: unsafe { LoadIconW(None, IDI_WARNING) }.unwrap_or_default(),
+            dwInfoFlags: NIIF_WARNING,
+            ..Default::default()
+        };
+        copy_wide(&mut data.szTip, title);
+        copy_wide(&mut data.szInfoTitle, title);
+        copy_wide(&mut data.szInfo, text);
+        if !unsafe { Shell_NotifyIconW(NIM_ADD, &data) }.as_bool() {
+            log::warn!("Failed to show notification {:?}", title);
+        }
+    }
+}
+
+impl Drop for Balloon {
+    fn drop(&mut self) {
+        remove_icon(self.hwnd);
+        unsafe {
+            let _ = DestroyWindow(self.hwnd);
+        }
+    }
+}
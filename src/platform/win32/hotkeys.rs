@@ -0,0 +1,664 @@
+//! Global hotkeys module.
+//!
+//! Manages global hotkeys for the application using Windows API. Every
+//! combination gets its own hotkey ID; presses are dispatched as actions.
+//! `RegisterHotKey` doesn't tell left and right modifiers apart, so bindings
+//! like `rctrl+f1` are checked against the key state when they fire.
+//! The registration result of every binding is kept for conflict reports.
+//! Gestures, and the release of a `show-while-held` hotkey, are recognised
+//! on the same thread from key events the low-level keyboard hook forwards.
+//! A config reload is applied on the thread too, since hotkeys belong to
+//! the thread that registered them. Remapped keys are decided in the hook
+//! itself, which has to swallow them before other applications see them.
+
+use crate::action::Action;
+use crate::config::{GestureBinding, HotkeyBinding, HotkeyConfig};
+use crate::gesture::{GestureKey, GestureRecognizer, KeyEvent};
+use crate::hotkey::{
+    self, BindingStatus, Hotkey, HotkeyRegistry, Key, Modifier, Modifiers, Registration, Side,
+};
+use crate::platform::{ActionCallback, HotkeyBackend};
+use crate::remap::{KeyRemapper, Remapped};
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+use windows::Win32::{
+    Foundation::{HWND, LPARAM, WPARAM},
+    System::Threading::GetCurrentThreadId,
+    UI::Input::KeyboardAndMouse::{
+        GetAsyncKeyState, RegisterHotKey, UnregisterHotKey, HOT_KEY_MODIFIERS, MOD_ALT,
+        MOD_CONTROL, MOD_SHIFT, MOD_WIN,
+    },
+    UI::WindowsAndMessaging::{
+        DispatchMessageW, GetMessageW, KillTimer, PostThreadMessageW, SetTimer, TranslateMessage,
+        MSG, WM_APP, WM_HOTKEY, WM_QUIT, WM_TIMER,
+    },
+};
+
+/// Maps a key to its virtual key code.
+fn virtual_key(key: Key) -> u32 {
+    match key {
+        // OEM punctuation (US layout positions)
+        Key::Char(';') => 0xBA,
+        Key::Char('=') => 0xBB,
+        Key::Char(',') => 0xBC,
+        Key::Char('-') => 0xBD,
+        Key::Char('.') => 0xBE,
+        Key::Char('/') => 0xBF,
+        Key::Char('`') => 0xC0,
+        Key::Char('[') => 0xDB,
+        Key::Char('\\') => 0xDC,
+        Key::Char(']') => 0xDD,
+        Key::Char('\'') => 0xDE,
+        // Letters and digits share their upper-case ASCII codes
+        Key::Char(c) => c.to_ascii_uppercase() as u32,
+        Key::F(n) => 0x6F + n as u32,
+        Key::Space => 0x20,
+        Key::Enter => 0x0D,
+        Key::Escape => 0x1B,
+        Key::Tab => 0x09,
+        Key::Backspace => 0x08,
+        Key::Up => 0x26,
+        Key::Down => 0x28,
+        Key::Left => 0x25,
+        Key::Right => 0x27,
+        Key::Insert => 0x2D,
+        Key::Delete => 0x2E,
+        Key::Home => 0x24,
+        Key::End => 0x23,
+        Key::PageUp => 0x21,
+        Key::PageDown => 0x22,
+        Key::PrintScreen => 0x2C,
+        Key::CapsLock => 0x14,
+        Key::ScrollLock => 0x91,
+        Key::Pause => 0x13,
+        Key::Numpad(n) => 0x60 + n as u32,
+        Key::NumpadMultiply => 0x6A,
+        Key::NumpadAdd => 0x6B,
+        Key::NumpadSubtract => 0x6D,
+        Key::NumpadDecimal => 0x6E,
+        Key::NumpadDivide => 0x6F,
+        Key::VolumeMute => 0xAD,
+        Key::VolumeDown => 0xAE,
+        Key::VolumeUp => 0xAF,
+        Key::MediaNext => 0xB0,
+        Key::MediaPrev => 0xB1,
+        Key::MediaStop => 0xB2,
+        Key::MediaPlayPause => 0xB3,
+    }
+}
+
+/// Returns the virtual key codes of the left and right key of a modifier.
+fn side_keys(modifier: Modifier) -> (i32, i32) {
+    match modifier {
+        Modifier::Ctrl => (0xA2, 0xA3),
+        Modifier::Alt => (0xA4, 0xA5),
+        Modifier::Shift => (0xA0, 0xA1),
+        Modifier::Win => (0x5B, 0x5C),
+    }
+}
+
+/// Maps a gesture key to its virtual key code. The keyboard hook reports
+/// modifiers by side, so `Side::Either` has no code of its own.
+fn gesture_virtual_key(key: GestureKey) -> Option<u32> {
+    match key {
+        GestureKey::Modifier(modifier, side) => {
+            let (left, right) = side_keys(modifier);
+            match side {
+                Side::Left => Some(left as u32),
+                Side::Right => Some(right as u32),
+                Side::Either => None,
+            }
+        }
+        GestureKey::Key(key) => Some(virtual_key(key)),
+    }
+}
+
+/// Maps hotkey modifiers to `RegisterHotKey` modifiers.
+fn hotkey_modifiers(modifiers: &Modifiers) -> HOT_KEY_MODIFIERS {
+    let mut flags = HOT_KEY_MODIFIERS(0);
+    for (modifier, flag) in [
+        (Modifier::Ctrl, MOD_CONTROL),
+        (Modifier::Alt, MOD_ALT),
+        (Modifier::Shift, MOD_SHIFT),
+        (Modifier::Win, MOD_WIN),
+    ] {
+        if modifiers.get(modifier).is_some() {
+            flags |= flag;
+        }
+    }
+    flags
+}
+
+/// Checks that the left/right modifiers a binding needs are held down.
+fn sides_down(modifiers: &Modifiers) -> bool {
+    let down = |vk: i32| unsafe { GetAsyncKeyState(vk) } as u16 & 0x8000 != 0;
+    modifiers.sided().into_iter().all(|(modifier, side)| {
+        let (left, right) = side_keys(modifier);
+        side.accepts(down(left), down(right))
+    })
+}
+
+/// Bindings registered under one hotkey ID: the same combination, possibly
+/// with different modifier sides. The most specific comes first.
+type Candidates = Vec<(Modifiers, Action)>;
+
+/// Hotkey ID used to check whether a combination is free.
+const PROBE_ID: i32 = 0xBFFF;
+
+/// Thread message carrying a key event from the keyboard hook: `wParam` is
+/// the virtual key code, `lParam` is 1 for a press and 0 for a release.
+const WM_KEY_EVENT: u32 = WM_APP + 1;
+
+/// Thread message asking to apply the config in `HotkeyState::reload`.
+const WM_RELOAD_HOTKEYS: u32 = WM_APP + 2;
+
+/// Global state for hotkey manager.
+struct HotkeyState {
+    /// Registration result of each binding, in config order.
+    status: Vec<BindingStatus>,
+    on_action: ActionCallback,
+    /// Config to apply on `WM_RELOAD_HOTKEYS` and who waits for it.
+    reload: Option<(HotkeyConfig, mpsc::Sender<()>)>,
+}
+
+static HOTKEY_STATE: Mutex<Option<HotkeyState>> = Mutex::new(None);
+static RUNNING: AtomicBool = AtomicBool::new(false);
+static HOTKEY_THREAD_ID: AtomicU32 = AtomicU32::new(0);
+/// Whether gestures or `show-while-held` hotkeys are configured, so key
+/// events are worth forwarding.
+static FORWARD_KEYS: AtomicBool = AtomicBool::new(false);
+
+/// Remaps of the running config, consulted by the keyboard hook.
+static REMAPPER: Mutex<Option<KeyRemapper<u32>>> = Mutex::new(None);
+
+/// Sets the remaps the keyboard hook applies.
+fn set_remaps(config: &HotkeyConfig) {
+    let remapper = KeyRemapper::new(&config.remap, gesture_virtual_key);
+    *REMAPPER.lock() = (!remapper.is_empty()).then_some(remapper);
+}
+
+/// Tells the low-level keyboard hook what to do with a key event and
+/// dispatches the action of a remapped key.
+pub fn remap_key(vk: u32, down: bool) -> Remapped {
+    let remapped = match *REMAPPER.lock() {
+        Some(ref mut remapper) => remapper.handle(vk, down),
+        None => return Remapped::default(),
+    };
+    if let Some(ref action) = remapped.action {
+        dispatch(vec![action.clone()]);
+    }
+    remapped
+}
+
+/// Forwards a key event from the low-level keyboard hook to the hotkey
+/// thread.
+pub fn key_event(vk: u32, down: bool) {
+    if !FORWARD_KEYS.load(Ordering::SeqCst) {
+        return;
+    }
+    let thread_id = HOTKEY_THREAD_ID.load(Ordering::SeqCst);
+    if thread_id != 0 {
+        unsafe {
+            let _ = PostThreadMessageW(
+                thread_id,
+                WM_KEY_EVENT,
+                WPARAM(vk as usize),
+                LPARAM(down as isize),
+            );
+        }
+    }
+}
+
+/// Hotkey manager.
+pub struct HotkeyManager {
+    thread: Option<JoinHandle<()>>,
+}
+
+impl HotkeyManager {
+    /// Creates a new hotkey manager.
+    pub fn new() -> Self {
+        Self { thread: None }
+    }
+}
+
+impl Default for HotkeyManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HotkeyBackend for HotkeyManager {
+    /// Starts the hotkey manager.
+    fn start(&mut self, config: &HotkeyConfig, on_action: ActionCallback) {
+        if !config.enabled || RUNNING.load(Ordering::SeqCst) {
+            return;
+        }
+
+        let bindings = config.all_bindings();
+        let gestures = config.gestures.clone();
+
+        // Initialize state
+        {
+            let mut state = HOTKEY_STATE.lock();
+            *state = Some(HotkeyState {
+                status: Vec::new(),
+                on_action,
+                reload: None,
+            });
+        }
+
+        RUNNING.store(true, Ordering::SeqCst);
+        set_remaps(config);
+
+        let (ready_tx, ready_rx) = mpsc::channel();
+        let thread = thread::spawn(move || {
+            hotkey_message_loop(bindings, gestures, ready_tx);
+        });
+
+        self.thread = Some(thread);
+
+        // Wait for the registration so its status can be reported
+        let _ = ready_rx.recv();
+    }
+
+    /// Returns the registration result of each binding.
+    fn status(&self) -> Vec<BindingStatus> {
+        let state = HOTKEY_STATE.lock();
+        state.as_ref().map(|s| s.status.clone()).unwrap_or_default()
+    }
+
+    /// Applies a reloaded config on the hotkey thread and waits for it.
+    fn reload(&mut self, config: &HotkeyConfig) {
+        let thread_id = HOTKEY_THREAD_ID.load(Ordering::SeqCst);
+        if !RUNNING.load(Ordering::SeqCst) || thread_id == 0 {
+            return;
+        }
+
+        let (done_tx, done_rx) = mpsc::channel();
+        {
+            let mut state = HOTKEY_STATE.lock();
+            let Some(ref mut s) = *state else {
+                return;
+            };
+            s.reload = Some((config.clone(), done_tx));
+        }
+        let posted =
+            unsafe { PostThreadMessageW(thread_id, WM_RELOAD_HOTKEYS, WPARAM(0), LPARAM(0)) };
+        if posted.is_ok() {
+            let _ = done_rx.recv();
+        }
+    }
+
+    /// Stops the hotkey manager.
+    fn stop(&mut self) {
+        if !RUNNING.load(Ordering::SeqCst) {
+            return;
+        }
+
+        RUNNING.store(false, Ordering::SeqCst);
+        FORWARD_KEYS.store(false, Ordering::SeqCst);
+        *REMAPPER.lock() = None;
+
+        // Post WM_QUIT to exit the message loop
+        let thread_id = HOTKEY_THREAD_ID.load(Ordering::SeqCst);
+        if thread_id != 0 {
+            unsafe {
+                let _ = PostThreadMessageW(thread_id, WM_QUIT, WPARAM(0), LPARAM(0));
+            }
+        }
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+
+        // Clear state
+        let mut state = HOTKEY_STATE.lock();
+        *state = None;
+    }
+}
+
+impl Drop for HotkeyManager {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Checks whether a combination can be registered by registering it under
+/// `PROBE_ID` and releasing it again.
+fn is_free(hotkey: &Hotkey) -> bool {
+    let modifiers = hotkey_modifiers(&hotkey.modifiers);
+    let vk = virtual_key(hotkey.key);
+    unsafe {
+        let free = RegisterHotKey(HWND::default(), PROBE_ID, modifiers, vk).is_ok();
+        if free {
+            let _ = UnregisterHotKey(HWND::default(), PROBE_ID);
+        }
+        free
+    }
+}
+
+/// Registers bindings on the calling thread, allocating a hotkey ID for
+/// each combination.
+struct Registrar {
+    /// Bindings of every registered ID.
+    actions: HashMap<i32, Candidates>,
+    /// ID of each registered (modifiers, virtual key) combination.
+    ids: HashMap<(u32, u32), i32>,
+    next_id: i32,
+    /// Every combination in the config, kept out of the suggestions.
+    configured: Vec<Hotkey>,
+}
+
+impl Registrar {
+    fn new(bindings: &[HotkeyBinding]) -> Self {
+        Self {
+            actions: HashMap::new(),
+            ids: HashMap::new(),
+            next_id: 1,
+            configured: hotkey::configured_hotkeys(bindings),
+        }
+    }
+
+    /// Registers one combination.
+    fn register_hotkey(&mut self, hotkey: Hotkey, action: Action) -> Registration {
+        let modifiers = hotkey_modifiers(&hotkey.modifiers);
+        let vk = virtual_key(hotkey.key);
+        let candidate = (hotkey.modifiers, action.clone());
+
+        // Same combination with other modifier sides: share the ID
+        if let Some(id) = self.ids.get(&(modifiers.0, vk)) {
+            if let Some(candidates) = self.actions.get_mut(id) {
+                candidates.push(candidate);
+                candidates.sort_by_key(|(m, _)| std::cmp::Reverse(m.sided().len()));
+                log::debug!("Registered {} hotkey: {}", action, hotkey);
+                return Registration::Registered;
+            }
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+        if unsafe { RegisterHotKey(HWND::default(), id, modifiers, vk) }.is_ok() {
+            self.ids.insert((modifiers.0, vk), id);
+            self.actions.insert(id, vec![candidate]);
+            log::debug!("Registered {} hotkey: {}", action, hotkey);
+            Registration::Registered
+        } else {
+            log::warn!("Failed to register {} hotkey: {}", action, hotkey);
+            Registration::Taken(hotkey::suggest_alternatives(
+                &hotkey,
+                &self.configured,
+                is_free,
+            ))
+        }
+    }
+}
+
+impl HotkeyRegistry for Registrar {
+    fn register(&mut self, binding: &HotkeyBinding) -> Registration {
+        match binding.parse() {
+            Ok((hotkey, action)) => self.register_hotkey(hotkey, action),
+            Err(e) => {
+                log::warn!(
+                    "Invalid {} hotkey {:?}: {}",
+                    binding.action,
+                    binding.keys,
+                    e
+                );
+                Registration::Invalid(e)
+            }
+        }
+    }
+
+    /// Unregisters one binding; the ID goes once no binding is left on it.
+    fn unregister(&mut self, binding: &HotkeyBinding) {
+        let Ok((hotkey, action)) = binding.parse() else {
+            return;
+        };
+        let combination = (
+            hotkey_modifiers(&hotkey.modifiers).0,
+            virtual_key(hotkey.key),
+        );
+        let Some(&id) = self.ids.get(&combination) else {
+            return;
+        };
+        let Some(candidates) = self.actions.get_mut(&id) else {
+            return;
+        };
+        if let Some(i) = candidates
+            .iter()
+            .position(|(m, a)| *m == hotkey.modifiers && *a == action)
+        {
+            candidates.remove(i);
+            log::debug!("Unregistered {} hotkey: {}", action, hotkey);
+        }
+        if candidates.is_empty() {
+            self.actions.remove(&id);
+            self.ids.remove(&combination);
+            let _ = unsafe { UnregisterHotKey(HWND::default(), id) };
+        }
+    }
+}
+
+/// Registers the bindings on the calling thread. Returns the registrar
+/// holding them and the registration result of each binding.
+fn register_bindings(bindings: &[HotkeyBinding]) -> (Registrar, Vec<BindingStatus>) {
+    let mut registrar = Registrar::new(bindings);
+    let status = bindings
+        .iter()
+        .map(|binding| BindingStatus {
+            keys: binding.keys.clone(),
+            action: binding.action.clone(),
+            registration: registrar.register(binding),
+        })
+        .collect();
+    (registrar, status)
+}
+
+/// Whether the keyboard hook has to forward key events for these settings.
+fn needs_key_events(bindings: &[HotkeyBinding], recognizer: &GestureRecognizer<u32>) -> bool {
+    !recognizer.is_empty()
+        || bindings
+            .iter()
+            .any(|b| b.parse().is_ok_and(|(_, action)| action == Action::PeekWhileHeld))
+}
+
+/// Dispatches actions to the callback in the global state.
+fn dispatch(actions: Vec<Action>) {
+    if actions.is_empty() {
+        return;
+    }
+    let callback = HOTKEY_STATE.lock().as_ref().map(|s| s.on_action.clone());
+    if let Some(cb) = callback {
+        for action in actions {
+            cb(action);
+        }
+    }
+}
+
+/// Returns the virtual key codes whose release ends a `show-while-held`
+/// hotkey: its key and both keys of each of its modifiers.
+fn release_keys(vk: u32, modifiers: &Modifiers) -> Vec<u32> {
+    let mut keys = vec![vk];
+    for modifier in [
+        Modifier::Ctrl,
+        Modifier::Alt,
+        Modifier::Shift,
+        Modifier::Win,
+    ] {
+        if modifiers.get(modifier).is_some() {
+            let (left, right) = side_keys(modifier);
+            keys.extend([left as u32, right as u32]);
+        }
+    }
+    keys
+}
+
+/// Sets the thread timer to fire when the recogniser next has something to
+/// do, replacing the previous one.
+fn schedule_gesture_tick(recognizer: &GestureRecognizer<u32>, started: Instant, timer: &mut usize) {
+    unsafe {
+        if *timer != 0 {
+            let _ = KillTimer(HWND::default(), *timer);
+            *timer = 0;
+        }
+        if let Some(deadline) = recognizer.next_deadline() {
+            let delay = deadline.saturating_sub(started.elapsed()).as_millis();
+            *timer = SetTimer(
+                HWND::default(),
+                0,
+                delay.clamp(1, u32::MAX as u128) as u32,
+                None,
+            );
+        }
+    }
+}
+
+/// Message loop for hotkey processing.
+fn hotkey_message_loop(
+    bindings: Vec<HotkeyBinding>,
+    gestures: Vec<GestureBinding>,
+    ready: mpsc::Sender<()>,
+) {
+    unsafe {
+        let thread_id = GetCurrentThreadId();
+        HOTKEY_THREAD_ID.store(thread_id, Ordering::SeqCst);
+
+        let mut recognizer = GestureRecognizer::new(&gestures, gesture_virtual_key);
+        let started = Instant::now();
+        let mut timer = 0;
+        // Keys whose release ends the held `show-while-held` hotkey
+        let mut held_peek: Vec<u32> = Vec::new();
+        FORWARD_KEYS.store(needs_key_events(&bindings, &recognizer), Ordering::SeqCst);
+
+        // Register hotkeys
+        let (mut registrar, status) = register_bindings(&bindings);
+        {
+            let mut state = HOTKEY_STATE.lock();
+            if let Some(ref mut s) = *state {
+                s.status = status;
+            }
+        }
+        let _ = ready.send(());
+
+        // Message loop
+        let mut msg = MSG::default();
+        while RUNNING.load(Ordering::SeqCst) {
+            let result = GetMessageW(&mut msg, HWND::default(), 0, 0);
+            if result.0 == 0 || result.0 == -1 {
+                break;
+            }
+
+            if msg.message == WM_HOTKEY {
+                let hotkey_id = msg.wParam.0 as i32;
+                let found = registrar
+                    .actions
+                    .get(&hotkey_id)
+                    .and_then(|candidates| {
+                        candidates
+                            .iter()
+                            .find(|(modifiers, _)| sides_down(modifiers))
+                    })
+                    .cloned();
+                if let Some((modifiers, action)) = found {
+                    if action == Action::PeekWhileHeld {
+                        // lParam: modifiers in the low word, key in the high word
+                        let vk = (msg.lParam.0 as u32 >> 16) & 0xFFFF;
+                        held_peek = release_keys(vk, &modifiers);
+                    }
+                    dispatch(vec![action]);
+                }
+            }
+
+            if msg.message == WM_RELOAD_HOTKEYS {
+                let reload = HOTKEY_STATE.lock().as_mut().and_then(|s| s.reload.take());
+                if let Some((config, done)) = reload {
+                    let bindings = config.all_bindings();
+                    let current = HOTKEY_STATE.lock().as_ref().map(|s| s.status.clone());
+                    registrar.configured = hotkey::configured_hotkeys(&bindings);
+                    let status = hotkey::reload_bindings(
+                        &current.unwrap_or_default(),
+                        &bindings,
+                        &mut registrar,
+                    );
+                    recognizer = GestureRecognizer::new(&config.gestures, gesture_virtual_key);
+                    schedule_gesture_tick(&recognizer, started, &mut timer);
+                    held_peek.clear();
+                    set_remaps(&config);
+                    FORWARD_KEYS.store(needs_key_events(&bindings, &recognizer), Ordering::SeqCst);
+                    if let Some(ref mut s) = *HOTKEY_STATE.lock() {
+                        s.status = status;
+                    }
+                    let _ = done.send(());
+                }
+            }
+
+            if msg.message == WM_KEY_EVENT
+                && msg.lParam.0 == 0
+                && held_peek.contains(&(msg.wParam.0 as u32))
+            {
+                held_peek.clear();
+                dispatch(vec![Action::EndPeek]);
+            }
+
+            if msg.message == WM_KEY_EVENT || msg.message == WM_TIMER {
+                let actions = if msg.message == WM_KEY_EVENT {
+                    recognizer.handle(KeyEvent {
+                        key: msg.wParam.0 as u32,
+                        down: msg.lParam.0 != 0,
+                        at: started.elapsed(),
+                    })
+                } else {
+                    recognizer.tick(started.elapsed())
+                };
+                dispatch(actions);
+                schedule_gesture_tick(&recognizer, started, &mut timer);
+            }
+
+            let _ = TranslateMessage(&msg);
+            DispatchMessageW(&msg);
+        }
+
+        FORWARD_KEYS.store(false, Ordering::SeqCst);
+        if timer != 0 {
+            let _ = KillTimer(HWND::default(), timer);
+        }
+
+        // Unregister hotkeys and clear state inside thread
+        for hotkey_id in registrar.actions.keys() {
+            let _ = UnregisterHotKey(HWND::default(), *hotkey_id);
+        }
+        *HOTKEY_STATE.lock() = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vk(hotkey: &str) -> u32 {
+        virtual_key(hotkey::parse_hotkey(hotkey).unwrap().key)
+    }
+
+    #[test]
+    fn keys_map_to_virtual_keys() {
+        assert_eq!(vk("l"), 0x4C);
+        assert_eq!(vk("7"), 0x37);
+        assert_eq!(vk("f1"), 0x70);
+        assert_eq!(vk("f24"), 0x87);
+        assert_eq!(vk("pgup"), 0x21);
+        assert_eq!(vk("num5"), 0x65);
+        assert_eq!(vk("semicolon"), 0xBA);
+        assert_eq!(vk("quote"), 0xDE);
+        assert_eq!(vk("mediaplay"), 0xB3);
+    }
+
+    #[test]
+    fn modifiers_map_to_hotkey_flags() {
+        let modifiers = hotkey::parse_hotkey("rctrl+win+l").unwrap().modifiers;
+        assert_eq!(hotkey_modifiers(&modifiers), MOD_CONTROL | MOD_WIN);
+    }
+}
@@ -0,0 +1,833 @@
+//! Keyboard hook module.
+//!
+//! Tracks keyboard layout changes using Windows hooks:
+//! - SetWinEventHook for window focus changes
+//! - SetWindowsHookEx with WH_KEYBOARD_LL for modifier key releases
+//!
+//! The keyboard hook also forwards key events to the hotkey thread for
+//! gestures and `show-while-held` hotkeys, and swallows remapped keys.
+//!
+//! Layouts are switched by posting `WM_INPUTLANGCHANGEREQUEST` to the
+//! foreground window, with the target resolved against
+//! `GetKeyboardLayoutList`.
+
+use crate::layout::{
+    self, HookTimings, InstalledLayout, LayoutCallback, LayoutEvent, LayoutInfo, LayoutSwitch,
+};
+use crate::platform::LayoutSource;
+use parking_lot::{Condvar, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use windows::core::{PCWSTR, PWSTR};
+use windows::Win32::{
+    Foundation::{ERROR_NO_MORE_ITEMS, HINSTANCE, HWND, LPARAM, LRESULT, WPARAM},
+    Globalization::{GetLocaleInfoW, LCIDToLocaleName},
+    System::Registry::{
+        RegCloseKey, RegEnumKeyExW, RegGetValueW, RegOpenKeyExW, HKEY, HKEY_LOCAL_MACHINE,
+        KEY_READ, RRF_RT_REG_SZ,
+    },
+    System::Threading::GetCurrentThreadId,
+    UI::{
+        Accessibility::{SetWinEventHook, UnhookWinEvent, HWINEVENTHOOK},
+        Input::KeyboardAndMouse::{
+            GetKeyboardLayout, GetKeyboardLayoutList, SendInput, HKL, INPUT, INPUT_0,
+            INPUT_KEYBOARD, KEYBDINPUT, KEYBD_EVENT_FLAGS, KEYEVENTF_EXTENDEDKEY, KEYEVENTF_KEYUP,
+            VIRTUAL_KEY,
+        },
+        WindowsAndMessaging::{
+            CallNextHookEx, DispatchMessageW, GetForegroundWindow, GetMessageW,
+            GetWindowThreadProcessId, PostMessageW, PostThreadMessageW, SetWindowsHookExW,
+            TranslateMessage, UnhookWindowsHookEx, HHOOK, KBDLLHOOKSTRUCT, LLKHF_EXTENDED,
+            LLKHF_INJECTED, MSG, WH_KEYBOARD_LL, WM_INPUTLANGCHANGEREQUEST, WM_KEYDOWN, WM_KEYUP,
+            WM_QUIT, WM_SYSKEYDOWN, WM_SYSKEYUP,
+        },
+    },
+};
+
+// WinEvent constants
+const WINEVENT_OUTOFCONTEXT: u32 = 0x0000;
+const WINEVENT_SKIPOWNPROCESS: u32 = 0x0002;
+
+// Windows event constants
+const EVENT_SYSTEM_FOREGROUND: u32 = 0x0003;
+
+// Virtual key codes for modifiers
+const VK_SHIFT: u32 = 0x10;
+const VK_CONTROL: u32 = 0x11;
+const VK_MENU: u32 = 0x12; // Alt
+const VK_LSHIFT: u32 = 0xA0;
+const VK_RSHIFT: u32 = 0xA1;
+const VK_LCONTROL: u32 = 0xA2;
+const VK_RCONTROL: u32 = 0xA3;
+const VK_LMENU: u32 = 0xA4;
+const VK_RMENU: u32 = 0xA5;
+
+// Unassigned virtual key pressed to keep a lone Alt from opening menus
+const VK_MASK: u16 = 0xE8;
+
+// Language IDs
+const LANG_EN_US: u32 = 0x409;
+const LANG_RU: u32 = 0x419;
+
+// Locale info type for English language name
+const LOCALE_SENGLISHLANGUAGENAME: u32 = 0x1001;
+
+// Locale info type for English display name ("Russian (Russia)")
+const LOCALE_SENGLISHDISPLAYNAME: u32 = 0x72;
+
+// Registry key with the installed keyboards, one subkey per KLID
+const KEYBOARD_LAYOUTS_KEY: &str = r"SYSTEM\CurrentControlSet\Control\Keyboard Layouts";
+
+// Maximum locale name length, including the terminating null
+const LOCALE_NAME_MAX_LENGTH: usize = 85;
+
+// Lower bound for the re-check interval while waiting for a late switch
+const MIN_RECHECK_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Gets a 2-letter abbreviation for a language ID using GetLocaleInfoW.
+fn get_language_abbreviation(lang_id: u32) -> String {
+    unsafe {
+        let mut buf = [0u16; 64];
+        let len = GetLocaleInfoW(
+            lang_id,
+            LOCALE_SENGLISHLANGUAGENAME,
+            Some(&mut buf),
+        );
+
+        if len > 0 {
+            // Get full language name and take first 2 characters
+            let name = String::from_utf16_lossy(&buf[..(len as usize).saturating_sub(1)]);
+            if name.len() >= 2 {
+                return name[..2].to_uppercase();
+            }
+        }
+
+        // Fallback to hex code
+        format!("{:X}", lang_id)
+    }
+}
+
+/// Wrapper for HHOOK to make it Send + Sync
+#[derive(Debug, Clone, Copy)]
+struct HhookWrapper(isize);
+
+// SAFETY: HHOOK is just a handle, thread-safe when properly synchronized
+unsafe impl Send for HhookWrapper {}
+unsafe impl Sync for HhookWrapper {}
+
+impl HhookWrapper {
+    fn new(hook: HHOOK) -> Self {
+        Self(hook.0 as isize)
+    }
+
+    fn as_hhook(&self) -> HHOOK {
+        HHOOK(self.0 as *mut std::ffi::c_void)
+    }
+}
+
+/// Global state for the hook callback.
+struct HookState {
+    callback: Option<LayoutCallback>,
+    last_layout: String,
+    keyboard_hook: Option<HhookWrapper>,
+    thread_id: u32,
+    start_time: Instant,
+    timings: HookTimings,
+}
+
+static HOOK_STATE: Mutex<Option<HookState>> = Mutex::new(None);
+static RUNNING: AtomicBool = AtomicBool::new(false);
+static HOOK_THREAD_ID: AtomicU32 = AtomicU32::new(0);
+// Last event timestamp in ms since start (for debounce)
+static LAST_EVENT_MS: AtomicU64 = AtomicU64::new(0);
+/// Trigger time of a pending layout check (set by hook callback,
+/// consumed by debounce worker thread).
+static PENDING_CHECK: Mutex<Option<Instant>> = Mutex::new(None);
+/// Wakes the debounce worker when a check is pending or the hook stops.
+static PENDING_CHECK_CV: Condvar = Condvar::new();
+
+/// Gets the keyboard layout of the foreground window's thread.
+fn get_current_hkl() -> HKL {
+    unsafe {
+        let hwnd = GetForegroundWindow();
+        let thread_id = GetWindowThreadProcessId(hwnd, None);
+        GetKeyboardLayout(thread_id)
+    }
+}
+
+/// Builds layout information from a keyboard layout handle.
+fn layout_info(hkl: HKL) -> LayoutInfo {
+    let lang_id = (hkl.0 as u32) & 0xFFFF;
+
+    let (name, is_russian) = match lang_id {
+        LANG_EN_US => ("EN".to_string(), false),
+        LANG_RU => ("RU".to_string(), true),
+        _ => (get_language_abbreviation(lang_id), false),
+    };
+
+    LayoutInfo { name, is_russian }
+}
+
+/// Gets the current keyboard layout.
+fn get_current_layout() -> LayoutInfo {
+    layout_info(get_current_hkl())
+}
+
+/// Gets the installed keyboard layouts in switching order.
+fn installed_layouts() -> Vec<HKL> {
+    unsafe {
+        let count = GetKeyboardLayoutList(None).max(0) as usize;
+        let mut layouts = vec![HKL::default(); count];
+        let count = GetKeyboardLayoutList(Some(&mut layouts)).max(0) as usize;
+        layouts.truncate(count);
+        layouts
+    }
+}
+
+/// Gets the codes `set-layout` finds a layout by: its locale name (`ru-RU`),
+/// its keyboard's KLID (`00000419`, `00010409` for US-Dvorak) and, last, its
+/// HKL (`F0020409`), the only one that tells apart two layouts with the same
+/// keyboard.
+fn layout_codes(hkl: HKL) -> Vec<String> {
+    let hkl = hkl.0 as usize;
+    let mut codes = Vec::new();
+
+    let mut buf = [0u16; LOCALE_NAME_MAX_LENGTH];
+    let len = unsafe { LCIDToLocaleName(hkl as u32 & 0xFFFF, Some(&mut buf), 0) };
+    if len > 1 {
+        codes.push(String::from_utf16_lossy(&buf[..len as usize - 1]));
+    }
+
+    codes.extend(keyboard_klid(hkl));
+    codes.push(hkl_code(hkl));
+    codes
+}
+
+/// Formats an HKL as eight hex digits.
+fn hkl_code(hkl: usize) -> String {
+    format!("{:08X}", hkl as u32)
+}
+
+/// Describes a layout like the language bar: its language and keyboard,
+/// e.g. `Russian (Russia) - Russian - Mnemonic`.
+fn layout_description(hkl: HKL) -> Option<String> {
+    let hkl = hkl.0 as usize;
+    let mut buf = [0u16; 128];
+    let len = unsafe {
+        GetLocaleInfoW(
+            hkl as u32 & 0xFFFF,
+            LOCALE_SENGLISHDISPLAYNAME,
+            Some(&mut buf),
+        )
+    };
+    if len <= 1 {
+        return None;
+    }
+    let language = String::from_utf16_lossy(&buf[..len as usize - 1]);
+
+    let keyboard = keyboard_klid(hkl).and_then(|klid| {
+        let key = format!(r"{}\{}", KEYBOARD_LAYOUTS_KEY, klid);
+        registry_string(HKEY_LOCAL_MACHINE, &key, "Layout Text")
+    });
+    Some(match keyboard {
+        Some(keyboard) => format!("{} - {}", language, keyboard),
+        None => language,
+    })
+}
+
+/// Gets the KLID of a layout's keyboard. IME layouts (device IDs `Exxx`)
+/// use their whole HKL as KLID; variant keyboards (device IDs `Fxxx`) are
+/// found by the `Layout Id` value of their registry key.
+fn keyboard_klid(hkl: usize) -> Option<String> {
+    let device = (hkl >> 16) as u32 & 0xFFFF;
+    match device & 0xF000 {
+        0xE000 => return Some(hkl_code(hkl)),
+        0xF000 => {}
+        _ => return Some(format!("{:08X}", device)),
+    }
+    let layout_id = format!("{:04X}", device & 0x0FFF);
+
+    let path = to_wide(KEYBOARD_LAYOUTS_KEY);
+    let mut key = HKEY::default();
+    let result = unsafe {
+        RegOpenKeyExW(
+            HKEY_LOCAL_MACHINE,
+            PCWSTR(path.as_ptr()),
+            0,
+            KEY_READ,
+            &mut key,
+        )
+    };
+    if result.is_err() {
+        return None;
+    }
+    let mut found = None;
+    for index in 0.. {
+        let mut name = [0u16; 64];
+        let mut len = name.len() as u32;
+        let result = unsafe {
+            RegEnumKeyExW(
+                key,
+                index,
+                PWSTR(name.as_mut_ptr()),
+                &mut len,
+                None,
+                PWSTR::null(),
+                None,
+                None,
+            )
+        };
+        if result == ERROR_NO_MORE_ITEMS {
+            break;
+        }
+        if result.is_err() {
+            continue;
+        }
+        let klid = String::from_utf16_lossy(&name[..len as usize]);
+        if registry_string(key, &klid, "Layout Id")
+            .is_some_and(|id| id.eq_ignore_ascii_case(&layout_id))
+        {
+            found = Some(klid);
+            break;
+        }
+    }
+    unsafe {
+        let _ = RegCloseKey(key);
+    }
+    found
+}
+
+/// Reads a string value of a registry key.
+fn registry_string(key: HKEY, subkey: &str, value: &str) -> Option<String> {
+    let subkey = to_wide(subkey);
+    let value = to_wide(value);
+    let mut buf = [0u16; 256];
+    let mut size = std::mem::size_of_val(&buf) as u32;
+    let result = unsafe {
+        RegGetValueW(
+            key,
+            PCWSTR(subkey.as_ptr()),
+            PCWSTR(value.as_ptr()),
+            RRF_RT_REG_SZ,
+            None,
+            Some(buf.as_mut_ptr().cast()),
+            Some(&mut size),
+        )
+    };
+    if result.is_err() {
+        return None;
+    }
+    // The size includes the terminating null
+    let len = (size as usize / 2).saturating_sub(1);
+    Some(String::from_utf16_lossy(&buf[..len]))
+}
+
+/// Converts a string to a null-terminated UTF-16 buffer.
+fn to_wide(text: &str) -> Vec<u16> {
+    text.encode_utf16().chain(std::iter::once(0)).collect()
+}
+
+/// Asks the foreground window to switch to a layout, as the language bar
+/// does. Fails for windows of elevated processes.
+fn request_layout(hkl: HKL) -> bool {
+    unsafe {
+        let hwnd = GetForegroundWindow();
+        if hwnd.is_invalid() {
+            log::warn!("No foreground window to switch the layout of");
+            return false;
+        }
+        match PostMessageW(
+            hwnd,
+            WM_INPUTLANGCHANGEREQUEST,
+            WPARAM(0),
+            LPARAM(hkl.0 as isize),
+        ) {
+            Ok(()) => true,
+            Err(e) => {
+                log::warn!("Failed to switch the layout: {}", e);
+                false
+            }
+        }
+    }
+}
+
+/// Returns the current detection timings.
+fn current_timings() -> HookTimings {
+    HOOK_STATE
+        .lock()
+        .as_ref()
+        .map(|s| s.timings)
+        .unwrap_or_default()
+}
+
+/// Checks for layout change and calls callback if changed.
+/// Returns true if callback was called.
+fn check_layout_change(triggered_at: Instant) -> bool {
+    report_layout(get_current_layout(), triggered_at)
+}
+
+/// Calls the callback if the layout differs from the last reported one.
+/// Returns true if callback was called.
+fn report_layout(layout: LayoutInfo, triggered_at: Instant) -> bool {
+    // Get callback outside of lock to avoid holding lock during callback
+    let callback = {
+        let mut state = HOOK_STATE.lock();
+        if let Some(ref mut s) = *state {
+            if layout.name != s.last_layout {
+                log::debug!("Layout: {} -> {}", s.last_layout, layout.name);
+                s.last_layout = layout.name.clone();
+                s.callback.clone()
+            } else {
+                None
+            }
+        } else {
+            None
+        }
+    };
+
+    // Call callback outside of lock
+    if let Some(cb) = callback {
+        cb(LayoutEvent {
+            layout,
+            triggered_at,
+            detected_at: Instant::now(),
+        });
+        true
+    } else {
+        false
+    }
+}
+
+/// Checks layout with debounce - ignores rapid events.
+/// Returns true if callback was called.
+fn check_layout_change_debounced(triggered_at: Instant) -> bool {
+    // Get current time in ms
+    let (now_ms, debounce_ms) = {
+        let state = HOOK_STATE.lock();
+        if let Some(ref s) = *state {
+            (
+                s.start_time.elapsed().as_millis() as u64,
+                s.timings.debounce.as_millis() as u64,
+            )
+        } else {
+            return false;
+        }
+    };
+
+    // Check debounce
+    let last = LAST_EVENT_MS.load(Ordering::SeqCst);
+    if now_ms.saturating_sub(last) < debounce_ms {
+        log::trace!(
+            "Debounced event ({}ms since last)",
+            now_ms.saturating_sub(last)
+        );
+        return false;
+    }
+
+    // Update last event time
+    LAST_EVENT_MS.store(now_ms, Ordering::SeqCst);
+
+    check_layout_change(triggered_at)
+}
+
+/// Schedules a layout check for the debounce worker.
+fn request_layout_check(triggered_at: Instant) {
+    let mut pending = PENDING_CHECK.lock();
+    // Keep the earliest trigger so latency is measured from the first key release
+    pending.get_or_insert(triggered_at);
+    PENDING_CHECK_CV.notify_one();
+}
+
+/// Debounce worker: sleeps on the condition variable until a check is
+/// requested, then reads the layout after the settle delay. If the layout
+/// hasn't changed yet, keeps re-reading it until the update delay expires.
+fn debounce_worker() {
+    loop {
+        let triggered_at = {
+            let mut pending = PENDING_CHECK.lock();
+            while pending.is_none() && RUNNING.load(Ordering::SeqCst) {
+                PENDING_CHECK_CV.wait(&mut pending);
+            }
+            match pending.take() {
+                Some(t) if RUNNING.load(Ordering::SeqCst) => t,
+                _ => return,
+            }
+        };
+
+        let timings = current_timings();
+        thread::sleep(timings.settle_delay);
+        if !RUNNING.load(Ordering::SeqCst) {
+            return;
+        }
+        if check_layout_change_debounced(triggered_at) {
+            continue;
+        }
+
+        // Some switches are applied after the modifier release; re-read until
+        // the update delay expires, a new trigger arrives or the hook stops.
+        let interval = timings.settle_delay.max(MIN_RECHECK_INTERVAL);
+        while triggered_at.elapsed() < timings.update_delay {
+            {
+                let mut pending = PENDING_CHECK.lock();
+                if pending.is_none() && RUNNING.load(Ordering::SeqCst) {
+                    PENDING_CHECK_CV.wait_for(&mut pending, interval);
+                }
+                if pending.is_some() || !RUNNING.load(Ordering::SeqCst) {
+                    break;
+                }
+            }
+            if check_layout_change(triggered_at) {
+                break;
+            }
+        }
+    }
+}
+
+/// Checks if a virtual key code is a modifier key.
+fn is_modifier_key(vk_code: u32) -> bool {
+    matches!(
+        vk_code,
+        VK_SHIFT
+            | VK_CONTROL
+            | VK_MENU
+            | VK_LSHIFT
+            | VK_RSHIFT
+            | VK_LCONTROL
+            | VK_RCONTROL
+            | VK_LMENU
+            | VK_RMENU
+    )
+}
+
+/// Sends a masking key press followed by the release of a remapped lone
+/// modifier, which the hook swallowed. A modifier released right after
+/// another key doesn't activate menus.
+fn send_masked_release(kb: &KBDLLHOOKSTRUCT) {
+    let key = |vk: u16, scan: u16, flags: KEYBD_EVENT_FLAGS| INPUT {
+        r#type: INPUT_KEYBOARD,
+        Anonymous: INPUT_0 {
+            ki: KEYBDINPUT {
+                wVk: VIRTUAL_KEY(vk),
+                wScan: scan,
+                dwFlags: flags,
+                time: 0,
+                dwExtraInfo: 0,
+            },
+        },
+    };
+    let mut release = KEYEVENTF_KEYUP;
+    if kb.flags.contains(LLKHF_EXTENDED) {
+        release |= KEYEVENTF_EXTENDEDKEY;
+    }
+    let inputs = [
+        key(VK_MASK, 0, KEYBD_EVENT_FLAGS(0)),
+        key(VK_MASK, 0, KEYEVENTF_KEYUP),
+        key(kb.vkCode as u16, kb.scanCode as u16, release),
+    ];
+    unsafe {
+        SendInput(&inputs, std::mem::size_of::<INPUT>() as i32);
+    }
+}
+
+/// Low-level keyboard hook callback.
+unsafe extern "system" fn keyboard_hook_proc(
+    n_code: i32,
+    w_param: WPARAM,
+    l_param: LPARAM,
+) -> LRESULT {
+    if n_code >= 0 && l_param.0 != 0 {
+        let kb = &*(l_param.0 as *const KBDLLHOOKSTRUCT);
+        let vk_code = kb.vkCode;
+
+        // Check for modifier key release (layout changes after keyup)
+        if is_modifier_key(vk_code)
+            && (w_param.0 == WM_KEYUP as usize || w_param.0 == WM_SYSKEYUP as usize)
+        {
+            // Signal pending layout check (handled by debounce worker thread)
+            request_layout_check(Instant::now());
+        }
+
+        // Synthesised input isn't a gesture or a remapped key
+        if !kb.flags.contains(LLKHF_INJECTED) {
+            let down = match w_param.0 as u32 {
+                WM_KEYDOWN | WM_SYSKEYDOWN => Some(true),
+                WM_KEYUP | WM_SYSKEYUP => Some(false),
+                _ => None,
+            };
+            if let Some(down) = down {
+                super::hotkeys::key_event(vk_code, down);
+                let remapped = super::hotkeys::remap_key(vk_code, down);
+                if remapped.mask {
+                    send_masked_release(kb);
+                }
+                if remapped.swallow {
+                    return LRESULT(1);
+                }
+            }
+        }
+    }
+
+    let hook = HOOK_STATE
+        .lock()
+        .as_ref()
+        .and_then(|s| s.keyboard_hook)
+        .map(|h| h.as_hhook())
+        .unwrap_or_default();
+    CallNextHookEx(hook, n_code, w_param, l_param)
+}
+
+/// WinEvent hook callback for window focus changes.
+unsafe extern "system" fn win_event_proc(
+    _h_win_event_hook: HWINEVENTHOOK,
+    _event: u32,
+    _hwnd: HWND,
+    _id_object: i32,
+    _id_child: i32,
+    _id_event_thread: u32,
+    _dwms_event_time: u32,
+) {
+    check_layout_change_debounced(Instant::now());
+}
+
+/// Keyboard layout hook manager.
+pub struct KeyboardLayoutHook {
+    thread: Option<JoinHandle<()>>,
+}
+
+impl KeyboardLayoutHook {
+    /// Creates a new keyboard layout hook.
+    pub fn new() -> Self {
+        Self { thread: None }
+    }
+}
+
+impl Default for KeyboardLayoutHook {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LayoutSource for KeyboardLayoutHook {
+    fn current_layout(&self) -> LayoutInfo {
+        get_current_layout()
+    }
+
+    fn layout_description(&self) -> Option<String> {
+        layout_description(get_current_hkl())
+    }
+
+    /// Lists the layouts by HKL, the code unique to each (see
+    /// `layout_codes`).
+    fn installed_layouts(&self) -> Vec<InstalledLayout> {
+        let current = get_current_hkl();
+        installed_layouts()
+            .into_iter()
+            .map(|hkl| InstalledLayout {
+                code: hkl_code(hkl.0 as usize),
+                label: layout_description(hkl).unwrap_or_else(|| layout_info(hkl).name),
+                active: hkl == current,
+            })
+            .collect()
+    }
+
+    /// Starts the hook in a separate thread.
+    ///
+    /// The initial layout prevents false triggering on startup.
+    fn start(&mut self, callback: LayoutCallback, initial: &LayoutInfo, timings: HookTimings) {
+        if RUNNING.load(Ordering::SeqCst) {
+            return;
+        }
+
+        {
+            let mut state = HOOK_STATE.lock();
+            *state = Some(HookState {
+                callback: Some(callback),
+                last_layout: initial.name.clone(),
+                keyboard_hook: None,
+                thread_id: 0,
+                start_time: Instant::now(),
+                timings,
+            });
+        }
+
+        RUNNING.store(true, Ordering::SeqCst);
+
+        let thread = thread::spawn(|| {
+            message_loop();
+        });
+
+        self.thread = Some(thread);
+    }
+
+    /// Updates the detection timings (e.g. after a config reload).
+    fn set_timings(&mut self, timings: HookTimings) {
+        if let Some(ref mut s) = *HOOK_STATE.lock() {
+            s.timings = timings;
+        }
+    }
+
+    /// Switches the foreground window's layout and reports the new layout
+    /// right away instead of waiting for the next check; a later check
+    /// corrects it if the window didn't switch.
+    fn switch_layout(&mut self, target: &LayoutSwitch) -> bool {
+        let layouts = installed_layouts();
+        let index = match target {
+            LayoutSwitch::Next => {
+                let current = get_current_hkl();
+                let index = layouts.iter().position(|&hkl| hkl == current);
+                index.map_or(0, |i| (i + 1) % layouts.len())
+            }
+            LayoutSwitch::To(code) => {
+                let codes: Vec<Vec<String>> =
+                    layouts.iter().map(|&hkl| layout_codes(hkl)).collect();
+                match layout::find_layout(code, &codes) {
+                    Some(index) => index,
+                    None => {
+                        log::warn!("No installed keyboard layout matches {:?}", code);
+                        return false;
+                    }
+                }
+            }
+        };
+
+        let Some(&hkl) = layouts.get(index) else {
+            return false;
+        };
+        if !request_layout(hkl) {
+            return false;
+        }
+        report_layout(layout_info(hkl), Instant::now());
+
+        // The window may refuse the request (console host, elevated windows);
+        // once it had time to switch, report the layout it actually has
+        let delay = current_timings().update_delay;
+        thread::spawn(move || {
+            thread::sleep(delay);
+            request_layout_check(Instant::now());
+        });
+        true
+    }
+
+    /// Stops the hook.
+    fn stop(&mut self) {
+        if !RUNNING.load(Ordering::SeqCst) {
+            return;
+        }
+
+        RUNNING.store(false, Ordering::SeqCst);
+
+        // Wake the debounce worker (under the lock, so the wakeup can't be lost)
+        {
+            let _pending = PENDING_CHECK.lock();
+            PENDING_CHECK_CV.notify_all();
+        }
+
+        // Post WM_QUIT to exit the message loop
+        let thread_id = HOOK_THREAD_ID.load(Ordering::SeqCst);
+        if thread_id != 0 {
+            unsafe {
+                let _ = PostThreadMessageW(thread_id, WM_QUIT, WPARAM(0), LPARAM(0));
+            }
+        }
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+
+        // Clear state
+        let mut state = HOOK_STATE.lock();
+        *state = None;
+    }
+}
+
+impl Drop for KeyboardLayoutHook {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Message loop for the hook thread.
+fn message_loop() {
+    unsafe {
+        let thread_id = GetCurrentThreadId();
+        HOOK_THREAD_ID.store(thread_id, Ordering::SeqCst);
+
+        // Update state with thread ID
+        {
+            let mut state = HOOK_STATE.lock();
+            if let Some(ref mut s) = *state {
+                s.thread_id = thread_id;
+            }
+        }
+
+        // Set up WinEvent hook for foreground window changes only
+        // (removed EVENT_OBJECT_FOCUS - too noisy)
+        let flags = WINEVENT_OUTOFCONTEXT | WINEVENT_SKIPOWNPROCESS;
+
+        let hook_foreground = SetWinEventHook(
+            EVENT_SYSTEM_FOREGROUND,
+            EVENT_SYSTEM_FOREGROUND,
+            None,
+            Some(win_event_proc),
+            0,
+            0,
+            flags,
+        );
+
+        // Set up low-level keyboard hook
+        let keyboard_hook = SetWindowsHookExW(
+            WH_KEYBOARD_LL,
+            Some(keyboard_hook_proc),
+            HINSTANCE::default(),
+            0,
+        );
+
+        if let Ok(hook) = keyboard_hook {
+            let mut state = HOOK_STATE.lock();
+            if let Some(ref mut s) = *state {
+                s.keyboard_hook = Some(HhookWrapper::new(hook));
+            }
+        }
+
+        // Check initial layout (no debounce for initial)
+        check_layout_change(Instant::now());
+
+        // Spawn single debounce worker thread (replaces per-event thread::spawn)
+        let debounce_thread = thread::spawn(debounce_worker);
+
+        // Message loop
+        let mut msg = MSG::default();
+        while RUNNING.load(Ordering::SeqCst) {
+            let result = GetMessageW(&mut msg, HWND::default(), 0, 0);
+            if result.0 == 0 || result.0 == -1 {
+                break;
+            }
+            let _ = TranslateMessage(&msg);
+            DispatchMessageW(&msg);
+        }
+
+        // Wait for debounce worker to finish
+        let _ = debounce_thread.join();
+
+        // Cleanup hooks
+        {
+            let mut state = HOOK_STATE.lock();
+            if let Some(ref mut s) = *state {
+                if let Some(hook) = s.keyboard_hook.take() {
+                    let _ = UnhookWindowsHookEx(hook.as_hhook());
+                }
+            }
+        }
+
+        if !hook_foreground.is_invalid() {
+            let _ = UnhookWinEvent(hook_foreground);
+        }
+
+        // Clear state inside thread to avoid lock contention with stop()
+        {
+            let mut state = HOOK_STATE.lock();
+            *state = None;
+        }
+    }
+}
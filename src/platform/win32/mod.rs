@@ -0,0 +1,119 @@
+//! Win32 platform module.
+//!
+//! Implements the platform traits on top of the Windows API.
+
+mod autostart;
+mod balloon;
+mod hotkeys;
+mod indicator;
+mod keyboard_hook;
+mod monitors;
+mod shell;
+mod single_instance;
+mod sound;
+mod tray;
+
+use super::{Backends, InstanceLock, Overlay, OverlayBackend, PlatformEvent};
+use crate::config::AppConfig;
+use crate::indicator::Position;
+use crate::monitors::MonitorInfo;
+use autostart::RegistryAutostart;
+use hotkeys::HotkeyManager;
+use indicator::{IndicatorWindow, DISPLAY_CHANGED};
+use keyboard_hook::KeyboardLayoutHook;
+use monitors::Win32Monitors;
+use shell::ShellOpener;
+use single_instance::MutexInstanceLock;
+use sound::BeepSound;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use tray::TrayIconManager;
+use windows::Win32::{
+    Foundation::HWND,
+    System::Console::{AttachConsole, ATTACH_PARENT_PROCESS},
+    System::SystemInformation::GetLocalTime,
+    UI::WindowsAndMessaging::{
+        DispatchMessageW, PeekMessageW, TranslateMessage, MSG, PM_REMOVE, WM_QUIT,
+    },
+};
+
+/// Overlay backend creating layered Win32 windows.
+pub struct Win32Overlays;
+
+impl OverlayBackend for Win32Overlays {
+    fn create_overlay(
+        &mut self,
+        position: Position,
+        config: &AppConfig,
+        monitor: &MonitorInfo,
+    ) -> Option<Box<dyn Overlay>> {
+        IndicatorWindow::new(position, config, monitor.clone())
+            .map(|window| Box::new(window) as Box<dyn Overlay>)
+    }
+
+    fn pump_events(&mut self) -> Vec<PlatformEvent> {
+        let mut events = Vec::new();
+
+        // Process Windows messages
+        let mut msg = MSG::default();
+        unsafe {
+            if PeekMessageW(&mut msg, HWND::default(), 0, 0, PM_REMOVE).as_bool() {
+                if msg.message == WM_QUIT {
+                    events.push(PlatformEvent::Quit);
+                } else {
+                    let _ = TranslateMessage(&msg);
+                    DispatchMessageW(&msg);
+                }
+            } else {
+                std::thread::sleep(Duration::from_millis(16));
+            }
+        }
+
+        // Check if monitors changed (WM_DISPLAYCHANGE received)
+        if DISPLAY_CHANGED.swap(false, Ordering::SeqCst) {
+            events.push(PlatformEvent::DisplayChanged);
+        }
+
+        events
+    }
+}
+
+/// Returns the Win32 backends.
+pub fn backends(config: &AppConfig) -> Backends {
+    let autostart = Arc::new(RegistryAutostart);
+    Backends {
+        overlays: Box::new(Win32Overlays),
+        monitors: Box::new(Win32Monitors),
+        layout: Box::new(KeyboardLayoutHook::new()),
+        hotkeys: Box::new(HotkeyManager::new()),
+        tray: Box::new(TrayIconManager::new(autostart.clone())),
+        autostart,
+        instance: instance_lock(config),
+        sound: Box::new(BeepSound),
+        opener: Box::new(ShellOpener),
+    }
+}
+
+/// Returns the Win32 instance lock.
+pub fn instance_lock(config: &AppConfig) -> Box<dyn InstanceLock> {
+    Box::new(MutexInstanceLock::new(config.instance_scope))
+}
+
+/// Returns the local calendar day, as days since 1970-01-01.
+pub fn local_day() -> i64 {
+    let time = unsafe { GetLocalTime() };
+    crate::status::days_from_civil(
+        i64::from(time.wYear),
+        u32::from(time.wMonth),
+        u32::from(time.wDay),
+    )
+}
+
+/// Attaches to the console of the parent process, so console commands print
+/// to the terminal they were started from.
+pub fn attach_console() {
+    unsafe {
+        let _ = AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
@@ -0,0 +1,75 @@
+//! Monitor information module.
+//!
+//! Provides functions to get information about connected monitors
+//! using Windows API (EnumDisplayMonitors).
+
+use crate::monitors::MonitorInfo;
+use crate::platform::MonitorProvider;
+use std::mem;
+use windows::Win32::{
+    Foundation::{BOOL, LPARAM, RECT},
+    Graphics::Gdi::{EnumDisplayMonitors, GetMonitorInfoW, HDC, HMONITOR, MONITORINFO},
+};
+
+/// MONITORINFOF_PRIMARY constant (equals 1).
+const MONITORINFOF_PRIMARY: u32 = 1;
+
+/// Monitor enumeration through EnumDisplayMonitors.
+pub struct Win32Monitors;
+
+impl MonitorProvider for Win32Monitors {
+    fn monitors(&self) -> Vec<MonitorInfo> {
+        get_monitors()
+    }
+}
+
+/// Gets a list of all connected monitors.
+fn get_monitors() -> Vec<MonitorInfo> {
+    let mut monitors: Vec<MonitorInfo> = Vec::new();
+
+    unsafe extern "system" fn callback(
+        h_monitor: HMONITOR,
+        _hdc: HDC,
+        _rect: *mut RECT,
+        lparam: LPARAM,
+    ) -> BOOL {
+        let monitors = &mut *(lparam.0 as *mut Vec<MonitorInfo>);
+
+        let mut mi = MONITORINFO {
+            cbSize: mem::size_of::<MONITORINFO>() as u32,
+            ..Default::default()
+        };
+
+        if GetMonitorInfoW(h_monitor, &mut mi).as_bool() {
+            let rect = mi.rcMonitor;
+            let work = mi.rcWork;
+            monitors.push(MonitorInfo {
+                x: rect.left,
+                y: rect.top,
+                width: rect.right - rect.left,
+                height: rect.bottom - rect.top,
+                work_x: work.left,
+                work_y: work.top,
+                work_width: work.right - work.left,
+                work_height: work.bottom - work.top,
+                is_primary: (mi.dwFlags & MONITORINFOF_PRIMARY) != 0,
+            });
+        }
+
+        BOOL::from(true)
+    }
+
+    unsafe {
+        let _ = EnumDisplayMonitors(
+            HDC::default(),
+            None,
+            Some(callback),
+            LPARAM(&mut monitors as *mut _ as isize),
+        );
+    }
+
+    // Sort: primary monitor first
+    monitors.sort_by_key(|m| (!m.is_primary, m.x, m.y));
+
+    monitors
+}
@@ -0,0 +1,34 @@
+//! Shell module.
+//!
+//! Opens files in their associated application with ShellExecuteW.
+
+use crate::platform::Opener;
+use std::path::Path;
+use windows::core::{w, PCWSTR};
+use windows::Win32::UI::{Shell::ShellExecuteW, WindowsAndMessaging::SW_SHOWNORMAL};
+
+/// Opener using the shell's "open" verb.
+pub struct ShellOpener;
+
+impl Opener for ShellOpener {
+    fn open(&self, path: &Path) -> bool {
+        let path: Vec<u16> = path
+            .as_os_str()
+            .to_string_lossy()
+            .encode_utf16()
+            .chain(std::iter::once(0))
+            .collect();
+        // Values above 32 mean success
+        let result = unsafe {
+            ShellExecuteW(
+                None,
+                w!("open"),
+                PCWSTR(path.as_ptr()),
+                None,
+                None,
+                SW_SHOWNORMAL,
+            )
+        };
+        result.0 as isize > 32
+    }
+}
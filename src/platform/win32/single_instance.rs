@@ -0,0 +1,314 @@
+//! Single instance module.
+//!
+//! Ensures only one instance of the application is running using Windows Mutex.
+//! The holder reads arguments of later launches from a named pipe.
+
+use crate::cli;
+use crate::config::InstanceScope;
+use crate::platform::{ForwardCallback, InstanceLock};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::os::windows::io::FromRawHandle;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use windows::{
+    core::{PCWSTR, PWSTR},
+    Win32::{
+        Foundation::{
+            CloseHandle, GetLastError, ERROR_ALREADY_EXISTS, ERROR_PIPE_CONNECTED, HANDLE,
+        },
+        Storage::FileSystem::PIPE_ACCESS_INBOUND,
+        System::Pipes::{
+            ConnectNamedPipe, CreateNamedPipeW, PIPE_REJECT_REMOTE_CLIENTS, PIPE_TYPE_BYTE,
+            PIPE_UNLIMITED_INSTANCES, PIPE_WAIT,
+        },
+        System::RemoteDesktop::ProcessIdToSessionId,
+        System::Threading::{CreateMutexW, GetCurrentProcessId},
+        System::WindowsProgramming::GetUserNameW,
+        UI::WindowsAndMessaging::{MessageBoxW, MB_ICONINFORMATION, MB_OK},
+    },
+};
+
+// Note: CreateMutexW requires both Win32_System_Threading and Win32_Security features
+
+/// Unique mutex name for the application (without namespace).
+const MUTEX_NAME: &str = "LayoutIndicatorMutex_UniqueInstance_Rust";
+
+/// Pipe later launches write their arguments to (without scope suffix).
+const PIPE_NAME: &str = r"\\.\pipe\LayoutIndicator_UniqueInstance_Rust";
+
+/// Returns the mutex and pipe names for a lock scope.
+///
+/// `Local\` mutexes are per session, `Global\` ones machine-wide. Pipe names
+/// are always machine-wide, so they carry the session or user themselves.
+fn lock_names(scope: InstanceScope, session_id: u32, user: &str) -> (String, String) {
+    // Backslashes would make the name a path
+    let user: String = user
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' { c } else { '_' })
+        .collect();
+    match scope {
+        InstanceScope::Global => (format!("Global\\{}", MUTEX_NAME), PIPE_NAME.to_string()),
+        InstanceScope::Session => (
+            format!("Local\\{}", MUTEX_NAME),
+            format!("{}_Session{}", PIPE_NAME, session_id),
+        ),
+        InstanceScope::User => (
+            format!("Global\\{}_User_{}", MUTEX_NAME, user),
+            format!("{}_User_{}", PIPE_NAME, user),
+        ),
+    }
+}
+
+/// Returns the Remote Desktop session of this process (0 on failure).
+fn current_session_id() -> u32 {
+    let mut session_id = 0;
+    unsafe {
+        let _ = ProcessIdToSessionId(GetCurrentProcessId(), &mut session_id);
+    }
+    session_id
+}
+
+/// Returns the name of the user running the process.
+fn current_user() -> String {
+    let mut buffer = [0u16; 257];
+    let mut len = buffer.len() as u32;
+    unsafe {
+        if GetUserNameW(PWSTR(buffer.as_mut_ptr()), &mut len).is_err() {
+            return std::env::var("USERNAME").unwrap_or_default();
+        }
+    }
+    // The length includes the terminating NUL
+    String::from_utf16_lossy(&buffer[..len.saturating_sub(1) as usize])
+}
+
+/// Largest forwarded message accepted.
+const MAX_MESSAGE: u64 = 64 * 1024;
+
+/// Thread reading forwarded arguments.
+struct Listener {
+    stopping: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+/// Single instance lock backed by a named Win32 mutex.
+pub struct MutexInstanceLock {
+    mutex_name: String,
+    pipe_name: String,
+    /// Handle to the mutex (0 if not held).
+    handle: isize,
+    listener: Option<Listener>,
+}
+
+impl MutexInstanceLock {
+    /// Creates an unlocked instance lock for `scope`.
+    pub fn new(scope: InstanceScope) -> Self {
+        let (mutex_name, pipe_name) = lock_names(scope, current_session_id(), &current_user());
+        log::debug!("Instance lock {} ({:?})", mutex_name, scope);
+        Self {
+            mutex_name,
+            pipe_name,
+            handle: 0,
+            listener: None,
+        }
+    }
+}
+
+impl InstanceLock for MutexInstanceLock {
+    /// Creates a named mutex. If the mutex already exists (created by another
+    /// instance), returns false.
+    fn acquire(&mut self) -> bool {
+        !self.is_already_running()
+    }
+
+    /// Starts reading forwarded arguments from the pipe.
+    fn listen(&mut self, on_forward: ForwardCallback) {
+        if self.handle == 0 || self.listener.is_some() {
+            return;
+        }
+        let stopping = Arc::new(AtomicBool::new(false));
+        let thread_stopping = stopping.clone();
+        let pipe_name = self.pipe_name.clone();
+        let thread =
+            std::thread::spawn(move || pipe_loop(&pipe_name, thread_stopping, on_forward));
+        self.listener = Some(Listener { stopping, thread });
+    }
+
+    /// Writes the arguments to the pipe of the running instance.
+    fn forward(&self, args: &[String]) -> bool {
+        let result = OpenOptions::new()
+            .write(true)
+            .open(&self.pipe_name)
+            .and_then(|mut pipe| pipe.write_all(&cli::encode_args(args)));
+        if let Err(e) = &result {
+            log::warn!("Cannot reach the running instance: {}", e);
+        }
+        result.is_ok()
+    }
+
+    /// Stops the pipe and releases the mutex when the application exits.
+    fn release(&mut self) {
+        if let Some(listener) = self.listener.take() {
+            listener.stopping.store(true, Ordering::SeqCst);
+            // Wake the pipe thread so it sees the flag
+            let _ = OpenOptions::new().write(true).open(&self.pipe_name);
+            let _ = listener.thread.join();
+        }
+
+        let handle_value = std::mem::take(&mut self.handle);
+        if handle_value != 0 {
+            unsafe {
+                let handle = HANDLE(handle_value as *mut std::ffi::c_void);
+                let _ = CloseHandle(handle);
+            }
+        }
+    }
+
+    /// Shows a message box informing the user that the application is already running.
+    fn notify_already_running(&self) {
+        show_already_running_message();
+    }
+}
+
+impl MutexInstanceLock {
+    /// Checks if another instance is already running, taking the mutex if not.
+    fn is_already_running(&mut self) -> bool {
+        let mutex_name: Vec<u16> = self
+            .mutex_name
+            .encode_utf16()
+            .chain(std::iter::once(0))
+            .collect();
+
+        unsafe {
+            let handle = CreateMutexW(None, true, PCWSTR(mutex_name.as_ptr()));
+
+            match handle {
+                Ok(h) => {
+                    if GetLastError() == ERROR_ALREADY_EXISTS {
+                        // Mutex already exists - another instance is running
+                        let _ = CloseHandle(h);
+                        true
+                    } else {
+                        // Mutex created successfully - this is the first instance
+                        self.handle = h.0 as isize;
+                        false
+                    }
+                }
+                Err(_) => {
+                    // Failed to create mutex - assume another instance is running
+                    true
+                }
+            }
+        }
+    }
+}
+
+/// Serves one pipe instance per launch until stopped, passing forwarded
+/// arguments on.
+fn pipe_loop(pipe_name: &str, stopping: Arc<AtomicBool>, on_forward: ForwardCallback) {
+    let pipe_name: Vec<u16> = pipe_name.encode_utf16().chain(std::iter::once(0)).collect();
+
+    loop {
+        let pipe = unsafe {
+            CreateNamedPipeW(
+                PCWSTR(pipe_name.as_ptr()),
+                PIPE_ACCESS_INBOUND,
+                PIPE_TYPE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+                PIPE_UNLIMITED_INSTANCES,
+                0,
+                4096,
+                0,
+                None,
+            )
+        };
+        if pipe.is_invalid() {
+            log::warn!("Cannot listen for other launches: {:?}", unsafe {
+                GetLastError()
+            });
+            return;
+        }
+
+        // A client may connect between creation and the wait
+        let connected = unsafe { ConnectNamedPipe(pipe, None) }.is_ok()
+            || unsafe { GetLastError() } == ERROR_PIPE_CONNECTED;
+        // The file owns the pipe instance and closes it when dropped
+        let pipe = unsafe { File::from_raw_handle(pipe.0) };
+        if stopping.load(Ordering::SeqCst) {
+            break;
+        }
+        if !connected {
+            continue;
+        }
+
+        let mut bytes = Vec::new();
+        if let Err(e) = pipe.take(MAX_MESSAGE).read_to_end(&mut bytes) {
+            log::warn!("Failed to read forwarded arguments: {}", e);
+            continue;
+        }
+        match cli::decode_args(&bytes) {
+            Some(args) => on_forward(args),
+            None => log::warn!("Malformed forwarded arguments"),
+        }
+    }
+}
+
+/// Shows a message box informing the user that the application is already running.
+fn show_already_running_message() {
+    let title: Vec<u16> = "Layout Indicator"
+        .encode_utf16()
+        .chain(std::iter::once(0))
+        .collect();
+    let message: Vec<u16> =
+        "Layout Indicator is already running.\n\nLook for the icon in the system tray."
+            .encode_utf16()
+            .chain(std::iter::once(0))
+            .collect();
+
+    unsafe {
+        let _ = MessageBoxW(
+            None,
+            PCWSTR(message.as_ptr()),
+            PCWSTR(title.as_ptr()),
+            MB_OK | MB_ICONINFORMATION,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn global_scope_uses_global_names() {
+        let (mutex, pipe) = lock_names(InstanceScope::Global, 3, "alice");
+        assert_eq!(mutex, r"Global\LayoutIndicatorMutex_UniqueInstance_Rust");
+        assert_eq!(pipe, r"\\.\pipe\LayoutIndicator_UniqueInstance_Rust");
+    }
+
+    #[test]
+    fn session_scope_uses_local_namespace_and_session_pipe() {
+        let (mutex, pipe) = lock_names(InstanceScope::Session, 3, "alice");
+        assert_eq!(mutex, r"Local\LayoutIndicatorMutex_UniqueInstance_Rust");
+        assert_eq!(
+            pipe,
+            r"\\.\pipe\LayoutIndicator_UniqueInstance_Rust_Session3"
+        );
+        assert_ne!(lock_names(InstanceScope::Session, 4, "alice").1, pipe);
+    }
+
+    #[test]
+    fn user_scope_uses_sanitized_user_name() {
+        let (mutex, pipe) = lock_names(InstanceScope::User, 3, r"CORP\Bob Smith");
+        assert_eq!(
+            mutex,
+            r"Global\LayoutIndicatorMutex_UniqueInstance_Rust_User_CORP_Bob_Smith"
+        );
+        assert_eq!(
+            pipe,
+            r"\\.\pipe\LayoutIndicator_UniqueInstance_Rust_User_CORP_Bob_Smith"
+        );
+        // The session doesn't matter
+        assert_eq!(lock_names(InstanceScope::User, 7, r"CORP\Bob Smith").0, mutex);
+    }
+}
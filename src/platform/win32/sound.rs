@@ -0,0 +1,18 @@
+//! Sound module for layout change notifications.
+//!
+//! Uses Windows Beep API to play short sounds.
+
+use crate::platform::SoundOutput;
+use windows::Win32::System::Diagnostics::Debug::Beep;
+
+/// Sound output through the Windows Beep API.
+pub struct BeepSound;
+
+impl SoundOutput for BeepSound {
+    fn beep(&self, frequency: u32, duration_ms: u32) {
+        // Windows Beep: frequency 37-32767 Hz
+        unsafe {
+            let _ = Beep(frequency, duration_ms);
+        }
+    }
+}
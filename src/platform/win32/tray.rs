@@ -0,0 +1,405 @@
+//! System tray module.
+//!
+//! Provides system tray icon with context menu for the application. The icon
+//! shows the current layout and is redrawn for the taskbar theme; the menu is
+//! rebuilt when its hotkey conflicts, settings or the indicators state change;
+//! a layout switch only moves the checkmark of the Layout submenu.
+
+use super::balloon::Balloon;
+use crate::action::Action;
+use crate::hotkey::BindingStatus;
+use crate::layout::LayoutInfo;
+use crate::menu::{self, MenuEntry};
+use crate::platform::{ActionCallback, Autostart, TrayBackend};
+use crate::render::{layout_icon, IconTheme};
+use crate::status::Status;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tray_icon::{
+    menu::{
+        CheckMenuItem, IsMenuItem, Menu, MenuEvent, MenuId, MenuItem, PredefinedMenuItem, Submenu,
+    },
+    Icon, TrayIcon, TrayIconBuilder,
+};
+use windows::core::w;
+use windows::Win32::System::Registry::{RegGetValueW, HKEY_CURRENT_USER, RRF_RT_REG_DWORD};
+use windows::Win32::UI::WindowsAndMessaging::{
+    GetSystemMetrics, MessageBoxW, MB_ICONINFORMATION, MB_OK, SM_CXSMICON,
+};
+
+/// Longest tooltip the notification area shows, in UTF-16 units.
+const MAX_TOOLTIP_LEN: usize = 127;
+
+/// Colour of the icon before the first layout is known.
+const NO_LAYOUT_COLOR: (u8, u8, u8) = (0x80, 0x80, 0x80);
+
+/// What the menu shows besides its fixed entries.
+#[derive(Default)]
+struct MenuContent {
+    /// Whether indicators are enabled, as last reported by the application.
+    indicators: bool,
+    /// Hotkeys that couldn't be registered, listed at the top.
+    conflicts: Vec<String>,
+    /// Settings entries, listed below the visibility entries.
+    settings: Vec<MenuEntry>,
+    /// A click flipped a checkmark; rebuild on the next update even if the
+    /// content is the same.
+    stale: bool,
+}
+
+/// IDs of the entries of the menu last built.
+#[derive(Default)]
+struct MenuIds {
+    indicators: Option<MenuId>,
+    autostart: Option<MenuId>,
+    about: Option<MenuId>,
+    exit: Option<MenuId>,
+    /// Settings entries and the actions they run.
+    settings: HashMap<MenuId, Action>,
+    /// Entries of the Layout submenu and their layout codes.
+    layouts: Vec<(String, CheckMenuItem)>,
+}
+
+/// Tray icon manager.
+pub struct TrayIconManager {
+    tray_icon: Option<TrayIcon>,
+    /// Owner of notification balloons, created with the icon.
+    balloon: Option<Balloon>,
+    autostart: Arc<dyn Autostart>,
+    on_action: Option<ActionCallback>,
+    content: Mutex<MenuContent>,
+    ids: Mutex<MenuIds>,
+    /// Tooltip text last shown.
+    tooltip: Mutex<String>,
+}
+
+impl TrayIconManager {
+    /// Creates a new tray icon manager.
+    pub fn new(autostart: Arc<dyn Autostart>) -> Self {
+        Self {
+            tray_icon: None,
+            balloon: None,
+            autostart,
+            on_action: None,
+            content: Mutex::new(MenuContent {
+                indicators: true,
+                ..MenuContent::default()
+            }),
+            ids: Mutex::new(MenuIds::default()),
+            tooltip: Mutex::new(String::new()),
+        }
+    }
+
+    /// Renders the tray icon for a layout at the system small-icon size.
+    fn create_icon(label: &str, color: (u8, u8, u8)) -> Result<Icon, tray_icon::BadIcon> {
+        let size = unsafe { GetSystemMetrics(SM_CXSMICON) }.max(16) as u32;
+        let canvas = layout_icon(label, color, size, taskbar_theme());
+        Icon::from_rgba(canvas.to_rgba_bytes(), size, size)
+    }
+
+    /// Builds the context menu: hotkey conflicts, visibility entries,
+    /// settings, autostart, About and Exit.
+    fn build_menu(&self) -> Result<Menu, tray_icon::menu::Error> {
+        let mut content = self.content.lock();
+        content.stale = false;
+        let mut ids = MenuIds::default();
+        let menu = Menu::new();
+
+        for conflict in &content.conflicts {
+            menu.append(&MenuItem::new(conflict, false, None))?;
+        }
+        if !content.conflicts.is_empty() {
+            menu.append(&PredefinedMenuItem::separator())?;
+        }
+
+        let indicators = CheckMenuItem::new("Indicators enabled", true, content.indicators, None);
+        ids.indicators = Some(indicators.id().clone());
+        menu.append(&indicators)?;
+        menu.append(&PredefinedMenuItem::separator())?;
+
+        if !content.settings.is_empty() {
+            for entry in &content.settings {
+                menu.append(settings_item(entry, &mut ids)?.as_ref())?;
+            }
+            menu.append(&PredefinedMenuItem::separator())?;
+        }
+
+        let autostart = CheckMenuItem::new("Autostart", true, self.autostart.is_enabled(), None);
+        let about = MenuItem::new("About", true, None);
+        let exit = MenuItem::new("Exit", true, None);
+        ids.autostart = Some(autostart.id().clone());
+        ids.about = Some(about.id().clone());
+        ids.exit = Some(exit.id().clone());
+        menu.append(&autostart)?;
+        menu.append(&PredefinedMenuItem::separator())?;
+        menu.append(&about)?;
+        menu.append(&exit)?;
+
+        *self.ids.lock() = ids;
+        Ok(menu)
+    }
+
+    /// Replaces the context menu of a started tray icon.
+    fn rebuild_menu(&self) {
+        let Some(ref tray) = self.tray_icon else {
+            return;
+        };
+        match self.build_menu() {
+            Ok(menu) => tray.set_menu(Some(Box::new(menu))),
+            Err(e) => log::warn!("Failed to update tray menu: {}", e),
+        }
+    }
+
+    /// Changes the menu content with `change` and rebuilds the menu if it
+    /// returns true or a click left the menu stale.
+    fn update_menu(&self, change: impl FnOnce(&mut MenuContent) -> bool) {
+        {
+            let mut content = self.content.lock();
+            if !change(&mut content) && !content.stale {
+                return;
+            }
+        }
+        self.rebuild_menu();
+    }
+
+    /// Toggles autostart; the rebuilt menu shows the new state.
+    fn toggle_autostart(&self) {
+        if self.autostart.is_enabled() {
+            self.autostart.disable();
+        } else {
+            self.autostart.enable();
+        }
+        self.rebuild_menu();
+    }
+
+    /// Passes a menu action to the application.
+    fn dispatch(&self, action: Action) {
+        if let Some(ref cb) = self.on_action {
+            cb(action);
+        }
+    }
+}
+
+impl TrayBackend for TrayIconManager {
+    /// Sets the callback for tray actions.
+    fn set_callback(&mut self, on_action: ActionCallback) {
+        self.on_action = Some(on_action);
+    }
+
+    /// Starts the tray icon.
+    fn start(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let menu = self.build_menu()?;
+        let icon = Self::create_icon("", NO_LAYOUT_COLOR)?;
+        let tray = TrayIconBuilder::new()
+            .with_icon(icon)
+            .with_tooltip("LangTip")
+            .with_menu(Box::new(menu))
+            .build()?;
+
+        self.tray_icon = Some(tray);
+        self.balloon = Balloon::new();
+        Ok(())
+    }
+
+    /// Redraws the icon with the layout name over its colour.
+    fn set_layout(&self, layout: &LayoutInfo, color: (u8, u8, u8)) {
+        let Some(ref tray) = self.tray_icon else {
+            return;
+        };
+        let result = Self::create_icon(&layout.name, color)
+            .map_err(|e| e.to_string())
+            .and_then(|icon| tray.set_icon(Some(icon)).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            log::warn!("Failed to update tray icon: {}", e);
+        }
+    }
+
+    /// Lists the hotkey conflicts as disabled entries at the top of the menu.
+    fn set_hotkey_conflicts(&self, conflicts: &[BindingStatus]) {
+        let conflicts: Vec<String> = conflicts.iter().map(ToString::to_string).collect();
+        self.update_menu(|content| {
+            if content.conflicts == conflicts {
+                return false;
+            }
+            content.conflicts = conflicts;
+            true
+        });
+    }
+
+    /// Shows the settings entries as submenus and checkable items.
+    fn set_menu(&self, entries: &[MenuEntry]) {
+        self.update_menu(|content| {
+            if content.settings == entries {
+                return false;
+            }
+            content.settings = entries.to_vec();
+            true
+        });
+    }
+
+    /// Moves the checkmark of the Layout submenu without rebuilding the menu.
+    /// All layout entries are set, a click may have flipped any of them.
+    fn set_active_layout(&self, code: &str) {
+        menu::check_layout(&mut self.content.lock().settings, code);
+        for (layout, item) in &self.ids.lock().layouts {
+            item.set_checked(layout == code);
+        }
+    }
+
+    /// Updates the indicators checkmark and, when the status text changes,
+    /// the tooltip.
+    fn set_status(&self, status: &Status) {
+        let enabled = status.indicators_enabled();
+        self.update_menu(|content| {
+            let changed = content.indicators != enabled;
+            content.indicators = enabled;
+            changed
+        });
+        let Some(ref tray) = self.tray_icon else {
+            return;
+        };
+        let text = status.tooltip(MAX_TOOLTIP_LEN);
+        let mut tooltip = self.tooltip.lock();
+        if *tooltip == text {
+            return;
+        }
+        // Not retried until the status changes again
+        if let Err(e) = tray.set_tooltip(Some(&text)) {
+            log::warn!("Failed to update tray tooltip: {}", e);
+        }
+        *tooltip = text;
+    }
+
+    /// Shows a balloon in the notification area.
+    fn notify(&self, title: &str, text: &str) {
+        match self.balloon {
+            Some(ref balloon) => balloon.show(title, text),
+            None => log::warn!("{}: {}", title, text),
+        }
+    }
+
+    /// Processes menu events. Should be called from the main event loop.
+    fn process_menu_events(&self) {
+        let Ok(event) = MenuEvent::receiver().try_recv() else {
+            return;
+        };
+        let id = Some(&event.id);
+        let action = {
+            let ids = self.ids.lock();
+            if id == ids.indicators.as_ref() {
+                // The click flipped the checkmark; the status brings it back
+                // in line if the application doesn't follow
+                self.content.lock().stale = true;
+                Some(Action::Toggle)
+            } else if id == ids.exit.as_ref() {
+                Some(Action::Exit)
+            } else if id == ids.autostart.as_ref() || id == ids.about.as_ref() {
+                None
+            } else {
+                let action = ids.settings.get(&event.id).cloned();
+                if action.is_some() {
+                    // The click flipped the checkmark; the application
+                    // sends the state it ends up in
+                    self.content.lock().stale = true;
+                }
+                action
+            }
+        };
+
+        if let Some(action) = action {
+            self.dispatch(action);
+        } else if id == self.ids.lock().autostart.as_ref() {
+            self.toggle_autostart();
+        } else if id == self.ids.lock().about.as_ref() {
+            show_about_dialog();
+        }
+    }
+
+    /// Stops the tray icon.
+    fn stop(&mut self) {
+        self.tray_icon = None;
+        self.balloon = None;
+    }
+}
+
+/// Creates the menu item of a settings entry and records the actions of
+/// the clickable ones and the layout entries in `ids`. Radio choices are
+/// drawn as checkmarks.
+fn settings_item(
+    entry: &MenuEntry,
+    ids: &mut MenuIds,
+) -> Result<Box<dyn IsMenuItem>, tray_icon::menu::Error> {
+    Ok(match entry {
+        MenuEntry::Item {
+            label,
+            action,
+            toggle: Some(toggle),
+        } => {
+            let item = CheckMenuItem::new(label, true, toggle.is_checked(), None);
+            ids.settings.insert(item.id().clone(), action.clone());
+            if let Action::SetLayout(code) = action {
+                ids.layouts.push((code.clone(), item.clone()));
+            }
+            Box::new(item)
+        }
+        MenuEntry::Item {
+            label,
+            action,
+            toggle: None,
+        } => {
+            let item = MenuItem::new(label, true, None);
+            ids.settings.insert(item.id().clone(), action.clone());
+            Box::new(item)
+        }
+        MenuEntry::Submenu { label, entries } => {
+            let submenu = Submenu::new(label, true);
+            for entry in entries {
+                submenu.append(settings_item(entry, ids)?.as_ref())?;
+            }
+            Box::new(submenu)
+        }
+        MenuEntry::Separator => Box::new(PredefinedMenuItem::separator()),
+    })
+}
+
+/// Returns the taskbar theme, dark unless Windows reports a light one.
+fn taskbar_theme() -> IconTheme {
+    let mut light: u32 = 0;
+    let mut size = std::mem::size_of::<u32>() as u32;
+    let result = unsafe {
+        RegGetValueW(
+            HKEY_CURRENT_USER,
+            w!(r"Software\Microsoft\Windows\CurrentVersion\Themes\Personalize"),
+            w!("SystemUsesLightTheme"),
+            RRF_RT_REG_DWORD,
+            None,
+            Some(&mut light as *mut u32 as *mut std::ffi::c_void),
+            Some(&mut size),
+        )
+    };
+    if result.is_ok() && light != 0 {
+        IconTheme::Light
+    } else {
+        IconTheme::Dark
+    }
+}
+
+/// Converts a string to a null-terminated UTF-16 buffer.
+fn to_wide(text: &str) -> Vec<u16> {
+    text.encode_utf16().chain(std::iter::once(0)).collect()
+}
+
+/// Shows the About dialog with application information.
+fn show_about_dialog() {
+    let version = env!("CARGO_PKG_VERSION");
+    let text = format!(
+        "LangTip v{version}\n\nKeyboard layout indicator for Windows\n\nhttps://github.com/alexhmt/LangTip"
+    );
+    let text_wide = to_wide(&text);
+    let text_pcwstr = windows::core::PCWSTR(text_wide.as_ptr());
+
+    unsafe {
+        let _ = MessageBoxW(None, text_pcwstr, w!("About LangTip"), MB_OK | MB_ICONINFORMATION);
+    }
+}
@@ -0,0 +1,843 @@
+//! X11 global hotkeys module.
+//!
+//! Grabs the configured key combinations on the root window with `XGrabKey`.
+//! X11 matches grabs against the exact modifier state, so every combination is
+//! also grabbed with Caps Lock and Num Lock on. Modifier masks don't tell left
+//! and right keys apart; bindings like `rctrl+f1` check the keyboard state
+//! when they fire. A `show-while-held` hotkey ends with the first key release
+//! during its grab; detectable auto-repeat keeps repeats from faking one.
+
+use super::X11Connection;
+use crate::action::Action;
+use crate::config::{HotkeyBinding, HotkeyConfig};
+use crate::hotkey::{
+    self, BindingStatus, Hotkey, HotkeyRegistry, Key, Modifier, Registration, Side,
+};
+use crate::platform::{ActionCallback, HotkeyBackend};
+use parking_lot::Mutex;
+use std::cell::OnceCell;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread::JoinHandle;
+use x11rb::connection::Connection;
+use x11rb::protocol::xkb::{self, ConnectionExt as _};
+use x11rb::protocol::xproto::{
+    AtomEnum, ClientMessageEvent, ConnectionExt as _, CreateWindowAux, EventMask, GrabMode,
+    Mapping, ModMask, WindowClass,
+};
+use x11rb::protocol::Event;
+use x11rb::COPY_DEPTH_FROM_PARENT;
+
+/// First data word of the client message asking the event thread to reload
+/// its bindings; any other message stops it.
+const RELOAD_MESSAGE: u32 = 1;
+
+/// `XK_Num_Lock` keysym.
+const NUM_LOCK_KEYSYM: u32 = 0xFF7F;
+
+/// Maps a key to its X keysym.
+fn keysym(key: Key) -> u32 {
+    match key {
+        // Latin-1 keysyms equal the lower-case ASCII codes
+        Key::Char(c) => c as u32,
+        Key::F(n) => 0xFFBE + (n as u32 - 1),
+        Key::Space => 0x0020,
+        Key::Enter => 0xFF0D,
+        Key::Escape => 0xFF1B,
+        Key::Tab => 0xFF09,
+        Key::Backspace => 0xFF08,
+        Key::Up => 0xFF52,
+        Key::Down => 0xFF54,
+        Key::Left => 0xFF51,
+        Key::Right => 0xFF53,
+        Key::Insert => 0xFF63,
+        Key::Delete => 0xFFFF,
+        Key::Home => 0xFF50,
+        Key::End => 0xFF57,
+        Key::PageUp => 0xFF55,
+        Key::PageDown => 0xFF56,
+        Key::PrintScreen => 0xFF61,
+        Key::CapsLock => 0xFFE5,
+        Key::ScrollLock => 0xFF14,
+        Key::Pause => 0xFF13,
+        Key::Numpad(n) => 0xFFB0 + n as u32,
+        Key::NumpadMultiply => 0xFFAA,
+        Key::NumpadAdd => 0xFFAB,
+        Key::NumpadSubtract => 0xFFAD,
+        Key::NumpadDecimal => 0xFFAE,
+        Key::NumpadDivide => 0xFFAF,
+        // XF86 media keysyms
+        Key::VolumeDown => 0x1008FF11,
+        Key::VolumeMute => 0x1008FF12,
+        Key::VolumeUp => 0x1008FF13,
+        Key::MediaPlayPause => 0x1008FF14,
+        Key::MediaStop => 0x1008FF15,
+        Key::MediaPrev => 0x1008FF16,
+        Key::MediaNext => 0x1008FF17,
+    }
+}
+
+/// Returns the keysyms of the left and right key of a modifier.
+///
+/// The right Alt of many layouts is AltGr (`ISO_Level3_Shift`) rather than
+/// `Alt_R`; its keycode is used when there is no `Alt_R`.
+fn side_keysyms(modifier: Modifier) -> (u32, &'static [u32]) {
+    match modifier {
+        Modifier::Ctrl => (0xFFE3, &[0xFFE4]),
+        Modifier::Shift => (0xFFE1, &[0xFFE2]),
+        Modifier::Alt => (0xFFE9, &[0xFFEA, 0xFE03]),
+        Modifier::Win => (0xFFEB, &[0xFFEC]),
+    }
+}
+
+/// Finds the first keycode producing `keysym` in a `GetKeyboardMapping`
+/// reply starting at `min_keycode`.
+fn keycode_for_keysym(
+    keysyms: &[u32],
+    keysyms_per_keycode: u8,
+    min_keycode: u8,
+    keysym: u32,
+) -> Option<u8> {
+    let per_keycode = keysyms_per_keycode.max(1) as usize;
+    keysyms
+        .chunks(per_keycode)
+        .position(|syms| syms.contains(&keysym))
+        .and_then(|i| u8::try_from(min_keycode as usize + i).ok())
+}
+
+/// Maps hotkey modifiers to an X modifier mask.
+fn modifier_mask(hotkey: &Hotkey) -> u16 {
+    let mut mask = 0;
+    for (modifier, bits) in [
+        (Modifier::Ctrl, ModMask::CONTROL),
+        (Modifier::Alt, ModMask::M1),
+        (Modifier::Shift, ModMask::SHIFT),
+        // Super is Mod4 in practically every keymap
+        (Modifier::Win, ModMask::M4),
+    ] {
+        if hotkey.modifiers.get(modifier).is_some() {
+            mask |= u16::from(bits);
+        }
+    }
+    mask
+}
+
+/// Modifier masks to grab `mask` with, covering every Caps Lock and
+/// Num Lock state.
+fn lock_variants(mask: u16, num_lock: u16) -> Vec<u16> {
+    let caps_lock = u16::from(ModMask::LOCK);
+    let mut variants = vec![mask, mask | caps_lock];
+    if num_lock != 0 {
+        variants.extend([mask | num_lock, mask | caps_lock | num_lock]);
+    }
+    variants
+}
+
+/// Strips lock modifiers, XKB group bits and mouse buttons from an event
+/// state, leaving the modifiers a grab was registered with.
+fn clean_state(state: u16, num_lock: u16) -> u16 {
+    state & 0xFF & !(u16::from(ModMask::LOCK) | num_lock)
+}
+
+/// Left/right keycodes a sided modifier of a grab needs.
+struct SideKeys {
+    side: Side,
+    left: Option<u8>,
+    right: Option<u8>,
+}
+
+/// A hotkey grabbed on the root window.
+struct Grab {
+    keycode: u8,
+    modifiers: u16,
+    sides: Vec<SideKeys>,
+    action: Action,
+}
+
+/// Grabs of the event thread's connection.
+struct Grabs {
+    grabs: Vec<Grab>,
+    num_lock: u16,
+    /// Keyboard mapping the grabs were resolved with.
+    keysyms: Vec<u32>,
+    keysyms_per_keycode: u8,
+    min_keycode: u8,
+    /// Every combination in the config, kept out of the suggestions.
+    configured: Vec<Hotkey>,
+}
+
+impl Grabs {
+    /// Reads the current keymap; nothing is grabbed yet.
+    fn new(x11: &X11Connection, bindings: &[HotkeyBinding]) -> Self {
+        let setup = x11.conn.setup();
+        let (min_keycode, max_keycode) = (setup.min_keycode, setup.max_keycode);
+        let mapping = x11
+            .conn
+            .get_keyboard_mapping(min_keycode, max_keycode - min_keycode + 1)
+            .ok()
+            .and_then(|c| c.reply().ok());
+        let (keysyms, keysyms_per_keycode) = mapping
+            .map(|m| (m.keysyms, m.keysyms_per_keycode))
+            .unwrap_or_default();
+        let mut grabs = Self {
+            grabs: Vec::new(),
+            num_lock: 0,
+            keysyms,
+            keysyms_per_keycode,
+            min_keycode,
+            configured: hotkey::configured_hotkeys(bindings),
+        };
+        grabs.num_lock = read_num_lock_mask(x11, grabs.keycode_for(NUM_LOCK_KEYSYM));
+        grabs
+    }
+
+    /// Resolves the bindings against the current keymap and grabs them.
+    /// Returns the grabs and how each binding went, in binding order.
+    fn grab(x11: &X11Connection, bindings: &[HotkeyBinding]) -> (Self, Vec<BindingStatus>) {
+        let mut grabs = Self::new(x11, bindings);
+        let status = bindings
+            .iter()
+            .map(|binding| BindingStatus {
+                keys: binding.keys.clone(),
+                action: binding.action.clone(),
+                registration: grabs.add(x11, binding),
+            })
+            .collect();
+        (grabs, status)
+    }
+
+    fn keycode_for(&self, keysym: u32) -> Option<u8> {
+        keycode_for_keysym(
+            &self.keysyms,
+            self.keysyms_per_keycode,
+            self.min_keycode,
+            keysym,
+        )
+    }
+
+    /// Grabs one binding.
+    fn add(&mut self, x11: &X11Connection, binding: &HotkeyBinding) -> Registration {
+        let (hotkey, action) = match binding.parse() {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                log::warn!(
+                    "Invalid {} hotkey {:?}: {}",
+                    binding.action,
+                    binding.keys,
+                    e
+                );
+                return Registration::Invalid(e);
+            }
+        };
+        let Some(keycode) = self.keycode_for(keysym(hotkey.key)) else {
+            log::warn!(
+                "No key for {} hotkey {} in the keymap",
+                binding.action,
+                hotkey
+            );
+            return Registration::NoKey;
+        };
+
+        // The same combination may already be grabbed for another side
+        let modifiers = modifier_mask(&hotkey);
+        let grabbed = self
+            .grabs
+            .iter()
+            .any(|g| g.keycode == keycode && g.modifiers == modifiers);
+        if !grabbed && !grab_key(x11, keycode, modifiers, self.num_lock) {
+            // BadAccess: another client holds the combination
+            log::warn!("Failed to register {} hotkey: {}", binding.action, hotkey);
+            return Registration::Taken(hotkey::suggest_alternatives(
+                &hotkey,
+                &self.configured,
+                |candidate| {
+                    let mask = modifier_mask(candidate);
+                    let free = grab_key(x11, keycode, mask, self.num_lock);
+                    if free {
+                        ungrab_key(x11, keycode, mask, self.num_lock);
+                    }
+                    free
+                },
+            ));
+        }
+
+        log::debug!("Registered {} hotkey: {}", binding.action, hotkey);
+        let sides = hotkey
+            .modifiers
+            .sided()
+            .into_iter()
+            .map(|(modifier, side)| {
+                let (left, right) = side_keysyms(modifier);
+                SideKeys {
+                    side,
+                    left: self.keycode_for(left),
+                    right: right.iter().find_map(|&keysym| self.keycode_for(keysym)),
+                }
+            })
+            .collect();
+        self.grabs.push(Grab {
+            keycode,
+            modifiers,
+            sides,
+            action,
+        });
+        // The same combination may be bound per side; specific ones win
+        self.grabs.sort_by_key(|g| std::cmp::Reverse(g.sides.len()));
+        Registration::Registered
+    }
+
+    /// Releases the grab of one binding, keeping the X grab while another
+    /// binding uses the same combination.
+    fn remove(&mut self, x11: &X11Connection, binding: &HotkeyBinding) {
+        let Ok((hotkey, action)) = binding.parse() else {
+            return;
+        };
+        let Some(keycode) = self.keycode_for(keysym(hotkey.key)) else {
+            return;
+        };
+        let modifiers = modifier_mask(&hotkey);
+        let sides: Vec<Side> = hotkey
+            .modifiers
+            .sided()
+            .into_iter()
+            .map(|(_, side)| side)
+            .collect();
+        let Some(index) = self.grabs.iter().position(|g| {
+            g.keycode == keycode
+                && g.modifiers == modifiers
+                && g.action == action
+                && g.sides.iter().map(|s| s.side).eq(sides.iter().copied())
+        }) else {
+            return;
+        };
+        self.grabs.remove(index);
+        log::debug!("Unregistered {} hotkey: {}", binding.action, hotkey);
+        if !self
+            .grabs
+            .iter()
+            .any(|g| g.keycode == keycode && g.modifiers == modifiers)
+        {
+            ungrab_key(x11, keycode, modifiers, self.num_lock);
+        }
+    }
+
+    /// Returns the action of the grab matching a key press. `pressed`
+    /// tells whether a keycode is held down.
+    fn find(&self, keycode: u8, state: u16, pressed: impl Fn(u8) -> bool) -> Option<&Action> {
+        let state = clean_state(state, self.num_lock);
+        let is_down = |keycode: Option<u8>| keycode.is_some_and(&pressed);
+        self.grabs
+            .iter()
+            .filter(|g| g.keycode == keycode && g.modifiers == state)
+            .find(|g| {
+                g.sides
+                    .iter()
+                    .all(|s| s.side.accepts(is_down(s.left), is_down(s.right)))
+            })
+            .map(|g| &g.action)
+    }
+
+    /// Releases all grabs.
+    fn ungrab(&self, x11: &X11Connection) {
+        for grab in &self.grabs {
+            ungrab_key(x11, grab.keycode, grab.modifiers, self.num_lock);
+        }
+        let _ = x11.conn.flush();
+    }
+}
+
+/// Grabs of the event thread, registering reloaded bindings one by one.
+struct GrabRegistry<'a> {
+    x11: &'a X11Connection,
+    grabs: &'a mut Grabs,
+}
+
+impl HotkeyRegistry for GrabRegistry<'_> {
+    fn register(&mut self, binding: &HotkeyBinding) -> Registration {
+        self.grabs.add(self.x11, binding)
+    }
+
+    fn unregister(&mut self, binding: &HotkeyBinding) {
+        self.grabs.remove(self.x11, binding);
+    }
+}
+
+/// Grabs a key combination in every lock state. Returns false, holding
+/// nothing, if another client holds it.
+fn grab_key(x11: &X11Connection, keycode: u8, modifiers: u16, num_lock: u16) -> bool {
+    let grabbed = lock_variants(modifiers, num_lock).into_iter().all(|mask| {
+        x11.conn
+            .grab_key(
+                false,
+                x11.root(),
+                ModMask::from(mask),
+                keycode,
+                GrabMode::ASYNC,
+                GrabMode::ASYNC,
+            )
+            .map(|cookie| cookie.check().is_ok())
+            .unwrap_or(false)
+    });
+    if !grabbed {
+        ungrab_key(x11, keycode, modifiers, num_lock);
+    }
+    grabbed
+}
+
+/// Releases a key combination grabbed with `grab_key`.
+fn ungrab_key(x11: &X11Connection, keycode: u8, modifiers: u16, num_lock: u16) {
+    for mask in lock_variants(modifiers, num_lock) {
+        let _ = x11
+            .conn
+            .ungrab_key(keycode, x11.root(), ModMask::from(mask));
+    }
+}
+
+/// Finds the modifier the Num Lock key is mapped to (usually Mod2), or 0 if
+/// the keymap has no Num Lock.
+fn read_num_lock_mask(x11: &X11Connection, num_lock: Option<u8>) -> u16 {
+    let modifiers = x11
+        .conn
+        .get_modifier_mapping()
+        .ok()
+        .and_then(|c| c.reply().ok());
+
+    match (num_lock, modifiers) {
+        (Some(keycode), Some(modifiers)) => {
+            let per_modifier = modifiers.keycodes_per_modifier().max(1) as usize;
+            modifiers
+                .keycodes
+                .chunks(per_modifier)
+                .position(|keycodes| keycodes.contains(&keycode))
+                .map(|index| 1 << index)
+                .unwrap_or(0)
+        }
+        _ => 0,
+    }
+}
+
+/// Warns about settings only the Windows keyboard hook can apply.
+fn warn_unsupported(config: &HotkeyConfig) {
+    if !config.gestures.is_empty() {
+        log::warn!("Key gestures are not supported on X11, ignoring them");
+    }
+    if !config.remap.is_empty() {
+        log::warn!("Key remapping is not supported on X11, ignoring it");
+    }
+}
+
+/// Global hotkeys grabbed on the X11 root window.
+pub struct X11Hotkeys {
+    x11: Arc<X11Connection>,
+    /// Window on the event thread's connection that receives the stop message.
+    wakeup_window: Arc<AtomicU32>,
+    /// Registration result of each binding, updated by the event thread.
+    status: Arc<Mutex<Vec<BindingStatus>>>,
+    /// Bindings waiting for the event thread to reload, and the channel it
+    /// answers on once they are grabbed.
+    reload: Arc<Mutex<Option<PendingReload>>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+type PendingReload = (Vec<HotkeyBinding>, mpsc::Sender<()>);
+
+impl X11Hotkeys {
+    pub fn new(x11: Arc<X11Connection>) -> Self {
+        Self {
+            x11,
+            wakeup_window: Arc::new(AtomicU32::new(0)),
+            status: Arc::new(Mutex::new(Vec::new())),
+            reload: Arc::new(Mutex::new(None)),
+            thread: None,
+        }
+    }
+
+    /// Sends a client message to the event thread's window.
+    fn send_message(&self, window: u32, message: u32) {
+        let event = ClientMessageEvent::new(32, window, AtomEnum::NONE, [message, 0, 0, 0, 0]);
+        let _ = self
+            .x11
+            .conn
+            .send_event(false, window, EventMask::NO_EVENT, event);
+        let _ = self.x11.conn.flush();
+    }
+}
+
+/// Event thread: grabs the hotkeys and dispatches key presses until the stop
+/// message arrives.
+fn event_loop(
+    mut bindings: Vec<HotkeyBinding>,
+    on_action: ActionCallback,
+    wakeup_window: Arc<AtomicU32>,
+    status: Arc<Mutex<Vec<BindingStatus>>>,
+    reload: Arc<Mutex<Option<PendingReload>>>,
+    ready: mpsc::Sender<()>,
+) {
+    let x11 = match X11Connection::connect() {
+        Ok(x11) => x11,
+        Err(e) => {
+            log::error!("Hotkey thread failed to connect: {}", e);
+            let _ = ready.send(());
+            return;
+        }
+    };
+
+    let setup = (|| -> Result<u32, Box<dyn std::error::Error>> {
+        let window = x11.conn.generate_id()?;
+        x11.conn
+            .create_window(
+                COPY_DEPTH_FROM_PARENT,
+                window,
+                x11.root(),
+                0,
+                0,
+                1,
+                1,
+                0,
+                WindowClass::INPUT_ONLY,
+                0,
+                &CreateWindowAux::new(),
+            )?
+            .check()?;
+        Ok(window)
+    })();
+
+    let window = match setup {
+        Ok(window) => window,
+        Err(e) => {
+            log::error!("Failed to create the hotkey window: {}", e);
+            let _ = ready.send(());
+            return;
+        }
+    };
+    // Auto-repeat would otherwise send a release before every repeated press
+    let detectable = x11
+        .conn
+        .xkb_per_client_flags(
+            xkb::ID::USE_CORE_KBD.into(),
+            xkb::PerClientFlag::DETECTABLE_AUTO_REPEAT,
+            xkb::PerClientFlag::DETECTABLE_AUTO_REPEAT,
+            xkb::BoolCtrl::from(0u32),
+            xkb::BoolCtrl::from(0u32),
+            xkb::BoolCtrl::from(0u32),
+        )
+        .ok()
+        .and_then(|c| c.reply().ok());
+    if detectable.is_none() {
+        log::warn!("Detectable auto-repeat is not available; held hotkeys may end early");
+    }
+
+    let (mut grabs, grab_status) = Grabs::grab(&x11, &bindings);
+    *status.lock() = grab_status;
+    // A `show-while-held` hotkey is down
+    let mut peek_held = false;
+    wakeup_window.store(window, Ordering::SeqCst);
+    let _ = ready.send(());
+
+    loop {
+        let event = match x11.conn.wait_for_event() {
+            Ok(event) => event,
+            Err(e) => {
+                log::error!("Hotkey thread lost the X connection: {}", e);
+                break;
+            }
+        };
+
+        match event {
+            Event::KeyPress(e) => {
+                // The key state is only read for sided modifiers
+                let keymap = OnceCell::new();
+                let pressed = |keycode: u8| {
+                    let keys = keymap.get_or_init(|| {
+                        x11.conn
+                            .query_keymap()
+                            .ok()
+                            .and_then(|c| c.reply().ok())
+                            .map(|r| r.keys)
+                            .unwrap_or_default()
+                    });
+                    keys[keycode as usize / 8] & (1 << (keycode % 8)) != 0
+                };
+                if let Some(action) = grabs.find(e.detail, u16::from(e.state), pressed) {
+                    peek_held |= *action == Action::PeekWhileHeld;
+                    on_action(action.clone());
+                }
+            }
+            // The keyboard stays grabbed until the hotkey's key is released,
+            // so releases of its modifiers arrive here as well
+            Event::KeyRelease(_) if peek_held => {
+                peek_held = false;
+                on_action(Action::EndPeek);
+            }
+            Event::MappingNotify(e) if e.request != Mapping::POINTER => {
+                // Keycodes or the Num Lock modifier may have moved
+                grabs.ungrab(&x11);
+                let (new_grabs, grab_status) = Grabs::grab(&x11, &bindings);
+                grabs = new_grabs;
+                *status.lock() = grab_status;
+            }
+            Event::ClientMessage(e) if e.window == window => {
+                if e.data.as_data32()[0] != RELOAD_MESSAGE {
+                    break;
+                }
+                let Some((new_bindings, done)) = reload.lock().take() else {
+                    continue;
+                };
+                grabs.configured = hotkey::configured_hotkeys(&new_bindings);
+                let current = status.lock().clone();
+                let mut registry = GrabRegistry {
+                    x11: &x11,
+                    grabs: &mut grabs,
+                };
+                let new_status = hotkey::reload_bindings(&current, &new_bindings, &mut registry);
+                let _ = x11.conn.flush();
+                *status.lock() = new_status;
+                bindings = new_bindings;
+                peek_held = false;
+                let _ = done.send(());
+            }
+            _ => {}
+        }
+    }
+
+    wakeup_window.store(0, Ordering::SeqCst);
+    reload.lock().take();
+    grabs.ungrab(&x11);
+    status.lock().clear();
+    let _ = x11.conn.destroy_window(window);
+    let _ = x11.conn.flush();
+}
+
+impl HotkeyBackend for X11Hotkeys {
+    fn start(&mut self, config: &HotkeyConfig, on_action: ActionCallback) {
+        if !config.enabled || self.thread.is_some() {
+            return;
+        }
+        warn_unsupported(config);
+
+        let bindings = config.all_bindings();
+        let wakeup_window = self.wakeup_window.clone();
+        let status = self.status.clone();
+        let reload = self.reload.clone();
+        let (ready_tx, ready_rx) = mpsc::channel();
+        self.thread = Some(std::thread::spawn(move || {
+            event_loop(bindings, on_action, wakeup_window, status, reload, ready_tx);
+        }));
+
+        // Wait until the keys are grabbed so presses right after start count
+        let _ = ready_rx.recv();
+    }
+
+    fn status(&self) -> Vec<BindingStatus> {
+        self.status.lock().clone()
+    }
+
+    fn reload(&mut self, config: &HotkeyConfig) {
+        if self.thread.is_none() {
+            return;
+        }
+        warn_unsupported(config);
+
+        let (done_tx, done_rx) = mpsc::channel();
+        *self.reload.lock() = Some((config.all_bindings(), done_tx));
+        // An event thread that lost its connection drops the request
+        let window = self.wakeup_window.load(Ordering::SeqCst);
+        if window == 0 {
+            self.reload.lock().take();
+            return;
+        }
+        self.send_message(window, RELOAD_MESSAGE);
+        // The grabs are in place once the event thread answers
+        let _ = done_rx.recv();
+    }
+
+    fn stop(&mut self) {
+        let window = self.wakeup_window.load(Ordering::SeqCst);
+        if window != 0 {
+            self.send_message(window, 0);
+        }
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for X11Hotkeys {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::HotkeyBinding;
+    use crate::platform::x11::test_connection;
+    use std::process::Command;
+    use std::time::Duration;
+
+    #[test]
+    fn keys_map_to_keysyms() {
+        assert_eq!(keysym(Key::Char('l')), 0x006C);
+        assert_eq!(keysym(Key::Char('7')), 0x0037);
+        assert_eq!(keysym(Key::F(1)), 0xFFBE);
+        assert_eq!(keysym(Key::F(12)), 0xFFC9);
+        assert_eq!(keysym(Key::Enter), 0xFF0D);
+        assert_eq!(keysym(Key::F(13)), 0xFFCA);
+        assert_eq!(keysym(Key::PageUp), 0xFF55);
+        assert_eq!(keysym(Key::Numpad(7)), 0xFFB7);
+        assert_eq!(keysym(Key::Char(';')), 0x003B);
+        assert_eq!(keysym(Key::VolumeMute), 0x1008FF12);
+    }
+
+    #[test]
+    fn sided_grabs_check_the_pressed_keys() {
+        let grab = |hotkey: &str, left: Option<u8>, right: Option<u8>, action| {
+            let hotkey = hotkey::parse_hotkey(hotkey).unwrap();
+            Grab {
+                keycode: 67,
+                modifiers: modifier_mask(&hotkey),
+                sides: hotkey
+                    .modifiers
+                    .sided()
+                    .into_iter()
+                    .map(|(_, side)| SideKeys { side, left, right })
+                    .collect(),
+                action,
+            }
+        };
+        // Control_L is 37, Control_R 105
+        let grabs = Grabs {
+            grabs: vec![
+                grab("rctrl+f1", Some(37), Some(105), Action::Toggle),
+                grab("ctrl+f1", None, None, Action::Exit),
+            ],
+            num_lock: 0,
+            keysyms: Vec::new(),
+            keysyms_per_keycode: 0,
+            min_keycode: 8,
+            configured: Vec::new(),
+        };
+        let ctrl = u16::from(ModMask::CONTROL);
+
+        assert_eq!(grabs.find(67, ctrl, |k| k == 105), Some(&Action::Toggle));
+        assert_eq!(grabs.find(67, ctrl, |k| k == 37), Some(&Action::Exit));
+        assert_eq!(grabs.find(68, ctrl, |_| true), None);
+        assert_eq!(grabs.find(67, 0, |_| true), None);
+    }
+
+    #[test]
+    fn keysyms_are_found_in_mapping() {
+        // Keycodes 8..=10 with two keysyms each
+        let keysyms = [0x0061, 0x0041, 0x006C, 0x004C, NUM_LOCK_KEYSYM, 0];
+        assert_eq!(keycode_for_keysym(&keysyms, 2, 8, 0x006C), Some(9));
+        assert_eq!(keycode_for_keysym(&keysyms, 2, 8, 0x004C), Some(9));
+        assert_eq!(
+            keycode_for_keysym(&keysyms, 2, 8, NUM_LOCK_KEYSYM),
+            Some(10)
+        );
+        assert_eq!(keycode_for_keysym(&keysyms, 2, 8, 0xFFBE), None);
+    }
+
+    #[test]
+    fn lock_states_are_grabbed_and_ignored() {
+        let ctrl_alt = modifier_mask(&hotkey::parse_hotkey("ctrl+alt+l").unwrap());
+        assert_eq!(
+            modifier_mask(&hotkey::parse_hotkey("super+l").unwrap()),
+            u16::from(ModMask::M4)
+        );
+        let caps = u16::from(ModMask::LOCK);
+        let num = u16::from(ModMask::M2);
+        assert_eq!(
+            lock_variants(ctrl_alt, num),
+            vec![
+                ctrl_alt,
+                ctrl_alt | caps,
+                ctrl_alt | num,
+                ctrl_alt | caps | num
+            ]
+        );
+        assert_eq!(lock_variants(ctrl_alt, 0), vec![ctrl_alt, ctrl_alt | caps]);
+
+        // Group bits (0x2000) and mouse buttons (0x100) are ignored too
+        assert_eq!(clean_state(ctrl_alt | caps | num | 0x2100, num), ctrl_alt);
+        assert_ne!(
+            clean_state(ctrl_alt | u16::from(ModMask::SHIFT), num),
+            ctrl_alt
+        );
+    }
+
+    /// Presses keys on the test display.
+    fn xdotool(keys: &str) -> bool {
+        Command::new("xdotool")
+            .args(["key", keys])
+            .status()
+            .map(|s| s.success())
+            .unwrap_or(false)
+    }
+
+    #[test]
+    fn grabbed_hotkeys_fire_under_xvfb() {
+        let Some(x11) = test_connection() else {
+            return;
+        };
+        if !xdotool("shift") {
+            eprintln!("xdotool not available, skipping");
+            return;
+        }
+
+        let (tx, rx) = mpsc::channel();
+        let tx = parking_lot::Mutex::new(tx);
+        let mut config = HotkeyConfig::default();
+        config.bindings.push(HotkeyBinding {
+            keys: "ctrl+alt+m".to_string(),
+            action: "mute-sound".to_string(),
+        });
+        let mut hotkeys = X11Hotkeys::new(x11.clone());
+        hotkeys.start(
+            &config,
+            Arc::new(move |action| {
+                let _ = tx.lock().send(action);
+            }),
+        );
+
+        assert!(xdotool("ctrl+alt+l"));
+        assert_eq!(rx.recv_timeout(Duration::from_secs(2)), Ok(Action::Toggle));
+        assert!(xdotool("ctrl+alt+q"));
+        assert_eq!(rx.recv_timeout(Duration::from_secs(2)), Ok(Action::Exit));
+        assert!(xdotool("ctrl+alt+m"));
+        assert_eq!(
+            rx.recv_timeout(Duration::from_secs(2)),
+            Ok(Action::MuteSound)
+        );
+        assert!(hotkeys.status().iter().all(|s| s.is_registered()));
+
+        // Another client can't grab them and gets free alternatives
+        let mut rival = X11Hotkeys::new(x11);
+        rival.start(&config, Arc::new(|_| {}));
+        let status = rival.status();
+        assert_eq!(status.len(), 3);
+        assert!(status.iter().all(|s| matches!(
+            s.registration,
+            Registration::Taken(ref alternatives) if !alternatives.is_empty()
+        )));
+        rival.stop();
+
+        // Num Lock doesn't change the combination
+        assert!(xdotool("Num_Lock"));
+        assert!(xdotool("ctrl+alt+l"));
+        let with_num_lock = rx.recv_timeout(Duration::from_secs(2));
+        assert!(xdotool("Num_Lock"));
+        assert_eq!(with_num_lock, Ok(Action::Toggle));
+
+        // Extra modifiers make a different combination
+        assert!(xdotool("ctrl+alt+shift+l"));
+        assert!(rx.recv_timeout(Duration::from_millis(200)).is_err());
+
+        hotkeys.stop();
+        assert!(hotkeys.thread.is_none());
+        assert!(xdotool("ctrl+alt+l"));
+        assert!(rx.recv_timeout(Duration::from_millis(200)).is_err());
+    }
+}
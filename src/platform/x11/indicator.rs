@@ -0,0 +1,443 @@
+//! Indicator window module.
+//!
+//! Indicator windows as override-redirect ARGB windows. The window content is
+//! drawn into a background pixmap, so the server repaints exposed areas by
+//! itself; an empty input shape makes the window click-through.
+
+use super::X11Connection;
+use crate::config::{parse_hex_color, AppConfig};
+use crate::indicator::{calculate_position, Fade, Position};
+use crate::monitors::MonitorInfo;
+use crate::platform::Overlay;
+use crate::render::premultiply;
+use parking_lot::Mutex;
+use std::sync::Arc;
+use x11rb::connection::Connection;
+use x11rb::errors::ReplyOrIdError;
+use x11rb::protocol::shape::{self, ConnectionExt as _};
+use x11rb::protocol::xproto::{
+    AtomEnum, ChangeGCAux, ChangeWindowAttributesAux, Char2b, ClipOrdering, ColormapAlloc,
+    ConfigureWindowAux, ConnectionExt as _, CreateGCAux, CreateWindowAux, PropMode, Rectangle,
+    StackMode, VisualClass, Visualid, WindowClass,
+};
+use x11rb::wrapper::ConnectionExt as _;
+
+/// Depth of ARGB visuals.
+const ARGB_DEPTH: u8 = 32;
+
+/// Text offset inside the window, same as the Win32 indicator.
+const TEXT_X: i16 = 10;
+const TEXT_Y: i16 = 5;
+
+/// Text and colour state of an indicator.
+struct WindowState {
+    text: String,
+    is_russian: bool,
+}
+
+/// A single indicator window.
+pub struct IndicatorWindow {
+    x11: Arc<X11Connection>,
+    window: u32,
+    pixmap: u32,
+    gc: u32,
+    font: u32,
+    colormap: u32,
+    width: u16,
+    height: u16,
+    font_ascent: i16,
+    color_en: (u8, u8, u8),
+    color_ru: (u8, u8, u8),
+    state: Mutex<WindowState>,
+    fade: Fade,
+}
+
+/// Finds a 32-bit TrueColor visual on the default screen.
+fn find_argb_visual(x11: &X11Connection) -> Option<Visualid> {
+    x11.screen()
+        .allowed_depths
+        .iter()
+        .filter(|d| d.depth == ARGB_DEPTH)
+        .flat_map(|d| d.visuals.iter())
+        .find(|v| v.class == VisualClass::TRUE_COLOR)
+        .map(|v| v.visual_id)
+}
+
+/// XLFD patterns tried in order for the configured family and pixel size.
+fn font_patterns(family: &str, size: u32) -> Vec<String> {
+    let family = family.to_lowercase();
+    vec![
+        format!("-*-{}-bold-r-normal--{}-*-*-*-*-*-iso10646-1", family, size),
+        format!("-*-*-bold-r-normal--{}-*-*-*-*-*-iso10646-1", size),
+        format!("-*-*-bold-r-normal--{}-*-*-*-*-*-*-*", size),
+        "fixed".to_string(),
+    ]
+}
+
+impl IndicatorWindow {
+    /// Creates a new indicator window.
+    pub fn new(
+        x11: Arc<X11Connection>,
+        position: Position,
+        config: &AppConfig,
+        monitor: MonitorInfo,
+    ) -> Option<Self> {
+        let Some(visual) = find_argb_visual(&x11) else {
+            log::error!("No 32-bit ARGB visual available");
+            return None;
+        };
+
+        match Self::create(x11, visual, position, config, monitor) {
+            Ok(window) => Some(window),
+            Err(e) => {
+                log::error!("Failed to create indicator window: {}", e);
+                None
+            }
+        }
+    }
+
+    fn create(
+        x11: Arc<X11Connection>,
+        visual: Visualid,
+        position: Position,
+        config: &AppConfig,
+        monitor: MonitorInfo,
+    ) -> Result<Self, ReplyOrIdError> {
+        let conn = &x11.conn;
+        let root = x11.root();
+
+        let is_center = position == Position::Center;
+        let font_size = if is_center {
+            config.font_size_center
+        } else {
+            config.font_size_corner
+        };
+
+        // Calculate window size based on font size
+        let width = (font_size * 3) as i32;
+        let height = (font_size as f32 * 1.5) as i32;
+
+        // Calculate position
+        let margin = config.margin;
+        let (x, y) = calculate_position(position, &monitor, width, height, margin);
+
+        let colormap = conn.generate_id()?;
+        conn.create_colormap(ColormapAlloc::NONE, colormap, root, visual)?;
+
+        // Override-redirect: not managed by the window manager, no decorations
+        let window = conn.generate_id()?;
+        conn.create_window(
+            ARGB_DEPTH,
+            window,
+            root,
+            x as i16,
+            y as i16,
+            width as u16,
+            height as u16,
+            0,
+            WindowClass::INPUT_OUTPUT,
+            visual,
+            &CreateWindowAux::new()
+                .override_redirect(1)
+                .background_pixel(0)
+                .border_pixel(0)
+                .colormap(colormap),
+        )?;
+
+        // Empty input shape makes the window click-through
+        conn.shape_rectangles(
+            shape::SO::SET,
+            shape::SK::INPUT,
+            ClipOrdering::UNSORTED,
+            window,
+            0,
+            0,
+            &[],
+        )?;
+
+        // Let compositors treat it like a notification (no shadow, no focus)
+        conn.change_property32(
+            PropMode::REPLACE,
+            window,
+            x11.atoms._NET_WM_WINDOW_TYPE,
+            AtomEnum::ATOM,
+            &[x11.atoms._NET_WM_WINDOW_TYPE_NOTIFICATION],
+        )?;
+        conn.change_property8(
+            PropMode::REPLACE,
+            window,
+            AtomEnum::WM_CLASS,
+            AtomEnum::STRING,
+            b"langtip\0LangTip\0",
+        )?;
+
+        // Window content lives in the background pixmap
+        let pixmap = conn.generate_id()?;
+        conn.create_pixmap(ARGB_DEPTH, pixmap, window, width as u16, height as u16)?;
+        conn.change_window_attributes(
+            window,
+            &ChangeWindowAttributesAux::new().background_pixmap(pixmap),
+        )?;
+
+        // Open the first available font
+        let font = conn.generate_id()?;
+        let mut opened = false;
+        for pattern in font_patterns(&config.font_family, font_size) {
+            if conn.open_font(font, pattern.as_bytes())?.check().is_ok() {
+                log::debug!("Using font {}", pattern);
+                opened = true;
+                break;
+            }
+        }
+        if !opened {
+            log::error!("No usable X font found");
+        }
+        let font_ascent = if opened {
+            conn.query_font(font)?.reply()?.font_ascent
+        } else {
+            0
+        };
+
+        let gc = conn.generate_id()?;
+        let mut gc_aux = CreateGCAux::new().graphics_exposures(0);
+        if opened {
+            gc_aux = gc_aux.font(font);
+        }
+        conn.create_gc(gc, pixmap, &gc_aux)?;
+        conn.flush()?;
+
+        log::debug!(
+            "Created window {:#x} at ({}, {}) size {}x{}",
+            window,
+            x,
+            y,
+            width,
+            height
+        );
+
+        let indicator = Self {
+            x11: x11.clone(),
+            window,
+            pixmap,
+            gc,
+            font: if opened { font } else { 0 },
+            colormap,
+            width: width as u16,
+            height: height as u16,
+            font_ascent,
+            color_en: parse_hex_color(&config.colors.en),
+            color_ru: parse_hex_color(&config.colors.ru),
+            state: Mutex::new(WindowState {
+                text: "EN".to_string(),
+                is_russian: false,
+            }),
+            fade: Fade::new(config.opacity),
+        };
+        indicator.redraw();
+        Ok(indicator)
+    }
+
+    /// Redraws the background pixmap at the current alpha and repaints the window.
+    fn redraw(&self) {
+        let alpha = self.fade.alpha();
+        let state = self.state.lock();
+        let color = if state.is_russian {
+            self.color_ru
+        } else {
+            self.color_en
+        };
+        let text: Vec<Char2b> = state
+            .text
+            .encode_utf16()
+            .map(|c| Char2b {
+                byte1: (c >> 8) as u8,
+                byte2: c as u8,
+            })
+            .collect();
+        drop(state);
+
+        let conn = &self.x11.conn;
+        let result = (|| {
+            // Transparent background
+            conn.change_gc(self.gc, &ChangeGCAux::new().foreground(0))?;
+            conn.poly_fill_rectangle(
+                self.pixmap,
+                self.gc,
+                &[Rectangle {
+                    x: 0,
+                    y: 0,
+                    width: self.width,
+                    height: self.height,
+                }],
+            )?;
+
+            // Text in the layout colour, premultiplied with the fade alpha
+            if self.font != 0 {
+                conn.change_gc(
+                    self.gc,
+                    &ChangeGCAux::new()
+                        .foreground(premultiply(color, alpha))
+                        .background(0),
+                )?;
+                conn.image_text16(
+                    self.pixmap,
+                    self.gc,
+                    TEXT_X,
+                    TEXT_Y + self.font_ascent,
+                    &text,
+                )?;
+            }
+
+            conn.clear_area(false, self.window, 0, 0, 0, 0)?;
+            conn.flush()
+        })();
+        if let Err(e) = result {
+            log::warn!("Failed to redraw indicator {:#x}: {}", self.window, e);
+        }
+    }
+
+    /// Returns the window id.
+    #[cfg(test)]
+    pub fn window(&self) -> u32 {
+        self.window
+    }
+}
+
+impl Overlay for IndicatorWindow {
+    /// Updates the indicator text.
+    fn update_text(&self, text: &str, is_russian: bool) {
+        {
+            let mut state = self.state.lock();
+            state.text = text.to_string();
+            state.is_russian = is_russian;
+        }
+        self.redraw();
+    }
+
+    /// Maps the window and starts the fade-in animation.
+    fn show(&self) {
+        self.fade.fade_in();
+        let conn = &self.x11.conn;
+        let _ = conn.map_window(self.window);
+        let _ = conn.configure_window(
+            self.window,
+            &ConfigureWindowAux::new().stack_mode(StackMode::ABOVE),
+        );
+        let _ = conn.flush();
+    }
+
+    /// Starts the fade-out animation. The window is unmapped when alpha reaches 0.
+    fn hide(&self) {
+        self.fade.fade_out();
+    }
+
+    /// Updates the fade animation. Returns true if animation is still in progress.
+    fn update_fade(&self) -> bool {
+        let Some(new_alpha) = self.fade.step() else {
+            return false; // Animation complete
+        };
+
+        self.redraw();
+
+        // Unmap window completely when fully transparent
+        if new_alpha == 0 {
+            let _ = self.x11.conn.unmap_window(self.window);
+            let _ = self.x11.conn.flush();
+        }
+
+        true // Animation in progress
+    }
+}
+
+impl Drop for IndicatorWindow {
+    fn drop(&mut self) {
+        let conn = &self.x11.conn;
+        let _ = conn.destroy_window(self.window);
+        let _ = conn.free_gc(self.gc);
+        let _ = conn.free_pixmap(self.pixmap);
+        if self.font != 0 {
+            let _ = conn.close_font(self.font);
+        }
+        let _ = conn.free_colormap(self.colormap);
+        let _ = conn.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::x11::test_connection;
+    use x11rb::protocol::xproto::MapState;
+
+    #[test]
+    fn window_is_override_redirect_argb_and_click_through() {
+        let Some(x11) = test_connection() else {
+            return;
+        };
+        let monitor = MonitorInfo {
+            x: 0,
+            y: 0,
+            width: 1024,
+            height: 768,
+            work_x: 0,
+            work_y: 0,
+            work_width: 1024,
+            work_height: 768,
+            is_primary: true,
+        };
+        let config = AppConfig::default();
+        let indicator =
+            IndicatorWindow::new(x11.clone(), Position::BottomRight, &config, monitor).unwrap();
+        let window = indicator.window();
+
+        let attributes = x11
+            .conn
+            .get_window_attributes(window)
+            .unwrap()
+            .reply()
+            .unwrap();
+        assert!(attributes.override_redirect);
+        assert_eq!(attributes.map_state, MapState::UNMAPPED);
+
+        let geometry = x11.conn.get_geometry(window).unwrap().reply().unwrap();
+        assert_eq!(geometry.depth, ARGB_DEPTH);
+        let width = (config.font_size_corner * 3) as i16;
+        assert_eq!(geometry.x, 1024 - width - config.margin as i16);
+
+        let input = x11
+            .conn
+            .shape_get_rectangles(window, shape::SK::INPUT)
+            .unwrap()
+            .reply()
+            .unwrap();
+        assert!(input.rectangles.is_empty());
+
+        // Shown after the fade-in starts, unmapped again after fading out
+        indicator.show();
+        let attributes = x11
+            .conn
+            .get_window_attributes(window)
+            .unwrap()
+            .reply()
+            .unwrap();
+        assert_eq!(attributes.map_state, MapState::VIEWABLE);
+        while indicator.update_fade() {}
+        indicator.hide();
+        while indicator.update_fade() {}
+        let attributes = x11
+            .conn
+            .get_window_attributes(window)
+            .unwrap()
+            .reply()
+            .unwrap();
+        assert_eq!(attributes.map_state, MapState::UNMAPPED);
+
+        drop(indicator);
+        assert!(x11
+            .conn
+            .get_window_attributes(window)
+            .unwrap()
+            .reply()
+            .is_err());
+    }
+}
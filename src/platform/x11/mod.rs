@@ -0,0 +1,172 @@
+//! X11 platform module.
+//!
+//! Implements the overlay, monitor, layout and hotkey backends on top of the
+//! X11 protocol (XKB, RandR and SHAPE extensions). Tray, autostart, instance
+//! lock and sound still come from the headless platform.
+
+mod hotkeys;
+mod indicator;
+mod monitors;
+mod xkb;
+
+use super::headless::HeadlessPlatform;
+use super::{Backends, Overlay, OverlayBackend, PlatformEvent};
+use crate::config::AppConfig;
+use crate::indicator::Position;
+use crate::monitors::MonitorInfo;
+use hotkeys::X11Hotkeys;
+use indicator::IndicatorWindow;
+use monitors::X11Monitors;
+use std::sync::Arc;
+use std::time::Duration;
+use x11rb::connection::Connection;
+use x11rb::protocol::randr::{ConnectionExt as _, NotifyMask};
+use x11rb::protocol::xkb::ConnectionExt as _;
+use x11rb::protocol::xproto::{ChangeWindowAttributesAux, ConnectionExt as _, EventMask, Screen};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+use xkb::XkbLayoutSource;
+
+x11rb::atom_manager! {
+    /// Atoms used by the X11 backends.
+    pub Atoms: AtomsCookie {
+        _NET_WORKAREA,
+        _NET_CURRENT_DESKTOP,
+        _NET_WM_WINDOW_TYPE,
+        _NET_WM_WINDOW_TYPE_NOTIFICATION,
+        _XKB_RULES_NAMES,
+    }
+}
+
+/// Connection to the X server shared by the backends.
+pub struct X11Connection {
+    conn: RustConnection,
+    screen_num: usize,
+    atoms: Atoms,
+}
+
+impl X11Connection {
+    /// Connects to the display named by `$DISPLAY` and initialises the
+    /// extensions the backends rely on.
+    pub fn connect() -> Result<Self, Box<dyn std::error::Error>> {
+        let (conn, screen_num) = x11rb::connect(None)?;
+        let atoms = Atoms::new(&conn)?.reply()?;
+
+        let xkb = conn.xkb_use_extension(1, 0)?.reply()?;
+        if !xkb.supported {
+            return Err("XKB extension is not supported".into());
+        }
+
+        Ok(Self {
+            conn,
+            screen_num,
+            atoms,
+        })
+    }
+
+    /// Returns the default screen.
+    fn screen(&self) -> &Screen {
+        &self.conn.setup().roots[self.screen_num]
+    }
+
+    /// Returns the root window of the default screen.
+    fn root(&self) -> u32 {
+        self.screen().root
+    }
+}
+
+/// Overlay backend creating override-redirect ARGB windows.
+pub struct X11Overlays {
+    x11: Arc<X11Connection>,
+}
+
+impl X11Overlays {
+    /// Creates the backend and subscribes to monitor and work area changes.
+    fn new(x11: Arc<X11Connection>) -> Self {
+        let root = x11.root();
+        let subscribed = x11
+            .conn
+            .randr_select_input(root, NotifyMask::SCREEN_CHANGE)
+            .and_then(|_| {
+                x11.conn.change_window_attributes(
+                    root,
+                    &ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE),
+                )
+            })
+            .and_then(|_| x11.conn.flush());
+        if let Err(e) = subscribed {
+            log::warn!("Failed to subscribe to display changes: {}", e);
+        }
+        Self { x11 }
+    }
+}
+
+impl OverlayBackend for X11Overlays {
+    fn create_overlay(
+        &mut self,
+        position: Position,
+        config: &AppConfig,
+        monitor: &MonitorInfo,
+    ) -> Option<Box<dyn Overlay>> {
+        IndicatorWindow::new(self.x11.clone(), position, config, monitor.clone())
+            .map(|window| Box::new(window) as Box<dyn Overlay>)
+    }
+
+    fn pump_events(&mut self) -> Vec<PlatformEvent> {
+        let mut events = Vec::new();
+        let mut display_changed = false;
+
+        loop {
+            match self.x11.conn.poll_for_event() {
+                Ok(Some(Event::RandrScreenChangeNotify(_))) => display_changed = true,
+                Ok(Some(Event::PropertyNotify(e))) if e.atom == self.x11.atoms._NET_WORKAREA => {
+                    display_changed = true;
+                }
+                Ok(Some(_)) => {}
+                Ok(None) => break,
+                Err(e) => {
+                    log::error!("X connection lost: {}", e);
+                    events.push(PlatformEvent::Quit);
+                    return events;
+                }
+            }
+        }
+
+        if display_changed {
+            log::info!("Monitor configuration or work area changed");
+            events.push(PlatformEvent::DisplayChanged);
+        } else {
+            std::thread::sleep(Duration::from_millis(16));
+        }
+
+        events
+    }
+}
+
+/// Returns the X11 backends, or `None` if no X server is reachable.
+pub fn backends() -> Option<Backends> {
+    let x11 = match X11Connection::connect() {
+        Ok(x11) => Arc::new(x11),
+        Err(e) => {
+            log::warn!("Cannot use X11: {}", e);
+            return None;
+        }
+    };
+
+    let mut backends = HeadlessPlatform::new().backends();
+    backends.overlays = Box::new(X11Overlays::new(x11.clone()));
+    backends.monitors = Box::new(X11Monitors::new(x11.clone()));
+    backends.layout = Box::new(XkbLayoutSource::new(x11.clone()));
+    backends.hotkeys = Box::new(X11Hotkeys::new(x11));
+    Some(backends)
+}
+
+/// Connects to the test X server, or returns `None` if `$DISPLAY` isn't set
+/// (run the tests under `xvfb-run` to exercise the X11 backends).
+#[cfg(test)]
+fn test_connection() -> Option<Arc<X11Connection>> {
+    std::env::var_os("DISPLAY")?;
+    Some(Arc::new(
+        X11Connection::connect().expect("failed to connect to $DISPLAY"),
+    ))
+}
@@ -0,0 +1,244 @@
+//! Monitor detection module.
+//!
+//! Enumerates monitors through RandR and derives their work areas from the
+//! EWMH `_NET_WORKAREA` root window property.
+
+use super::X11Connection;
+use crate::monitors::MonitorInfo;
+use crate::platform::MonitorProvider;
+use std::sync::Arc;
+use x11rb::protocol::randr::ConnectionExt as _;
+use x11rb::protocol::xproto::{AtomEnum, ConnectionExt as _};
+
+/// A rectangle in root window coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Rect {
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+}
+
+impl Rect {
+    /// Returns the overlap of two rectangles, if any.
+    fn intersect(&self, other: &Rect) -> Option<Rect> {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = (self.x + self.width).min(other.x + other.width);
+        let bottom = (self.y + self.height).min(other.y + other.height);
+        (right > x && bottom > y).then_some(Rect {
+            x,
+            y,
+            width: right - x,
+            height: bottom - y,
+        })
+    }
+}
+
+/// Builds monitor information, clipping the work area to the monitor.
+///
+/// `_NET_WORKAREA` is a single rectangle for the whole screen, so a monitor
+/// that doesn't overlap it keeps its full area.
+fn to_monitor_info(area: Rect, work_area: Option<Rect>, is_primary: bool) -> MonitorInfo {
+    let work = work_area.and_then(|w| w.intersect(&area)).unwrap_or(area);
+    MonitorInfo {
+        x: area.x,
+        y: area.y,
+        width: area.width,
+        height: area.height,
+        work_x: work.x,
+        work_y: work.y,
+        work_width: work.width,
+        work_height: work.height,
+        is_primary,
+    }
+}
+
+/// Picks the work area of `desktop` out of the `_NET_WORKAREA` values
+/// (four cardinals per desktop).
+fn select_work_area(values: &[u32], desktop: usize) -> Option<Rect> {
+    let area = values.chunks_exact(4).nth(desktop)?;
+    Some(Rect {
+        x: area[0] as i32,
+        y: area[1] as i32,
+        width: area[2] as i32,
+        height: area[3] as i32,
+    })
+}
+
+/// Monitor provider backed by RandR.
+pub struct X11Monitors {
+    x11: Arc<X11Connection>,
+}
+
+impl X11Monitors {
+    pub fn new(x11: Arc<X11Connection>) -> Self {
+        Self { x11 }
+    }
+
+    /// Reads the work area of the current desktop.
+    fn work_area(&self) -> Option<Rect> {
+        let conn = &self.x11.conn;
+        let root = self.x11.root();
+
+        let desktop = conn
+            .get_property(
+                false,
+                root,
+                self.x11.atoms._NET_CURRENT_DESKTOP,
+                AtomEnum::CARDINAL,
+                0,
+                1,
+            )
+            .ok()?
+            .reply()
+            .ok()
+            .and_then(|r| r.value32().and_then(|mut v| v.next()))
+            .unwrap_or(0);
+
+        let reply = conn
+            .get_property(
+                false,
+                root,
+                self.x11.atoms._NET_WORKAREA,
+                AtomEnum::CARDINAL,
+                0,
+                1024,
+            )
+            .ok()?
+            .reply()
+            .ok()?;
+        let values: Vec<u32> = reply.value32()?.collect();
+        select_work_area(&values, desktop as usize)
+    }
+
+    /// Reads the RandR monitors, falling back to the whole screen.
+    fn screen_areas(&self) -> Vec<(Rect, bool)> {
+        let conn = &self.x11.conn;
+        let screen = self.x11.screen();
+
+        let monitors = conn
+            .randr_get_monitors(screen.root, true)
+            .ok()
+            .and_then(|c| c.reply().ok());
+
+        match monitors {
+            Some(reply) if !reply.monitors.is_empty() => reply
+                .monitors
+                .iter()
+                .map(|m| {
+                    (
+                        Rect {
+                            x: m.x as i32,
+                            y: m.y as i32,
+                            width: m.width as i32,
+                            height: m.height as i32,
+                        },
+                        m.primary,
+                    )
+                })
+                .collect(),
+            _ => {
+                log::warn!("RandR monitors unavailable, using the whole screen");
+                vec![(
+                    Rect {
+                        x: 0,
+                        y: 0,
+                        width: screen.width_in_pixels as i32,
+                        height: screen.height_in_pixels as i32,
+                    },
+                    true,
+                )]
+            }
+        }
+    }
+}
+
+impl MonitorProvider for X11Monitors {
+    fn monitors(&self) -> Vec<MonitorInfo> {
+        let work_area = self.work_area();
+        let mut areas = self.screen_areas();
+
+        // No primary output set: treat the first one as primary
+        if !areas.iter().any(|(_, primary)| *primary) {
+            if let Some(first) = areas.first_mut() {
+                first.1 = true;
+            }
+        }
+
+        let mut monitors: Vec<MonitorInfo> = areas
+            .into_iter()
+            .map(|(area, primary)| to_monitor_info(area, work_area, primary))
+            .collect();
+
+        // Sort: primary monitor first
+        monitors.sort_by_key(|m| (!m.is_primary, m.x, m.y));
+        monitors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::x11::test_connection;
+
+    fn rect(x: i32, y: i32, width: i32, height: i32) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn work_area_is_clipped_to_each_monitor() {
+        // Two 1920x1080 monitors side by side, a 32 px panel on top of both
+        let work_area = Some(rect(0, 32, 3840, 1048));
+
+        let left = to_monitor_info(rect(0, 0, 1920, 1080), work_area, true);
+        assert_eq!(
+            (left.work_x, left.work_y, left.work_width, left.work_height),
+            (0, 32, 1920, 1048)
+        );
+
+        let right = to_monitor_info(rect(1920, 0, 1920, 1080), work_area, false);
+        assert_eq!(
+            (
+                right.work_x,
+                right.work_y,
+                right.work_width,
+                right.work_height
+            ),
+            (1920, 32, 1920, 1048)
+        );
+
+        // Monitor outside the work area keeps its full area
+        let outside = to_monitor_info(rect(3840, 0, 1280, 1024), work_area, false);
+        assert_eq!(outside.work_width, 1280);
+        assert_eq!(outside.work_height, 1024);
+    }
+
+    #[test]
+    fn work_area_of_current_desktop_is_selected() {
+        let values = [0, 0, 1920, 1080, 0, 40, 1920, 1040];
+        assert_eq!(select_work_area(&values, 1), Some(rect(0, 40, 1920, 1040)));
+        assert_eq!(select_work_area(&values, 2), None);
+        assert_eq!(select_work_area(&[], 0), None);
+    }
+
+    #[test]
+    fn xvfb_screen_is_reported_as_primary_monitor() {
+        let Some(x11) = test_connection() else {
+            return;
+        };
+        let screen = x11.screen().clone();
+        let monitors = X11Monitors::new(x11).monitors();
+        assert!(!monitors.is_empty());
+        assert!(monitors[0].is_primary);
+        assert!(monitors
+            .iter()
+            .all(|m| m.right() <= screen.width_in_pixels as i32
+                && m.bottom() <= screen.height_in_pixels as i32));
+    }
+}
@@ -0,0 +1,401 @@
+//! XKB layout tracking module.
+//!
+//! Follows the active keyboard group through `XkbStateNotify` events and maps
+//! it to the layout codes from the `_XKB_RULES_NAMES` root property.
+
+use super::X11Connection;
+use crate::layout::{
+    self, HookTimings, InstalledLayout, LayoutCallback, LayoutInfo, LayoutReporter, LayoutSwitch,
+};
+use crate::platform::LayoutSource;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread::JoinHandle;
+use std::time::Instant;
+use x11rb::connection::Connection;
+use x11rb::protocol::xkb::{
+    self, ConnectionExt as _, EventType, MapPart, SelectEventsAux, StatePart,
+};
+use x11rb::protocol::xproto::{
+    AtomEnum, ClientMessageEvent, ConnectionExt as _, CreateWindowAux, EventMask, WindowClass,
+};
+use x11rb::protocol::Event;
+use x11rb::COPY_DEPTH_FROM_PARENT;
+
+/// Core keyboard device (`XkbUseCoreKbd`).
+const CORE_KBD: xkb::DeviceSpec = 0x100;
+
+/// Extracts the layout list from a `_XKB_RULES_NAMES` value
+/// (`rules\0model\0layouts\0variants\0options`).
+fn parse_rules_layouts(value: &[u8]) -> Vec<String> {
+    let fields: Vec<&[u8]> = value.split(|b| *b == 0).collect();
+    let layouts = fields.get(2).copied().unwrap_or_default();
+    let variants = fields.get(3).copied().unwrap_or_default();
+    let variants: Vec<&str> = std::str::from_utf8(variants)
+        .unwrap_or("")
+        .split(',')
+        .collect();
+
+    std::str::from_utf8(layouts)
+        .unwrap_or("")
+        .split(',')
+        .filter(|l| !l.is_empty())
+        .enumerate()
+        .map(
+            |(i, layout)| match variants.get(i).filter(|v| !v.is_empty()) {
+                Some(variant) => format!("{}({})", layout, variant),
+                None => layout.to_string(),
+            },
+        )
+        .collect()
+}
+
+/// Maps a keyboard group to layout information.
+fn layout_for_group(layouts: &[String], group: u8) -> LayoutInfo {
+    match layouts.get(group as usize) {
+        Some(code) => LayoutInfo::from_xkb(code),
+        None => LayoutInfo {
+            name: format!("G{}", group + 1),
+            is_russian: false,
+        },
+    }
+}
+
+/// Reads the configured layouts from the root window.
+fn read_layouts(x11: &X11Connection) -> Vec<String> {
+    let reply = x11
+        .conn
+        .get_property(
+            false,
+            x11.root(),
+            x11.atoms._XKB_RULES_NAMES,
+            AtomEnum::STRING,
+            0,
+            1024,
+        )
+        .ok()
+        .and_then(|c| c.reply().ok());
+    match reply {
+        Some(reply) => parse_rules_layouts(&reply.value),
+        None => Vec::new(),
+    }
+}
+
+/// Reads the active keyboard group.
+fn read_group(x11: &X11Connection) -> u8 {
+    x11.conn
+        .xkb_get_state(CORE_KBD)
+        .ok()
+        .and_then(|c| c.reply().ok())
+        .map(|s| u8::from(s.group))
+        .unwrap_or(0)
+}
+
+/// Locks keyboard group `group`, as the layout switch key does. Returns
+/// false if the server refuses.
+fn lock_group(x11: &X11Connection, group: u8) -> bool {
+    x11.conn
+        .xkb_latch_lock_state(
+            CORE_KBD,
+            0u16.into(),
+            0u16.into(),
+            true,
+            xkb::Group::from(group),
+            0u16.into(),
+            false,
+            0,
+        )
+        .map(|cookie| cookie.check().is_ok())
+        .unwrap_or(false)
+}
+
+/// Layout source following XKB group changes.
+pub struct XkbLayoutSource {
+    x11: Arc<X11Connection>,
+    reporter: LayoutReporter,
+    /// Window on the event thread's connection that receives the stop message.
+    wakeup_window: Arc<AtomicU32>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl XkbLayoutSource {
+    pub fn new(x11: Arc<X11Connection>) -> Self {
+        Self {
+            x11,
+            reporter: LayoutReporter::default(),
+            wakeup_window: Arc::new(AtomicU32::new(0)),
+            thread: None,
+        }
+    }
+}
+
+/// Event thread: waits for XKB events until the stop message arrives.
+fn event_loop(
+    reporter: LayoutReporter,
+    wakeup_window: Arc<AtomicU32>,
+    ready: mpsc::Sender<()>,
+) {
+    let x11 = match X11Connection::connect() {
+        Ok(x11) => x11,
+        Err(e) => {
+            log::error!("XKB event thread failed to connect: {}", e);
+            let _ = ready.send(());
+            return;
+        }
+    };
+
+    let setup = (|| -> Result<u32, Box<dyn std::error::Error>> {
+        let window = x11.conn.generate_id()?;
+        x11.conn.create_window(
+            COPY_DEPTH_FROM_PARENT,
+            window,
+            x11.root(),
+            0,
+            0,
+            1,
+            1,
+            0,
+            WindowClass::INPUT_ONLY,
+            0,
+            &CreateWindowAux::new(),
+        )?;
+
+        let events =
+            EventType::STATE_NOTIFY | EventType::NEW_KEYBOARD_NOTIFY | EventType::NAMES_NOTIFY;
+        x11.conn
+            .xkb_select_events(
+                CORE_KBD,
+                EventType::from(0u16),
+                events,
+                MapPart::from(0u16),
+                MapPart::from(0u16),
+                &SelectEventsAux::new(),
+            )?
+            .check()?;
+        x11.conn.flush()?;
+        Ok(window)
+    })();
+
+    let window = match setup {
+        Ok(window) => window,
+        Err(e) => {
+            log::error!("Failed to select XKB events: {}", e);
+            let _ = ready.send(());
+            return;
+        }
+    };
+    wakeup_window.store(window, Ordering::SeqCst);
+    let mut layouts = read_layouts(&x11);
+    let _ = ready.send(());
+
+    loop {
+        let event = match x11.conn.wait_for_event() {
+            Ok(event) => event,
+            Err(e) => {
+                log::error!("XKB event thread lost the X connection: {}", e);
+                break;
+            }
+        };
+
+        match event {
+            Event::XkbStateNotify(e) if e.changed.contains(StatePart::GROUP_STATE) => {
+                reporter.report(
+                    layout_for_group(&layouts, u8::from(e.group)),
+                    Instant::now(),
+                );
+            }
+            Event::XkbNewKeyboardNotify(_) | Event::XkbNamesNotify(_) => {
+                // Layout list changed (e.g. by setxkbmap)
+                layouts = read_layouts(&x11);
+                log::debug!("XKB layouts: {:?}", layouts);
+                reporter.report(
+                    layout_for_group(&layouts, read_group(&x11)),
+                    Instant::now(),
+                );
+            }
+            Event::ClientMessage(e) if e.window == window => break,
+            _ => {}
+        }
+    }
+
+    wakeup_window.store(0, Ordering::SeqCst);
+    let _ = x11.conn.destroy_window(window);
+    let _ = x11.conn.flush();
+}
+
+impl LayoutSource for XkbLayoutSource {
+    fn current_layout(&self) -> LayoutInfo {
+        layout_for_group(&read_layouts(&self.x11), read_group(&self.x11))
+    }
+
+    fn layout_description(&self) -> Option<String> {
+        let layouts = read_layouts(&self.x11);
+        let code = layouts.get(read_group(&self.x11) as usize)?;
+        Some(layout::describe_xkb(code))
+    }
+
+    fn installed_layouts(&self) -> Vec<InstalledLayout> {
+        let group = read_group(&self.x11) as usize;
+        read_layouts(&self.x11)
+            .into_iter()
+            .enumerate()
+            .map(|(i, code)| InstalledLayout {
+                label: layout::describe_xkb(&code),
+                code,
+                active: i == group,
+            })
+            .collect()
+    }
+
+    fn start(&mut self, callback: LayoutCallback, initial: &LayoutInfo, _timings: HookTimings) {
+        self.reporter.start(callback, initial);
+
+        let reporter = self.reporter.clone();
+        let wakeup_window = self.wakeup_window.clone();
+        let (ready_tx, ready_rx) = mpsc::channel();
+        self.thread = Some(std::thread::spawn(move || {
+            event_loop(reporter, wakeup_window, ready_tx);
+        }));
+
+        // Wait until events are selected so no switch right after start is missed
+        let _ = ready_rx.recv();
+    }
+
+    /// Locks the group of the target layout; `XkbStateNotify` reports it.
+    fn switch_layout(&mut self, target: &LayoutSwitch) -> bool {
+        let layouts = read_layouts(&self.x11);
+        if layouts.is_empty() {
+            log::warn!("Keyboard layouts are unknown, can't switch ({:?})", target);
+            return false;
+        }
+        let group = match target {
+            LayoutSwitch::Next => (read_group(&self.x11) as usize + 1) % layouts.len(),
+            LayoutSwitch::To(code) => {
+                // XKB codes name countries (`us`); the shown name adds the language
+                let codes: Vec<[String; 2]> = layouts
+                    .iter()
+                    .map(|l| [l.clone(), LayoutInfo::from_xkb(l).name.to_lowercase()])
+                    .collect();
+                match layout::find_layout(code, &codes) {
+                    Some(group) => group,
+                    None => {
+                        log::warn!("No configured keyboard layout matches {:?}", code);
+                        return false;
+                    }
+                }
+            }
+        };
+        lock_group(&self.x11, group as u8)
+    }
+
+    /// XKB reports group changes as they happen, so there is nothing to debounce.
+    fn set_timings(&mut self, _timings: HookTimings) {}
+
+    fn stop(&mut self) {
+        self.reporter.stop();
+
+        let window = self.wakeup_window.load(Ordering::SeqCst);
+        if window != 0 {
+            let event = ClientMessageEvent::new(32, window, AtomEnum::NONE, [0u32; 5]);
+            let _ = self
+                .x11
+                .conn
+                .send_event(false, window, EventMask::NO_EVENT, event);
+            let _ = self.x11.conn.flush();
+        }
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for XkbLayoutSource {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::LayoutEvent;
+    use crate::platform::x11::test_connection;
+    use parking_lot::Mutex;
+    use std::process::Command;
+    use std::time::Duration;
+
+    #[test]
+    fn rules_names_give_layouts_with_variants() {
+        let value = b"evdev\0pc105\0us,ru,de\0,phonetic,\0grp:alt_shift_toggle\0";
+        assert_eq!(
+            parse_rules_layouts(value),
+            vec![
+                "us".to_string(),
+                "ru(phonetic)".to_string(),
+                "de".to_string()
+            ]
+        );
+        assert!(parse_rules_layouts(b"").is_empty());
+    }
+
+    #[test]
+    fn groups_map_to_layouts() {
+        let layouts = vec!["us".to_string(), "ru".to_string()];
+        assert_eq!(layout_for_group(&layouts, 0).name, "EN");
+        assert!(layout_for_group(&layouts, 1).is_russian);
+        assert_eq!(layout_for_group(&layouts, 2).name, "G3");
+    }
+
+    #[test]
+    fn group_switches_are_reported_under_xvfb() {
+        let Some(x11) = test_connection() else {
+            return;
+        };
+        let configured = Command::new("setxkbmap")
+            .args(["-layout", "us,ru"])
+            .status()
+            .map(|s| s.success())
+            .unwrap_or(false);
+        if !configured {
+            eprintln!("setxkbmap not available, skipping");
+            return;
+        }
+        assert!(lock_group(&x11, 0));
+
+        let mut source = XkbLayoutSource::new(x11.clone());
+        assert_eq!(source.current_layout().name, "EN");
+
+        let (tx, rx) = mpsc::channel();
+        let tx = Mutex::new(tx);
+        let initial = source.current_layout();
+        source.start(
+            Arc::new(move |event: LayoutEvent| {
+                let _ = tx.lock().send(event.layout);
+            }),
+            &initial,
+            HookTimings::default(),
+        );
+
+        assert!(lock_group(&x11, 1));
+        let layout = rx.recv_timeout(Duration::from_secs(2)).unwrap();
+        assert_eq!(layout.name, "RU");
+        assert!(layout.is_russian);
+        assert_eq!(source.current_layout().name, "RU");
+
+        assert!(lock_group(&x11, 0));
+        assert_eq!(rx.recv_timeout(Duration::from_secs(2)).unwrap().name, "EN");
+
+        // Switching by code locks the group of that layout
+        assert!(source.switch_layout(&LayoutSwitch::To("ru-RU".to_string())));
+        assert_eq!(rx.recv_timeout(Duration::from_secs(2)).unwrap().name, "RU");
+        assert!(source.switch_layout(&LayoutSwitch::Next));
+        assert_eq!(rx.recv_timeout(Duration::from_secs(2)).unwrap().name, "EN");
+        assert!(!source.switch_layout(&LayoutSwitch::To("de".to_string())));
+
+        source.stop();
+        assert!(lock_group(&x11, 1));
+        assert!(rx.recv_timeout(Duration::from_millis(200)).is_err());
+        assert!(lock_group(&x11, 0));
+    }
+}
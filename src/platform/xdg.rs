@@ -0,0 +1,363 @@
+//! XDG autostart module.
+//!
+//! Manages autostart on Linux through an XDG autostart entry
+//! (`$XDG_CONFIG_HOME/autostart/langtip.desktop`) or a systemd user unit
+//! (`$XDG_CONFIG_HOME/systemd/user/langtip.service`) that restarts the
+//! program on failure. Files are opened with `xdg-open`.
+
+use crate::config::AutostartMethod;
+use crate::platform::{Autostart, Opener};
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const APP_ID: &str = "langtip";
+/// Target the unit is started with, so `DISPLAY`/`WAYLAND_DISPLAY` are set.
+const SESSION_TARGET: &str = "graphical-session.target";
+
+/// Returns `$XDG_CONFIG_HOME`, falling back to `~/.config`.
+///
+/// Relative values are invalid per the XDG spec and ignored.
+fn config_home(xdg_config_home: Option<OsString>, home: Option<OsString>) -> Option<PathBuf> {
+    xdg_config_home
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| home.map(|home| PathBuf::from(home).join(".config")))
+}
+
+/// Quotes a path for the `Exec=` key of a desktop entry or the `ExecStart=`
+/// line of a unit; both take double quotes and treat `%` as a specifier.
+fn quote_exec(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('%', "%%");
+    if !path
+        .chars()
+        .any(|c| c.is_whitespace() || "\"'\\`$;&|<>()*?#~=".contains(c))
+    {
+        return path;
+    }
+    let mut quoted = String::from("\"");
+    for c in path.chars() {
+        if matches!(c, '"' | '`' | '$' | '\\') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Contents of the XDG autostart entry.
+fn desktop_entry(exe: &Path) -> String {
+    format!(
+        "[Desktop Entry]\n\
+         Type=Application\n\
+         Name=LangTip\n\
+         Comment=Keyboard layout indicator\n\
+         Exec={}\n\
+         Terminal=false\n\
+         NoDisplay=true\n\
+         X-GNOME-Autostart-enabled=true\n",
+        quote_exec(exe)
+    )
+}
+
+/// Contents of the systemd user unit.
+fn service_unit(exe: &Path) -> String {
+    format!(
+        "[Unit]\n\
+         Description=LangTip keyboard layout indicator\n\
+         PartOf={target}\n\
+         After={target}\n\
+         \n\
+         [Service]\n\
+         ExecStart={exe}\n\
+         Restart=on-failure\n\
+         RestartSec=2\n\
+         \n\
+         [Install]\n\
+         WantedBy={target}\n",
+        target = SESSION_TARGET,
+        exe = quote_exec(exe)
+    )
+}
+
+/// Removes a file, treating a missing file as success.
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Autostart through an XDG autostart entry or a systemd user unit.
+pub struct XdgAutostart {
+    config_dir: Option<PathBuf>,
+    exe: Option<PathBuf>,
+    method: AutostartMethod,
+    /// Whether to run `systemctl --user daemon-reload` after changes.
+    reload_systemd: bool,
+}
+
+impl XdgAutostart {
+    /// Creates autostart for the running executable in the user's config
+    /// directory.
+    pub fn new(method: AutostartMethod) -> Self {
+        Self {
+            config_dir: config_home(
+                std::env::var_os("XDG_CONFIG_HOME"),
+                std::env::var_os("HOME"),
+            ),
+            exe: std::env::current_exe().ok(),
+            method,
+            reload_systemd: true,
+        }
+    }
+
+    /// Creates autostart for `exe` with config directory `config_dir`.
+    #[cfg(test)]
+    fn with_paths(config_dir: PathBuf, exe: PathBuf, method: AutostartMethod) -> Self {
+        Self {
+            config_dir: Some(config_dir),
+            exe: Some(exe),
+            method,
+            reload_systemd: false,
+        }
+    }
+
+    fn desktop_path(&self) -> Option<PathBuf> {
+        Some(
+            self.config_dir
+                .as_ref()?
+                .join("autostart")
+                .join(format!("{}.desktop", APP_ID)),
+        )
+    }
+
+    fn unit_dir(&self) -> Option<PathBuf> {
+        Some(self.config_dir.as_ref()?.join("systemd").join("user"))
+    }
+
+    fn unit_path(&self) -> Option<PathBuf> {
+        Some(self.unit_dir()?.join(format!("{}.service", APP_ID)))
+    }
+
+    /// Symlink `systemctl --user enable` would create for the unit.
+    fn wants_link(&self) -> Option<PathBuf> {
+        Some(
+            self.unit_dir()?
+                .join(format!("{}.wants", SESSION_TARGET))
+                .join(format!("{}.service", APP_ID)),
+        )
+    }
+
+    fn enable_desktop(&self, exe: &Path) -> io::Result<()> {
+        let path = self.desktop_path().ok_or(io::ErrorKind::NotFound)?;
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(path, desktop_entry(exe))
+    }
+
+    fn enable_systemd(&self, exe: &Path) -> io::Result<()> {
+        let unit = self.unit_path().ok_or(io::ErrorKind::NotFound)?;
+        let link = self.wants_link().ok_or(io::ErrorKind::NotFound)?;
+        fs::create_dir_all(link.parent().unwrap())?;
+        fs::write(&unit, service_unit(exe))?;
+        remove_if_exists(&link)?;
+        std::os::unix::fs::symlink(&unit, &link)?;
+        self.reload_systemd();
+        Ok(())
+    }
+
+    /// Asks the user's systemd to pick up unit changes; without a running
+    /// systemd the unit is read at the next login anyway.
+    fn reload_systemd(&self) {
+        if self.reload_systemd {
+            let _ = Command::new("systemctl")
+                .args(["--user", "daemon-reload"])
+                .status();
+        }
+    }
+
+    fn disable_all(&self) -> io::Result<()> {
+        let paths = [self.desktop_path(), self.wants_link(), self.unit_path()];
+        for path in paths.into_iter().flatten() {
+            remove_if_exists(&path)?;
+        }
+        self.reload_systemd();
+        Ok(())
+    }
+}
+
+impl Autostart for XdgAutostart {
+    /// Checks if autostart is enabled by either method.
+    fn is_enabled(&self) -> bool {
+        let desktop = self.desktop_path().is_some_and(|p| p.exists());
+        // `exists` follows the symlink, so a dangling link doesn't count
+        let systemd = self.wants_link().is_some_and(|p| p.exists());
+        desktop || systemd
+    }
+
+    /// Enables autostart with the configured method, removing the other one.
+    fn enable(&self) -> bool {
+        let Some(exe) = &self.exe else {
+            return false;
+        };
+        let result = self.disable_all().and_then(|_| match self.method {
+            AutostartMethod::Desktop => self.enable_desktop(exe),
+            AutostartMethod::Systemd => self.enable_systemd(exe),
+        });
+        if let Err(e) = &result {
+            log::warn!("Failed to enable autostart: {}", e);
+        }
+        result.is_ok()
+    }
+
+    /// Disables autostart by either method.
+    fn disable(&self) -> bool {
+        let result = self.disable_all();
+        if let Err(e) = &result {
+            log::warn!("Failed to disable autostart: {}", e);
+        }
+        result.is_ok()
+    }
+}
+
+/// Opens files with `xdg-open`.
+pub struct XdgOpener;
+
+impl Opener for XdgOpener {
+    fn open(&self, path: &Path) -> bool {
+        // xdg-open waits for some editors; don't block the main loop on it
+        match Command::new("xdg-open").arg(path).spawn() {
+            Ok(mut child) => {
+                std::thread::spawn(move || child.wait());
+                true
+            }
+            Err(e) => {
+                log::warn!("Cannot run xdg-open: {}", e);
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_config_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("langtip-autostart-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn config_home_honours_xdg_config_home() {
+        assert_eq!(
+            config_home(Some("/xdg".into()), Some("/home/u".into())),
+            Some(PathBuf::from("/xdg"))
+        );
+        assert_eq!(
+            config_home(None, Some("/home/u".into())),
+            Some(PathBuf::from("/home/u/.config"))
+        );
+        // Relative and empty values fall back to ~/.config
+        assert_eq!(
+            config_home(Some("cfg".into()), Some("/home/u".into())),
+            Some(PathBuf::from("/home/u/.config"))
+        );
+        assert_eq!(
+            config_home(Some("".into()), Some("/home/u".into())),
+            Some(PathBuf::from("/home/u/.config"))
+        );
+        assert_eq!(config_home(None, None), None);
+    }
+
+    #[test]
+    fn exec_paths_are_quoted() {
+        assert_eq!(
+            quote_exec(Path::new("/usr/bin/langtip")),
+            "/usr/bin/langtip"
+        );
+        assert_eq!(
+            quote_exec(Path::new("/opt/Lang Tip/langtip")),
+            "\"/opt/Lang Tip/langtip\""
+        );
+        assert_eq!(
+            quote_exec(Path::new("/home/u/$x/100%/langtip")),
+            "\"/home/u/\\$x/100%%/langtip\""
+        );
+    }
+
+    #[test]
+    fn desktop_entry_is_written_and_removed() {
+        let dir = temp_config_dir("desktop");
+        let autostart = XdgAutostart::with_paths(
+            dir.clone(),
+            PathBuf::from("/opt/Lang Tip/langtip"),
+            AutostartMethod::Desktop,
+        );
+        assert!(!autostart.is_enabled());
+
+        assert!(autostart.enable());
+        assert!(autostart.is_enabled());
+        let entry = fs::read_to_string(dir.join("autostart/langtip.desktop")).unwrap();
+        assert!(entry.starts_with("[Desktop Entry]\n"));
+        assert!(entry.contains("Type=Application\n"));
+        assert!(entry.contains("Exec=\"/opt/Lang Tip/langtip\"\n"));
+        assert!(!dir.join("systemd").exists());
+
+        assert!(autostart.disable());
+        assert!(!autostart.is_enabled());
+        assert!(!dir.join("autostart/langtip.desktop").exists());
+        // Disabling twice is fine
+        assert!(autostart.disable());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn systemd_unit_is_written_and_removed() {
+        let dir = temp_config_dir("systemd");
+        let autostart = XdgAutostart::with_paths(
+            dir.clone(),
+            PathBuf::from("/usr/bin/langtip"),
+            AutostartMethod::Systemd,
+        );
+
+        assert!(autostart.enable());
+        assert!(autostart.is_enabled());
+        let unit_path = dir.join("systemd/user/langtip.service");
+        let unit = fs::read_to_string(&unit_path).unwrap();
+        assert!(unit.contains("[Service]\nExecStart=/usr/bin/langtip\nRestart=on-failure\n"));
+        assert!(unit.contains("WantedBy=graphical-session.target\n"));
+        let link = dir.join("systemd/user/graphical-session.target.wants/langtip.service");
+        assert_eq!(fs::read_link(&link).unwrap(), unit_path);
+
+        assert!(autostart.disable());
+        assert!(!autostart.is_enabled());
+        assert!(!unit_path.exists());
+        assert!(fs::symlink_metadata(&link).is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn switching_method_replaces_the_other_one() {
+        let dir = temp_config_dir("switch");
+        let exe = PathBuf::from("/usr/bin/langtip");
+        let desktop = XdgAutostart::with_paths(dir.clone(), exe.clone(), AutostartMethod::Desktop);
+        let systemd = XdgAutostart::with_paths(dir.clone(), exe, AutostartMethod::Systemd);
+
+        assert!(desktop.enable());
+        assert!(systemd.is_enabled());
+        assert!(systemd.enable());
+        assert!(!dir.join("autostart/langtip.desktop").exists());
+        assert!(dir.join("systemd/user/langtip.service").exists());
+
+        // Either method turns off both
+        assert!(desktop.disable());
+        assert!(!systemd.is_enabled());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,233 @@
+//! Key remap module.
+//!
+//! Turns a single key into a layout switch key. A plain key such as Caps Lock
+//! is swallowed and runs its action on press; with Shift held it keeps its
+//! own function. A modifier such as Right Alt still works in combinations
+//! and runs its action when it is pressed and released alone. `KeyRemapper`
+//! is a pure state machine; the low-level keyboard hook asks it what to do
+//! with every key event.
+
+use crate::action::Action;
+use crate::config::KeyRemap;
+use crate::gesture::GestureKey;
+use crate::hotkey::{Modifier, Side};
+
+/// What to do with a key event.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Remapped {
+    /// Keep the event from other applications.
+    pub swallow: bool,
+    /// The event is the release of a lone remapped modifier: swallow it and
+    /// send it again after a masking key press, so that the lone modifier
+    /// doesn't open menus.
+    pub mask: bool,
+    /// Action to run.
+    pub action: Option<Action>,
+}
+
+/// A remap resolved to platform keys.
+struct Resolved<K> {
+    keys: Vec<K>,
+    /// A modifier acts when released alone rather than on press.
+    modifier: bool,
+    action: Action,
+}
+
+/// Decides which key events are remapped.
+pub struct KeyRemapper<K> {
+    remaps: Vec<Resolved<K>>,
+    /// Both Shift keys.
+    shift_keys: Vec<K>,
+    /// Keys held down.
+    held: Vec<K>,
+    /// Remapped keys pressed with Shift, passed through until released.
+    passing: Vec<K>,
+    /// Remapped modifier pressed with no other key since, and its action.
+    lone: Option<(K, Action)>,
+}
+
+impl<K: Copy + PartialEq> KeyRemapper<K> {
+    /// Creates a remapper for the remaps. `key` maps a physical key to the
+    /// platform's key; remaps with unmapped keys never apply, invalid ones
+    /// are skipped.
+    pub fn new(remaps: &[KeyRemap], key: impl Fn(GestureKey) -> Option<K>) -> Self {
+        let remaps = remaps
+            .iter()
+            .filter_map(|remap| remap.parse().ok())
+            .map(|(remap_key, action)| Resolved {
+                keys: remap_key.physical().into_iter().filter_map(&key).collect(),
+                modifier: matches!(remap_key, GestureKey::Modifier(..)),
+                action,
+            })
+            .collect();
+        let shift_keys = [Side::Left, Side::Right]
+            .into_iter()
+            .filter_map(|side| key(GestureKey::Modifier(Modifier::Shift, side)))
+            .collect();
+        Self {
+            remaps,
+            shift_keys,
+            held: Vec::new(),
+            passing: Vec::new(),
+            lone: None,
+        }
+    }
+
+    /// Returns whether there are no remaps.
+    pub fn is_empty(&self) -> bool {
+        self.remaps.is_empty()
+    }
+
+    /// Feeds a key press (`down`) or release.
+    pub fn handle(&mut self, key: K, down: bool) -> Remapped {
+        let repeat = down && self.held.contains(&key);
+        if down && !repeat {
+            self.held.push(key);
+        } else if !down {
+            self.held.retain(|k| *k != key);
+        }
+
+        // Any other key makes a held modifier part of a combination
+        if down && self.lone.as_ref().is_some_and(|(k, _)| *k != key) {
+            self.lone = None;
+        }
+
+        let Some(remap) = self.remaps.iter().find(|r| r.keys.contains(&key)) else {
+            return Remapped::default();
+        };
+
+        if remap.modifier {
+            if down && !repeat {
+                self.lone = Some((key, remap.action.clone()));
+            }
+            return match self.lone.take() {
+                Some((k, action)) if !down && k == key => Remapped {
+                    swallow: true,
+                    mask: true,
+                    action: Some(action),
+                },
+                lone => {
+                    self.lone = lone;
+                    Remapped::default()
+                }
+            };
+        }
+
+        if down && !repeat && self.shift_keys.iter().any(|k| self.held.contains(k)) {
+            self.passing.push(key);
+        }
+        if self.passing.contains(&key) {
+            if !down {
+                self.passing.retain(|k| *k != key);
+            }
+            return Remapped::default();
+        }
+        Remapped {
+            swallow: true,
+            mask: false,
+            action: (down && !repeat).then(|| remap.action.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn remapper(remaps: &[(&str, Action)]) -> KeyRemapper<GestureKey> {
+        let remaps: Vec<KeyRemap> = remaps
+            .iter()
+            .map(|(key, action)| KeyRemap {
+                key: key.to_string(),
+                action: action.to_string(),
+            })
+            .collect();
+        KeyRemapper::new(&remaps, Some)
+    }
+
+    fn key(name: &str) -> GestureKey {
+        let key: GestureKey = name.parse().unwrap();
+        key.physical()[0]
+    }
+
+    fn down(r: &mut KeyRemapper<GestureKey>, name: &str) -> Remapped {
+        r.handle(key(name), true)
+    }
+
+    fn up(r: &mut KeyRemapper<GestureKey>, name: &str) -> Remapped {
+        r.handle(key(name), false)
+    }
+
+    fn swallowed(action: Option<Action>) -> Remapped {
+        Remapped {
+            swallow: true,
+            mask: false,
+            action,
+        }
+    }
+
+    #[test]
+    fn caps_lock_switches_unless_shifted() {
+        let mut r = remapper(&[("capslock", Action::CycleLayout)]);
+        assert!(!r.is_empty());
+        assert_eq!(
+            down(&mut r, "capslock"),
+            swallowed(Some(Action::CycleLayout))
+        );
+        // Auto-repeat doesn't switch again
+        assert_eq!(down(&mut r, "capslock"), swallowed(None));
+        assert_eq!(up(&mut r, "capslock"), swallowed(None));
+
+        // Shift+CapsLock keeps the real Caps Lock, even if Shift goes first
+        assert_eq!(down(&mut r, "lshift"), Remapped::default());
+        assert_eq!(down(&mut r, "capslock"), Remapped::default());
+        assert_eq!(up(&mut r, "lshift"), Remapped::default());
+        assert_eq!(up(&mut r, "capslock"), Remapped::default());
+        assert_eq!(
+            down(&mut r, "capslock"),
+            swallowed(Some(Action::CycleLayout))
+        );
+        assert_eq!(down(&mut r, "rshift"), Remapped::default());
+        assert_eq!(up(&mut r, "capslock"), swallowed(None));
+
+        assert_eq!(down(&mut r, "a"), Remapped::default());
+    }
+
+    #[test]
+    fn lone_modifiers_switch_on_release() {
+        let set_ru = Action::SetLayout("ru".to_string());
+        let mut r = remapper(&[("ralt", set_ru.clone()), ("rctrl", Action::CycleLayout)]);
+        assert_eq!(down(&mut r, "ralt"), Remapped::default());
+        assert_eq!(down(&mut r, "ralt"), Remapped::default());
+        assert_eq!(
+            up(&mut r, "ralt"),
+            Remapped {
+                swallow: true,
+                mask: true,
+                action: Some(set_ru),
+            }
+        );
+
+        // AltGr+key and Ctrl+C stay combinations
+        assert_eq!(down(&mut r, "ralt"), Remapped::default());
+        assert_eq!(down(&mut r, "e"), Remapped::default());
+        assert_eq!(up(&mut r, "e"), Remapped::default());
+        assert_eq!(up(&mut r, "ralt"), Remapped::default());
+        assert_eq!(down(&mut r, "rctrl"), Remapped::default());
+        assert_eq!(down(&mut r, "c"), Remapped::default());
+        assert_eq!(up(&mut r, "rctrl"), Remapped::default());
+
+        // The left key isn't remapped
+        assert_eq!(down(&mut r, "lalt"), Remapped::default());
+        assert_eq!(up(&mut r, "lalt"), Remapped::default());
+        assert_eq!(up(&mut r, "rctrl").action, None);
+    }
+
+    #[test]
+    fn no_remaps_pass_everything() {
+        let mut r = remapper(&[]);
+        assert!(r.is_empty());
+        assert_eq!(down(&mut r, "capslock"), Remapped::default());
+        assert_eq!(up(&mut r, "capslock"), Remapped::default());
+    }
+}
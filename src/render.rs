@@ -0,0 +1,535 @@
+//! Rendering module.
+//!
+//! Platform-independent rasterisation of indicator text into premultiplied
+//! ARGB pixel buffers, for backends that have no text drawing of their own.
+//! Text uses a built-in 5x7 font (upper-case Latin letters, digits and a few
+//! symbols), scaled to the requested height with 4x4 supersampling.
+//! `layout_icon` draws the tray icon for a layout.
+
+/// Glyph grid size of the built-in font.
+const GLYPH_WIDTH: usize = 5;
+const GLYPH_HEIGHT: usize = 7;
+
+/// Samples per pixel along each axis.
+const SUPERSAMPLE: usize = 4;
+
+/// Glyph shown for characters the font doesn't have.
+const UNKNOWN_GLYPH: [u8; GLYPH_HEIGHT] = [
+    0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b00000, 0b00100,
+];
+
+/// Returns the rows of a glyph (bit 4 = leftmost column).
+fn glyph(c: char) -> [u8; GLYPH_HEIGHT] {
+    match c.to_ascii_uppercase() {
+        'A' => [
+            0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001,
+        ],
+        'B' => [
+            0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110,
+        ],
+        'C' => [
+            0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110,
+        ],
+        'D' => [
+            0b11110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b11110,
+        ],
+        'E' => [
+            0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111,
+        ],
+        'F' => [
+            0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000,
+        ],
+        'G' => [
+            0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111,
+        ],
+        'H' => [
+            0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001,
+        ],
+        'I' => [
+            0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110,
+        ],
+        'J' => [
+            0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100,
+        ],
+        'K' => [
+            0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001,
+        ],
+        'L' => [
+            0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111,
+        ],
+        'M' => [
+            0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001,
+        ],
+        'N' => [
+            0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001,
+        ],
+        'O' => [
+            0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110,
+        ],
+        'P' => [
+            0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000,
+        ],
+        'Q' => [
+            0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101,
+        ],
+        'R' => [
+            0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001,
+        ],
+        'S' => [
+            0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110,
+        ],
+        'T' => [
+            0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100,
+        ],
+        'U' => [
+            0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110,
+        ],
+        'V' => [
+            0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100,
+        ],
+        'W' => [
+            0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010,
+        ],
+        'X' => [
+            0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001,
+        ],
+        'Y' => [
+            0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100, 0b00100,
+        ],
+        'Z' => [
+            0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111,
+        ],
+        '0' => [
+            0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110,
+        ],
+        '1' => [
+            0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110,
+        ],
+        '2' => [
+            0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111,
+        ],
+        '3' => [
+            0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110,
+        ],
+        '4' => [
+            0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010,
+        ],
+        '5' => [
+            0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110,
+        ],
+        '6' => [
+            0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110,
+        ],
+        '7' => [
+            0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000,
+        ],
+        '8' => [
+            0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110,
+        ],
+        '9' => [
+            0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100,
+        ],
+        '-' => [
+            0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000,
+        ],
+        ' ' => [0; GLYPH_HEIGHT],
+        _ => UNKNOWN_GLYPH,
+    }
+}
+
+/// Premultiplies an RGB colour with `alpha` into an ARGB pixel value.
+pub fn premultiply((r, g, b): (u8, u8, u8), alpha: u8) -> u32 {
+    let scale = |c: u8| (c as u32 * alpha as u32 + 127) / 255;
+    ((alpha as u32) << 24) | (scale(r) << 16) | (scale(g) << 8) | scale(b)
+}
+
+/// Blends premultiplied `src` over premultiplied `dst`.
+fn blend_over(dst: u32, src: u32) -> u32 {
+    let inv = 255 - (src >> 24);
+    let channel = |shift: u32| {
+        let s = (src >> shift) & 0xFF;
+        let d = (dst >> shift) & 0xFF;
+        (s + (d * inv + 127) / 255).min(255) << shift
+    };
+    channel(24) | channel(16) | channel(8) | channel(0)
+}
+
+/// Returns straight-alpha `[a, r, g, b]` of a premultiplied pixel.
+fn unpremultiply(p: u32) -> [u8; 4] {
+    let alpha = p >> 24;
+    let channel = |shift: u32| match alpha {
+        0 => 0,
+        _ => ((((p >> shift) & 0xFF) * 255 + alpha / 2) / alpha).min(255) as u8,
+    };
+    [alpha as u8, channel(16), channel(8), channel(0)]
+}
+
+/// Width of `text` drawn at `height` pixels, including one glyph column of
+/// spacing between characters.
+pub fn text_width(text: &str, height: f32) -> f32 {
+    let count = text.chars().count();
+    if count == 0 {
+        return 0.0;
+    }
+    let cell = height / GLYPH_HEIGHT as f32;
+    (count * GLYPH_WIDTH + (count - 1)) as f32 * cell
+}
+
+/// Premultiplied ARGB pixel buffer (`0xAARRGGBB`, row-major).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Canvas {
+    width: u32,
+    height: u32,
+    pixels: Vec<u32>,
+}
+
+impl Canvas {
+    /// Creates a fully transparent canvas.
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![0; (width * height) as usize],
+        }
+    }
+
+    /// Canvas width in pixels.
+    #[cfg(any(target_os = "linux", test))]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Canvas height in pixels.
+    #[cfg(any(target_os = "linux", test))]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns the pixel at (`x`, `y`).
+    #[cfg(test)]
+    pub fn pixel(&self, x: u32, y: u32) -> u32 {
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    /// Returns all pixels, row by row.
+    #[cfg(test)]
+    pub fn pixels(&self) -> &[u32] {
+        &self.pixels
+    }
+
+    /// Returns the pixels as little-endian bytes (`ARGB8888` in memory order).
+    #[cfg(any(target_os = "linux", test))]
+    pub fn to_le_bytes(&self) -> Vec<u8> {
+        self.pixels.iter().flat_map(|p| p.to_le_bytes()).collect()
+    }
+
+    /// Returns the pixels as straight-alpha ARGB bytes in network byte order,
+    /// as StatusNotifierItem icons expect.
+    #[cfg(any(target_os = "linux", test))]
+    pub fn to_argb_be_bytes(&self) -> Vec<u8> {
+        self.pixels.iter().flat_map(|&p| unpremultiply(p)).collect()
+    }
+
+    /// Returns the pixels as straight-alpha RGBA bytes, as Windows tray icons
+    /// expect.
+    #[cfg(any(windows, test))]
+    pub fn to_rgba_bytes(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|&p| {
+                let [a, r, g, b] = unpremultiply(p);
+                [r, g, b, a]
+            })
+            .collect()
+    }
+
+    /// Fills the whole canvas with a colour.
+    #[cfg(test)]
+    pub fn fill(&mut self, color: (u8, u8, u8), alpha: u8) {
+        self.pixels.fill(premultiply(color, alpha));
+    }
+
+    /// Fills a rectangle with corners rounded to `radius`, antialiased.
+    pub fn fill_rounded_rect(
+        &mut self,
+        (x, y, width, height): (f32, f32, f32, f32),
+        radius: f32,
+        color: (u8, u8, u8),
+        alpha: u8,
+    ) {
+        let radius = radius.min(width / 2.0).min(height / 2.0).max(0.0);
+        let inside = |fx: f32, fy: f32| {
+            let cx = fx.clamp(x + radius, x + width - radius);
+            let cy = fy.clamp(y + radius, y + height - radius);
+            fx >= x
+                && fx < x + width
+                && fy >= y
+                && fy < y + height
+                && (fx - cx).powi(2) + (fy - cy).powi(2) <= radius * radius
+        };
+        let left = x.floor().max(0.0) as u32;
+        let top = y.floor().max(0.0) as u32;
+        let right = ((x + width).ceil().max(0.0) as u32).min(self.width);
+        let bottom = ((y + height).ceil().max(0.0) as u32).min(self.height);
+        let samples = (SUPERSAMPLE * SUPERSAMPLE) as u32;
+
+        for py in top..bottom {
+            for px in left..right {
+                let mut hits = 0;
+                for sy in 0..SUPERSAMPLE {
+                    for sx in 0..SUPERSAMPLE {
+                        let fx = px as f32 + (sx as f32 + 0.5) / SUPERSAMPLE as f32;
+                        let fy = py as f32 + (sy as f32 + 0.5) / SUPERSAMPLE as f32;
+                        if inside(fx, fy) {
+                            hits += 1;
+                        }
+                    }
+                }
+                if hits > 0 {
+                    let coverage = (alpha as u32 * hits / samples) as u8;
+                    let index = (py * self.width + px) as usize;
+                    self.pixels[index] =
+                        blend_over(self.pixels[index], premultiply(color, coverage));
+                }
+            }
+        }
+    }
+
+    /// Draws `text` with its top-left corner at (`x`, `y`), `height` pixels tall.
+    pub fn draw_text(
+        &mut self,
+        text: &str,
+        x: f32,
+        y: f32,
+        height: f32,
+        color: (u8, u8, u8),
+        alpha: u8,
+    ) {
+        let cell = height / GLYPH_HEIGHT as f32;
+        if cell <= 0.0 {
+            return;
+        }
+        let mut origin = x;
+        for c in text.chars() {
+            self.draw_glyph(glyph(c), origin, y, cell, color, alpha);
+            origin += (GLYPH_WIDTH + 1) as f32 * cell;
+        }
+    }
+
+    /// Draws one glyph scaled so that each font cell is `cell` pixels wide.
+    fn draw_glyph(
+        &mut self,
+        rows: [u8; GLYPH_HEIGHT],
+        x: f32,
+        y: f32,
+        cell: f32,
+        color: (u8, u8, u8),
+        alpha: u8,
+    ) {
+        let left = x.floor().max(0.0) as u32;
+        let top = y.floor().max(0.0) as u32;
+        let right = ((x + GLYPH_WIDTH as f32 * cell).ceil().max(0.0) as u32).min(self.width);
+        let bottom = ((y + GLYPH_HEIGHT as f32 * cell).ceil().max(0.0) as u32).min(self.height);
+        let samples = (SUPERSAMPLE * SUPERSAMPLE) as u32;
+
+        for py in top..bottom {
+            for px in left..right {
+                let mut hits = 0;
+                for sy in 0..SUPERSAMPLE {
+                    for sx in 0..SUPERSAMPLE {
+                        let fx = (px as f32 + (sx as f32 + 0.5) / SUPERSAMPLE as f32 - x) / cell;
+                        let fy = (py as f32 + (sy as f32 + 0.5) / SUPERSAMPLE as f32 - y) / cell;
+                        if fx < 0.0 || fy < 0.0 {
+                            continue;
+                        }
+                        let (col, row) = (fx as usize, fy as usize);
+                        if col < GLYPH_WIDTH
+                            && row < GLYPH_HEIGHT
+                            && rows[row] & (1 << (GLYPH_WIDTH - 1 - col)) != 0
+                        {
+                            hits += 1;
+                        }
+                    }
+                }
+                if hits > 0 {
+                    let coverage = (alpha as u32 * hits / samples) as u8;
+                    let index = (py * self.width + px) as usize;
+                    self.pixels[index] =
+                        blend_over(self.pixels[index], premultiply(color, coverage));
+                }
+            }
+        }
+    }
+}
+
+/// Taskbar theme a tray icon is drawn for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IconTheme {
+    /// Light taskbar: the tile gets a dark outline.
+    Light,
+    /// Dark taskbar: the tile gets a light outline.
+    Dark,
+}
+
+/// Renders a `size`×`size` tray icon: `label` over a rounded tile of the
+/// layout `color`, outlined to stand out on the taskbar. The label is black
+/// or white, whichever reads better on the colour, and shrinks to fit.
+pub fn layout_icon(label: &str, color: (u8, u8, u8), size: u32, theme: IconTheme) -> Canvas {
+    let mut canvas = Canvas::new(size, size);
+    let extent = size as f32;
+    let border = (extent / 16.0).round().max(1.0);
+    let radius = extent / 5.0;
+    let outline = match theme {
+        IconTheme::Light => (0x20, 0x20, 0x20),
+        IconTheme::Dark => (0xF0, 0xF0, 0xF0),
+    };
+    canvas.fill_rounded_rect((0.0, 0.0, extent, extent), radius, outline, 255);
+    canvas.fill_rounded_rect(
+        (border, border, extent - 2.0 * border, extent - 2.0 * border),
+        radius - border,
+        color,
+        255,
+    );
+
+    let (r, g, b) = color;
+    let luminance = (299 * r as u32 + 587 * g as u32 + 114 * b as u32) / 1000;
+    let text_color = if luminance >= 140 {
+        (0, 0, 0)
+    } else {
+        (0xFF, 0xFF, 0xFF)
+    };
+    let room = extent - 2.0 * (border + 1.0);
+    let mut height = extent * 0.5;
+    let width = text_width(label, height);
+    if width > room {
+        height *= room / width;
+    }
+    let width = text_width(label, height);
+    canvas.draw_text(
+        label,
+        ((extent - width) / 2.0).round(),
+        ((extent - height) / 2.0).round(),
+        height,
+        text_color,
+        255,
+    );
+    canvas
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GREEN: (u8, u8, u8) = (0x55, 0xFF, 0x55);
+
+    #[test]
+    fn premultiply_scales_channels_by_alpha() {
+        assert_eq!(premultiply(GREEN, 255), 0xFF55FF55);
+        assert_eq!(premultiply(GREEN, 0), 0);
+        assert_eq!(premultiply((0xFF, 0xFF, 0xFF), 128), 0x80808080);
+    }
+
+    #[test]
+    fn text_at_glyph_grid_size_matches_font_bits() {
+        let mut canvas = Canvas::new(11, 7);
+        canvas.draw_text("EN", 0.0, 0.0, 7.0, GREEN, 255);
+
+        // Top row of "E" is solid, of "N" only the outer columns
+        let row: Vec<bool> = (0..11).map(|x| canvas.pixel(x, 0) != 0).collect();
+        let expected = [
+            true, true, true, true, true, false, true, false, false, false, true,
+        ];
+        assert_eq!(row, expected);
+        assert_eq!(canvas.pixel(0, 0), 0xFF55FF55);
+    }
+
+    #[test]
+    fn scaled_text_has_partial_coverage_only_at_edges() {
+        let mut canvas = Canvas::new(40, 20);
+        canvas.draw_text("I", 3.5, 0.0, 17.5, (255, 255, 255), 255);
+
+        // Stem of "I" is fully covered, edges are antialiased
+        assert_eq!(canvas.pixel(3 + 6, 8) >> 24, 255);
+        let edge = canvas.pixel(3 + 5, 8) >> 24;
+        assert!(edge > 0 && edge < 255, "edge alpha {}", edge);
+        assert_eq!(canvas.pixel(0, 8), 0);
+    }
+
+    #[test]
+    fn text_width_includes_spacing() {
+        assert_eq!(text_width("", 14.0), 0.0);
+        assert_eq!(text_width("E", 14.0), 10.0);
+        assert_eq!(text_width("EN", 14.0), 22.0);
+    }
+
+    #[test]
+    fn text_is_blended_over_background() {
+        let mut canvas = Canvas::new(5, 7);
+        canvas.fill((0, 0, 0), 255);
+        canvas.draw_text("-", 0.0, 0.0, 7.0, (255, 255, 255), 128);
+        assert_eq!(canvas.pixel(0, 3), 0xFF808080);
+        assert_eq!(canvas.pixel(0, 0), 0xFF000000);
+    }
+
+    #[test]
+    fn drawing_outside_the_canvas_is_clipped() {
+        let mut canvas = Canvas::new(4, 4);
+        canvas.draw_text("WW", -3.0, -2.0, 14.0, GREEN, 255);
+        canvas.draw_text("W", 10.0, 10.0, 14.0, GREEN, 255);
+        assert_eq!(canvas.to_le_bytes().len(), 64);
+    }
+
+    #[test]
+    fn layout_icon_draws_label_over_layout_color() {
+        for size in [16, 20, 24, 32] {
+            let icon = layout_icon("EN", GREEN, size, IconTheme::Dark);
+            assert_eq!((icon.width(), icon.height()), (size, size));
+            // Rounded corners are transparent, the tile edge is the layout colour
+            assert_eq!(icon.pixel(0, 0), 0);
+            let edge = size / 8;
+            assert_eq!(icon.pixel(size / 2, edge), 0xFF55FF55);
+            assert_eq!(icon.pixel(size / 2, size - 1 - edge), 0xFF55FF55);
+            // The label on light green is black
+            assert!(icon.pixels().contains(&0xFF000000));
+        }
+
+        // Dark colours get a white label
+        let icon = layout_icon("RU", (0x20, 0x20, 0x80), 16, IconTheme::Dark);
+        assert!(icon.pixels().contains(&0xFFFFFFFF));
+        assert!(!icon.pixels().contains(&0xFF000000));
+    }
+
+    #[test]
+    fn layout_icon_outline_follows_the_taskbar_theme() {
+        let light = layout_icon("EN", GREEN, 32, IconTheme::Light);
+        let dark = layout_icon("EN", GREEN, 32, IconTheme::Dark);
+        assert_eq!(light.pixel(0, 16), 0xFF202020);
+        assert_eq!(dark.pixel(0, 16), 0xFFF0F0F0);
+        assert_eq!(light.pixel(2, 16), dark.pixel(2, 16));
+
+        // Long labels shrink to fit
+        let long = layout_icon("ENGB", GREEN, 16, IconTheme::Light);
+        assert_eq!(long.pixel(1, 8), 0xFF55FF55);
+        assert_eq!(long.pixel(14, 8), 0xFF55FF55);
+    }
+
+    #[test]
+    fn rgba_bytes_are_straight_alpha() {
+        let mut canvas = Canvas::new(2, 1);
+        canvas.pixels[0] = premultiply((200, 100, 50), 255);
+        assert_eq!(canvas.to_rgba_bytes(), vec![200, 100, 50, 255, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn straight_alpha_bytes_undo_premultiplication() {
+        let mut canvas = Canvas::new(2, 1);
+        assert_eq!(canvas.to_argb_be_bytes(), vec![0; 8]);
+
+        canvas.pixels[0] = premultiply((200, 100, 50), 128);
+        canvas.pixels[1] = premultiply((255, 255, 255), 255);
+        let bytes = canvas.to_argb_be_bytes();
+        assert_eq!(bytes[0], 128);
+        assert!(bytes[1].abs_diff(200) <= 1 && bytes[2].abs_diff(100) <= 1);
+        assert_eq!(&bytes[4..], &[255, 255, 255, 255]);
+    }
+}
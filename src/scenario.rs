@@ -0,0 +1,395 @@
+//! Scenario trace module.
+//!
+//! Text format for controller event traces. A trace can be recorded from a
+//! running instance (set `LANGTIP_TRACE` to a file path) and replayed against
+//! `IndicatorController` in `cargo test` on any platform.
+//!
+//! Format, one statement per line (`#` starts a comment):
+//!
+//! ```text
+//! initial EN            # initial layout (before `start`)
+//! hide_delay 5000       # auto-hide delay in ms (default 5000)
+//! start                 # controller startup
+//! expect text EN; show  # commands produced by the previous statement
+//! current RU            # layout read when the controller asks for a refresh
+//! 120 layout RU         # <ms since start> <input>
+//! expect text RU; sound RU; show
+//! 5120 tick
+//! expect hide
+//! ```
+//!
+//! Inputs: `layout <L>`, `toggle`, `visible on|off`, `peek`, `hold on|off`,
+//! `snooze <ms>`, `tick`, `reload <hide_delay_ms> <L>`, `display <L>`,
+//! `exit`.
+//!
+//! Commands: `recreate`, `text <L>`, `show`, `hide`, `sound <L>`, `refresh`,
+//! `exit`; `expect none` checks that nothing was produced. Every `start` and
+//! input must be followed by its `expect`. Layouts are written by name, `RU`
+//! being the Russian layout.
+
+use crate::controller::{Command, Input};
+use crate::layout::LayoutInfo;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+/// Environment variable with the path of the trace file to record.
+pub const TRACE_ENV: &str = "LANGTIP_TRACE";
+
+impl fmt::Display for Input {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Input::Layout(layout) => write!(f, "layout {}", layout.name),
+            Input::Toggle => write!(f, "toggle"),
+            Input::SetVisible(visible) => {
+                write!(f, "visible {}", if *visible { "on" } else { "off" })
+            }
+            Input::Peek => write!(f, "peek"),
+            Input::PeekHeld(held) => write!(f, "hold {}", if *held { "on" } else { "off" }),
+            Input::Snooze(duration) => write!(f, "snooze {}", duration.as_millis()),
+            Input::Tick => write!(f, "tick"),
+            Input::ConfigReloaded { hide_delay, layout } => {
+                write!(f, "reload {} {}", hide_delay.as_millis(), layout.name)
+            }
+            Input::DisplayChanged { layout } => write!(f, "display {}", layout.name),
+            Input::Exit => write!(f, "exit"),
+        }
+    }
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Command::RecreateIndicators => write!(f, "recreate"),
+            Command::UpdateText(layout) => write!(f, "text {}", layout.name),
+            Command::Show => write!(f, "show"),
+            Command::Hide => write!(f, "hide"),
+            Command::PlaySound(layout) => write!(f, "sound {}", layout.name),
+            Command::RefreshLayout => write!(f, "refresh"),
+            Command::Exit => write!(f, "exit"),
+        }
+    }
+}
+
+/// Formats the `expect` statement for a list of commands.
+fn format_expect(commands: &[Command]) -> String {
+    if commands.is_empty() {
+        return "expect none".to_string();
+    }
+    let list: Vec<String> = commands.iter().map(|c| c.to_string()).collect();
+    format!("expect {}", list.join("; "))
+}
+
+/// Writes a controller trace in the scenario format.
+pub struct TraceRecorder {
+    out: BufWriter<File>,
+}
+
+impl TraceRecorder {
+    /// Creates a recorder from `LANGTIP_TRACE`, if set.
+    pub fn from_env(initial: &LayoutInfo, hide_delay: Duration) -> Option<Self> {
+        let path = std::env::var_os(TRACE_ENV)?;
+        match Self::create(Path::new(&path), initial, hide_delay) {
+            Ok(recorder) => {
+                log::info!("Recording controller trace to {:?}", path);
+                Some(recorder)
+            }
+            Err(e) => {
+                log::warn!("Failed to create trace file {:?}: {}", path, e);
+                None
+            }
+        }
+    }
+
+    /// Creates a trace file and writes its header.
+    pub fn create(path: &Path, initial: &LayoutInfo, hide_delay: Duration) -> io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "# LangTip controller trace v{}", env!("CARGO_PKG_VERSION"))?;
+        writeln!(out, "initial {}", initial.name)?;
+        writeln!(out, "hide_delay {}", hide_delay.as_millis())?;
+        Ok(Self { out })
+    }
+
+    /// Records controller startup and its commands.
+    pub fn record_start(&mut self, commands: &[Command]) {
+        let result = writeln!(self.out, "start")
+            .and_then(|_| writeln!(self.out, "{}", format_expect(commands)));
+        self.check(result);
+    }
+
+    /// Records an input handled at `at` after startup.
+    ///
+    /// `probes` are the layouts read while carrying out `RefreshLayout`
+    /// commands; `commands` includes the commands of those follow-ups.
+    pub fn record(
+        &mut self,
+        at: Duration,
+        input: &Input,
+        probes: &[LayoutInfo],
+        commands: &[Command],
+    ) {
+        // Idle ticks change nothing later inputs see (an expired snooze is
+        // over whether or not a tick cleared it); skip them to keep traces small
+        if *input == Input::Tick && commands.is_empty() {
+            return;
+        }
+
+        let mut result = Ok(());
+        for layout in probes {
+            result = result.and_then(|_| writeln!(self.out, "current {}", layout.name));
+        }
+        result = result
+            .and_then(|_| writeln!(self.out, "{} {}", at.as_millis(), input))
+            .and_then(|_| writeln!(self.out, "{}", format_expect(commands)))
+            .and_then(|_| self.out.flush());
+        self.check(result);
+    }
+
+    fn check(&self, result: io::Result<()>) {
+        if let Err(e) = result {
+            log::warn!("Failed to write controller trace: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod replay {
+    //! Scenario parser and replayer.
+
+    use super::*;
+    use crate::controller::{IndicatorController, ManualClock};
+
+    fn layout(name: &str) -> LayoutInfo {
+        LayoutInfo {
+            name: name.to_string(),
+            is_russian: name == "RU",
+        }
+    }
+
+    fn arg<'a>(args: &[&'a str], index: usize, line: usize) -> Result<&'a str, String> {
+        args.get(index)
+            .copied()
+            .ok_or_else(|| format!("line {}: missing argument", line))
+    }
+
+    fn parse_input(args: &[&str], line: usize) -> Result<Input, String> {
+        let input = match args.first().copied() {
+            Some("layout") => Input::Layout(layout(arg(args, 1, line)?)),
+            Some("toggle") => Input::Toggle,
+            Some("visible") => match arg(args, 1, line)? {
+                "on" => Input::SetVisible(true),
+                "off" => Input::SetVisible(false),
+                other => return Err(format!("line {}: bad visibility '{}'", line, other)),
+            },
+            Some("peek") => Input::Peek,
+            Some("hold") => match arg(args, 1, line)? {
+                "on" => Input::PeekHeld(true),
+                "off" => Input::PeekHeld(false),
+                other => return Err(format!("line {}: bad hold state '{}'", line, other)),
+            },
+            Some("snooze") => {
+                Input::Snooze(Duration::from_millis(parse_ms(arg(args, 1, line)?, line)?))
+            }
+            Some("tick") => Input::Tick,
+            Some("reload") => Input::ConfigReloaded {
+                hide_delay: Duration::from_millis(parse_ms(arg(args, 1, line)?, line)?),
+                layout: layout(arg(args, 2, line)?),
+            },
+            Some("display") => Input::DisplayChanged {
+                layout: layout(arg(args, 1, line)?),
+            },
+            Some("exit") => Input::Exit,
+            other => return Err(format!("line {}: unknown input {:?}", line, other)),
+        };
+        Ok(input)
+    }
+
+    fn parse_command(text: &str, line: usize) -> Result<Command, String> {
+        let args: Vec<&str> = text.split_whitespace().collect();
+        let command = match args.first().copied() {
+            Some("recreate") => Command::RecreateIndicators,
+            Some("text") => Command::UpdateText(layout(arg(&args, 1, line)?)),
+            Some("show") => Command::Show,
+            Some("hide") => Command::Hide,
+            Some("sound") => Command::PlaySound(layout(arg(&args, 1, line)?)),
+            Some("refresh") => Command::RefreshLayout,
+            Some("exit") => Command::Exit,
+            other => return Err(format!("line {}: unknown command {:?}", line, other)),
+        };
+        Ok(command)
+    }
+
+    fn parse_expect(rest: &str, line: usize) -> Result<Vec<Command>, String> {
+        if rest.trim() == "none" {
+            return Ok(Vec::new());
+        }
+        rest.split(';')
+            .map(|c| parse_command(c.trim(), line))
+            .collect()
+    }
+
+    fn parse_ms(text: &str, line: usize) -> Result<u64, String> {
+        text.parse()
+            .map_err(|_| format!("line {}: bad number '{}'", line, text))
+    }
+
+    /// Fails if the commands of the last statement weren't checked.
+    fn check_expected(last: &Option<(usize, Vec<Command>)>) -> Result<(), String> {
+        match last {
+            Some((line, commands)) => Err(format!(
+                "line {}: no `expect` follows, got `{}`",
+                line,
+                format_expect(commands)
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Replays a scenario, checking every `expect` statement. Commands left
+    /// without an `expect` (even none) fail the scenario.
+    pub fn run(source: &str) -> Result<(), String> {
+        let clock = ManualClock::new();
+        let mut initial = layout("EN");
+        let mut hide_delay = Duration::from_millis(5000);
+        let mut current: Option<LayoutInfo> = None;
+        let mut controller: Option<IndicatorController<ManualClock>> = None;
+        // Commands of the last statement and its line, until checked
+        let mut last: Option<(usize, Vec<Command>)> = None;
+        let mut last_at = 0;
+
+        for (index, raw) in source.lines().enumerate() {
+            let line = index + 1;
+            let text = raw.split('#').next().unwrap_or("").trim();
+            if text.is_empty() {
+                continue;
+            }
+            let args: Vec<&str> = text.split_whitespace().collect();
+
+            match args[0] {
+                "initial" => initial = layout(arg(&args, 1, line)?),
+                "hide_delay" => {
+                    hide_delay = Duration::from_millis(parse_ms(arg(&args, 1, line)?, line)?)
+                }
+                "current" => current = Some(layout(arg(&args, 1, line)?)),
+                "start" => {
+                    check_expected(&last)?;
+                    let mut c = IndicatorController::new(clock.clone(), initial.clone(), hide_delay);
+                    last = Some((line, c.start()));
+                    controller = Some(c);
+                }
+                "expect" => {
+                    let expected = parse_expect(text["expect".len()..].trim(), line)?;
+                    let (_, actual) = last
+                        .take()
+                        .ok_or_else(|| format!("line {}: nothing to check", line))?;
+                    if actual != expected {
+                        return Err(format!(
+                            "line {}: expected `{}`, got `{}`",
+                            line,
+                            format_expect(&expected),
+                            format_expect(&actual)
+                        ));
+                    }
+                }
+                time => {
+                    check_expected(&last)?;
+                    let at = parse_ms(time, line)?;
+                    if at < last_at {
+                        return Err(format!("line {}: time goes backwards", line));
+                    }
+                    last_at = at;
+                    clock.set(Duration::from_millis(at));
+
+                    let c = controller
+                        .as_mut()
+                        .ok_or_else(|| format!("line {}: input before start", line))?;
+                    let input = parse_input(&args[1..], line)?;
+
+                    // Carry out refreshes like the platform layer does
+                    let mut commands = Vec::new();
+                    let mut queue = c.handle(input);
+                    while !queue.is_empty() {
+                        let command = queue.remove(0);
+                        if command == Command::RefreshLayout {
+                            let layout = current
+                                .clone()
+                                .ok_or_else(|| format!("line {}: refresh without current", line))?;
+                            queue.extend(c.handle(Input::Layout(layout)));
+                        }
+                        commands.push(command);
+                    }
+                    last = Some((line, commands));
+                }
+            }
+        }
+
+        check_expected(&last)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn scenarios_dir() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/scenarios")
+    }
+
+    #[test]
+    fn recorded_scenarios_replay() {
+        let mut count = 0;
+        for entry in std::fs::read_dir(scenarios_dir()).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|e| e == "scenario") {
+                let source = std::fs::read_to_string(&path).unwrap();
+                if let Err(e) = replay::run(&source) {
+                    panic!("{}: {}", path.display(), e);
+                }
+                count += 1;
+            }
+        }
+        assert!(count > 0, "no scenarios found");
+    }
+
+    #[test]
+    fn mismatched_expectation_is_reported() {
+        let source = "initial EN\nstart\nexpect text EN; show\n10 layout RU\nexpect hide\n";
+        let err = replay::run(source).unwrap_err();
+        assert!(err.starts_with("line 5:"), "{}", err);
+    }
+
+    #[test]
+    fn unchecked_commands_are_reported() {
+        // The show of line 4 is overwritten by the next input
+        let source = "initial EN\nstart\nexpect text EN; show\n10 layout RU\n20 tick\n";
+        let err = replay::run(source).unwrap_err();
+        assert!(err.starts_with("line 4:"), "{}", err);
+
+        // Nothing produced still needs `expect none`, also at the end
+        let source = "initial EN\nstart\nexpect text EN; show\n10 tick\n";
+        let err = replay::run(source).unwrap_err();
+        assert_eq!(err, "line 4: no `expect` follows, got `expect none`");
+        assert!(replay::run(&format!("{}expect none\n", source)).is_ok());
+    }
+
+    #[test]
+    fn input_and_command_round_trip_through_text() {
+        let en = LayoutInfo {
+            name: "EN".to_string(),
+            is_russian: false,
+        };
+        let input = Input::ConfigReloaded {
+            hide_delay: Duration::from_millis(3000),
+            layout: en.clone(),
+        };
+        assert_eq!(input.to_string(), "reload 3000 EN");
+        assert_eq!(
+            format_expect(&[Command::RecreateIndicators, Command::UpdateText(en)]),
+            "expect recreate; text EN"
+        );
+        assert_eq!(format_expect(&[]), "expect none");
+    }
+}
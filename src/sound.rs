@@ -1,17 +1,18 @@
 //! Sound module for layout change notifications.
 //!
-//! Uses Windows Beep API to play short sounds with different frequencies
-//! for different layouts.
+//! Plays short sounds with different frequencies for different layouts
+//! through the platform sound output.
 
 use crate::config::SoundConfig;
-use windows::Win32::System::Diagnostics::Debug::Beep;
+use crate::platform::SoundOutput;
 
 /// Plays a sound when the layout changes.
 ///
 /// # Arguments
+/// * `output` - Platform sound output
 /// * `is_russian` - true for Russian layout, false for English
 /// * `config` - Sound configuration
-pub fn play_layout_sound(is_russian: bool, config: &SoundConfig) {
+pub fn play_layout_sound(output: &dyn SoundOutput, is_russian: bool, config: &SoundConfig) {
     if !config.enabled {
         return;
     }
@@ -22,8 +23,5 @@ pub fn play_layout_sound(is_russian: bool, config: &SoundConfig) {
         config.frequency_en
     };
 
-    // Windows Beep: frequency 37-32767 Hz
-    unsafe {
-        let _ = Beep(freq, config.duration_ms);
-    }
+    output.beep(freq, config.duration_ms);
 }
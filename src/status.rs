@@ -0,0 +1,260 @@
+//! Status module.
+//!
+//! What the tray tooltip tells about the running application: the layout,
+//! whether indicators are on, the profile, how many times the layout was
+//! switched today and the warnings the user should look at.
+
+use std::time::Duration;
+
+/// Whether layout changes show the indicators.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Visibility {
+    /// Indicators are enabled.
+    Visible,
+    /// Indicators are disabled.
+    Hidden,
+    /// Indicators are disabled for this long, then come back on their own.
+    Snoozed(Duration),
+}
+
+/// Application status shown in the tray tooltip.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Status {
+    /// Full name of the current layout, with its variant.
+    pub layout: String,
+    /// Whether indicators are on.
+    pub visibility: Visibility,
+    /// Active config profile.
+    pub profile: String,
+    /// Layout switches since local midnight.
+    pub switches_today: u32,
+    /// Problems such as config errors and hotkey conflicts.
+    pub warnings: Vec<String>,
+}
+
+impl Status {
+    /// Whether the tray's "Indicators enabled" entry is checked; snoozed
+    /// indicators count as disabled.
+    pub fn indicators_enabled(&self) -> bool {
+        self.visibility == Visibility::Visible
+    }
+
+    /// Returns whether `other` shows the same, i.e. a snooze differs at most
+    /// in the part of a minute the tooltip doesn't show.
+    pub fn shows_same(&self, other: &Status) -> bool {
+        let visibility = match (self.visibility, other.visibility) {
+            (Visibility::Snoozed(a), Visibility::Snoozed(b)) => {
+                snooze_minutes(a) == snooze_minutes(b)
+            }
+            (a, b) => a == b,
+        };
+        visibility
+            && self.layout == other.layout
+            && self.profile == other.profile
+            && self.switches_today == other.switches_today
+            && self.warnings == other.warnings
+    }
+
+    /// First tooltip line: the application and the layout.
+    pub fn title(&self) -> String {
+        format!("LangTip: {}", self.layout)
+    }
+
+    /// Tooltip lines below the title.
+    pub fn details(&self) -> String {
+        let visibility = match self.visibility {
+            Visibility::Visible => "on".to_string(),
+            Visibility::Snoozed(left) => {
+                format!("snoozed, {} min left", snooze_minutes(left))
+            }
+            Visibility::Hidden => "off".to_string(),
+        };
+        let mut lines = vec![
+            format!("Indicators: {}", visibility),
+            format!("Profile: {}", self.profile),
+            format!("Switches today: {}", self.switches_today),
+        ];
+        lines.extend(self.warnings.iter().map(|w| format!("! {}", w)));
+        lines.join("\n")
+    }
+
+    /// Returns the tooltip text, at most `max_len` UTF-16 units long.
+    ///
+    /// Warnings that don't fit are replaced by their count; a title that
+    /// doesn't fit on its own is cut off.
+    #[cfg_attr(not(any(windows, test)), allow(dead_code))]
+    pub fn tooltip(&self, max_len: usize) -> String {
+        let fits = |text: &str| text.encode_utf16().count() <= max_len;
+        let text = format!("{}\n{}", self.title(), self.details());
+        if fits(&text) {
+            return text;
+        }
+
+        let summary = Self {
+            warnings: Vec::new(),
+            ..self.clone()
+        };
+        let mut text = format!("{}\n{}", summary.title(), summary.details());
+        if !self.warnings.is_empty() {
+            text.push_str(&format!(
+                "\n! {} warnings, see the menu",
+                self.warnings.len()
+            ));
+        }
+        if fits(&text) {
+            return text;
+        }
+
+        let mut cut = String::new();
+        for c in text.chars() {
+            if !fits(&format!("{}{}…", cut, c)) {
+                break;
+            }
+            cut.push(c);
+        }
+        cut.push('…');
+        cut
+    }
+}
+
+/// Minutes of snooze shown for `left`, rounded up so the last minute doesn't
+/// show as 0.
+fn snooze_minutes(left: Duration) -> u64 {
+    (left + Duration::from_secs(59)).as_secs() / 60
+}
+
+/// Counts layout switches per local calendar day.
+#[derive(Debug, Default)]
+pub struct SwitchCounter {
+    day: i64,
+    count: u32,
+}
+
+impl SwitchCounter {
+    /// Records a switch on `today` (see `days_from_civil`).
+    pub fn record(&mut self, today: i64) {
+        if self.day != today {
+            self.day = today;
+            self.count = 0;
+        }
+        self.count += 1;
+    }
+
+    /// Returns the number of switches on `today`.
+    pub fn count(&self, today: i64) -> u32 {
+        if self.day == today {
+            self.count
+        } else {
+            0
+        }
+    }
+}
+
+/// Converts a calendar date to days since 1970-01-01.
+#[cfg_attr(not(any(windows, target_os = "linux", test)), allow(dead_code))]
+pub fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    // Howard Hinnant's algorithm: years start in March, so leap days come last
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = i64::from(month);
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status() -> Status {
+        Status {
+            layout: "Russian (Russia) - Russian".to_string(),
+            visibility: Visibility::Visible,
+            profile: "default".to_string(),
+            switches_today: 3,
+            warnings: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn snoozes_show_the_same_within_a_minute() {
+        let snoozed = |secs| Status {
+            visibility: Visibility::Snoozed(Duration::from_secs(secs)),
+            ..status()
+        };
+        assert!(snoozed(600).shows_same(&snoozed(541)));
+        assert!(!snoozed(600).shows_same(&snoozed(540)));
+        assert!(!snoozed(60).shows_same(&status()));
+        assert!(!status().shows_same(&Status {
+            switches_today: 4,
+            ..status()
+        }));
+    }
+
+    #[test]
+    fn tooltip_lists_the_status() {
+        assert_eq!(
+            status().tooltip(usize::MAX),
+            "LangTip: Russian (Russia) - Russian\n\
+             Indicators: on\n\
+             Profile: default\n\
+             Switches today: 3"
+        );
+
+        let status = Status {
+            visibility: Visibility::Snoozed(Duration::from_secs(61)),
+            warnings: vec!["Config: expected value at line 1 column 1".to_string()],
+            ..status()
+        };
+        let text = status.tooltip(usize::MAX);
+        assert!(text.contains("Indicators: snoozed, 2 min left"));
+        assert!(text.ends_with("\n! Config: expected value at line 1 column 1"));
+        let hidden = Status {
+            visibility: Visibility::Hidden,
+            ..status
+        };
+        assert!(hidden.details().starts_with("Indicators: off\n"));
+    }
+
+    #[test]
+    fn long_tooltips_are_shortened() {
+        let status = Status {
+            warnings: vec!["ctrl+alt+l (toggle): already registered".to_string(); 3],
+            ..status()
+        };
+        let text = status.tooltip(127);
+        assert!(text.encode_utf16().count() <= 127);
+        assert!(text.ends_with("\n! 3 warnings, see the menu"));
+        assert!(text.contains("Switches today: 3"));
+
+        let text = status.tooltip(20);
+        assert_eq!(text.encode_utf16().count(), 20);
+        assert!(text.starts_with("LangTip: Russian"));
+        assert!(text.ends_with('…'));
+    }
+
+    #[test]
+    fn switch_count_restarts_every_day() {
+        let mut counter = SwitchCounter::default();
+        assert_eq!(counter.count(100), 0);
+        counter.record(100);
+        counter.record(100);
+        assert_eq!(counter.count(100), 2);
+        assert_eq!(counter.count(101), 0);
+        counter.record(101);
+        assert_eq!(counter.count(101), 1);
+    }
+
+    #[test]
+    fn civil_dates_count_days_since_the_epoch() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(1969, 12, 31), -1);
+        assert_eq!(days_from_civil(2000, 3, 1), 11_017);
+        assert_eq!(
+            days_from_civil(2024, 2, 29) + 1,
+            days_from_civil(2024, 3, 1)
+        );
+        assert_eq!(days_from_civil(2026, 10, 18), 20_744);
+    }
+}